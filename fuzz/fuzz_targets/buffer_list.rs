@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use render_callback::fuzzing::{self, BufferListInput};
+
+fuzz_target!(|input: BufferListInput| {
+    let mut input = input;
+    let _ = fuzzing::buffer_list(&mut input);
+});
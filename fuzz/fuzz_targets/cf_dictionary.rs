@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use render_callback::fuzzing::{self, MultiOutputInput};
+
+fuzz_target!(|input: MultiOutputInput| {
+    fuzzing::cf_dictionary(&input);
+});
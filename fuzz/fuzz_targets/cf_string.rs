@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use render_callback::fuzzing;
+
+fuzz_target!(|data: &[u8]| {
+    fuzzing::cf_string(data);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use render_callback::fuzzing::{self, SampleConversionInput};
+
+fuzz_target!(|input: SampleConversionInput| {
+    fuzzing::sample_conversion(&input);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use render_callback::fuzzing::{self, WideLayoutInput};
+
+fuzz_target!(|input: WideLayoutInput| {
+    fuzzing::wide_layout(&input);
+});
@@ -0,0 +1,165 @@
+use std::sync::{Arc, OnceLock};
+
+use asio_sys::Asio;
+
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::processor::{processor_callback, Processor};
+use crate::retry::{retry, RetryPolicy};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::{default_driver_name, AsioDevice};
+use super::error::AsioError;
+use super::session::AsioSession;
+
+/// The ASIO host is process-wide state, and it keeps track of which driver
+/// is loaded, so every backend shares the same one.
+static ASIO: OnceLock<Arc<Asio>> = OnceLock::new();
+
+/// Talks to the ASIO drivers installed on the machine.
+pub struct AsioBackend {
+    asio: Arc<Asio>,
+}
+
+impl AsioBackend {
+    fn device(&self, driver_name: &str) -> AsioDevice {
+        AsioDevice::new(driver_name.to_owned(), self.asio.clone())
+    }
+
+    /// ASIO has no default driver, so this is the one a session has
+    /// already loaded, if any, or else the first one installed.
+    fn default_device(&self) -> Result<AsioDevice, AsioError> {
+        default_driver_name(&self.asio)
+            .map(|name| self.device(&name))
+            .ok_or(AsioError::NoDrivers)
+    }
+
+    fn new_session(
+        &self,
+        policy: RetryPolicy,
+        sample_rate: f64,
+        input_device: AsioDevice,
+        output_device: AsioDevice,
+        buffer_size: Option<usize>,
+    ) -> Result<AsioSession, AsioError> {
+        if input_device != output_device {
+            return Err(AsioError::DifferentDrivers);
+        }
+
+        retry(policy, AsioError::is_transient, || {
+            AsioSession::new(sample_rate, input_device.clone(), buffer_size)
+        })
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(&self, config: &SessionConfig<Self>) -> Result<AsioSession, AsioError> {
+        if config.physical_format.is_some() {
+            return Err(AsioError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(AsioError::Unsupported("preroll"));
+        }
+        if config.routing.is_some() {
+            return Err(AsioError::Unsupported("routing matrices"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(AsioError::Unsupported("more than one driver per session"));
+        }
+
+        let session = self.new_session(
+            config.retry,
+            config.sample_rate,
+            config.input_device.clone(),
+            config.output_device.clone(),
+            config.buffer_size,
+        )?;
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for AsioBackend {
+    type Session = AsioSession;
+    type Error = AsioError;
+    type Device = AsioDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    fn new() -> Result<Self, AsioError> {
+        Ok(AsioBackend {
+            asio: ASIO.get_or_init(|| Arc::new(Asio::new())).clone(),
+        })
+    }
+
+    fn is_available() -> bool {
+        Self::new().is_ok_and(|backend| !backend.asio.driver_names().is_empty())
+    }
+
+    fn all_devices(&self) -> Result<Vec<AsioDevice>, AsioError> {
+        Ok(self
+            .asio
+            .driver_names()
+            .iter()
+            .map(|name| self.device(name))
+            .collect())
+    }
+
+    fn default_input_device(&self) -> Result<AsioDevice, AsioError> {
+        self.default_device()
+    }
+
+    fn default_output_device(&self) -> Result<AsioDevice, AsioError> {
+        self.default_device()
+    }
+
+    /// The driver picks the sample format and a session can only use one
+    /// driver, so a `physical_format` and additional devices are refused,
+    /// along with preroll and routing, which only CoreAudio sessions
+    /// implement so far. `clock_master` and the metadata are ignored.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<AsioSession, AsioError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<AsioSession, AsioError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<AsioSession, AsioError> {
+        Err(AsioError::Unsupported("process taps"))
+    }
+}
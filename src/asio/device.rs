@@ -0,0 +1,159 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use asio_sys::{Asio, AsioSampleType, Driver};
+
+use crate::config::PhysicalFormat;
+use crate::direction::Direction;
+use crate::sample::SampleFormat;
+use crate::traits::Device;
+
+use super::backend::AsioBackend;
+use super::error::AsioError;
+
+/// An installed ASIO driver. Each driver is a single full duplex device.
+///
+/// Only one driver can be loaded at a time, so while a session runs, every
+/// other device fails to answer queries with `AsioError::LoadDriver`.
+/// Devices compare and order by driver name.
+#[derive(Clone)]
+pub struct AsioDevice {
+    driver_name: String,
+    asio: Arc<Asio>,
+}
+
+impl AsioDevice {
+    pub(crate) fn new(driver_name: String, asio: Arc<Asio>) -> Self {
+        AsioDevice { driver_name, asio }
+    }
+
+    pub fn driver_name(&self) -> &str {
+        &self.driver_name
+    }
+
+    /// The loaded driver, loading it first if no session has.
+    pub(crate) fn driver(&self) -> Result<Driver, AsioError> {
+        Ok(self.asio.load_driver(&self.driver_name)?)
+    }
+}
+
+/// The crate's equivalent of a driver sample type. Only little endian
+/// types are supported, which is what drivers on Windows use.
+/// The name of the driver `AsioBackend` treats as the default.
+pub(super) fn default_driver_name(asio: &Asio) -> Option<String> {
+    if let Some(driver) = asio.loaded_driver() {
+        return Some(driver.name().to_owned());
+    }
+
+    asio.driver_names().into_iter().next()
+}
+
+pub(crate) fn sample_format(sample_type: AsioSampleType) -> Result<SampleFormat, AsioError> {
+    match sample_type {
+        AsioSampleType::ASIOSTInt16LSB => Ok(SampleFormat::I16),
+        AsioSampleType::ASIOSTInt24LSB => Ok(SampleFormat::I24),
+        AsioSampleType::ASIOSTInt32LSB => Ok(SampleFormat::I32),
+        AsioSampleType::ASIOSTFloat32LSB => Ok(SampleFormat::F32),
+        AsioSampleType::ASIOSTFloat64LSB => Ok(SampleFormat::F64),
+        other => Err(AsioError::UnsupportedSampleType(other)),
+    }
+}
+
+impl fmt::Debug for AsioDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AsioDevice")
+            .field(&self.driver_name)
+            .finish()
+    }
+}
+
+impl PartialEq for AsioDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.driver_name == other.driver_name
+    }
+}
+
+impl Eq for AsioDevice {}
+
+impl Hash for AsioDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.driver_name.hash(state);
+    }
+}
+
+impl PartialOrd for AsioDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for AsioDevice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.driver_name.cmp(&other.driver_name)
+    }
+}
+
+impl Device<AsioBackend> for AsioDevice {
+    fn num_inputs(&self) -> Result<usize, AsioError> {
+        Ok(self.driver()?.channels()?.ins as usize)
+    }
+
+    fn num_outputs(&self) -> Result<usize, AsioError> {
+        Ok(self.driver()?.channels()?.outs as usize)
+    }
+
+    fn name(&self) -> Result<String, AsioError> {
+        Ok(self.driver_name.clone())
+    }
+
+    /// Driver names come from the registry, where the driver's installer
+    /// put them, so they stay the same until it's reinstalled.
+    fn persistent_id(&self) -> Result<String, AsioError> {
+        Ok(self.driver_name.clone())
+    }
+
+    fn native_format(&self, direction: Direction) -> Result<Option<PhysicalFormat>, AsioError> {
+        let driver = self.driver()?;
+        let channels = driver.channels()?;
+        let sample_type = match direction {
+            Direction::Input if channels.ins > 0 => driver.input_data_type()?,
+            Direction::Output if channels.outs > 0 => driver.output_data_type()?,
+            _ => return Ok(None),
+        };
+
+        let format = sample_format(sample_type)?;
+        Ok(Some(PhysicalFormat {
+            sample_rate: driver.sample_rate()?,
+            bits_per_sample: format.bits_per_sample(),
+            is_float: format.is_float(),
+        }))
+    }
+
+    /// Every driver is full duplex, so the default driver is the default
+    /// both ways.
+    fn is_default_input(&self) -> Result<bool, AsioError> {
+        Ok(default_driver_name(&self.asio).as_ref() == Some(&self.driver_name))
+    }
+
+    fn is_default_output(&self) -> Result<bool, AsioError> {
+        self.is_default_input()
+    }
+
+    fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), AsioError> {
+        Ok(self.driver()?.set_sample_rate(sample_rate)?)
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), AsioError> {
+        Err(AsioError::Unsupported("selecting clock sources"))
+    }
+
+    fn nominal_sample_rate(&self) -> Result<f64, AsioError> {
+        Ok(self.driver()?.sample_rate()?)
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, AsioError> {
+        self.nominal_sample_rate()
+    }
+}
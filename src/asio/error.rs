@@ -0,0 +1,98 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use asio_sys::{AsioSampleType, LoadDriverError};
+
+#[derive(Debug)]
+pub enum AsioError {
+    Driver(asio_sys::AsioError),
+    LoadDriver(LoadDriverError),
+    /// No ASIO drivers are installed.
+    NoDrivers,
+    /// ASIO drivers are full duplex and only one can be loaded at a time, so
+    /// a session's input and output must be the same driver.
+    DifferentDrivers,
+    /// The driver's buffers hold samples in a format the session can't
+    /// convert, like big endian integers.
+    UnsupportedSampleType(AsioSampleType),
+    /// The session was started, but the driver never called it.
+    StartupTimeout(Duration),
+    /// The operation or session option has no ASIO equivalent.
+    Unsupported(&'static str),
+}
+
+impl AsioError {
+    /// Whether the error is expected to clear up by itself, like another
+    /// session still unloading its driver.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            AsioError::LoadDriver(LoadDriverError::DriverAlreadyExists)
+        )
+    }
+}
+
+impl From<asio_sys::AsioError> for AsioError {
+    fn from(error: asio_sys::AsioError) -> Self {
+        AsioError::Driver(error)
+    }
+}
+
+impl From<LoadDriverError> for AsioError {
+    fn from(error: LoadDriverError) -> Self {
+        AsioError::LoadDriver(error)
+    }
+}
+
+impl fmt::Display for AsioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsioError::Driver(error) => write!(f, "ASIO driver error: {}", error),
+            AsioError::LoadDriver(error) => write!(f, "Could not load ASIO driver: {}", error),
+            AsioError::NoDrivers => write!(f, "No ASIO drivers are installed"),
+            AsioError::DifferentDrivers => write!(
+                f,
+                "ASIO sessions must use the same driver for input and output"
+            ),
+            AsioError::UnsupportedSampleType(sample_type) => {
+                write!(f, "Unsupported ASIO sample type {:?}", sample_type)
+            }
+            AsioError::StartupTimeout(timeout) => {
+                write!(
+                    f,
+                    "ASIO driver did not start the session within {:?}",
+                    timeout
+                )
+            }
+            AsioError::Unsupported(what) => write!(f, "Not supported by ASIO: {}", what),
+        }
+    }
+}
+
+impl Error for AsioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AsioError::Driver(error) => Some(error),
+            AsioError::LoadDriver(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<AsioError> for crate::Error {
+    fn from(error: AsioError) -> Self {
+        let kind = match &error {
+            AsioError::Driver(asio_sys::AsioError::NoDrivers) | AsioError::NoDrivers => {
+                Self::DeviceNotFound
+            }
+            AsioError::LoadDriver(LoadDriverError::DriverAlreadyExists) => Self::DeviceBusy,
+            AsioError::Driver(_) | AsioError::LoadDriver(_) => Self::Backend,
+            AsioError::UnsupportedSampleType(_) => Self::FormatNotSupported,
+            AsioError::StartupTimeout(_) => Self::StartupTimeout,
+            AsioError::DifferentDrivers | AsioError::Unsupported(_) => Self::Unsupported,
+        };
+
+        kind(Box::new(error))
+    }
+}
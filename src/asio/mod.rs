@@ -0,0 +1,9 @@
+mod backend;
+mod device;
+mod error;
+mod session;
+
+pub use backend::AsioBackend as Backend;
+pub use device::AsioDevice;
+pub use error::AsioError;
+pub use session::AsioSession;
@@ -0,0 +1,503 @@
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use asio_sys::{AsioStream, CallbackId, Driver};
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::CycleTimes;
+use crate::deadline::DeadlineHistogram;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::Events;
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::sample::{OwnedBuffer, Sample, SampleFormat};
+use crate::traits::{RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+
+use super::backend::AsioBackend;
+use super::device::{sample_format, AsioDevice};
+use super::error::AsioError;
+
+/// How long each buffer size is tried out for when tuning for low latency.
+const LATENCY_TRIAL_PERIOD: Duration = Duration::from_secs(1);
+
+/// A loaded ASIO driver with buffers for all of its channels. The callback
+/// gets one input and one output buffer, with the driver's channels
+/// interleaved in order.
+///
+/// The driver's sample position isn't passed on, so dropouts aren't
+/// detected, and resets the driver asks for aren't followed.
+pub struct AsioSession {
+    engine: Arc<RenderEngine<AsioBackend>>,
+    device: AsioDevice,
+    driver: Driver,
+    /// The driver's input and output channel counts.
+    channels: (usize, usize),
+    buffer_size: usize,
+    /// Set up for the current driver buffers, but not handed to the driver
+    /// yet.
+    process: Option<Process>,
+    callback: Option<CallbackId>,
+}
+
+impl AsioSession {
+    pub(crate) fn new(
+        sample_rate: f64,
+        device: AsioDevice,
+        buffer_size: Option<usize>,
+    ) -> Result<Self, AsioError> {
+        let driver = device.driver()?;
+        if driver.sample_rate()? != sample_rate {
+            driver.set_sample_rate(sample_rate)?;
+        }
+        let channels = driver.channels()?;
+
+        let mut session = AsioSession {
+            engine: Arc::new(RenderEngine::new(sample_rate)),
+            device,
+            driver,
+            channels: (channels.ins as usize, channels.outs as usize),
+            buffer_size: 0,
+            process: None,
+            callback: None,
+        };
+        session.prepare(buffer_size)?;
+
+        Ok(session)
+    }
+
+    /// Hands `callback` to the driver and starts it.
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<AsioBackend>>,
+    ) -> Result<(), AsioError> {
+        self.engine.set_callback(callback);
+        self.run()
+    }
+
+    /// Creates the driver's buffers, at `buffer_size` or the driver's
+    /// preferred size, and the process callback to go with them.
+    fn prepare(&mut self, buffer_size: Option<usize>) -> Result<(), AsioError> {
+        let (ins, outs) = self.channels;
+        let input_format = match ins {
+            0 => SampleFormat::F32,
+            _ => sample_format(self.driver.input_data_type()?)?,
+        };
+        let output_format = match outs {
+            0 => SampleFormat::F32,
+            _ => sample_format(self.driver.output_data_type()?)?,
+        };
+
+        let size = buffer_size.map(|frames| frames as i32);
+        let streams = match (ins, outs) {
+            (0, 0) => return Err(AsioError::Unsupported("drivers without channels")),
+            (ins, 0) => self.driver.prepare_input_stream(None, ins, size)?,
+            (0, outs) => self.driver.prepare_output_stream(None, outs, size)?,
+            (ins, outs) => {
+                let input = self.driver.prepare_input_stream(None, ins, size)?.input;
+                self.driver.prepare_output_stream(input, outs, size)?
+            }
+        };
+
+        self.buffer_size = streams
+            .output
+            .as_ref()
+            .or(streams.input.as_ref())
+            .map_or(0, |stream| stream.buffer_size as usize);
+        self.engine.resize_scratch(self.buffer_size, ins.max(outs));
+        self.engine.reset_timeline();
+
+        self.process = Some(Process {
+            engine: self.engine.clone(),
+            frames: self.buffer_size,
+            input: DriverBuffers::new(input_format, streams.input.as_ref()),
+            output: DriverBuffers::new(output_format, streams.output.as_ref()),
+            input_buffers: vec![buffer(self.buffer_size, ins)],
+            output_buffers: vec![buffer(self.buffer_size, outs)],
+        });
+
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), AsioError> {
+        let mut process = match self.process.take() {
+            Some(process) => process,
+            None => return Ok(()),
+        };
+
+        let callback = self
+            .driver
+            .add_callback(move |info| process.process(info.buffer_index as usize));
+        self.callback = Some(callback);
+        self.engine.valid.store(true, Ordering::Release);
+
+        Ok(self.driver.start()?)
+    }
+
+    /// Stops the driver and takes the callback back. Returns whether it was
+    /// running.
+    fn halt(&mut self) -> Result<bool, AsioError> {
+        let callback = match self.callback.take() {
+            Some(callback) => callback,
+            None => return Ok(false),
+        };
+
+        self.engine.valid.store(false, Ordering::Release);
+        let stopped = self.driver.stop();
+        self.driver.remove_callback(callback);
+        stopped?;
+
+        Ok(true)
+    }
+
+    /// Recreates the driver's buffers at `frames` frames, which interrupts
+    /// the audio briefly. Drivers may round it to a size they support.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), AsioError> {
+        let was_running = self.halt()?;
+        self.prepare(Some(frames))?;
+
+        if was_running {
+            self.run()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        (vec![self.channels.0], vec![self.channels.1])
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.engine.set_output_policy(policy);
+    }
+
+    /// Blocks until the driver has called the session at least once.
+    pub fn wait_until_running(&self, timeout: Duration) -> Result<(), AsioError> {
+        let deadline = Instant::now() + timeout;
+
+        while self.engine.cycles.load(Ordering::Acquire) == 0 {
+            if Instant::now() >= deadline {
+                return Err(AsioError::StartupTimeout(timeout));
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for AsioSession {
+    fn drop(&mut self) {
+        let _ = self.halt();
+
+        // The driver no longer calls the session, so this is guaranteed to
+        // drop the callback here rather than on the real-time thread.
+        drop(self.engine.take_callback());
+    }
+}
+
+fn buffer(frames: usize, channels: usize) -> OwnedBuffer<f32> {
+    let mut buffer = OwnedBuffer::with_capacity(frames, channels);
+    buffer.reshape(frames, channels);
+    buffer
+}
+
+/// The driver's buffers in one direction: two halves per channel, which the
+/// driver alternates between.
+struct DriverBuffers {
+    format: SampleFormat,
+    channels: Vec<[*mut c_void; 2]>,
+}
+
+impl DriverBuffers {
+    fn new(format: SampleFormat, stream: Option<&AsioStream>) -> Self {
+        DriverBuffers {
+            format,
+            channels: stream.map_or_else(Vec::new, |stream| {
+                stream
+                    .buffer_infos
+                    .iter()
+                    .map(|info| info.buffers)
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// The session's side of the buffer switch callback.
+struct Process {
+    engine: Arc<RenderEngine<AsioBackend>>,
+    frames: usize,
+    input: DriverBuffers,
+    output: DriverBuffers,
+    input_buffers: Vec<OwnedBuffer<f32>>,
+    output_buffers: Vec<OwnedBuffer<f32>>,
+}
+
+// The driver's buffers stay where they are until the session recreates
+// them, which it only does after taking the callback back from the driver.
+unsafe impl Send for Process {}
+
+impl Process {
+    /// Renders into half `half` of the driver's buffers.
+    fn process(&mut self, half: usize) {
+        self.engine.cycles.fetch_add(1, Ordering::Release);
+
+        let channels = self.input.channels.len();
+        for (channel, halves) in self.input.channels.iter().enumerate() {
+            let source = halves[half] as *const u8;
+            let frames = self.input_buffers[0]
+                .interleaved_frames_mut()
+                .chunks_mut(channels);
+            for (index, frame) in frames.enumerate() {
+                frame[channel] = unsafe { read_sample(self.input.format, source, index) };
+            }
+        }
+
+        unsafe {
+            self.engine.render(
+                &self.input_buffers,
+                &mut self.output_buffers,
+                self.frames,
+                CycleTimes::default(),
+            );
+        }
+
+        let channels = self.output.channels.len();
+        for (channel, halves) in self.output.channels.iter().enumerate() {
+            let destination = halves[half] as *mut u8;
+            let frames = self.output_buffers[0].interleaved_frames().chunks(channels);
+            for (index, frame) in frames.enumerate() {
+                unsafe { write_sample(self.output.format, destination, index, frame[channel]) };
+            }
+        }
+    }
+}
+
+/// Reads sample `index` from a driver buffer of little endian `format`
+/// samples. Integers are widened to 32 bits, keeping their most significant
+/// bytes, so they can share `i32`'s conversion.
+unsafe fn read_sample(format: SampleFormat, buffer: *const u8, index: usize) -> f32 {
+    let size = format.bits_per_sample() as usize / 8;
+    let source = buffer.add(index * size);
+
+    match format {
+        SampleFormat::F64 => ptr::read_unaligned(source as *const f64) as f32,
+        SampleFormat::F32 => ptr::read_unaligned(source as *const f32),
+        _ => {
+            let mut bytes = [0; 4];
+            ptr::copy_nonoverlapping(source, bytes[4 - size..].as_mut_ptr(), size);
+            i32::from_le_bytes(bytes).to_f32()
+        }
+    }
+}
+
+unsafe fn write_sample(format: SampleFormat, buffer: *mut u8, index: usize, sample: f32) {
+    let size = format.bits_per_sample() as usize / 8;
+    let destination = buffer.add(index * size);
+
+    match format {
+        SampleFormat::F64 => ptr::write_unaligned(destination as *mut f64, f64::from(sample)),
+        SampleFormat::F32 => ptr::write_unaligned(destination as *mut f32, sample),
+        _ => {
+            let bytes = i32::from_f32(sample).to_le_bytes();
+            ptr::copy_nonoverlapping(bytes[4 - size..].as_ptr(), destination, size);
+        }
+    }
+}
+
+impl Session<AsioBackend> for AsioSession {
+    fn input_device(&self) -> Result<AsioDevice, AsioError> {
+        Ok(self.device.clone())
+    }
+
+    fn output_device(&self) -> Result<AsioDevice, AsioError> {
+        Ok(self.device.clone())
+    }
+
+    /// The driver handles both directions, so a session can't switch to
+    /// another one. Start a new session instead.
+    fn set_input_device(&mut self, device: AsioDevice) -> Result<(), AsioError> {
+        if device == self.device {
+            Ok(())
+        } else {
+            Err(AsioError::DifferentDrivers)
+        }
+    }
+
+    fn set_output_device(&mut self, device: AsioDevice) -> Result<(), AsioError> {
+        self.set_input_device(device)
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, AsioError> {
+        Ok(AsioSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, AsioError> {
+        let side = |channels| {
+            vec![StreamMapping {
+                device_id: self.device.driver_name().to_owned(),
+                first_device_channel: 0,
+                channels,
+            }]
+        };
+
+        Ok(ChannelMap {
+            input: side(self.channels.0),
+            output: side(self.channels.1),
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.engine.clock.clone()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<AsioBackend>>) {
+        self.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.engine.is_bypassed()
+    }
+
+    /// Tries the driver's smallest and largest buffer sizes and the powers
+    /// of two in between. Since dropouts aren't detected, a size counts as
+    /// stable when no callback overran its deadline.
+    fn tune_for_low_latency(&mut self, target_ms: f64) -> Result<Option<LatencyTuning>, AsioError> {
+        let sample_rate = self.engine.clock.sample_rate();
+        let target_frames = (target_ms * sample_rate / 1000.0) as usize;
+
+        let (min, max) = self.driver.buffersize_range()?;
+        let (min, max) = (min as usize, max as usize);
+        let mut candidates = vec![min];
+        let mut size = min.next_power_of_two();
+        while size < max {
+            if size > min {
+                candidates.push(size);
+            }
+            size *= 2;
+        }
+        if max > min {
+            candidates.push(max);
+        }
+
+        // Start from the largest size within the target: anything smaller
+        // only adds risk of overloads.
+        let first = candidates
+            .iter()
+            .rposition(|&size| 2 * size <= target_frames)
+            .unwrap_or(0);
+
+        let original = self.buffer_size;
+        let mut stable = false;
+        for &size in &candidates[first..] {
+            self.set_buffer_size(size)?;
+
+            let before = self.engine.deadlines.histogram().overruns;
+            thread::sleep(LATENCY_TRIAL_PERIOD);
+
+            if self.engine.deadlines.histogram().overruns == before {
+                stable = true;
+                break;
+            }
+        }
+
+        if !stable {
+            // Nothing ran cleanly, so leave the session as it was found.
+            self.set_buffer_size(original)?;
+            return Ok(None);
+        }
+
+        Ok(Some(LatencyTuning {
+            buffer_size: self.buffer_size,
+            input_latency_frames: self.buffer_size,
+            output_latency_frames: self.buffer_size,
+            sample_rate,
+        }))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, AsioError> {
+        Err(AsioError::Unsupported("starting at a host time"))
+    }
+
+    fn stop(&mut self) -> Result<(), AsioError> {
+        self.halt()?;
+        Ok(())
+    }
+
+    /// The process callback, and the buffers it renders into, go away when
+    /// the session stops, so they're made again at the same size.
+    fn start(&mut self) -> Result<(), AsioError> {
+        if self.callback.is_some() {
+            return Ok(());
+        }
+        if self.process.is_none() {
+            self.prepare(Some(self.buffer_size))?;
+        }
+        self.run()
+    }
+
+    fn is_running(&self) -> bool {
+        self.callback.is_some()
+    }
+}
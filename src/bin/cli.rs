@@ -0,0 +1,266 @@
+//! Lists audio devices and runs test sessions between them, printing what the
+//! session reports as it goes. Handy for checking a setup, and for including
+//! the output when reporting a bug.
+//!
+//! ```text
+//! render-callback list
+//! render-callback passthrough [options]
+//! render-callback sine [options]
+//! ```
+//!
+//! Options for `passthrough` and `sine`:
+//!
+//! - `--input <name or id>`, `--output <name or id>`: devices to use,
+//!   defaulting to the system defaults
+//! - `--rate <hz>`: sample rate, 48000 by default
+//! - `--seconds <n>`: how long to run, until interrupted by default
+//! - `--tune-ms <ms>`: tune the buffer size for this round-trip latency first
+//! - `--frequency <hz>`: the test tone, 440 by default
+
+use std::env;
+use std::f64::consts::TAU;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use render_callback::{
+    AudioBuffers, Backend, CurrentPlatformBackend, CurrentPlatformDevice, Device, Direction,
+    Session, SessionConfig,
+};
+
+const USAGE: &str = "usage: render-callback (list | passthrough | sine) [--input DEVICE] \
+                     [--output DEVICE] [--rate HZ] [--seconds N] [--tune-ms MS] [--frequency HZ]";
+
+/// The test tone's amplitude, about -12 dBFS.
+const SINE_AMPLITUDE: f64 = 0.25;
+
+struct Options {
+    input: Option<String>,
+    output: Option<String>,
+    sample_rate: f64,
+    duration: Option<Duration>,
+    tune_ms: Option<f64>,
+    frequency: f64,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            input: None,
+            output: None,
+            sample_rate: 48000.0,
+            duration: None,
+            tune_ms: None,
+            frequency: 440.0,
+        };
+
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", flag))?;
+            let number = || {
+                value
+                    .parse::<f64>()
+                    .map_err(|_| format!("{} takes a number, not {:?}", flag, value))
+            };
+
+            match flag.as_str() {
+                "--input" => options.input = Some(value.clone()),
+                "--output" => options.output = Some(value.clone()),
+                "--rate" => options.sample_rate = number()?,
+                "--seconds" => options.duration = Some(Duration::from_secs_f64(number()?)),
+                "--tune-ms" => options.tune_ms = Some(number()?),
+                "--frequency" => options.frequency = number()?,
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let command = args.next();
+
+    let result = match command.as_deref() {
+        Some("list") => list(),
+        Some(mode @ ("passthrough" | "sine")) => {
+            Options::parse(args).and_then(|options| run(mode == "sine", options))
+        }
+        _ => Err(USAGE.to_owned()),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn list() -> Result<(), String> {
+    let backend = CurrentPlatformBackend::new().map_err(|e| e.to_string())?;
+
+    for info in backend.device_infos().map_err(|e| e.to_string())? {
+        let defaults = match (info.is_default_input, info.is_default_output) {
+            (true, true) => " (default input and output)",
+            (true, false) => " (default input)",
+            (false, true) => " (default output)",
+            (false, false) => "",
+        };
+        println!("{}{}", info.name, defaults);
+        println!("  id: {}", info.persistent_id);
+        println!(
+            "  channels: {} in, {} out",
+            info.num_inputs, info.num_outputs
+        );
+
+        if let Ok(Some(device)) = backend.device_by_id(&info.persistent_id) {
+            if let Ok(rate) = device.nominal_sample_rate() {
+                println!("  sample rate: {} Hz", rate);
+            }
+            for direction in [Direction::Input, Direction::Output] {
+                if let Ok(Some(format)) = device.native_format(direction) {
+                    println!(
+                        "  {:?} format: {:?}, {} Hz",
+                        direction,
+                        format.sample_format(),
+                        format.sample_rate
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds a device by persistent ID or exact name.
+fn find_device(
+    backend: &CurrentPlatformBackend,
+    query: Option<&str>,
+    default: CurrentPlatformDevice,
+) -> Result<CurrentPlatformDevice, String> {
+    let query = match query {
+        Some(query) => query,
+        None => return Ok(default),
+    };
+
+    if let Some(device) = backend.device_by_id(query).map_err(|e| e.to_string())? {
+        return Ok(device);
+    }
+    for device in backend.all_devices().map_err(|e| e.to_string())? {
+        if device.name().map_err(|e| e.to_string())? == query {
+            return Ok(device);
+        }
+    }
+
+    Err(format!("no device named {:?}", query))
+}
+
+fn run(sine: bool, options: Options) -> Result<(), String> {
+    let backend = CurrentPlatformBackend::new().map_err(|e| e.to_string())?;
+    let (default_input, default_output) = backend.default_devices().map_err(|e| e.to_string())?;
+    let input = find_device(&backend, options.input.as_deref(), default_input)?;
+    let output = find_device(&backend, options.output.as_deref(), default_output)?;
+
+    println!(
+        "{} -> {} at {} Hz",
+        input.name().map_err(|e| e.to_string())?,
+        output.name().map_err(|e| e.to_string())?,
+        options.sample_rate
+    );
+
+    let step = TAU * options.frequency / options.sample_rate;
+    let mut phase = 0.0f64;
+    let mut session = SessionConfig::new(options.sample_rate, input, output)
+        .start(
+            &backend,
+            Box::new(move |_, _, outputs| {
+                // Passthrough sessions are bypassed as soon as they start,
+                // and stay silent until then.
+                if !sine {
+                    return;
+                }
+
+                let frames = frames_in(outputs);
+                for output in outputs {
+                    let channels = output.num_channels();
+                    let mut frame_phase = phase;
+                    for frame in output.interleaved_frames_mut().chunks_mut(channels) {
+                        frame.fill((frame_phase.sin() * SINE_AMPLITUDE) as f32);
+                        frame_phase = (frame_phase + step) % TAU;
+                    }
+                }
+                phase = (phase + step * frames as f64) % TAU;
+            }),
+        )
+        .map_err(|e| e.to_string())?;
+    session.set_bypassed(!sine);
+
+    if let Some(target_ms) = options.tune_ms {
+        match session
+            .tune_for_low_latency(target_ms)
+            .map_err(|e| e.to_string())?
+        {
+            Some(tuning) => println!(
+                "tuned to {} frames, {:.1} ms round trip",
+                tuning.buffer_size,
+                tuning.round_trip_ms()
+            ),
+            None => println!("no buffer size ran without dropouts, left as it was"),
+        }
+    }
+
+    let buffer = Session::max_frames_per_callback(&session).map_err(|e| e.to_string())?;
+    println!(
+        "buffer: {} frames, {:.1} ms",
+        buffer,
+        buffer as f64 * 1000.0 / options.sample_rate
+    );
+
+    let events = session.events();
+    let clock = session.sample_clock();
+    let meters = session.meters();
+    let started = Instant::now();
+
+    while options
+        .duration
+        .is_none_or(|duration| started.elapsed() < duration)
+    {
+        thread::sleep(Duration::from_secs(1));
+
+        for event in events.drain() {
+            println!("event: {:?}", event);
+        }
+
+        let readings = meters.read();
+        let peak = |levels: &[render_callback::Level]| {
+            levels.iter().map(|level| level.peak).fold(0.0f32, f32::max)
+        };
+        let deadlines = session.deadline_margins();
+        println!(
+            "{:.1} s: {} frames, {} dropouts, {} overruns, worst margin {:.0}%, peak in {:.2} out {:.2}",
+            clock.seconds(),
+            clock.frames(),
+            session.dropouts().count,
+            deadlines.overruns,
+            deadlines.worst_margin * 100.0,
+            peak(&readings.input),
+            peak(&readings.output)
+        );
+    }
+
+    let dropouts = session.dropouts();
+    println!(
+        "done: {} dropouts, largest {} frames, {} events lost",
+        dropouts.count,
+        dropouts.largest_dropout_frames,
+        events.lost()
+    );
+
+    Ok(())
+}
+
+fn frames_in<A: AudioBuffers>(buffers: &[A]) -> usize {
+    buffers.first().map_or(0, AudioBuffers::num_frames)
+}
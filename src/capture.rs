@@ -0,0 +1,8 @@
+/// The application whose audio `Backend::capture_process` records.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CaptureTarget {
+    Pid(i32),
+    BundleId(String),
+    /// Everything played by every process except this one.
+    System,
+}
@@ -0,0 +1,164 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::context::RenderContext;
+use crate::processor::Processor;
+use crate::queue::Queue;
+use crate::traits::{Backend, RenderCallback};
+
+/// Callbacks in a chain beyond this are rejected.
+pub const MAX_CHAINED_CALLBACKS: usize = 32;
+
+const COMMAND_QUEUE_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallbackId(u64);
+
+enum Command<B: Backend> {
+    Insert {
+        index: usize,
+        id: CallbackId,
+        callback: Box<RenderCallback<B>>,
+    },
+    Remove(CallbackId),
+}
+
+struct ChainQueues<B: Backend> {
+    commands: Queue<Command<B>>,
+    /// Callbacks that were removed or didn't fit, sent back so they're
+    /// dropped off the real-time thread.
+    finished: Queue<Box<RenderCallback<B>>>,
+    next_id: AtomicU64,
+}
+
+/// Runs several callbacks one after the other on the same session, like a
+/// capture tap, the main DSP and a metering tap, instead of one closure
+/// doing it all.
+///
+/// `CallbackChain` is the control handle for adding and removing callbacks
+/// while the session runs; the `ChainProcessor` it comes with calls them and
+/// is meant to be the session's processor. Every callback sees the same
+/// input, and the output as the callbacks before it left it.
+pub struct CallbackChain<B: Backend> {
+    queues: Arc<ChainQueues<B>>,
+}
+
+impl<B: Backend> CallbackChain<B> {
+    pub fn new() -> (CallbackChain<B>, ChainProcessor<B>) {
+        let queues = Arc::new(ChainQueues {
+            commands: Queue::new(COMMAND_QUEUE_SIZE),
+            finished: Queue::new(MAX_CHAINED_CALLBACKS + COMMAND_QUEUE_SIZE),
+            next_id: AtomicU64::new(0),
+        });
+
+        (
+            CallbackChain {
+                queues: queues.clone(),
+            },
+            ChainProcessor {
+                queues,
+                callbacks: Vec::with_capacity(MAX_CHAINED_CALLBACKS),
+            },
+        )
+    }
+
+    /// Adds `callback` to the end of the chain. Returns `None` if the chain
+    /// is too busy to accept more commands.
+    pub fn push(&self, callback: Box<RenderCallback<B>>) -> Option<CallbackId> {
+        self.insert(usize::MAX, callback)
+    }
+
+    /// Adds `callback` at `index` in the chain as it is when the command
+    /// reaches the real-time thread, or at the end if the chain is shorter.
+    pub fn insert(&self, index: usize, callback: Box<RenderCallback<B>>) -> Option<CallbackId> {
+        let id = CallbackId(self.queues.next_id.fetch_add(1, Ordering::Relaxed));
+
+        self.send(Command::Insert {
+            index,
+            id,
+            callback,
+        })
+        .then_some(id)
+    }
+
+    pub fn remove(&self, id: CallbackId) -> bool {
+        self.send(Command::Remove(id))
+    }
+
+    fn send(&self, command: Command<B>) -> bool {
+        while self.queues.finished.pop().is_some() {}
+
+        self.queues.commands.push(command).is_ok()
+    }
+}
+
+impl<B: Backend> Clone for CallbackChain<B> {
+    fn clone(&self) -> Self {
+        CallbackChain {
+            queues: self.queues.clone(),
+        }
+    }
+}
+
+pub struct ChainProcessor<B: Backend> {
+    queues: Arc<ChainQueues<B>>,
+    callbacks: Vec<(CallbackId, Box<RenderCallback<B>>)>,
+}
+
+impl<B: Backend> ChainProcessor<B> {
+    fn retire(&self, callback: Box<RenderCallback<B>>) {
+        // Only fails if the application hasn't touched the chain in a long
+        // time, in which case dropping here is the lesser evil.
+        let _ = self.queues.finished.push(callback);
+    }
+
+    fn handle_commands(&mut self) {
+        while let Some(command) = self.queues.commands.pop() {
+            match command {
+                Command::Insert {
+                    index,
+                    id,
+                    callback,
+                } => {
+                    if self.callbacks.len() < MAX_CHAINED_CALLBACKS {
+                        let index = index.min(self.callbacks.len());
+                        self.callbacks.insert(index, (id, callback));
+                    } else {
+                        self.retire(callback);
+                    }
+                }
+                Command::Remove(id) => {
+                    if let Some(index) = self.callbacks.iter().position(|(other, _)| *other == id) {
+                        let (_, callback) = self.callbacks.remove(index);
+                        self.retire(callback);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<B: Backend> Processor<B> for ChainProcessor<B> {
+    fn prepare(&mut self, _sample_rate: f64, _max_frames: usize) {}
+
+    fn process(
+        &mut self,
+        input: &[B::AudioBuffers],
+        output: &mut [B::AudioBuffers],
+        ctx: &RenderContext<'_>,
+    ) {
+        self.handle_commands();
+
+        for (_, callback) in self.callbacks.iter_mut() {
+            callback(ctx, input, output);
+        }
+    }
+
+    fn reset(&mut self) {}
+}
+
+impl<B: Backend> Drop for ChainProcessor<B> {
+    fn drop(&mut self) {
+        while self.queues.commands.pop().is_some() {}
+    }
+}
@@ -0,0 +1,16 @@
+/// Where the buffers handed to the render callback come from. Entry `n` of
+/// `input` describes `input[n]` in the callback, and likewise for outputs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelMap {
+    pub input: Vec<StreamMapping>,
+    pub output: Vec<StreamMapping>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamMapping {
+    /// The persistent ID of the device the stream belongs to.
+    pub device_id: String,
+    /// The device channel, counting from zero, of the buffer's first channel.
+    pub first_device_channel: usize,
+    pub channels: usize,
+}
@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::time;
+
+struct ClockState {
+    frames: AtomicU64,
+    sample_rate: AtomicU64,
+}
+
+/// A cheap, cloneable handle to the number of frames a session has rendered
+/// since it started. It only ever moves forwards, in steps of one callback,
+/// so other threads can use it as a time base that's locked to the audio.
+#[derive(Clone)]
+pub struct SampleClock {
+    state: Arc<ClockState>,
+}
+
+impl SampleClock {
+    pub(crate) fn new(sample_rate: f64) -> Self {
+        SampleClock {
+            state: Arc::new(ClockState {
+                frames: AtomicU64::new(0),
+                sample_rate: AtomicU64::new(sample_rate.to_bits()),
+            }),
+        }
+    }
+
+    /// Called from the real-time thread after each callback.
+    pub(crate) fn advance(&self, frames: usize) {
+        self.state
+            .frames
+            .fetch_add(frames as u64, Ordering::Release);
+    }
+
+    pub(crate) fn set_sample_rate(&self, sample_rate: f64) {
+        self.state
+            .sample_rate
+            .store(sample_rate.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.state.frames.load(Ordering::Acquire)
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        f64::from_bits(self.state.sample_rate.load(Ordering::Relaxed))
+    }
+
+    /// The clock in seconds, at the session's nominal sample rate.
+    pub fn seconds(&self) -> f64 {
+        self.frames() as f64 / self.sample_rate()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        time::frames_to_duration(self.frames() as f64, self.sample_rate())
+    }
+}
@@ -0,0 +1,797 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use crate::direction::Direction;
+use crate::retry::RetryPolicy;
+use crate::routing::{ChannelRef, RoutingMatrix};
+use crate::sample::SampleFormat;
+use crate::traits::{Backend, Device, RenderCallback};
+
+/// Everything needed to start a session, beyond the render callback.
+///
+/// It holds device handles, which only mean something to the running
+/// process. Save it with `to_persisted` and load it with `from_persisted`.
+pub struct SessionConfig<B: Backend> {
+    pub(crate) sample_rate: f64,
+    pub(crate) input_device: B::Device,
+    pub(crate) output_device: B::Device,
+    pub(crate) additional_input_devices: Vec<B::Device>,
+    pub(crate) additional_output_devices: Vec<B::Device>,
+    pub(crate) clock_master: Option<B::Device>,
+    pub(crate) buffer_size: Option<usize>,
+    pub(crate) input_channels: Option<usize>,
+    pub(crate) output_channels: Option<usize>,
+    pub(crate) physical_format: Option<PhysicalFormat>,
+    pub(crate) preroll_buffers: usize,
+    pub(crate) startup_timeout: Option<Duration>,
+    pub(crate) auto_resume: bool,
+    pub(crate) restart_on_wake: bool,
+    pub(crate) follow_default_devices: bool,
+    pub(crate) output_policy: OutputPolicy,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) routing: Option<RoutingMatrix>,
+    pub(crate) metadata: StreamMetadata,
+    pub(crate) voice_processing: bool,
+}
+
+/// How the application and its audio are presented by desktop mixers. Sound
+/// servers like PulseAudio and PipeWire show these next to the streams they
+/// are attached to, and use the role to route them. Backends without a place
+/// for them ignore them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamMetadata {
+    /// Defaults to the name of the executable.
+    pub application_name: Option<String>,
+    pub media_role: Option<MediaRole>,
+    /// An icon name from the desktop's icon theme.
+    pub icon_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MediaRole {
+    Music,
+    Video,
+    Game,
+    Phone,
+    Event,
+    Animation,
+    Production,
+    Accessibility,
+}
+
+impl MediaRole {
+    /// The value of the `media.role` stream property.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MediaRole::Music => "music",
+            MediaRole::Video => "video",
+            MediaRole::Game => "game",
+            MediaRole::Phone => "phone",
+            MediaRole::Event => "event",
+            MediaRole::Animation => "animation",
+            MediaRole::Production => "production",
+            MediaRole::Accessibility => "a11y",
+        }
+    }
+}
+
+/// What state output buffers are in when the callback gets them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OutputPolicy {
+    /// Every output sample is zero, so the callback only has to write what it
+    /// plays. The default.
+    #[default]
+    Zeroed,
+    /// The buffers are handed over exactly as the driver provided them, and
+    /// may hold anything, including the previous cycle's output. The
+    /// callback has to write every sample.
+    Untouched,
+}
+
+/// The format a device's hardware runs at, as opposed to the format the
+/// callback sees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhysicalFormat {
+    pub sample_rate: f64,
+    pub bits_per_sample: u32,
+    pub is_float: bool,
+}
+
+impl PhysicalFormat {
+    /// None for sizes that don't correspond to a `SampleFormat`, like 20 bit
+    /// integers.
+    pub fn sample_format(&self) -> Option<SampleFormat> {
+        SampleFormat::from_bits(self.bits_per_sample, self.is_float)
+    }
+}
+
+/// Everything in a `SessionConfig`, with devices identified by their
+/// persistent IDs so it can be saved between runs of an application. With
+/// the `serde` feature it can be written to and read from any format serde
+/// supports; fields missing from older files get their defaults.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PersistedSessionConfig {
+    pub sample_rate: f64,
+    pub input_device_id: Option<String>,
+    pub output_device_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub additional_input_device_ids: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub additional_output_device_ids: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub clock_master_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub buffer_size: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub input_channels: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub output_channels: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub physical_format: Option<PhysicalFormat>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preroll_buffers: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub startup_timeout: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub auto_resume: bool,
+    #[cfg_attr(feature = "serde", serde(default = "default_restart_on_wake"))]
+    pub restart_on_wake: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub follow_default_devices: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub output_policy: OutputPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub retry: RetryPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub routing: Option<RoutingMatrix>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: StreamMetadata,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub voice_processing: bool,
+}
+
+#[cfg(feature = "serde")]
+fn default_restart_on_wake() -> bool {
+    true
+}
+
+impl<B: Backend> SessionConfig<B> {
+    pub fn new(sample_rate: f64, input_device: B::Device, output_device: B::Device) -> Self {
+        SessionConfig {
+            sample_rate,
+            input_device,
+            output_device,
+            additional_input_devices: Vec::new(),
+            additional_output_devices: Vec::new(),
+            clock_master: None,
+            buffer_size: None,
+            input_channels: None,
+            output_channels: None,
+            physical_format: None,
+            preroll_buffers: 0,
+            startup_timeout: None,
+            auto_resume: false,
+            restart_on_wake: true,
+            follow_default_devices: false,
+            output_policy: OutputPolicy::Zeroed,
+            retry: RetryPolicy::default(),
+            routing: None,
+            metadata: StreamMetadata::default(),
+            voice_processing: false,
+        }
+    }
+
+    /// Adds another device to capture from in the same session. Its buffers
+    /// follow those of the main input device in the callback.
+    pub fn add_input_device(mut self, device: B::Device) -> Self {
+        self.additional_input_devices.push(device);
+        self
+    }
+
+    /// Adds another device to play through in the same session. Its buffers
+    /// follow those of the main output device in the callback.
+    pub fn add_output_device(mut self, device: B::Device) -> Self {
+        self.additional_output_devices.push(device);
+        self
+    }
+
+    /// The device whose clock all others follow, for setups where the devices
+    /// are locked to a common clock. Defaults to the main input device.
+    pub fn clock_master(mut self, device: B::Device) -> Self {
+        self.clock_master = Some(device);
+        self
+    }
+
+    /// Asks for a specific number of frames per callback, rather than the
+    /// device's current setting.
+    pub fn buffer_size(mut self, frames: usize) -> Self {
+        self.buffer_size = Some(frames);
+        self
+    }
+
+    /// Requires the input device to have at least `channels` input channels.
+    /// The callback still gets all of them; use `routing` to pick some.
+    pub fn input_channels(mut self, channels: usize) -> Self {
+        self.input_channels = Some(channels);
+        self
+    }
+
+    /// Requires the output device to have at least `channels` output
+    /// channels.
+    pub fn output_channels(mut self, channels: usize) -> Self {
+        self.output_channels = Some(channels);
+        self
+    }
+
+    /// Switches every stream of the session's devices to `format` before
+    /// starting, and back to what it was when the session is dropped.
+    pub fn physical_format(mut self, format: PhysicalFormat) -> Self {
+        self.physical_format = Some(format);
+        self
+    }
+
+    /// Calls the callback `buffers` times before starting the device, with
+    /// silent input, so the first cycles play real output instead of silence.
+    /// The output stays that many buffers behind the callback for the rest of
+    /// the session.
+    pub fn preroll(mut self, buffers: usize) -> Self {
+        self.preroll_buffers = buffers;
+        self
+    }
+
+    /// Fails the session if the device hasn't asked for any audio this long
+    /// after being started, instead of silently never calling the callback.
+    /// By default the session doesn't wait for the device at all.
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = Some(timeout);
+        self
+    }
+
+    /// Restarts the session by itself when an interruption ends. Either way,
+    /// `SessionEvent::Interrupted` and `SessionEvent::Resumed` are reported.
+    pub fn auto_resume(mut self, auto_resume: bool) -> Self {
+        self.auto_resume = auto_resume;
+        self
+    }
+
+    /// Whether to restart the session when the system wakes from sleep. On
+    /// by default, since devices often don't resume by themselves.
+    pub fn restart_on_wake(mut self, restart_on_wake: bool) -> Self {
+        self.restart_on_wake = restart_on_wake;
+        self
+    }
+
+    /// Moves the session to the new system default input or output device
+    /// whenever it changes, like when headphones are plugged in. Backends
+    /// that can't switch devices while running ignore this.
+    pub fn follow_default_devices(mut self, follow: bool) -> Self {
+        self.follow_default_devices = follow;
+        self
+    }
+
+    pub fn output_policy(mut self, policy: OutputPolicy) -> Self {
+        self.output_policy = policy;
+        self
+    }
+
+    /// How to retry setting up the session when the audio system isn't
+    /// ready yet. Retries for about ten seconds by default.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Picks the device channels the callback sees, by index or by name. For
+    /// a plain selection, like only the interface's inputs 3 and 4, use
+    /// `RoutingMatrix::select_inputs`.
+    pub fn routing(mut self, routing: RoutingMatrix) -> Self {
+        self.routing = Some(routing);
+        self
+    }
+
+    pub fn stream_metadata(mut self, metadata: StreamMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Runs the session through the platform's voice processing, with echo
+    /// cancellation and gain control, for calls. Only the iOS backend has
+    /// it so far; the others ignore this.
+    pub fn voice_processing(mut self, voice_processing: bool) -> Self {
+        self.voice_processing = voice_processing;
+        self
+    }
+
+    /// Checks the settings against each other and against the devices,
+    /// including the additional devices and the channels routes refer to,
+    /// without changing anything.
+    pub fn validate(&self) -> Result<(), ConfigError<B::Error>> {
+        if !(self.sample_rate.is_finite() && self.sample_rate > 0.0) {
+            return Err(ConfigError::InvalidSampleRate(self.sample_rate));
+        }
+
+        if self.buffer_size == Some(0) {
+            return Err(ConfigError::InvalidBufferSize(0));
+        }
+
+        if let Some(format) = self.physical_format {
+            if format.sample_format().is_none() {
+                return Err(ConfigError::UnsupportedFormat(format));
+            }
+            if format.sample_rate != self.sample_rate {
+                return Err(ConfigError::SampleRateMismatch {
+                    sample_rate: self.sample_rate,
+                    format,
+                });
+            }
+        }
+
+        let requirements = [
+            (Direction::Input, &self.input_device, self.input_channels),
+            (Direction::Output, &self.output_device, self.output_channels),
+        ];
+        for &(direction, device, requested) in &requirements {
+            let requested = match requested {
+                Some(requested) => requested,
+                None => continue,
+            };
+            let available = num_channels::<B>(device, direction).map_err(ConfigError::Backend)?;
+
+            if available < requested {
+                return Err(ConfigError::NotEnoughChannels {
+                    direction,
+                    requested,
+                    available,
+                });
+            }
+        }
+
+        let sides = [
+            (
+                Direction::Input,
+                &self.input_device,
+                &self.additional_input_devices,
+            ),
+            (
+                Direction::Output,
+                &self.output_device,
+                &self.additional_output_devices,
+            ),
+        ];
+        for &(direction, main, additional) in &sides {
+            for (i, device) in additional.iter().enumerate() {
+                if device == main || additional[..i].contains(device) {
+                    return Err(ConfigError::DuplicateDevice(direction));
+                }
+                if num_channels::<B>(device, direction).map_err(ConfigError::Backend)? == 0 {
+                    return Err(ConfigError::NoChannels(direction));
+                }
+            }
+        }
+
+        if let Some(master) = &self.clock_master {
+            let mut devices = sides
+                .iter()
+                .flat_map(|&(_, main, additional)| Some(main).into_iter().chain(additional));
+            if !devices.any(|device| device == master) {
+                return Err(ConfigError::ClockMasterNotInSession);
+            }
+        }
+
+        if let Some(routing) = &self.routing {
+            for &(direction, main, additional) in &sides {
+                let routes = match direction {
+                    Direction::Input => &routing.input,
+                    Direction::Output => &routing.output,
+                };
+                let devices: Vec<_> = Some(main).into_iter().chain(additional).collect();
+                for route in routes {
+                    if !has_channel::<B>(&devices, direction, &route.device_channel)
+                        .map_err(ConfigError::Backend)?
+                    {
+                        return Err(ConfigError::UnknownChannel {
+                            direction,
+                            channel: route.device_channel.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validates the config and starts a session with it.
+    pub fn start(
+        self,
+        backend: &B,
+        callback: Box<RenderCallback<B>>,
+    ) -> Result<B::Session, ConfigError<B::Error>> {
+        self.validate()?;
+        backend
+            .start_session_with_config(self, callback)
+            .map_err(ConfigError::Backend)
+    }
+
+    pub fn to_persisted(&self) -> Result<PersistedSessionConfig, B::Error> {
+        Ok(PersistedSessionConfig {
+            sample_rate: self.sample_rate,
+            input_device_id: Some(self.input_device.persistent_id()?),
+            output_device_id: Some(self.output_device.persistent_id()?),
+            additional_input_device_ids: persistent_ids::<B>(&self.additional_input_devices)?,
+            additional_output_device_ids: persistent_ids::<B>(&self.additional_output_devices)?,
+            clock_master_id: match &self.clock_master {
+                Some(device) => Some(device.persistent_id()?),
+                None => None,
+            },
+            buffer_size: self.buffer_size,
+            input_channels: self.input_channels,
+            output_channels: self.output_channels,
+            physical_format: self.physical_format,
+            preroll_buffers: self.preroll_buffers,
+            startup_timeout: self.startup_timeout,
+            auto_resume: self.auto_resume,
+            restart_on_wake: self.restart_on_wake,
+            follow_default_devices: self.follow_default_devices,
+            output_policy: self.output_policy,
+            retry: self.retry,
+            routing: self.routing.clone(),
+            metadata: self.metadata.clone(),
+            voice_processing: self.voice_processing,
+        })
+    }
+
+    /// Restores a persisted configuration. Main devices that are no longer
+    /// connected are replaced by the current default devices, and missing
+    /// additional devices are left out.
+    pub fn from_persisted(
+        backend: &B,
+        persisted: &PersistedSessionConfig,
+    ) -> Result<Self, B::Error> {
+        let input_device = match find_device(backend, &persisted.input_device_id)? {
+            Some(device) => device,
+            None => backend.default_input_device()?,
+        };
+        let output_device = match find_device(backend, &persisted.output_device_id)? {
+            Some(device) => device,
+            None => backend.default_output_device()?,
+        };
+
+        Ok(SessionConfig {
+            sample_rate: persisted.sample_rate,
+            input_device,
+            output_device,
+            additional_input_devices: find_devices(
+                backend,
+                &persisted.additional_input_device_ids,
+            )?,
+            additional_output_devices: find_devices(
+                backend,
+                &persisted.additional_output_device_ids,
+            )?,
+            clock_master: find_device(backend, &persisted.clock_master_id)?,
+            buffer_size: persisted.buffer_size,
+            input_channels: persisted.input_channels,
+            output_channels: persisted.output_channels,
+            physical_format: persisted.physical_format,
+            preroll_buffers: persisted.preroll_buffers,
+            startup_timeout: persisted.startup_timeout,
+            auto_resume: persisted.auto_resume,
+            restart_on_wake: persisted.restart_on_wake,
+            follow_default_devices: persisted.follow_default_devices,
+            output_policy: persisted.output_policy,
+            retry: persisted.retry,
+            routing: persisted.routing.clone(),
+            metadata: persisted.metadata.clone(),
+            voice_processing: persisted.voice_processing,
+        })
+    }
+}
+
+/// Why a `SessionConfig` couldn't be used.
+#[derive(Debug)]
+pub enum ConfigError<E> {
+    InvalidSampleRate(f64),
+    InvalidBufferSize(usize),
+    /// A physical format without a matching `SampleFormat`.
+    UnsupportedFormat(PhysicalFormat),
+    /// The physical format runs at a different rate than the session.
+    SampleRateMismatch {
+        sample_rate: f64,
+        format: PhysicalFormat,
+    },
+    NotEnoughChannels {
+        direction: Direction,
+        requested: usize,
+        available: usize,
+    },
+    /// An additional device is already part of the session in this
+    /// direction.
+    DuplicateDevice(Direction),
+    /// An additional device has no channels in the direction it was added
+    /// for.
+    NoChannels(Direction),
+    /// The clock master is none of the session's devices.
+    ClockMasterNotInSession,
+    /// A route names a channel none of the session's devices have.
+    UnknownChannel {
+        direction: Direction,
+        channel: ChannelRef,
+    },
+    Backend(E),
+}
+
+impl<E: fmt::Display> fmt::Display for ConfigError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidSampleRate(rate) => write!(f, "Invalid sample rate {}", rate),
+            ConfigError::InvalidBufferSize(frames) => {
+                write!(f, "Invalid buffer size of {} frames", frames)
+            }
+            ConfigError::UnsupportedFormat(format) => write!(
+                f,
+                "Unsupported physical format of {} bit {}",
+                format.bits_per_sample,
+                if format.is_float {
+                    "floats"
+                } else {
+                    "integers"
+                }
+            ),
+            ConfigError::SampleRateMismatch {
+                sample_rate,
+                format,
+            } => write!(
+                f,
+                "Physical format at {} Hz doesn't match the session's {} Hz",
+                format.sample_rate, sample_rate
+            ),
+            ConfigError::NotEnoughChannels {
+                direction,
+                requested,
+                available,
+            } => write!(
+                f,
+                "Asked for {} {:?} channels, but the device only has {}",
+                requested, direction, available
+            ),
+            ConfigError::DuplicateDevice(direction) => {
+                write!(f, "A device was added twice as a {:?} device", direction)
+            }
+            ConfigError::NoChannels(direction) => {
+                write!(
+                    f,
+                    "An additional {:?} device has no such channels",
+                    direction
+                )
+            }
+            ConfigError::ClockMasterNotInSession => {
+                write!(f, "The clock master isn't one of the session's devices")
+            }
+            ConfigError::UnknownChannel { direction, channel } => write!(
+                f,
+                "No {:?} channel {} on the session's devices",
+                direction,
+                match channel {
+                    ChannelRef::Index(index) => index.to_string(),
+                    ChannelRef::Name(name) => format!("{:?}", name),
+                }
+            ),
+            ConfigError::Backend(e) => write!(f, "Audio backend error: {}", e),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for ConfigError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Backend(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+fn find_device<B: Backend>(
+    backend: &B,
+    id: &Option<String>,
+) -> Result<Option<B::Device>, B::Error> {
+    match id {
+        Some(id) => backend.device_by_id(id),
+        None => Ok(None),
+    }
+}
+
+fn find_devices<B: Backend>(backend: &B, ids: &[String]) -> Result<Vec<B::Device>, B::Error> {
+    let mut devices = Vec::new();
+    for id in ids {
+        devices.extend(backend.device_by_id(id)?);
+    }
+    Ok(devices)
+}
+
+fn num_channels<B: Backend>(device: &B::Device, direction: Direction) -> Result<usize, B::Error> {
+    match direction {
+        Direction::Input => device.num_inputs(),
+        Direction::Output => device.num_outputs(),
+    }
+}
+
+/// Whether `channel` is one of the `devices`' channels, counting across
+/// them in order the way routes do.
+fn has_channel<B: Backend>(
+    devices: &[&B::Device],
+    direction: Direction,
+    channel: &ChannelRef,
+) -> Result<bool, B::Error> {
+    match channel {
+        ChannelRef::Index(index) => {
+            let mut total = 0;
+            for device in devices {
+                total += num_channels::<B>(device, direction)?;
+            }
+            Ok(*index < total)
+        }
+        ChannelRef::Name(name) => {
+            for device in devices {
+                for channel in 0..num_channels::<B>(device, direction)? {
+                    if device.channel_name(direction, channel)?.as_deref() == Some(name.as_str()) {
+                        return Ok(true);
+                    }
+                }
+            }
+            Ok(false)
+        }
+    }
+}
+
+fn persistent_ids<B: Backend>(devices: &[B::Device]) -> Result<Vec<String>, B::Error> {
+    devices
+        .iter()
+        .map(|device| device.persistent_id())
+        .collect()
+}
+
+impl<B: Backend> Clone for SessionConfig<B> {
+    fn clone(&self) -> Self {
+        SessionConfig {
+            sample_rate: self.sample_rate,
+            input_device: self.input_device.clone(),
+            output_device: self.output_device.clone(),
+            additional_input_devices: self.additional_input_devices.clone(),
+            additional_output_devices: self.additional_output_devices.clone(),
+            clock_master: self.clock_master.clone(),
+            buffer_size: self.buffer_size,
+            input_channels: self.input_channels,
+            output_channels: self.output_channels,
+            physical_format: self.physical_format,
+            preroll_buffers: self.preroll_buffers,
+            startup_timeout: self.startup_timeout,
+            auto_resume: self.auto_resume,
+            restart_on_wake: self.restart_on_wake,
+            follow_default_devices: self.follow_default_devices,
+            output_policy: self.output_policy,
+            retry: self.retry,
+            routing: self.routing.clone(),
+            metadata: self.metadata.clone(),
+            voice_processing: self.voice_processing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NullBackend;
+
+    fn config(sample_rate: f64) -> SessionConfig<NullBackend> {
+        let backend = NullBackend::new().unwrap();
+        let input = backend.default_input_device().unwrap();
+        let output = backend.default_output_device().unwrap();
+        SessionConfig::new(sample_rate, input, output)
+    }
+
+    #[test]
+    fn validate_accepts_every_channel_of_the_device() {
+        let config = config(48_000.0);
+        let inputs = config.input_device.num_inputs().unwrap();
+        let outputs = config.output_device.num_outputs().unwrap();
+
+        let config = config.input_channels(inputs).output_channels(outputs);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_more_channels_than_the_device_has() {
+        let config = config(48_000.0);
+        let inputs = config.input_device.num_inputs().unwrap();
+
+        match config.input_channels(inputs + 1).validate() {
+            Err(ConfigError::NotEnoughChannels {
+                direction: Direction::Input,
+                requested,
+                available,
+            }) => {
+                assert_eq!(requested, inputs + 1);
+                assert_eq!(available, inputs);
+            }
+            other => panic!("Expected NotEnoughChannels, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_duplicate_additional_devices() {
+        let config = config(48_000.0);
+        let device = config.input_device.clone();
+
+        assert!(matches!(
+            config.add_input_device(device).validate(),
+            Err(ConfigError::DuplicateDevice(Direction::Input))
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_additional_devices_without_channels() {
+        let destination = |path| crate::FileDevice::destination(path, 2);
+        let config = SessionConfig::<crate::FileBackend>::new(
+            48_000.0,
+            destination("a.wav"),
+            destination("b.wav"),
+        )
+        .add_input_device(destination("c.wav"));
+
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::NoChannels(Direction::Input))
+        ));
+    }
+
+    #[test]
+    fn validate_checks_routed_channels() {
+        let config = config(48_000.0);
+        let inputs = config.input_device.num_inputs().unwrap();
+        let name = config
+            .input_device
+            .channel_name(Direction::Input, 0)
+            .unwrap()
+            .unwrap();
+
+        let routed = config
+            .clone()
+            .routing(RoutingMatrix::new().select_inputs([ChannelRef::Name(name)]));
+        assert!(routed.validate().is_ok());
+
+        let routed = config
+            .clone()
+            .routing(RoutingMatrix::new().select_inputs([inputs]));
+        assert!(matches!(
+            routed.validate(),
+            Err(ConfigError::UnknownChannel {
+                direction: Direction::Input,
+                channel: ChannelRef::Index(index),
+            }) if index == inputs
+        ));
+
+        let routed = config.routing(RoutingMatrix::new().select_outputs(["missing"]));
+        assert!(matches!(
+            routed.validate(),
+            Err(ConfigError::UnknownChannel {
+                direction: Direction::Output,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_invalid_sample_rates() {
+        assert!(matches!(
+            config(0.0).validate(),
+            Err(ConfigError::InvalidSampleRate(_))
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::profiling::ProfileScope;
+#[cfg(feature = "profiling")]
+use crate::profiling::Profiler;
+use crate::scratch::Scratch;
+use crate::traits::AudioBuffers;
+
+/// When the first frame of a cycle's buffers was captured or will be
+/// played. Either part is None where the platform doesn't say.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AudioTimestamp {
+    /// In the device's sample timeline, the one dropouts are counted in.
+    pub sample_time: Option<f64>,
+    /// In host clock ticks, which the `time` module converts.
+    pub host_time: Option<u64>,
+}
+
+/// The time stamps of one cycle, as the backend got them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct CycleTimes {
+    pub now: AudioTimestamp,
+    pub input: AudioTimestamp,
+    pub output: AudioTimestamp,
+}
+
+impl CycleTimes {
+    /// For backends that only count the frames they've rendered, where input
+    /// and output are both at `sample_time`.
+    pub fn at(sample_time: f64) -> Self {
+        let timestamp = AudioTimestamp {
+            sample_time: Some(sample_time),
+            host_time: None,
+        };
+
+        CycleTimes {
+            now: AudioTimestamp::default(),
+            input: timestamp,
+            output: timestamp,
+        }
+    }
+
+    /// The sample time the cycle's dropouts are detected from.
+    pub fn sample_time(&self) -> Option<f64> {
+        self.output.sample_time.or(self.input.sample_time)
+    }
+}
+
+/// Per-cycle information handed to the render callback alongside the audio
+/// buffers.
+pub struct RenderContext<'a> {
+    pub(crate) valid: &'a AtomicBool,
+    pub(crate) discontinuity: bool,
+    pub(crate) dropout: Option<u64>,
+    pub(crate) sample_rate: f64,
+    pub(crate) frames: usize,
+    pub(crate) stream_frames: u64,
+    pub(crate) times: CycleTimes,
+    pub(crate) output_silent: Cell<bool>,
+    pub(crate) scratch: &'a Scratch,
+    #[cfg(feature = "profiling")]
+    pub(crate) profiler: Option<&'a Profiler>,
+    #[cfg(feature = "profiling")]
+    pub(crate) cycle: u64,
+}
+
+impl<'a> RenderContext<'a> {
+    /// A context for calling processors directly in tests, for a valid
+    /// session at 48 kHz.
+    #[cfg(test)]
+    pub(crate) fn for_test(valid: &'a AtomicBool, scratch: &'a Scratch, frames: usize) -> Self {
+        RenderContext {
+            valid,
+            discontinuity: false,
+            dropout: None,
+            sample_rate: 48_000.0,
+            frames,
+            stream_frames: 0,
+            times: CycleTimes::at(0.0),
+            output_silent: Cell::new(false),
+            scratch,
+            #[cfg(feature = "profiling")]
+            profiler: None,
+            #[cfg(feature = "profiling")]
+            cycle: 0,
+        }
+    }
+
+    /// Returns false once the session is being torn down or one of its
+    /// devices has died. The buffers of an invalid session will never be
+    /// played, so callbacks can skip any expensive processing.
+    pub fn is_valid(&self) -> bool {
+        self.valid.load(Ordering::Acquire)
+    }
+
+    /// Returns true if this cycle doesn't directly follow the previous one,
+    /// because of a dropout or because the session's devices changed. Any
+    /// state carried over from the previous cycle is stale.
+    pub fn is_discontinuity(&self) -> bool {
+        self.discontinuity
+    }
+
+    /// How many frames the device skipped right before this cycle, if it
+    /// skipped any. Such cycles are also discontinuities.
+    pub fn dropout(&self) -> Option<u64> {
+        self.dropout
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// The number of frames in this cycle's buffers.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Frames rendered before this cycle, as counted by
+    /// `Session::sample_clock`.
+    pub fn stream_frames(&self) -> u64 {
+        self.stream_frames
+    }
+
+    /// `stream_frames` in seconds.
+    pub fn stream_time(&self) -> f64 {
+        self.stream_frames as f64 / self.sample_rate
+    }
+
+    /// When the cycle started. The host time is always there: backends
+    /// whose platform doesn't report one read the clock before rendering.
+    pub fn now(&self) -> AudioTimestamp {
+        self.times.now
+    }
+
+    /// When the input buffers' first frame was captured, which is in the
+    /// past by the device's input latency.
+    pub fn input_time(&self) -> AudioTimestamp {
+        self.times.input
+    }
+
+    /// When the output buffers' first frame will be played, which is in the
+    /// future by the device's output latency.
+    pub fn output_time(&self) -> AudioTimestamp {
+        self.times.output
+    }
+
+    /// Marks this cycle's output as silent, so the callback doesn't have to
+    /// write zeros itself. The output buffers are cleared once the callback
+    /// returns, whatever it wrote to them.
+    pub fn output_silence(&self) {
+        self.output_silent.set(true);
+    }
+
+    pub fn is_output_silent(&self) -> bool {
+        self.output_silent.get()
+    }
+
+    /// Temporary buffers for this cycle, sized for the session's largest
+    /// callback.
+    pub fn scratch(&self) -> &'a Scratch {
+        self.scratch
+    }
+
+    /// Times everything until the returned scope is dropped, recording it
+    /// under `name` for `Session::take_profile`. Only records anything with
+    /// the `profiling` feature enabled, and is free otherwise, so scopes can
+    /// stay in the code.
+    pub fn scope(&self, name: &'static str) -> ProfileScope<'a> {
+        #[cfg(feature = "profiling")]
+        {
+            ProfileScope {
+                profiler: self.profiler,
+                name,
+                cycle: self.cycle,
+                start: crate::time::host_time_now(),
+            }
+        }
+        #[cfg(not(feature = "profiling"))]
+        {
+            let _ = name;
+            ProfileScope {
+                _marker: std::marker::PhantomData,
+            }
+        }
+    }
+
+    /// Clears `output` if the callback marked it as silent, and returns
+    /// whether it did.
+    pub(crate) fn take_output_silence<A: AudioBuffers>(&self, output: &mut [A]) -> bool {
+        if !self.output_silent.replace(false) {
+            return false;
+        }
+
+        for buffer in output {
+            buffer.interleaved_frames_mut().fill(Default::default());
+        }
+        true
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::SessionConfig;
+use crate::dropout::DropoutStats;
+use crate::recorder::{
+    ring_buffer_len, RecorderError, RecorderProcessor, RecorderState, DISK_POLL_INTERVAL,
+};
+use crate::ring_buffer::{ring_buffer, Consumer};
+use crate::traits::{Backend, Session};
+use crate::wav::WavWriter;
+
+/// How long segments are, and how many of them to keep around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub segment_duration: Duration,
+    /// Oldest segments are deleted once the finished ones add up to more
+    /// than this much audio.
+    pub max_age: Option<Duration>,
+    /// Oldest segments are deleted once the finished ones take up more than
+    /// this many bytes.
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            segment_duration: Duration::from_secs(60),
+            max_age: Some(Duration::from_secs(10 * 60)),
+            max_bytes: None,
+        }
+    }
+}
+
+/// A finished WAV file written by a `ContinuousRecorder`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub path: PathBuf,
+    pub started_at: SystemTime,
+    pub duration: Duration,
+    pub bytes: u64,
+}
+
+/// Records every input channel of a device to a rolling set of WAV files in a
+/// directory, deleting the oldest ones according to a `RetentionPolicy`.
+///
+/// Like `Recorder`, audio is handed from the real-time thread to a disk
+/// thread through a ring buffer, and the disk thread takes care of rolling
+/// over to new files and deleting old ones. The segment being written isn't
+/// counted against the policy, so the directory may hold up to one segment
+/// more than it allows.
+pub struct ContinuousRecorder<B: Backend> {
+    session: Option<B::Session>,
+    state: Arc<RecorderState>,
+    segments: Arc<Mutex<VecDeque<Segment>>>,
+    disk_thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl<B: Backend> ContinuousRecorder<B> {
+    /// Starts recording from `input_device` into `directory`, creating it if
+    /// needed. As with `Recorder`, the default output device is only ever
+    /// sent silence.
+    pub fn start(
+        backend: &B,
+        input_device: B::Device,
+        sample_rate: f64,
+        directory: impl AsRef<Path>,
+        policy: RetentionPolicy,
+    ) -> Result<Self, RecorderError<B::Error>> {
+        let directory = directory.as_ref().to_owned();
+        fs::create_dir_all(&directory).map_err(RecorderError::Io)?;
+        let output_device = backend
+            .default_output_device()
+            .map_err(RecorderError::Backend)?;
+
+        let state = RecorderState::new(sample_rate);
+        let (producer, consumer) = ring_buffer(ring_buffer_len::<B>(sample_rate, &input_device));
+        let started_at = SystemTime::now();
+
+        let session = backend
+            .start_session_with_processor(
+                SessionConfig::new(sample_rate, input_device, output_device),
+                RecorderProcessor::new(state.clone(), producer),
+            )
+            .map_err(RecorderError::Backend)?;
+
+        let segments = Arc::new(Mutex::new(VecDeque::new()));
+        let disk_thread = {
+            let state = state.clone();
+            let segments = segments.clone();
+
+            thread::Builder::new()
+                .name("render_callback continuous recorder".to_owned())
+                .spawn(move || {
+                    SegmentWriter {
+                        directory,
+                        policy,
+                        started_at,
+                        frames_written: 0,
+                        segments,
+                    }
+                    .run(&state, consumer)
+                })
+                .map_err(RecorderError::Io)?
+        };
+
+        Ok(ContinuousRecorder {
+            session: Some(session),
+            state,
+            segments,
+            disk_thread: Some(disk_thread),
+        })
+    }
+
+    pub fn session(&self) -> &B::Session {
+        self.session.as_ref().unwrap()
+    }
+
+    /// The finished segments currently on disk, oldest first.
+    pub fn segments(&self) -> Vec<Segment> {
+        self.segments.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Frames lost so far because the disk thread fell behind.
+    pub fn overflowed_frames(&self) -> u64 {
+        self.state.overflowed_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn dropouts(&self) -> DropoutStats {
+        self.session().dropouts()
+    }
+
+    /// Stops the session, finishes the segment being written, and returns
+    /// every segment left on disk.
+    pub fn stop(mut self) -> Result<Vec<Segment>, RecorderError<B::Error>> {
+        self.finish().map_err(RecorderError::Io)?;
+        Ok(self.segments())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        drop(self.session.take());
+        self.state.stop.store(true, Ordering::Release);
+
+        match self.disk_thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("Disk thread panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<B: Backend> Drop for ContinuousRecorder<B> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+struct OpenSegment {
+    writer: WavWriter<BufWriter<File>>,
+    path: PathBuf,
+    started_at: SystemTime,
+}
+
+/// The disk thread's side of a continuous recording.
+struct SegmentWriter {
+    directory: PathBuf,
+    policy: RetentionPolicy,
+    started_at: SystemTime,
+    frames_written: u64,
+    segments: Arc<Mutex<VecDeque<Segment>>>,
+}
+
+impl SegmentWriter {
+    fn run(mut self, state: &RecorderState, mut consumer: Consumer) -> io::Result<()> {
+        let mut current = None;
+        let mut buffer = vec![0.0; 1 << 16];
+
+        loop {
+            // Check before draining, so everything pushed before the session
+            // stopped is written out.
+            let stopping = state.stop.load(Ordering::Acquire);
+
+            let channels = state.channels.load(Ordering::Acquire);
+            let sample_rate = f64::from_bits(state.sample_rate.load(Ordering::Relaxed));
+            let segment_frames =
+                (self.policy.segment_duration.as_secs_f64() * sample_rate).max(1.0) as u64;
+
+            while channels > 0 && consumer.len() >= channels {
+                let segment: &mut OpenSegment = match current {
+                    Some(ref mut segment) => segment,
+                    None => current.insert(self.open(sample_rate, channels)?),
+                };
+
+                let remaining = (segment_frames - segment.writer.frames()) as usize;
+                let frames = (consumer.len() / channels)
+                    .min(buffer.len() / channels)
+                    .min(remaining);
+                let count = consumer.pop(&mut buffer[..frames * channels]);
+                segment.writer.write(&buffer[..count])?;
+                self.frames_written += (count / channels) as u64;
+
+                if segment.writer.frames() >= segment_frames {
+                    self.close(current.take().unwrap(), sample_rate)?;
+                }
+            }
+
+            if stopping {
+                break;
+            }
+
+            thread::sleep(DISK_POLL_INTERVAL);
+        }
+
+        match current {
+            Some(segment) => {
+                let sample_rate = f64::from_bits(state.sample_rate.load(Ordering::Relaxed));
+                self.close(segment, sample_rate)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn open(&self, sample_rate: f64, channels: usize) -> io::Result<OpenSegment> {
+        let started_at =
+            self.started_at + Duration::from_secs_f64(self.frames_written as f64 / sample_rate);
+        let millis = started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = self.directory.join(format!("recording-{}.wav", millis));
+
+        let file = BufWriter::new(File::create(&path)?);
+        Ok(OpenSegment {
+            writer: WavWriter::new(file, sample_rate, channels as u16, started_at)?,
+            path,
+            started_at,
+        })
+    }
+
+    fn close(&self, segment: OpenSegment, sample_rate: f64) -> io::Result<()> {
+        let duration = Duration::from_secs_f64(segment.writer.frames() as f64 / sample_rate);
+        let mut file = segment.writer.finish()?;
+        file.flush()?;
+        let bytes = file.get_ref().metadata()?.len();
+
+        let mut segments = self.segments.lock().unwrap();
+        segments.push_back(Segment {
+            path: segment.path,
+            started_at: segment.started_at,
+            duration,
+            bytes,
+        });
+
+        // Always keep the newest segment, even if it alone breaks the policy.
+        while segments.len() > 1 && self.over_limit(&segments) {
+            let oldest = segments.pop_front().unwrap();
+            match fs::remove_file(&oldest.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn over_limit(&self, segments: &VecDeque<Segment>) -> bool {
+        let age: Duration = segments.iter().map(|segment| segment.duration).sum();
+        let bytes: u64 = segments.iter().map(|segment| segment.bytes).sum();
+
+        self.policy.max_age.is_some_and(|max_age| age > max_age)
+            || self
+                .policy
+                .max_bytes
+                .is_some_and(|max_bytes| bytes > max_bytes)
+    }
+}
@@ -1,42 +1,78 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::ffi::{c_void, CStr};
 use std::fmt;
 use std::mem::MaybeUninit;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use coreaudio_sys::{
-    kAudioAggregateDeviceIsPrivateKey, kAudioAggregateDeviceNameKey, kAudioAggregateDeviceUIDKey,
-    kAudioObjectSystemObject, AudioObjectID, AudioValueTranslation, CFStringRef,
+    kAudioAggregateDeviceIsPrivateKey, kAudioAggregateDeviceIsStackedKey,
+    kAudioAggregateDeviceMasterSubDeviceKey, kAudioAggregateDeviceNameKey,
+    kAudioAggregateDeviceSubDeviceListKey, kAudioAggregateDeviceUIDKey,
+    kAudioHardwareBadDeviceError, kAudioHardwareUnsupportedOperationError,
+    kAudioObjectSystemObject, kAudioSubDeviceDriftCompensationKey, kAudioSubDeviceUIDKey,
+    AudioObjectID, AudioValueTranslation, CFStringRef, OSStatus,
 };
 
-use crate::traits::Backend;
-
-use super::backend::CABackend;
-use super::cf::{CFError, CFMutableArray, CFMutableDictionary, CFNumber, CFString};
+use super::cf::{
+    CFArray, CFDictionary, CFError, CFMutableArray, CFMutableDictionary, CFNumber, CFString,
+};
 use super::device::CADevice;
 use super::properties::{self, element, scope, selector};
 
 const AGGREGATE_DEVICE_UID: &str = "com.github.mhallin.Audioshop";
 
+/// A UID no other aggregate device made by this process has, so that devices
+/// belonging to different sessions never get mixed up.
+fn unique_uid(kind: &str) -> String {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    format!(
+        "{}.{}.{}.{}",
+        AGGREGATE_DEVICE_UID,
+        kind,
+        process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
 pub struct AggregateDevice {
-    plugin_id: AudioObjectID,
+    /// `None` when the session runs on its output device directly, without
+    /// an aggregate.
+    plugin_id: Option<AudioObjectID>,
     device: CADevice,
     input: CADevice,
     output: CADevice,
+    additional: Vec<CADevice>,
+    clock_master: Option<CADevice>,
+    /// Device UIDs and sub-device arrays built ahead of time by `prepare`, so
+    /// switching between known devices doesn't have to query or allocate.
+    uids: HashMap<CADevice, CFString>,
+    prepared_arrays: HashMap<(CADevice, CADevice), CFArray>,
 }
 
+// The strings and arrays it holds are immutable, which CoreFoundation lets
+// any thread use. Sessions following the default devices switch them from a
+// thread of their own.
+unsafe impl Send for AggregateDevice {}
+
 impl AggregateDevice {
-    pub fn new(backend: &CABackend, input: CADevice, output: CADevice) -> Result<Self, CFError> {
+    /// Creates a new private aggregate device. Each session gets its own, so
+    /// that sessions can't change or destroy each other's.
+    pub fn new(input: CADevice, output: CADevice) -> Result<Self, CFError> {
         let audio_plugin_id = get_audio_plugin_id()?;
-
-        let device = match find_existing_aggregate_device(backend)? {
-            Some(device) => device,
-            None => create_aggregate_device(audio_plugin_id)?,
-        };
+        let device = create_aggregate_device(audio_plugin_id)?;
 
         let aggregate_device = AggregateDevice {
-            plugin_id: audio_plugin_id,
+            plugin_id: Some(audio_plugin_id),
             device,
             input,
             output,
+            additional: Vec::new(),
+            clock_master: None,
+            uids: HashMap::new(),
+            prepared_arrays: HashMap::new(),
         };
 
         aggregate_device.refresh_sub_device_array()?;
@@ -44,6 +80,38 @@ impl AggregateDevice {
         Ok(aggregate_device)
     }
 
+    /// Stands in for an aggregate of just `output`, running the IOProc on the
+    /// device itself. Playback sessions use this to skip the aggregate and
+    /// the clocking it brings. The devices can't be changed afterwards.
+    pub fn direct(output: CADevice) -> Self {
+        AggregateDevice {
+            plugin_id: None,
+            device: output,
+            input: output,
+            output,
+            additional: Vec::new(),
+            clock_master: None,
+            uids: HashMap::new(),
+            prepared_arrays: HashMap::new(),
+        }
+    }
+
+    /// False for a device made by `direct`.
+    pub fn is_aggregate(&self) -> bool {
+        self.plugin_id.is_some()
+    }
+
+    /// Fails for a device made by `direct`, which has no aggregate to change.
+    pub fn check_aggregate(&self) -> Result<(), CFError> {
+        if self.is_aggregate() {
+            Ok(())
+        } else {
+            Err(CFError::Status(
+                kAudioHardwareUnsupportedOperationError as OSStatus,
+            ))
+        }
+    }
+
     pub fn device(&self) -> CADevice {
         self.device
     }
@@ -56,25 +124,97 @@ impl AggregateDevice {
         self.output
     }
 
+    /// Every device making up the aggregate, in the order their streams
+    /// appear in the IOProc's buffer lists.
+    pub fn sub_devices(&self) -> Vec<CADevice> {
+        let mut devices = Vec::new();
+        for &device in [self.input, self.output].iter().chain(&self.additional) {
+            if !devices.contains(&device) {
+                devices.push(device);
+            }
+        }
+        devices
+    }
+
+    /// Adds devices beyond the main input and output, clocked from
+    /// `clock_master` or from the first device if `None`.
+    pub fn set_additional(
+        &mut self,
+        additional: Vec<CADevice>,
+        clock_master: Option<CADevice>,
+    ) -> Result<(), CFError> {
+        self.check_aggregate()?;
+        self.additional = additional;
+        self.clock_master = clock_master;
+        // Prepared arrays include the additional devices.
+        self.prepared_arrays.clear();
+        self.refresh_sub_device_array()
+    }
+
+    /// Builds the sub-device arrays for every combination of `devices` as
+    /// input and output, so that switching between them later is a single
+    /// property change.
+    pub fn prepare(&mut self, devices: &[CADevice]) -> Result<(), CFError> {
+        self.check_aggregate()?;
+        for &device in devices {
+            if let Entry::Vacant(entry) = self.uids.entry(device) {
+                entry.insert(device.uid()?);
+            }
+        }
+
+        for &input in devices {
+            for &output in devices {
+                let array = self.build_sub_device_array(input, output)?;
+                self.prepared_arrays.insert((input, output), array);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn set_input(&mut self, input: CADevice) -> Result<(), CFError> {
+        self.check_aggregate()?;
         self.input = input;
         self.refresh_sub_device_array()
     }
 
     pub fn set_output(&mut self, output: CADevice) -> Result<(), CFError> {
+        self.check_aggregate()?;
         self.output = output;
         self.refresh_sub_device_array()
     }
 
-    fn refresh_sub_device_array(&self) -> Result<(), CFError> {
-        let sub_device_array = {
-            let mut array = CFMutableArray::new();
-            array.push(self.input.uid()?.as_void_ptr());
+    fn build_sub_device_array(
+        &self,
+        input: CADevice,
+        output: CADevice,
+    ) -> Result<CFArray, CFError> {
+        let mut array = CFMutableArray::new();
+        let mut added = Vec::new();
 
-            if self.input != self.output {
-                array.push(self.output.uid()?.as_void_ptr());
+        for &device in [input, output].iter().chain(&self.additional) {
+            if added.contains(&device) {
+                continue;
+            }
+            added.push(device);
+
+            match self.uids.get(&device) {
+                Some(uid) => array.push(uid.as_void_ptr()),
+                None => array.push(device.uid()?.as_void_ptr()),
+            }
+        }
+
+        Ok(array.clone_immutable())
+    }
+
+    fn refresh_sub_device_array(&self) -> Result<(), CFError> {
+        let built;
+        let sub_device_array = match self.prepared_arrays.get(&(self.input, self.output)) {
+            Some(array) => array,
+            None => {
+                built = self.build_sub_device_array(self.input, self.output)?;
+                &built
             }
-            array
         };
 
         unsafe {
@@ -83,7 +223,27 @@ impl AggregateDevice {
                 scope::Global,
                 selector::AggregateDevicePropertyFullSubDeviceList,
                 self.device.id(),
-                &sub_device_array.clone_immutable(),
+                sub_device_array,
+            )?;
+        }
+
+        let clock_master = self.clock_master.unwrap_or(self.input);
+        let fetched;
+        let clock_master_uid = match self.uids.get(&clock_master) {
+            Some(uid) => uid,
+            None => {
+                fetched = clock_master.uid()?;
+                &fetched
+            }
+        };
+
+        unsafe {
+            properties::set(
+                element::Master,
+                scope::Global,
+                selector::AggregateDevicePropertyMasterSubDevice,
+                self.device.id(),
+                clock_master_uid,
             )
         }
     }
@@ -116,12 +276,17 @@ fn get_audio_plugin_id() -> Result<AudioObjectID, CFError> {
 
 impl Drop for AggregateDevice {
     fn drop(&mut self) {
+        let plugin_id = match self.plugin_id {
+            Some(plugin_id) => plugin_id,
+            None => return,
+        };
+
         unsafe {
             properties::translate(
                 element::Master,
                 scope::Global,
                 selector::PlugInDestroyAggregateDevice,
-                self.plugin_id,
+                plugin_id,
                 &mut self.device,
             )
             .expect("Could not destroy aggregate device");
@@ -139,33 +304,24 @@ impl fmt::Debug for AggregateDevice {
     }
 }
 
-fn find_existing_aggregate_device(backend: &CABackend) -> Result<Option<CADevice>, CFError> {
-    for device in backend.all_devices()? {
-        if device.uid()?.to_string() == AGGREGATE_DEVICE_UID {
-            return Ok(Some(device));
-        }
-    }
-
-    Ok(None)
+pub(super) fn dictionary_key(key: &[u8]) -> CFString {
+    CFString::from_cstr(CStr::from_bytes_with_nul(key).unwrap())
 }
 
 fn create_aggregate_device(audio_plugin_id: AudioObjectID) -> Result<CADevice, CFError> {
     let mut aggregate_dict = CFMutableDictionary::new();
     aggregate_dict.insert(
-        CFString::from_cstr(&CStr::from_bytes_with_nul(kAudioAggregateDeviceNameKey).unwrap())
-            .as_void_ptr(),
+        dictionary_key(kAudioAggregateDeviceNameKey).as_void_ptr(),
         CFString::new("Audioshop aggregate device").as_void_ptr(),
     );
 
     aggregate_dict.insert(
-        CFString::from_cstr(&CStr::from_bytes_with_nul(kAudioAggregateDeviceUIDKey).unwrap())
-            .as_void_ptr(),
-        CFString::new(AGGREGATE_DEVICE_UID).as_void_ptr(),
+        dictionary_key(kAudioAggregateDeviceUIDKey).as_void_ptr(),
+        CFString::new(&unique_uid("session")).as_void_ptr(),
     );
 
     aggregate_dict.insert(
-        CFString::from_cstr(&CStr::from_bytes_with_nul(kAudioAggregateDeviceIsPrivateKey).unwrap())
-            .as_void_ptr(),
+        dictionary_key(kAudioAggregateDeviceIsPrivateKey).as_void_ptr(),
         CFNumber::new(1).as_void_ptr(),
     );
 
@@ -179,3 +335,115 @@ fn create_aggregate_device(audio_plugin_id: AudioObjectID) -> Result<CADevice, C
         )
     }
 }
+
+/// The dictionary `PlugInCreateAggregateDevice` takes to make a multi-output
+/// device from the sub-devices with `sub_device_uids`, the first being the
+/// clock master.
+pub(super) fn multi_output_description(
+    name: &str,
+    uid: &str,
+    sub_device_uids: &[CFString],
+) -> CFDictionary {
+    let mut sub_devices = CFMutableArray::new();
+    for (index, sub_device_uid) in sub_device_uids.iter().enumerate() {
+        let mut sub_device = CFMutableDictionary::new();
+        sub_device.insert(
+            dictionary_key(kAudioSubDeviceUIDKey).as_void_ptr(),
+            sub_device_uid.as_void_ptr(),
+        );
+        sub_device.insert(
+            dictionary_key(kAudioSubDeviceDriftCompensationKey).as_void_ptr(),
+            CFNumber::new((index > 0) as i32).as_void_ptr(),
+        );
+        sub_devices.push(sub_device.clone_immutable().as_void_ptr());
+    }
+
+    let mut aggregate_dict = CFMutableDictionary::new();
+    aggregate_dict.insert(
+        dictionary_key(kAudioAggregateDeviceNameKey).as_void_ptr(),
+        CFString::new(name).as_void_ptr(),
+    );
+    aggregate_dict.insert(
+        dictionary_key(kAudioAggregateDeviceUIDKey).as_void_ptr(),
+        CFString::new(uid).as_void_ptr(),
+    );
+    aggregate_dict.insert(
+        dictionary_key(kAudioAggregateDeviceIsPrivateKey).as_void_ptr(),
+        CFNumber::new(1).as_void_ptr(),
+    );
+    aggregate_dict.insert(
+        dictionary_key(kAudioAggregateDeviceIsStackedKey).as_void_ptr(),
+        CFNumber::new(1).as_void_ptr(),
+    );
+    aggregate_dict.insert(
+        dictionary_key(kAudioAggregateDeviceSubDeviceListKey).as_void_ptr(),
+        sub_devices.clone_immutable().as_void_ptr(),
+    );
+    if let Some(master_uid) = sub_device_uids.first() {
+        aggregate_dict.insert(
+            dictionary_key(kAudioAggregateDeviceMasterSubDeviceKey).as_void_ptr(),
+            master_uid.as_void_ptr(),
+        );
+    }
+
+    aggregate_dict.clone_immutable()
+}
+
+/// A stacked aggregate device that plays the same audio on several output
+/// devices at once, like a multi-output device made in Audio MIDI Setup. The
+/// first device is the clock master, and the others are drift compensated
+/// against it.
+///
+/// The device is private to this process, and is destroyed when this is
+/// dropped.
+pub struct MultiOutputDevice {
+    plugin_id: AudioObjectID,
+    device: CADevice,
+}
+
+impl MultiOutputDevice {
+    pub fn new(name: &str, devices: &[CADevice]) -> Result<Self, CFError> {
+        if devices.is_empty() {
+            return Err(CFError::Status(kAudioHardwareBadDeviceError as OSStatus));
+        }
+        let plugin_id = get_audio_plugin_id()?;
+
+        let uids = devices
+            .iter()
+            .map(|device| device.uid())
+            .collect::<Result<Vec<_>, _>>()?;
+        let description = multi_output_description(name, &unique_uid("multi-output"), &uids);
+
+        let device = unsafe {
+            properties::get_qualified(
+                element::Master,
+                scope::Global,
+                selector::PlugInCreateAggregateDevice,
+                &description,
+                plugin_id,
+            )?
+        };
+
+        Ok(MultiOutputDevice { plugin_id, device })
+    }
+
+    /// The aggregate device, to be used as the output of a session.
+    pub fn device(&self) -> CADevice {
+        self.device
+    }
+}
+
+impl Drop for MultiOutputDevice {
+    fn drop(&mut self) {
+        unsafe {
+            properties::translate(
+                element::Master,
+                scope::Global,
+                selector::PlugInDestroyAggregateDevice,
+                self.plugin_id,
+                &mut self.device,
+            )
+            .expect("Could not destroy multi-output device");
+        }
+    }
+}
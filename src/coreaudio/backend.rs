@@ -1,14 +1,116 @@
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use coreaudio_sys::kAudioObjectSystemObject;
 
-use crate::traits::{Backend, RenderCallback};
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::device_watcher::{DeviceEvent, DeviceWatcher};
+use crate::event_log::EventLog;
+use crate::processor::{processor_callback, Processor};
+use crate::retry::{retry, RetryPolicy};
+use crate::routing::ResolvedRouting;
+use crate::traits::{Backend, Device, RenderCallback};
 
 use super::cf::CFError;
 use super::device::CADevice;
+use super::device_watcher::DeviceListeners;
+use super::event_log;
 use super::properties::{self, element, scope, selector};
 use super::session::{CASession, InterleavedBuffer};
+use super::tap::ProcessTap;
 
 pub struct CABackend;
 
+impl CABackend {
+    /// Sets up a session, retrying while the HAL isn't running yet.
+    fn new_session(
+        &self,
+        policy: RetryPolicy,
+        sample_rate: f64,
+        input_device: CADevice,
+        output_device: CADevice,
+    ) -> Result<Box<CASession>, CFError> {
+        retry(policy, CFError::is_transient, || {
+            CASession::new(self, sample_rate, input_device, output_device)
+        })
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(&self, config: &SessionConfig<Self>) -> Result<Box<CASession>, CFError> {
+        let mut session = self.new_session(
+            config.retry,
+            config.sample_rate,
+            config.input_device,
+            config.output_device,
+        )?;
+
+        let additional: Vec<_> = config
+            .additional_input_devices
+            .iter()
+            .chain(&config.additional_output_devices)
+            .copied()
+            .collect();
+        if !additional.is_empty() || config.clock_master.is_some() {
+            session.set_additional_devices(additional, config.clock_master)?;
+        }
+
+        if let Some(format) = config.physical_format {
+            session.set_physical_format(format)?;
+        }
+
+        if let Some(frames) = config.buffer_size {
+            session.set_buffer_size(frames)?;
+        }
+
+        session.set_auto_resume(config.auto_resume);
+        session.set_restart_on_wake(config.restart_on_wake);
+        session.follow_default_devices(config.follow_default_devices)?;
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+
+    fn resolve_routing(
+        &self,
+        config: &SessionConfig<Self>,
+        session: &<Self as Backend>::Session,
+    ) -> Result<Option<ResolvedRouting>, CFError> {
+        config
+            .routing
+            .as_ref()
+            .map(|routing| routing.resolve(self, session))
+            .transpose()
+    }
+
+    /// Starts a session from `configured_session`, with the preroll and
+    /// startup timeout from `config`.
+    fn start_configured(
+        &self,
+        mut session: Box<CASession>,
+        config: &SessionConfig<Self>,
+        routing: Option<ResolvedRouting>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<Box<CASession>, CFError> {
+        let callback = match routing {
+            Some(routing) => session.route(callback, routing)?,
+            None => callback,
+        };
+
+        if config.preroll_buffers > 0 {
+            session.start_primed(callback, config.preroll_buffers)?;
+        } else {
+            session.start_with(callback)?;
+        }
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+}
+
 impl Backend for CABackend {
     type Session = Box<CASession>;
     type Error = CFError;
@@ -16,9 +118,39 @@ impl Backend for CABackend {
     type AudioBuffers = InterleavedBuffer;
 
     fn new() -> Result<Self, Self::Error> {
+        // By default the HAL delivers notifications on the main run loop,
+        // which command line tools and daemons never run. Having it use its
+        // own thread instead makes listeners, and the property changes that
+        // wait on them, behave the same everywhere.
+        //
+        // Setting it is cheap and harmless to repeat, so racing callers may
+        // both do it, but it's only marked done once it worked. There's no
+        // retrying here, to keep `is_available` quick while the HAL is down;
+        // sessions retry on their own.
+        static RUN_LOOP_SET: AtomicBool = AtomicBool::new(false);
+        if !RUN_LOOP_SET.load(Ordering::Acquire) {
+            unsafe {
+                properties::set(
+                    element::Master,
+                    scope::Global,
+                    selector::HardwarePropertyRunLoop,
+                    kAudioObjectSystemObject,
+                    &ptr::null_mut(),
+                )?;
+            }
+            RUN_LOOP_SET.store(true, Ordering::Release);
+        }
+
         Ok(CABackend)
     }
 
+    fn is_available() -> bool {
+        Self::new()
+            .and_then(|backend| backend.all_devices())
+            .map(|devices| !devices.is_empty())
+            .unwrap_or(false)
+    }
+
     fn all_devices(&self) -> Result<Vec<CADevice>, CFError> {
         unsafe {
             properties::get(
@@ -52,13 +184,92 @@ impl Backend for CABackend {
         }
     }
 
-    fn start_session(
+    fn event_log(&self) -> Result<EventLog, CFError> {
+        let log = EventLog::open();
+        event_log::install_listeners(self)?;
+
+        Ok(log)
+    }
+
+    /// Also polls, in case a change slips in between the watcher listing
+    /// devices and the listeners being added.
+    fn watch_devices<F>(&self, f: F) -> Result<DeviceWatcher, CFError>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        let mut watcher = DeviceWatcher::spawn(self, f)?;
+        watcher.set_notifier(DeviceListeners::install(watcher.thread())?);
+
+        Ok(watcher)
+    }
+
+    /// Runs the IOProc on the output device itself rather than on an
+    /// aggregate device. Inputs of the output device, if it has any, still
+    /// show up as input buffers.
+    fn start_playback_session(
         &self,
         sample_rate: f64,
-        input_device: Self::Device,
         output_device: Self::Device,
         callback: Box<RenderCallback<Self>>,
     ) -> Result<Self::Session, Self::Error> {
-        CASession::new_started(self, sample_rate, input_device, output_device, callback)
+        let mut session = retry(RetryPolicy::default(), CFError::is_transient, || {
+            CASession::new_playback(self, sample_rate, output_device)
+        })?;
+        session.start_with(callback)?;
+
+        Ok(session)
+    }
+
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<Self::Session, Self::Error> {
+        let session = self.configured_session(&config)?;
+        let routing = self.resolve_routing(&config, &session)?;
+
+        self.start_configured(session, &config, routing, callback)
+    }
+
+    /// With routing, the processor is prepared for the routed channels it
+    /// gets instead of the devices' buffers.
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<Self::Session, Self::Error> {
+        let session = self.configured_session(&config)?;
+        let routing = self.resolve_routing(&config, &session)?;
+
+        processor.prepare(
+            session.io_device().nominal_sample_rate()?,
+            session.max_frames_per_callback()?,
+        );
+        let (input_channels, output_channels) = match &routing {
+            Some(routing) => routing.buffer_channels(),
+            None => session.buffer_channels()?,
+        };
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        self.start_configured(session, &config, routing, processor_callback(processor))
+    }
+
+    fn capture_process(
+        &self,
+        target: &CaptureTarget,
+        sample_rate: f64,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<Self::Session, Self::Error> {
+        let output_device = self.default_output_device()?;
+        let mut session = self.new_session(
+            RetryPolicy::default(),
+            sample_rate,
+            output_device,
+            output_device,
+        )?;
+        session.add_process_tap(ProcessTap::new(target)?)?;
+        session.start_with(callback)?;
+
+        Ok(session)
     }
 }
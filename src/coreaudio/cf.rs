@@ -1,18 +1,53 @@
 use std::error::Error;
 use std::ffi::{c_void, CStr};
 use std::fmt;
+use std::time::Duration;
 
 use coreaudio_sys::{
-    kCFNumberIntType, kCFStringEncodingUTF8, kCFTypeArrayCallBacks, kCFTypeDictionaryKeyCallBacks,
-    kCFTypeDictionaryValueCallBacks, noErr, CFArrayAppendValue, CFArrayCreateMutable, CFArrayRef,
-    CFDataGetBytes, CFDataGetLength, CFDataRef, CFDictionaryAddValue, CFDictionaryCreateMutable,
-    CFDictionaryRef, CFMutableArrayRef, CFMutableDictionaryRef, CFNumberCreate, CFNumberRef,
-    CFRange, CFRelease, CFRetain, CFStringCreateExternalRepresentation, CFStringCreateWithBytes,
-    CFStringCreateWithCString, CFStringGetSystemEncoding, CFStringRef, OSStatus,
+    kAudioDevicePermissionsError, kAudioDeviceUnsupportedFormatError, kAudioHardwareBadDeviceError,
+    kAudioHardwareBadObjectError, kAudioHardwareNotRunningError,
+    kAudioHardwareUnsupportedOperationError, kCFNumberIntType, kCFStringEncodingUTF8,
+    kCFTypeArrayCallBacks, kCFTypeDictionaryKeyCallBacks, kCFTypeDictionaryValueCallBacks, noErr,
+    CFArrayAppendValue, CFArrayCreateMutable, CFArrayRef, CFDataGetBytes, CFDataGetLength,
+    CFDataRef, CFDictionaryAddValue, CFDictionaryCreateMutable, CFDictionaryRef, CFMutableArrayRef,
+    CFMutableDictionaryRef, CFNumberCreate, CFNumberRef, CFRange, CFRelease, CFRetain,
+    CFStringCreateExternalRepresentation, CFStringCreateWithBytes, CFStringCreateWithCString,
+    CFStringGetSystemEncoding, CFStringRef, OSStatus,
 };
 
+use super::device::CADevice;
+
+#[cfg(feature = "cf-leak-tracking")]
+pub use self::tracking::{leak_report, LeakReport, LiveObjects};
+#[cfg(feature = "cf-leak-tracking")]
+use self::tracking::{track_release, track_retain};
+
 #[derive(Debug)]
-pub struct CFError(OSStatus);
+pub enum CFError {
+    Status(OSStatus),
+    /// The device was started, but never called the IOProc.
+    StartupTimeout(Box<StartupDiagnostics>),
+    /// Another session in this process is using `device` at `sample_rate`,
+    /// and this one wanted it at a different rate.
+    DeviceBusy {
+        device: CADevice,
+        sample_rate: f64,
+    },
+}
+
+/// The state of a session's devices when it failed to start.
+#[derive(Debug, Clone)]
+pub struct StartupDiagnostics {
+    pub timeout: Duration,
+    pub device_name: Option<String>,
+    pub is_alive: Option<bool>,
+    pub is_running: Option<bool>,
+    pub is_running_somewhere: Option<bool>,
+    pub nominal_sample_rate: Option<f64>,
+    pub actual_sample_rate: Option<f64>,
+    pub input_sample_rate: Option<f64>,
+    pub output_sample_rate: Option<f64>,
+}
 
 pub struct CFString(CFStringRef);
 pub struct CFDictionary(CFDictionaryRef);
@@ -26,29 +61,119 @@ pub fn check_os_status(s: OSStatus) -> Result<(), CFError> {
     if s == noErr as OSStatus {
         Ok(())
     } else {
-        Err(CFError(s))
+        Err(CFError::Status(s))
+    }
+}
+
+impl CFError {
+    /// Whether the error is expected to clear up by itself, like the HAL not
+    /// running yet right after login.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            CFError::Status(status) if *status == kAudioHardwareNotRunningError as OSStatus
+        )
     }
 }
 
 impl fmt::Display for CFError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "OSStatus({:x})", self.0)
+        match self {
+            CFError::Status(status) => write!(f, "OSStatus({:x})", status),
+            CFError::StartupTimeout(diagnostics) => write!(
+                f,
+                "Device did not start within {:?}: {}",
+                diagnostics.timeout, diagnostics
+            ),
+            CFError::DeviceBusy {
+                device,
+                sample_rate,
+            } => write!(
+                f,
+                "Device {} is in use by another session at {} Hz",
+                device.id(),
+                sample_rate
+            ),
+        }
+    }
+}
+
+impl fmt::Display for StartupDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn field<T: fmt::Debug>(value: &Option<T>) -> String {
+            value
+                .as_ref()
+                .map_or_else(|| "unknown".to_owned(), |value| format!("{:?}", value))
+        }
+
+        write!(
+            f,
+            "device {}, alive {}, running {}, running somewhere {}, \
+             nominal rate {}, actual rate {}, input rate {}, output rate {}",
+            field(&self.device_name),
+            field(&self.is_alive),
+            field(&self.is_running),
+            field(&self.is_running_somewhere),
+            field(&self.nominal_sample_rate),
+            field(&self.actual_sample_rate),
+            field(&self.input_sample_rate),
+            field(&self.output_sample_rate),
+        )
     }
 }
 
 impl Error for CFError {}
 
+impl From<CFError> for crate::Error {
+    fn from(error: CFError) -> Self {
+        let kind = match &error {
+            // Device IDs stop being valid when the device goes away.
+            CFError::Status(status)
+                if *status == kAudioHardwareBadDeviceError as OSStatus
+                    || *status == kAudioHardwareBadObjectError as OSStatus =>
+            {
+                Self::DeviceDisconnected
+            }
+            CFError::Status(status) if *status == kAudioDevicePermissionsError as OSStatus => {
+                Self::DeviceBusy
+            }
+            CFError::Status(status)
+                if *status == kAudioDeviceUnsupportedFormatError as OSStatus =>
+            {
+                Self::FormatNotSupported
+            }
+            CFError::Status(status)
+                if *status == kAudioHardwareUnsupportedOperationError as OSStatus =>
+            {
+                Self::Unsupported
+            }
+            CFError::Status(_) => Self::Backend,
+            CFError::StartupTimeout(_) => Self::StartupTimeout,
+            CFError::DeviceBusy { .. } => Self::DeviceBusy,
+        };
+
+        kind(Box::new(error))
+    }
+}
+
 #[link(name = "CoreFoundation", kind = "framework")]
 extern "C" {}
 
+#[cfg(not(feature = "cf-leak-tracking"))]
+fn track_retain(_type_name: &'static str, _ptr: *const c_void) {}
+
+#[cfg(not(feature = "cf-leak-tracking"))]
+fn track_release(_type_name: &'static str, _ptr: *const c_void) {}
+
 impl CFString {
     pub fn new_retained(s: CFStringRef) -> Self {
+        track_retain("CFString", s as *const c_void);
         CFString(s)
     }
 
     pub fn new(s: &str) -> Self {
         unsafe {
-            CFString(CFStringCreateWithBytes(
+            CFString::new_retained(CFStringCreateWithBytes(
                 std::ptr::null_mut(),
                 s.as_ptr(),
                 s.len() as i64,
@@ -60,7 +185,7 @@ impl CFString {
 
     pub fn from_cstr(s: &CStr) -> Self {
         unsafe {
-            CFString(CFStringCreateWithCString(
+            CFString::new_retained(CFStringCreateWithCString(
                 std::ptr::null_mut(),
                 s.as_ptr(),
                 CFStringGetSystemEncoding(),
@@ -79,7 +204,7 @@ impl CFString {
 
         assert!(!data_ref.is_null());
 
-        let data = CFData(data_ref);
+        let data = CFData::new_retained(data_ref);
 
         String::from_utf8(data.to_vec()).expect("Invalid UTF-8")
     }
@@ -87,6 +212,7 @@ impl CFString {
 
 impl Drop for CFString {
     fn drop(&mut self) {
+        track_release("CFString", self.0 as *const c_void);
         unsafe {
             CFRelease(self.0 as *const c_void);
         }
@@ -94,6 +220,11 @@ impl Drop for CFString {
 }
 
 impl CFDictionary {
+    pub fn new_retained(d: CFDictionaryRef) -> Self {
+        track_retain("CFDictionary", d as *const c_void);
+        CFDictionary(d)
+    }
+
     pub fn as_void_ptr(&self) -> *const c_void {
         self.0 as *const c_void
     }
@@ -101,6 +232,7 @@ impl CFDictionary {
 
 impl Drop for CFDictionary {
     fn drop(&mut self) {
+        track_release("CFDictionary", self.0 as *const c_void);
         unsafe {
             CFRelease(self.0 as *const c_void);
         }
@@ -110,7 +242,7 @@ impl Drop for CFDictionary {
 impl CFMutableDictionary {
     pub fn new() -> Self {
         unsafe {
-            CFMutableDictionary(CFDictionaryCreateMutable(
+            CFMutableDictionary::new_retained(CFDictionaryCreateMutable(
                 std::ptr::null_mut(),
                 0,
                 &kCFTypeDictionaryKeyCallBacks,
@@ -119,17 +251,23 @@ impl CFMutableDictionary {
         }
     }
 
+    pub fn new_retained(d: CFMutableDictionaryRef) -> Self {
+        track_retain("CFMutableDictionary", d as *const c_void);
+        CFMutableDictionary(d)
+    }
+
     pub fn insert(&mut self, key: *const c_void, value: *const c_void) {
         unsafe { CFDictionaryAddValue(self.0, key, value) }
     }
 
     pub fn clone_immutable(&self) -> CFDictionary {
-        unsafe { CFDictionary(CFRetain(self.0 as *const c_void) as CFDictionaryRef) }
+        unsafe { CFDictionary::new_retained(CFRetain(self.0 as *const c_void) as CFDictionaryRef) }
     }
 }
 
 impl Drop for CFMutableDictionary {
     fn drop(&mut self) {
+        track_release("CFMutableDictionary", self.0 as *const c_void);
         unsafe {
             CFRelease(self.0 as *const c_void);
         }
@@ -137,9 +275,14 @@ impl Drop for CFMutableDictionary {
 }
 
 impl CFNumber {
+    pub fn new_retained(n: CFNumberRef) -> Self {
+        track_retain("CFNumber", n as *const c_void);
+        CFNumber(n)
+    }
+
     pub fn new(value: i32) -> Self {
         unsafe {
-            CFNumber(CFNumberCreate(
+            CFNumber::new_retained(CFNumberCreate(
                 std::ptr::null_mut(),
                 kCFNumberIntType as i64,
                 &value as *const i32 as *const c_void,
@@ -154,6 +297,7 @@ impl CFNumber {
 
 impl Drop for CFNumber {
     fn drop(&mut self) {
+        track_release("CFNumber", self.0 as *const c_void);
         unsafe {
             CFRelease(self.0 as *const c_void);
         }
@@ -162,6 +306,7 @@ impl Drop for CFNumber {
 
 impl CFArray {
     pub fn new_retained(a: CFArrayRef) -> Self {
+        track_retain("CFArray", a as *const c_void);
         CFArray(a)
     }
 
@@ -172,6 +317,7 @@ impl CFArray {
 
 impl Drop for CFArray {
     fn drop(&mut self) {
+        track_release("CFArray", self.0 as *const c_void);
         unsafe {
             CFRelease(self.0 as *const c_void);
         }
@@ -179,9 +325,14 @@ impl Drop for CFArray {
 }
 
 impl CFMutableArray {
+    pub fn new_retained(a: CFMutableArrayRef) -> Self {
+        track_retain("CFMutableArray", a as *const c_void);
+        CFMutableArray(a)
+    }
+
     pub fn new() -> Self {
         unsafe {
-            CFMutableArray(CFArrayCreateMutable(
+            CFMutableArray::new_retained(CFArrayCreateMutable(
                 std::ptr::null_mut(),
                 0,
                 &kCFTypeArrayCallBacks,
@@ -194,12 +345,13 @@ impl CFMutableArray {
     }
 
     pub fn clone_immutable(&self) -> CFArray {
-        unsafe { CFArray(CFRetain(self.0 as *const c_void) as CFArrayRef) }
+        unsafe { CFArray::new_retained(CFRetain(self.0 as *const c_void) as CFArrayRef) }
     }
 }
 
 impl Drop for CFMutableArray {
     fn drop(&mut self) {
+        track_release("CFMutableArray", self.0 as *const c_void);
         unsafe {
             CFRelease(self.0 as *const c_void);
         }
@@ -207,6 +359,11 @@ impl Drop for CFMutableArray {
 }
 
 impl CFData {
+    pub fn new_retained(d: CFDataRef) -> Self {
+        track_retain("CFData", d as *const c_void);
+        CFData(d)
+    }
+
     pub fn to_vec(&self) -> Vec<u8> {
         let len = unsafe { CFDataGetLength(self.0) };
 
@@ -230,8 +387,251 @@ impl CFData {
 
 impl Drop for CFData {
     fn drop(&mut self) {
+        track_release("CFData", self.0 as *const c_void);
         unsafe {
             CFRelease(self.0 as *const c_void);
         }
     }
 }
+
+/// Reading values back out of dictionaries and arrays. Only the fuzz targets
+/// need this so far: everything else hands dictionaries to CoreAudio rather
+/// than getting them back.
+#[cfg(feature = "fuzzing")]
+mod decoding {
+    use std::ffi::c_void;
+
+    use coreaudio_sys::{
+        kCFNumberIntType, CFArrayGetCount, CFArrayGetTypeID, CFArrayGetValueAtIndex, CFArrayRef,
+        CFDictionaryGetTypeID, CFDictionaryGetValue, CFDictionaryRef, CFGetTypeID,
+        CFNumberGetTypeID, CFNumberGetValue, CFNumberRef, CFRetain, CFStringGetTypeID, CFStringRef,
+        CFTypeID,
+    };
+
+    use super::{CFArray, CFDictionary, CFNumber, CFString};
+
+    /// A CoreFoundation type that can be read out of a dictionary or array,
+    /// checking that the value really is one.
+    pub trait CFType: Sized {
+        fn type_id() -> CFTypeID;
+
+        /// Takes ownership of a reference that has already been retained.
+        fn from_retained(value: *const c_void) -> Self;
+    }
+
+    /// Retains `value` as a `T`, or returns `None` if it is null or of some
+    /// other type.
+    fn retain_as<T: CFType>(value: *const c_void) -> Option<T> {
+        if value.is_null() || unsafe { CFGetTypeID(value) } != T::type_id() {
+            return None;
+        }
+
+        Some(T::from_retained(unsafe { CFRetain(value) }))
+    }
+
+    impl CFType for CFString {
+        fn type_id() -> CFTypeID {
+            unsafe { CFStringGetTypeID() }
+        }
+
+        fn from_retained(value: *const c_void) -> Self {
+            CFString::new_retained(value as CFStringRef)
+        }
+    }
+
+    impl CFType for CFNumber {
+        fn type_id() -> CFTypeID {
+            unsafe { CFNumberGetTypeID() }
+        }
+
+        fn from_retained(value: *const c_void) -> Self {
+            CFNumber::new_retained(value as CFNumberRef)
+        }
+    }
+
+    impl CFType for CFArray {
+        fn type_id() -> CFTypeID {
+            unsafe { CFArrayGetTypeID() }
+        }
+
+        fn from_retained(value: *const c_void) -> Self {
+            CFArray::new_retained(value as CFArrayRef)
+        }
+    }
+
+    impl CFType for CFDictionary {
+        fn type_id() -> CFTypeID {
+            unsafe { CFDictionaryGetTypeID() }
+        }
+
+        fn from_retained(value: *const c_void) -> Self {
+            CFDictionary::new_retained(value as CFDictionaryRef)
+        }
+    }
+
+    impl CFDictionary {
+        /// The value for `key`, if there is one and it is a `T`.
+        pub fn get<T: CFType>(&self, key: &CFString) -> Option<T> {
+            retain_as(unsafe { CFDictionaryGetValue(self.0, key.as_void_ptr()) })
+        }
+    }
+
+    impl CFArray {
+        pub fn len(&self) -> usize {
+            unsafe { CFArrayGetCount(self.0) as usize }
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// The value at `index`, if the array is long enough and it is a `T`.
+        pub fn get<T: CFType>(&self, index: usize) -> Option<T> {
+            if index >= self.len() {
+                return None;
+            }
+
+            retain_as(unsafe { CFArrayGetValueAtIndex(self.0, index as i64) })
+        }
+    }
+
+    impl CFNumber {
+        /// The value as an `i32`, or `None` if it doesn't fit.
+        pub fn to_i32(&self) -> Option<i32> {
+            let mut value = 0i32;
+            let exact = unsafe {
+                CFNumberGetValue(
+                    self.0,
+                    kCFNumberIntType as i64,
+                    &mut value as *mut i32 as *mut c_void,
+                )
+            };
+
+            if exact != 0 {
+                Some(value)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(feature = "cf-leak-tracking")]
+mod tracking {
+    use std::backtrace::Backtrace;
+    use std::collections::HashMap;
+    use std::ffi::c_void;
+    use std::fmt;
+    use std::sync::{Mutex, MutexGuard};
+
+    #[derive(Default)]
+    struct TypeStats {
+        created: usize,
+        live: isize,
+        backtraces: HashMap<usize, Vec<Backtrace>>,
+    }
+
+    static REGISTRY: Mutex<Option<HashMap<&'static str, TypeStats>>> = Mutex::new(None);
+
+    fn registry() -> MutexGuard<'static, Option<HashMap<&'static str, TypeStats>>> {
+        REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn track_retain(type_name: &'static str, ptr: *const c_void) {
+        let backtrace = Backtrace::force_capture();
+
+        let mut registry = registry();
+        let stats = registry
+            .get_or_insert_with(HashMap::new)
+            .entry(type_name)
+            .or_default();
+
+        stats.created += 1;
+        stats.live += 1;
+        stats
+            .backtraces
+            .entry(ptr as usize)
+            .or_default()
+            .push(backtrace);
+    }
+
+    pub fn track_release(type_name: &'static str, ptr: *const c_void) {
+        let mut registry = registry();
+        let stats = registry
+            .get_or_insert_with(HashMap::new)
+            .entry(type_name)
+            .or_default();
+
+        stats.live -= 1;
+        if let Some(backtraces) = stats.backtraces.get_mut(&(ptr as usize)) {
+            backtraces.pop();
+            if backtraces.is_empty() {
+                stats.backtraces.remove(&(ptr as usize));
+            }
+        }
+    }
+
+    /// Live wrapper count for a single CF type. A negative count means more
+    /// releases than retains were issued through the wrappers.
+    #[derive(Debug)]
+    pub struct LiveObjects {
+        pub type_name: &'static str,
+        pub created: usize,
+        pub live: isize,
+        pub backtraces: Vec<String>,
+    }
+
+    #[derive(Debug)]
+    pub struct LeakReport {
+        pub types: Vec<LiveObjects>,
+    }
+
+    impl LeakReport {
+        pub fn is_balanced(&self) -> bool {
+            self.types.iter().all(|t| t.live == 0)
+        }
+    }
+
+    impl fmt::Display for LeakReport {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for objects in &self.types {
+                writeln!(
+                    f,
+                    "{}: {} live ({} created)",
+                    objects.type_name, objects.live, objects.created
+                )?;
+
+                for backtrace in &objects.backtraces {
+                    writeln!(f, "  created at:\n{}", backtrace)?;
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Snapshot of every CF wrapper created so far that hasn't been dropped,
+    /// along with the backtraces of where they were created.
+    pub fn leak_report() -> LeakReport {
+        let registry = registry();
+        let mut types = registry
+            .iter()
+            .flatten()
+            .map(|(type_name, stats)| LiveObjects {
+                type_name,
+                created: stats.created,
+                live: stats.live,
+                backtraces: stats
+                    .backtraces
+                    .values()
+                    .flatten()
+                    .map(|b| b.to_string())
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        types.sort_by_key(|t| t.type_name);
+
+        LeakReport { types }
+    }
+}
@@ -1,14 +1,27 @@
 use std::fmt;
 
-use coreaudio_sys::AudioDeviceID;
+use std::ffi::c_void;
+use std::mem::{self, MaybeUninit};
+use std::ops::RangeInclusive;
+use std::slice;
 
-use crate::traits::Device;
+use coreaudio_sys::{
+    kAudioFormatFlagIsFloat, kAudioObjectSystemObject, AudioDeviceID, AudioValueRange,
+    AudioValueTranslation, CFStringRef,
+};
+
+use crate::config::PhysicalFormat;
+use crate::device_info::{ClockSource, DataSource, DeviceUser};
+use crate::direction::Direction;
+use crate::traits::{Device, DeviceControls};
 
 use super::backend::CABackend;
 use super::cf::{CFError, CFString};
 use super::properties::{self, element, scope, selector};
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// Devices compare and order by their handle, which is only stable while the
+/// device stays connected. Sort by `persistent_id` for an order that lasts.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct CADevice(pub(crate) AudioDeviceID);
 
 impl CADevice {
@@ -34,6 +47,99 @@ impl CADevice {
             )
         }
     }
+
+    /// The smallest and largest buffer frame size the device accepts.
+    /// The channels of all of the device's streams in `direction` together.
+    /// The stream configuration has one buffer per stream, each of which may
+    /// hold several channels.
+    fn num_channels(&self, direction: Direction) -> Result<usize, CFError> {
+        let config = unsafe {
+            properties::get_in(
+                element::Master,
+                direction,
+                selector::DevicePropertyStreamConfiguration,
+                self.0,
+            )?
+        };
+        let streams = unsafe {
+            slice::from_raw_parts(config.mBuffers.as_ptr(), config.mNumberBuffers as usize)
+        };
+
+        Ok(streams
+            .iter()
+            .map(|stream| stream.mNumberChannels as usize)
+            .sum())
+    }
+
+    pub(crate) fn buffer_size_range(&self) -> Result<(usize, usize), CFError> {
+        let range = unsafe {
+            properties::get(
+                element::Master,
+                scope::Global,
+                selector::DevicePropertyBufferFrameSizeRange,
+                self.0,
+            )?
+        };
+
+        Ok((range.mMinimum as usize, range.mMaximum as usize))
+    }
+
+    fn data_source_name(&self, direction: Direction, id: u32) -> Result<String, CFError> {
+        let mut name = MaybeUninit::<CFStringRef>::uninit();
+        let mut translation = AudioValueTranslation {
+            mInputData: &id as *const u32 as *mut c_void,
+            mInputDataSize: mem::size_of::<u32>() as u32,
+            mOutputData: name.as_mut_ptr() as *mut c_void,
+            mOutputDataSize: mem::size_of::<CFStringRef>() as u32,
+        };
+
+        let name = unsafe {
+            match direction {
+                Direction::Input => properties::translate(
+                    element::Master,
+                    scope::Input,
+                    selector::DevicePropertyDataSourceNameForIDCFString,
+                    self.0,
+                    &mut translation,
+                )?,
+                Direction::Output => properties::translate(
+                    element::Master,
+                    scope::Output,
+                    selector::DevicePropertyDataSourceNameForIDCFString,
+                    self.0,
+                    &mut translation,
+                )?,
+            }
+
+            CFString::new_retained(name.assume_init())
+        };
+
+        Ok(name.to_string())
+    }
+
+    fn clock_source_name(&self, id: u32) -> Result<String, CFError> {
+        let mut name = MaybeUninit::<CFStringRef>::uninit();
+        let mut translation = AudioValueTranslation {
+            mInputData: &id as *const u32 as *mut c_void,
+            mInputDataSize: mem::size_of::<u32>() as u32,
+            mOutputData: name.as_mut_ptr() as *mut c_void,
+            mOutputDataSize: mem::size_of::<CFStringRef>() as u32,
+        };
+
+        let name = unsafe {
+            properties::translate(
+                element::Master,
+                scope::Global,
+                selector::DevicePropertyClockSourceNameForIDCFString,
+                self.0,
+                &mut translation,
+            )?;
+
+            CFString::new_retained(name.assume_init())
+        };
+
+        Ok(name.to_string())
+    }
 }
 
 impl fmt::Debug for CADevice {
@@ -49,40 +155,269 @@ impl fmt::Debug for CADevice {
 
 impl Device<CABackend> for CADevice {
     fn num_inputs(&self) -> Result<usize, CFError> {
-        let inputs = unsafe {
+        self.num_channels(Direction::Input)
+    }
+
+    fn num_outputs(&self) -> Result<usize, CFError> {
+        self.num_channels(Direction::Output)
+    }
+
+    fn name(&self) -> Result<String, CFError> {
+        let cfstr = unsafe {
             properties::get(
                 element::Master,
-                scope::Input,
-                selector::DevicePropertyStreamConfiguration,
+                scope::Wildcard,
+                selector::ObjectPropertyName,
                 self.0,
             )?
         };
-        Ok(inputs.mNumberBuffers as usize)
+
+        Ok(cfstr.to_string())
     }
 
-    fn num_outputs(&self) -> Result<usize, CFError> {
-        let outputs = unsafe {
+    fn channel_name(
+        &self,
+        direction: Direction,
+        channel: usize,
+    ) -> Result<Option<String>, CFError> {
+        let name = unsafe {
+            properties::get_in(
+                element::Channel(channel),
+                direction,
+                selector::ObjectPropertyElementName,
+                self.0,
+            )
+        };
+
+        // Most drivers don't name their channels, and report that as an error
+        // or an empty string.
+        Ok(name
+            .ok()
+            .map(|name| name.to_string())
+            .filter(|name| !name.is_empty()))
+    }
+
+    fn data_sources(&self, direction: Direction) -> Result<Vec<DataSource>, CFError> {
+        let ids = unsafe {
+            properties::get_in(
+                element::Master,
+                direction,
+                selector::DevicePropertyDataSources,
+                self.0,
+            )
+        };
+
+        // Devices without selectable sources don't have the property at all.
+        ids.unwrap_or_default()
+            .into_iter()
+            .map(|id| {
+                Ok(DataSource {
+                    id,
+                    name: self.data_source_name(direction, id)?,
+                })
+            })
+            .collect()
+    }
+
+    fn current_data_source(&self, direction: Direction) -> Result<Option<DataSource>, CFError> {
+        let id = unsafe {
+            properties::get_in(
+                element::Master,
+                direction,
+                selector::DevicePropertyDataSource,
+                self.0,
+            )
+        };
+
+        match id {
+            Ok(id) => Ok(Some(DataSource {
+                id,
+                name: self.data_source_name(direction, id)?,
+            })),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn clock_sources(&self) -> Result<Vec<ClockSource>, CFError> {
+        let ids = unsafe {
             properties::get(
                 element::Master,
-                scope::Output,
-                selector::DevicePropertyStreamConfiguration,
+                scope::Global,
+                selector::DevicePropertyClockSources,
                 self.0,
-            )?
+            )
         };
-        Ok(outputs.mNumberBuffers as usize)
+
+        // Like data sources, the property is missing on devices with only
+        // their internal clock.
+        ids.unwrap_or_default()
+            .into_iter()
+            .map(|id| {
+                Ok(ClockSource {
+                    id,
+                    name: self.clock_source_name(id)?,
+                })
+            })
+            .collect()
     }
 
-    fn name(&self) -> Result<String, CFError> {
-        let cfstr = unsafe {
+    fn current_clock_source(&self) -> Result<Option<ClockSource>, CFError> {
+        let id = unsafe {
             properties::get(
                 element::Master,
-                scope::Wildcard,
-                selector::ObjectPropertyName,
+                scope::Global,
+                selector::DevicePropertyClockSource,
+                self.0,
+            )
+        };
+
+        match id {
+            Ok(id) => Ok(Some(ClockSource {
+                id,
+                name: self.clock_source_name(id)?,
+            })),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn set_clock_source(&mut self, id: u32) -> Result<(), CFError> {
+        unsafe {
+            properties::set(
+                element::Master,
+                scope::Global,
+                selector::DevicePropertyClockSource,
                 self.0,
+                &id,
+            )
+        }
+    }
+
+    fn users(&self) -> Result<Vec<DeviceUser>, CFError> {
+        let hog_pid = unsafe {
+            properties::get(
+                element::Master,
+                scope::Global,
+                selector::DevicePropertyHogMode,
+                self.0,
+            )
+        }
+        .map(|pid| pid as i32)
+        .unwrap_or(-1);
+
+        // Process objects only exist on macOS 14 and later.
+        let processes = unsafe {
+            properties::get(
+                element::Master,
+                scope::Global,
+                selector::HardwarePropertyProcessObjectList,
+                kAudioObjectSystemObject,
+            )
+        }
+        .unwrap_or_default();
+
+        let mut users = Vec::new();
+        for process in processes {
+            let uses_device = [Direction::Input, Direction::Output]
+                .iter()
+                .any(|&direction| {
+                    unsafe {
+                        properties::get_in(
+                            element::Master,
+                            direction,
+                            selector::ProcessPropertyDevices,
+                            process,
+                        )
+                    }
+                    .is_ok_and(|devices| devices.contains(&self.0))
+                });
+            if !uses_device {
+                continue;
+            }
+
+            let pid = unsafe {
+                properties::get(
+                    element::Master,
+                    scope::Global,
+                    selector::ProcessPropertyPID,
+                    process,
+                )?
+            } as i32;
+            let bundle_id = unsafe {
+                properties::get(
+                    element::Master,
+                    scope::Global,
+                    selector::ProcessPropertyBundleID,
+                    process,
+                )
+            }
+            .ok()
+            .map(|bundle_id| bundle_id.to_string())
+            .filter(|bundle_id| !bundle_id.is_empty());
+
+            users.push(DeviceUser {
+                pid: Some(pid),
+                bundle_id,
+                exclusive: pid == hog_pid,
+            });
+        }
+
+        if hog_pid != -1 && !users.iter().any(|user| user.pid == Some(hog_pid)) {
+            users.push(DeviceUser {
+                pid: Some(hog_pid),
+                bundle_id: None,
+                exclusive: true,
+            });
+        }
+
+        if users.is_empty() {
+            let running_somewhere = unsafe {
+                properties::get(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyDeviceIsRunningSomewhere,
+                    self.0,
+                )?
+            };
+            if running_somewhere != 0 {
+                users.push(DeviceUser {
+                    pid: None,
+                    bundle_id: None,
+                    exclusive: false,
+                });
+            }
+        }
+
+        Ok(users)
+    }
+
+    fn persistent_id(&self) -> Result<String, CFError> {
+        Ok(self.uid()?.to_string())
+    }
+
+    fn is_default_input(&self) -> Result<bool, CFError> {
+        let default: CADevice = unsafe {
+            properties::get(
+                element::Master,
+                scope::Global,
+                selector::HardwarePropertyDefaultInputDevice,
+                kAudioObjectSystemObject,
             )?
         };
 
-        Ok(cfstr.to_string())
+        Ok(default == *self)
+    }
+
+    fn is_default_output(&self) -> Result<bool, CFError> {
+        let default: CADevice = unsafe {
+            properties::get(
+                element::Master,
+                scope::Global,
+                selector::HardwarePropertyDefaultOutputDevice,
+                kAudioObjectSystemObject,
+            )?
+        };
+
+        Ok(default == *self)
     }
 
     fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), CFError> {
@@ -108,6 +443,59 @@ impl Device<CABackend> for CADevice {
         }
     }
 
+    fn available_sample_rates(&self) -> Result<Vec<RangeInclusive<f64>>, CFError> {
+        let ranges: Vec<AudioValueRange> = unsafe {
+            properties::get(
+                element::Master,
+                scope::Wildcard,
+                selector::DevicePropertyAvailableNominalSampleRates,
+                self.0,
+            )?
+        };
+
+        Ok(ranges
+            .into_iter()
+            .map(|range| range.mMinimum..=range.mMaximum)
+            .collect())
+    }
+
+    fn buffer_frame_size_range(&self) -> Result<Option<RangeInclusive<usize>>, CFError> {
+        let (min, max) = self.buffer_size_range()?;
+        Ok(Some(min..=max))
+    }
+
+    /// The physical format of the device's first stream in `direction`.
+    /// Devices with several streams usually run them all the same way.
+    fn native_format(&self, direction: Direction) -> Result<Option<PhysicalFormat>, CFError> {
+        let streams = unsafe {
+            properties::get_in(
+                element::Master,
+                direction,
+                selector::DevicePropertyStreams,
+                self.0,
+            )?
+        };
+        let stream = match streams.first() {
+            Some(&stream) => stream,
+            None => return Ok(None),
+        };
+
+        let description = unsafe {
+            properties::get(
+                element::Master,
+                scope::Global,
+                selector::StreamPropertyPhysicalFormat,
+                stream,
+            )?
+        };
+
+        Ok(Some(PhysicalFormat {
+            sample_rate: description.mSampleRate,
+            bits_per_sample: description.mBitsPerChannel,
+            is_float: description.mFormatFlags & kAudioFormatFlagIsFloat != 0,
+        }))
+    }
+
     fn actual_sample_rate(&self) -> Result<f64, CFError> {
         unsafe {
             properties::get(
@@ -119,3 +507,63 @@ impl Device<CABackend> for CADevice {
         }
     }
 }
+
+impl DeviceControls<CABackend> for CADevice {
+    fn volume(&self, direction: Direction, channel: Option<usize>) -> Result<f32, CFError> {
+        unsafe {
+            properties::get_in(
+                element::OptionalChannel(channel),
+                direction,
+                selector::DevicePropertyVolumeScalar,
+                self.0,
+            )
+        }
+    }
+
+    fn set_volume(
+        &mut self,
+        direction: Direction,
+        channel: Option<usize>,
+        volume: f32,
+    ) -> Result<(), CFError> {
+        unsafe {
+            properties::set_in(
+                element::OptionalChannel(channel),
+                direction,
+                selector::DevicePropertyVolumeScalar,
+                self.0,
+                &volume.clamp(0.0, 1.0),
+            )
+        }
+    }
+
+    fn is_muted(&self, direction: Direction, channel: Option<usize>) -> Result<bool, CFError> {
+        let muted = unsafe {
+            properties::get_in(
+                element::OptionalChannel(channel),
+                direction,
+                selector::DevicePropertyMute,
+                self.0,
+            )?
+        };
+
+        Ok(muted != 0)
+    }
+
+    fn set_muted(
+        &mut self,
+        direction: Direction,
+        channel: Option<usize>,
+        muted: bool,
+    ) -> Result<(), CFError> {
+        unsafe {
+            properties::set_in(
+                element::OptionalChannel(channel),
+                direction,
+                selector::DevicePropertyMute,
+                self.0,
+                &u32::from(muted),
+            )
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::ffi::c_void;
+use std::thread::Thread;
+
+use coreaudio_sys::{
+    kAudioObjectSystemObject, noErr, AudioObjectID, AudioObjectPropertyAddress, OSStatus,
+};
+
+use super::cf::CFError;
+use super::properties::{self, element, scope, selector};
+
+/// Listeners that wake a thread up as soon as the device list or the default
+/// devices change. Removed when dropped.
+pub struct DeviceListeners {
+    thread: Box<Thread>,
+}
+
+// The boxed thread is only touched through the listeners, which are removed
+// before it's dropped.
+unsafe impl Send for DeviceListeners {}
+
+impl DeviceListeners {
+    pub fn install(thread: Thread) -> Result<Self, CFError> {
+        let listeners = DeviceListeners {
+            thread: Box::new(thread),
+        };
+        let client_data = listeners.client_data();
+
+        unsafe {
+            properties::add_listener(
+                element::Master,
+                scope::Global,
+                selector::HardwarePropertyDevices,
+                kAudioObjectSystemObject,
+                Some(listener),
+                client_data,
+            )?;
+            properties::add_listener(
+                element::Master,
+                scope::Global,
+                selector::HardwarePropertyDefaultInputDevice,
+                kAudioObjectSystemObject,
+                Some(listener),
+                client_data,
+            )?;
+            properties::add_listener(
+                element::Master,
+                scope::Global,
+                selector::HardwarePropertyDefaultOutputDevice,
+                kAudioObjectSystemObject,
+                Some(listener),
+                client_data,
+            )?;
+        }
+
+        Ok(listeners)
+    }
+
+    fn client_data(&self) -> *mut c_void {
+        &*self.thread as *const Thread as *mut c_void
+    }
+}
+
+impl Drop for DeviceListeners {
+    fn drop(&mut self) {
+        let client_data = self.client_data();
+
+        // Listeners that were never added fail to be removed, which is fine.
+        unsafe {
+            let _ = properties::remove_listener(
+                element::Master,
+                scope::Global,
+                selector::HardwarePropertyDevices,
+                kAudioObjectSystemObject,
+                Some(listener),
+                client_data,
+            );
+            let _ = properties::remove_listener(
+                element::Master,
+                scope::Global,
+                selector::HardwarePropertyDefaultInputDevice,
+                kAudioObjectSystemObject,
+                Some(listener),
+                client_data,
+            );
+            let _ = properties::remove_listener(
+                element::Master,
+                scope::Global,
+                selector::HardwarePropertyDefaultOutputDevice,
+                kAudioObjectSystemObject,
+                Some(listener),
+                client_data,
+            );
+        }
+    }
+}
+
+unsafe extern "C" fn listener(
+    _in_object_id: AudioObjectID,
+    _in_number_addresses: u32,
+    _in_addresses: *const AudioObjectPropertyAddress,
+    in_client_data: *mut c_void,
+) -> OSStatus {
+    let thread = &*(in_client_data as *const Thread);
+    thread.unpark();
+
+    noErr as OSStatus
+}
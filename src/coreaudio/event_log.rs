@@ -0,0 +1,206 @@
+use std::ffi::c_void;
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+use coreaudio_sys::{
+    kAudioDevicePropertyDataSource, kAudioDevicePropertyNominalSampleRate,
+    kAudioHardwarePropertyDefaultInputDevice, kAudioHardwarePropertyDefaultOutputDevice,
+    kAudioHardwarePropertyDevices, kAudioObjectPropertyScopeInput, kAudioObjectSystemObject, noErr,
+    AudioObjectID, AudioObjectPropertyAddress, OSStatus,
+};
+
+use crate::direction::Direction;
+use crate::event_log::{record, HardwareEventKind};
+use crate::traits::{Backend, Device};
+
+use super::backend::CABackend;
+use super::cf::CFError;
+use super::device::CADevice;
+use super::properties::{self, element, scope, selector};
+
+/// Every device being listened to, with its persistent ID as of when it was
+/// added. None until the listeners have been installed.
+static KNOWN_DEVICES: Mutex<Option<Vec<(CADevice, String)>>> = Mutex::new(None);
+
+/// Installs the listeners feeding the event log, once per process. They are
+/// never removed, since the log lives as long as the process does.
+pub fn install_listeners(backend: &CABackend) -> Result<(), CFError> {
+    let mut known = KNOWN_DEVICES.lock().unwrap();
+    if known.is_some() {
+        return Ok(());
+    }
+
+    unsafe {
+        properties::add_listener(
+            element::Master,
+            scope::Global,
+            selector::HardwarePropertyDevices,
+            kAudioObjectSystemObject,
+            Some(system_listener),
+            ptr::null_mut(),
+        )?;
+        properties::add_listener(
+            element::Master,
+            scope::Global,
+            selector::HardwarePropertyDefaultInputDevice,
+            kAudioObjectSystemObject,
+            Some(system_listener),
+            ptr::null_mut(),
+        )?;
+        properties::add_listener(
+            element::Master,
+            scope::Global,
+            selector::HardwarePropertyDefaultOutputDevice,
+            kAudioObjectSystemObject,
+            Some(system_listener),
+            ptr::null_mut(),
+        )?;
+    }
+
+    let mut devices = Vec::new();
+    for device in backend.all_devices()? {
+        watch_device(device)?;
+        devices.push((device, device.persistent_id()?));
+    }
+    *known = Some(devices);
+
+    Ok(())
+}
+
+fn watch_device(device: CADevice) -> Result<(), CFError> {
+    unsafe {
+        properties::add_listener(
+            element::Master,
+            scope::Wildcard,
+            selector::DevicePropertyNominalSampleRate,
+            device.id(),
+            Some(device_listener),
+            ptr::null_mut(),
+        )?;
+        properties::add_listener(
+            element::Master,
+            scope::Input,
+            selector::DevicePropertyDataSource,
+            device.id(),
+            Some(device_listener),
+            ptr::null_mut(),
+        )?;
+        properties::add_listener(
+            element::Master,
+            scope::Output,
+            selector::DevicePropertyDataSource,
+            device.id(),
+            Some(device_listener),
+            ptr::null_mut(),
+        )
+    }
+}
+
+/// Diffs the device list against the last one seen, and starts listening to
+/// the new devices.
+fn refresh_devices() -> Result<(), CFError> {
+    let current = CABackend.all_devices()?;
+
+    let mut known = KNOWN_DEVICES.lock().unwrap();
+    let known = known.get_or_insert_with(Vec::new);
+
+    known.retain(|(device, persistent_id)| {
+        let present = current.contains(device);
+        if !present {
+            record(HardwareEventKind::DeviceRemoved {
+                device: persistent_id.clone(),
+            });
+        }
+        present
+    });
+
+    for device in current {
+        if known
+            .iter()
+            .any(|&(known_device, _)| known_device == device)
+        {
+            continue;
+        }
+
+        // Devices that vanish again before we get to them aren't worth
+        // reporting.
+        let persistent_id = match device.persistent_id() {
+            Ok(persistent_id) => persistent_id,
+            Err(_) => continue,
+        };
+        let _ = watch_device(device);
+        record(HardwareEventKind::DeviceAdded {
+            device: persistent_id.clone(),
+        });
+        known.push((device, persistent_id));
+    }
+
+    Ok(())
+}
+
+unsafe extern "C" fn system_listener(
+    _in_object_id: AudioObjectID,
+    in_number_addresses: u32,
+    in_addresses: *const AudioObjectPropertyAddress,
+    _in_client_data: *mut c_void,
+) -> OSStatus {
+    for address in slice::from_raw_parts(in_addresses, in_number_addresses as usize) {
+        #[allow(non_upper_case_globals)]
+        let _ = match address.mSelector {
+            kAudioHardwarePropertyDevices => refresh_devices(),
+            kAudioHardwarePropertyDefaultInputDevice => CABackend
+                .default_input_device()
+                .and_then(|device| device.persistent_id())
+                .map(|device| record(HardwareEventKind::DefaultInputChanged { device })),
+            kAudioHardwarePropertyDefaultOutputDevice => CABackend
+                .default_output_device()
+                .and_then(|device| device.persistent_id())
+                .map(|device| record(HardwareEventKind::DefaultOutputChanged { device })),
+            _ => Ok(()),
+        };
+    }
+
+    noErr as OSStatus
+}
+
+unsafe extern "C" fn device_listener(
+    in_object_id: AudioObjectID,
+    in_number_addresses: u32,
+    in_addresses: *const AudioObjectPropertyAddress,
+    _in_client_data: *mut c_void,
+) -> OSStatus {
+    let device = CADevice::new(in_object_id);
+    let persistent_id = match device.persistent_id() {
+        Ok(persistent_id) => persistent_id,
+        Err(_) => return noErr as OSStatus,
+    };
+
+    for address in slice::from_raw_parts(in_addresses, in_number_addresses as usize) {
+        #[allow(non_upper_case_globals)]
+        match address.mSelector {
+            kAudioDevicePropertyNominalSampleRate => {
+                if let Ok(sample_rate) = device.nominal_sample_rate() {
+                    record(HardwareEventKind::SampleRateChanged {
+                        device: persistent_id.clone(),
+                        sample_rate,
+                    });
+                }
+            }
+            kAudioDevicePropertyDataSource => {
+                let direction = if address.mScope == kAudioObjectPropertyScopeInput {
+                    Direction::Input
+                } else {
+                    Direction::Output
+                };
+                record(HardwareEventKind::DataSourceChanged {
+                    device: persistent_id.clone(),
+                    direction,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    noErr as OSStatus
+}
@@ -0,0 +1,310 @@
+use std::ffi::c_void;
+use std::{alloc, mem};
+
+use arbitrary::Arbitrary;
+use coreaudio_sys::{
+    kAudioAggregateDeviceIsPrivateKey, kAudioAggregateDeviceIsStackedKey,
+    kAudioAggregateDeviceMasterSubDeviceKey, kAudioAggregateDeviceNameKey,
+    kAudioAggregateDeviceSubDeviceListKey, kAudioAggregateDeviceUIDKey,
+    kAudioSubDeviceDriftCompensationKey, kAudioSubDeviceUIDKey, AudioBuffer, AudioBufferList,
+};
+
+use crate::meters::{MeterBank, Meters, MAX_METERED_CHANNELS};
+use crate::traits::AudioBuffers;
+
+use super::aggregate_device::{dictionary_key, multi_output_description};
+use super::cf::{CFArray, CFDictionary, CFNumber, CFString};
+use super::session::InterleavedBuffer;
+use super::validation::{BufferListError, BufferListValidator};
+
+#[derive(Debug, Arbitrary)]
+pub struct BufferInput {
+    pub channels: u32,
+    pub byte_size: u32,
+    pub null_data: bool,
+    pub samples: Vec<f32>,
+}
+
+#[derive(Debug, Arbitrary)]
+pub struct BufferListInput {
+    pub input: Vec<BufferInput>,
+    pub output: Vec<BufferInput>,
+    pub input_layout: Vec<u32>,
+    pub output_layout: Vec<u32>,
+}
+
+/// Runs a driver-supplied pair of buffer lists through the same validation
+/// and buffer views the IOProc uses, touching every sample the render
+/// callback would be able to see.
+pub fn buffer_list(input: &mut BufferListInput) -> Result<(), BufferListError> {
+    let validator = BufferListValidator::new();
+
+    let input_layout = OwnedBufferList::layout(&input.input_layout);
+    let output_layout = OwnedBufferList::layout(&input.output_layout);
+    validator.set_input_layout(input_layout.as_ref());
+    validator.set_output_layout(output_layout.as_ref());
+
+    let input_list = OwnedBufferList::new(&mut input.input);
+    let mut output_list = OwnedBufferList::new(&mut input.output);
+
+    if let Err(error) = validator.validate(input_list.as_ref(), output_list.as_ref()) {
+        if error.is_fatal() {
+            return Err(error);
+        }
+    }
+
+    for buffer in input_list.buffers() {
+        let frames = buffer.interleaved_frames();
+        assert_eq!(frames.len(), buffer.num_frames() * buffer.num_channels());
+        std::hint::black_box(frames.iter().sum::<f32>());
+    }
+
+    for buffer in output_list.buffers_mut() {
+        let len = buffer.num_frames() * buffer.num_channels();
+        let frames = buffer.interleaved_frames_mut();
+        assert_eq!(frames.len(), len);
+        for sample in frames {
+            *sample = 0.0;
+        }
+    }
+
+    Ok(())
+}
+
+/// A device with many identical streams, like a MADI interface.
+#[derive(Debug, Arbitrary)]
+pub struct WideLayoutInput {
+    pub streams: u8,
+    pub channels_per_stream: u8,
+    pub frames: u16,
+}
+
+/// Pushes a wide but well-formed layout through validation and metering, and
+/// checks that every channel comes out where it went in.
+pub fn wide_layout(input: &WideLayoutInput) {
+    let streams = usize::from(input.streams.max(1));
+    let channels = usize::from(input.channels_per_stream.max(1));
+    let frames = usize::from(input.frames % 512 + 1);
+
+    let mut buffers: Vec<BufferInput> = (0..streams)
+        .map(|stream| {
+            // Every channel gets its own constant level, so the meters can be
+            // checked channel by channel.
+            let samples = (0..frames * channels)
+                .map(|index| channel_level(stream * channels + index % channels))
+                .collect::<Vec<_>>();
+
+            BufferInput {
+                channels: channels as u32,
+                byte_size: (samples.len() * mem::size_of::<f32>()) as u32,
+                null_data: false,
+                samples,
+            }
+        })
+        .collect();
+    let layout: Vec<u32> = vec![channels as u32; streams];
+
+    let validator = BufferListValidator::new();
+    let input_layout = OwnedBufferList::layout(&layout);
+    validator.set_input_layout(input_layout.as_ref());
+    validator.set_output_layout(input_layout.as_ref());
+
+    let list = OwnedBufferList::new(&mut buffers);
+    assert_eq!(validator.validate(list.as_ref(), list.as_ref()), Ok(()));
+
+    let bank = std::sync::Arc::new(MeterBank::new(1.0));
+    let meters = Meters::new(bank.clone());
+    bank.process(list.buffers(), list.buffers());
+
+    let readings = meters.read();
+    let metered = (streams * channels).min(MAX_METERED_CHANNELS);
+    assert_eq!(readings.input.len(), metered);
+    for (channel, level) in readings.input.iter().enumerate() {
+        assert_eq!(level.peak, channel_level(channel));
+    }
+}
+
+fn channel_level(channel: usize) -> f32 {
+    (channel % 1024) as f32 / 1024.0
+}
+
+/// Round-trips arbitrary text through a CFString.
+pub fn cf_string(data: &[u8]) {
+    let string = String::from_utf8_lossy(data);
+    let cf_string = CFString::new(&string);
+
+    assert_eq!(cf_string.to_string(), string);
+}
+
+#[derive(Debug, Arbitrary)]
+pub struct MultiOutputInput {
+    pub name: String,
+    pub uid: String,
+    pub sub_device_uids: Vec<String>,
+}
+
+/// Builds the dictionary that describes a multi-output device and decodes it
+/// again, checking every value comes back with the type and contents it went
+/// in with, and that asking for the wrong type gets nothing.
+pub fn cf_dictionary(input: &MultiOutputInput) {
+    let sub_device_uids = input
+        .sub_device_uids
+        .iter()
+        .map(|uid| CFString::new(uid))
+        .collect::<Vec<_>>();
+    let description = multi_output_description(&input.name, &input.uid, &sub_device_uids);
+
+    let string = |dictionary: &CFDictionary, key: &[u8]| {
+        dictionary
+            .get::<CFString>(&dictionary_key(key))
+            .map(|value| value.to_string())
+    };
+    let number = |dictionary: &CFDictionary, key: &[u8]| {
+        dictionary
+            .get::<CFNumber>(&dictionary_key(key))
+            .and_then(|value| value.to_i32())
+    };
+
+    assert_eq!(
+        string(&description, kAudioAggregateDeviceNameKey).as_deref(),
+        Some(input.name.as_str())
+    );
+    assert_eq!(
+        string(&description, kAudioAggregateDeviceUIDKey).as_deref(),
+        Some(input.uid.as_str())
+    );
+    assert_eq!(
+        number(&description, kAudioAggregateDeviceIsPrivateKey),
+        Some(1)
+    );
+    assert_eq!(
+        number(&description, kAudioAggregateDeviceIsStackedKey),
+        Some(1)
+    );
+    assert_eq!(
+        string(&description, kAudioAggregateDeviceMasterSubDeviceKey),
+        input.sub_device_uids.first().cloned()
+    );
+
+    let sub_devices = description
+        .get::<CFArray>(&dictionary_key(kAudioAggregateDeviceSubDeviceListKey))
+        .unwrap();
+    assert_eq!(sub_devices.len(), input.sub_device_uids.len());
+    assert_eq!(sub_devices.is_empty(), input.sub_device_uids.is_empty());
+    for (index, uid) in input.sub_device_uids.iter().enumerate() {
+        let sub_device = sub_devices.get::<CFDictionary>(index).unwrap();
+        assert_eq!(
+            string(&sub_device, kAudioSubDeviceUIDKey).as_deref(),
+            Some(uid.as_str())
+        );
+        assert_eq!(
+            number(&sub_device, kAudioSubDeviceDriftCompensationKey),
+            Some((index > 0) as i32)
+        );
+        assert!(sub_devices.get::<CFString>(index).is_none());
+    }
+    assert!(sub_devices
+        .get::<CFDictionary>(input.sub_device_uids.len())
+        .is_none());
+
+    assert!(description
+        .get::<CFNumber>(&dictionary_key(kAudioAggregateDeviceNameKey))
+        .is_none());
+}
+
+struct OwnedBufferList {
+    list: *mut AudioBufferList,
+    layout: alloc::Layout,
+}
+
+impl OwnedBufferList {
+    fn allocate(count: usize) -> Self {
+        let size = mem::size_of::<AudioBufferList>()
+            + count.saturating_sub(1) * mem::size_of::<AudioBuffer>();
+        let layout =
+            alloc::Layout::from_size_align(size, mem::align_of::<AudioBufferList>()).unwrap();
+
+        let list = unsafe { alloc::alloc_zeroed(layout) as *mut AudioBufferList };
+        assert!(!list.is_null());
+
+        unsafe {
+            (*list).mNumberBuffers = count as u32;
+        }
+
+        OwnedBufferList { list, layout }
+    }
+
+    fn layout(channels: &[u32]) -> Self {
+        let mut list = Self::allocate(channels.len());
+        for (buffer, &channels) in list.raw_buffers_mut().iter_mut().zip(channels) {
+            buffer.mNumberChannels = channels;
+        }
+        list
+    }
+
+    /// The data pointers borrow from `buffers`, and byte sizes are clamped to
+    /// the samples actually backing them: the validator can't tell how much
+    /// memory a driver really handed us, only whether it is self-consistent.
+    fn new(buffers: &mut [BufferInput]) -> Self {
+        let mut list = Self::allocate(buffers.len());
+        for (raw, buffer) in list.raw_buffers_mut().iter_mut().zip(buffers) {
+            let available = (buffer.samples.len() * mem::size_of::<f32>()) as u32;
+
+            raw.mNumberChannels = buffer.channels;
+            raw.mDataByteSize = buffer.byte_size.min(available);
+            raw.mData = if buffer.null_data {
+                std::ptr::null_mut()
+            } else {
+                buffer.samples.as_mut_ptr() as *mut c_void
+            };
+        }
+        list
+    }
+
+    fn raw_buffers(&self) -> &[AudioBuffer] {
+        unsafe {
+            std::slice::from_raw_parts(
+                (*self.list).mBuffers.as_ptr(),
+                (*self.list).mNumberBuffers as usize,
+            )
+        }
+    }
+
+    fn raw_buffers_mut(&mut self) -> &mut [AudioBuffer] {
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                (*self.list).mBuffers.as_mut_ptr(),
+                (*self.list).mNumberBuffers as usize,
+            )
+        }
+    }
+
+    fn buffers(&self) -> &[InterleavedBuffer] {
+        let buffers = self.raw_buffers();
+        unsafe {
+            std::slice::from_raw_parts(buffers.as_ptr() as *const InterleavedBuffer, buffers.len())
+        }
+    }
+
+    fn buffers_mut(&mut self) -> &mut [InterleavedBuffer] {
+        let buffers = self.raw_buffers_mut();
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                buffers.as_mut_ptr() as *mut InterleavedBuffer,
+                buffers.len(),
+            )
+        }
+    }
+}
+
+impl AsRef<AudioBufferList> for OwnedBufferList {
+    fn as_ref(&self) -> &AudioBufferList {
+        unsafe { &*self.list }
+    }
+}
+
+impl Drop for OwnedBufferList {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.list as *mut u8, self.layout) }
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::cf::CFError;
+use super::device::CADevice;
+
+struct LiveSession {
+    id: u64,
+    devices: Vec<CADevice>,
+    sample_rate: f64,
+}
+
+/// The devices used by every session in the process, and the rate each
+/// session runs them at.
+static LIVE_SESSIONS: Mutex<Vec<LiveSession>> = Mutex::new(Vec::new());
+
+/// A session's hold on its devices. Several sessions can share a device, with
+/// the HAL mixing their output, but only at the same sample rate: otherwise
+/// each would keep switching the device's rate out from under the other.
+pub struct DeviceClaim {
+    id: u64,
+}
+
+impl DeviceClaim {
+    pub fn new(devices: Vec<CADevice>, sample_rate: f64) -> Result<Self, CFError> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let mut live = LIVE_SESSIONS.lock().unwrap();
+        check_conflicts(&live, id, &devices, sample_rate)?;
+        live.push(LiveSession {
+            id,
+            devices,
+            sample_rate,
+        });
+
+        Ok(DeviceClaim { id })
+    }
+
+    /// Replaces the claimed devices and rate, failing without changing
+    /// anything if another session uses one of the devices at another rate.
+    pub fn update(&self, devices: Vec<CADevice>, sample_rate: f64) -> Result<(), CFError> {
+        let mut live = LIVE_SESSIONS.lock().unwrap();
+        check_conflicts(&live, self.id, &devices, sample_rate)?;
+
+        if let Some(session) = live.iter_mut().find(|session| session.id == self.id) {
+            session.devices = devices;
+            session.sample_rate = sample_rate;
+        }
+
+        Ok(())
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        LIVE_SESSIONS
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|session| session.id == self.id)
+            .map_or(0.0, |session| session.sample_rate)
+    }
+}
+
+impl Drop for DeviceClaim {
+    fn drop(&mut self) {
+        LIVE_SESSIONS
+            .lock()
+            .unwrap()
+            .retain(|session| session.id != self.id);
+    }
+}
+
+fn check_conflicts(
+    live: &[LiveSession],
+    id: u64,
+    devices: &[CADevice],
+    sample_rate: f64,
+) -> Result<(), CFError> {
+    for session in live {
+        if session.id == id || session.sample_rate == sample_rate {
+            continue;
+        }
+
+        if let Some(&device) = devices
+            .iter()
+            .find(|device| session.devices.contains(device))
+        {
+            return Err(CFError::DeviceBusy {
+                device,
+                sample_rate: session.sample_rate,
+            });
+        }
+    }
+
+    Ok(())
+}
@@ -2,7 +2,20 @@ mod aggregate_device;
 mod backend;
 mod cf;
 mod device;
+mod device_watcher;
+mod event_log;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+mod live_sessions;
+mod power;
 mod properties;
 mod session;
+mod tap;
+mod validation;
 
+pub use aggregate_device::MultiOutputDevice;
 pub use backend::CABackend as Backend;
+pub use cf::StartupDiagnostics;
+
+#[cfg(feature = "cf-leak-tracking")]
+pub use cf::{leak_report, LeakReport, LiveObjects};
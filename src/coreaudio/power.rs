@@ -0,0 +1,143 @@
+use std::ffi::c_void;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[allow(non_camel_case_types)]
+type io_object_t = u32;
+#[allow(non_camel_case_types)]
+type io_connect_t = u32;
+type IONotificationPortRef = *mut c_void;
+type IOServiceInterestCallback = unsafe extern "C" fn(
+    refcon: *mut c_void,
+    service: io_object_t,
+    message_type: u32,
+    message_argument: *mut c_void,
+);
+
+const IO_MESSAGE_CAN_SYSTEM_SLEEP: u32 = 0xe000_0270;
+const IO_MESSAGE_SYSTEM_WILL_SLEEP: u32 = 0xe000_0280;
+const IO_MESSAGE_SYSTEM_HAS_POWERED_ON: u32 = 0xe000_0300;
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IORegisterForSystemPower(
+        refcon: *mut c_void,
+        the_port_ref: *mut IONotificationPortRef,
+        callback: IOServiceInterestCallback,
+        notifier: *mut io_object_t,
+    ) -> io_connect_t;
+    fn IODeregisterForSystemPower(notifier: *mut io_object_t) -> i32;
+    fn IOAllowPowerChange(kernel_port: io_connect_t, notification_id: isize) -> i32;
+    fn IONotificationPortSetDispatchQueue(notify: IONotificationPortRef, queue: *mut c_void);
+    fn IONotificationPortDestroy(notify: IONotificationPortRef);
+    fn IOServiceClose(connect: io_connect_t) -> i32;
+}
+
+extern "C" {
+    fn dispatch_queue_create(label: *const c_char, attr: *mut c_void) -> *mut c_void;
+    fn dispatch_sync_f(queue: *mut c_void, context: *mut c_void, work: extern "C" fn(*mut c_void));
+    fn dispatch_release(object: *mut c_void);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerEvent {
+    WillSleep,
+    DidWake,
+}
+
+struct PowerState {
+    root_port: AtomicU32,
+    callback: Box<dyn Fn(PowerEvent) + Send + Sync>,
+}
+
+/// Calls a function when the system is about to sleep, before letting it do
+/// so, and again once it has woken up. Notifications arrive on a private
+/// dispatch queue, so this works without a run loop.
+pub struct PowerWatcher {
+    state: Box<PowerState>,
+    port: IONotificationPortRef,
+    notifier: io_object_t,
+    queue: *mut c_void,
+}
+
+unsafe impl Send for PowerWatcher {}
+
+impl PowerWatcher {
+    pub fn new(callback: impl Fn(PowerEvent) + Send + Sync + 'static) -> Option<Self> {
+        let state = Box::new(PowerState {
+            root_port: AtomicU32::new(0),
+            callback: Box::new(callback),
+        });
+
+        let mut port = ptr::null_mut();
+        let mut notifier = 0;
+        unsafe {
+            let root_port = IORegisterForSystemPower(
+                &*state as *const PowerState as *mut c_void,
+                &mut port,
+                power_callback,
+                &mut notifier,
+            );
+            if root_port == 0 {
+                return None;
+            }
+            state.root_port.store(root_port, Ordering::Release);
+
+            let queue = dispatch_queue_create(
+                b"render_callback power\0".as_ptr() as *const c_char,
+                ptr::null_mut(),
+            );
+            IONotificationPortSetDispatchQueue(port, queue);
+
+            Some(PowerWatcher {
+                state,
+                port,
+                notifier,
+                queue,
+            })
+        }
+    }
+}
+
+impl Drop for PowerWatcher {
+    fn drop(&mut self) {
+        extern "C" fn flush(_context: *mut c_void) {}
+
+        unsafe {
+            IODeregisterForSystemPower(&mut self.notifier);
+            // Wait out any notification already being delivered, since it
+            // points at our state.
+            dispatch_sync_f(self.queue, ptr::null_mut(), flush);
+
+            IOServiceClose(self.state.root_port.load(Ordering::Acquire));
+            IONotificationPortDestroy(self.port);
+            dispatch_release(self.queue);
+        }
+    }
+}
+
+unsafe extern "C" fn power_callback(
+    refcon: *mut c_void,
+    _service: io_object_t,
+    message_type: u32,
+    message_argument: *mut c_void,
+) {
+    let state = match (refcon as *const PowerState).as_ref() {
+        Some(state) => state,
+        None => return,
+    };
+    let root_port = state.root_port.load(Ordering::Acquire);
+
+    match message_type {
+        IO_MESSAGE_CAN_SYSTEM_SLEEP => {
+            IOAllowPowerChange(root_port, message_argument as isize);
+        }
+        IO_MESSAGE_SYSTEM_WILL_SLEEP => {
+            (state.callback)(PowerEvent::WillSleep);
+            IOAllowPowerChange(root_port, message_argument as isize);
+        }
+        IO_MESSAGE_SYSTEM_HAS_POWERED_ON => (state.callback)(PowerEvent::DidWake),
+        _ => {}
+    }
+}
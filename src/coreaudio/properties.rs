@@ -1,17 +1,21 @@
 use std::ffi::c_void;
 use std::{alloc, mem, ptr};
 
+use crate::direction::Direction;
+
 use super::cf::{check_os_status, CFArray, CFDictionary, CFError, CFString};
 use super::device::CADevice;
 
 use coreaudio_sys::{
-    AudioDeviceID, AudioObjectGetPropertyData, AudioObjectGetPropertyDataSize, AudioObjectID,
-    AudioObjectPropertyAddress, AudioObjectPropertyElement, AudioObjectPropertyScope,
-    AudioObjectPropertySelector, AudioObjectSetPropertyData, AudioValueTranslation,
+    AudioDeviceID, AudioObjectAddPropertyListener, AudioObjectGetPropertyData,
+    AudioObjectGetPropertyDataSize, AudioObjectID, AudioObjectPropertyAddress,
+    AudioObjectPropertyElement, AudioObjectPropertyListenerProc, AudioObjectPropertyScope,
+    AudioObjectPropertySelector, AudioObjectRemovePropertyListener, AudioObjectSetPropertyData,
+    AudioStreamBasicDescription, AudioValueRange, AudioValueTranslation, CFRunLoopRef,
 };
 
 pub trait Element {
-    fn element() -> AudioObjectPropertyElement;
+    fn element(&self) -> AudioObjectPropertyElement;
 }
 
 pub trait Scope {
@@ -53,7 +57,7 @@ pub trait Selector {
 }
 
 pub unsafe fn get<El: Element, Sc: Scope, Se: Selector>(
-    _element: El,
+    element: El,
     _scope: Sc,
     _selector: Se,
     obj: AudioObjectID,
@@ -64,7 +68,7 @@ where
     Se::Type::get(
         obj,
         AudioObjectPropertyAddress {
-            mElement: El::element(),
+            mElement: element.element(),
             mScope: Sc::scope(),
             mSelector: Se::selector(),
         },
@@ -72,7 +76,7 @@ where
 }
 
 pub unsafe fn get_qualified<El: Element, Sc: Scope, Se: Selector, TInput>(
-    _element: El,
+    element: El,
     _scope: Sc,
     _selector: Se,
     qualifier: &TInput,
@@ -84,7 +88,7 @@ where
     Se::Type::get_qualified(
         obj,
         AudioObjectPropertyAddress {
-            mElement: El::element(),
+            mElement: element.element(),
             mScope: Sc::scope(),
             mSelector: Se::selector(),
         },
@@ -93,7 +97,7 @@ where
 }
 
 pub unsafe fn set<El: Element, Sc: Scope, Se: Selector>(
-    _element: El,
+    element: El,
     _scope: Sc,
     _selector: Se,
     obj: AudioObjectID,
@@ -105,7 +109,7 @@ where
     Se::Type::set(
         obj,
         AudioObjectPropertyAddress {
-            mElement: El::element(),
+            mElement: element.element(),
             mScope: Sc::scope(),
             mSelector: Se::selector(),
         },
@@ -114,7 +118,7 @@ where
 }
 
 pub unsafe fn translate<El: Element, Sc: Scope, Se: Selector>(
-    _element: El,
+    element: El,
     _scope: Sc,
     _selector: Se,
     obj: AudioObjectID,
@@ -126,7 +130,7 @@ where
     Se::Type::translate(
         obj,
         AudioObjectPropertyAddress {
-            mElement: El::element(),
+            mElement: element.element(),
             mScope: Sc::scope(),
             mSelector: Se::selector(),
         },
@@ -134,6 +138,79 @@ where
     )
 }
 
+pub unsafe fn add_listener<El: Element, Sc: Scope, Se: Selector>(
+    element: El,
+    _scope: Sc,
+    _selector: Se,
+    obj: AudioObjectID,
+    listener: AudioObjectPropertyListenerProc,
+    client_data: *mut c_void,
+) -> Result<(), CFError> {
+    check_os_status(AudioObjectAddPropertyListener(
+        obj,
+        &AudioObjectPropertyAddress {
+            mElement: element.element(),
+            mScope: Sc::scope(),
+            mSelector: Se::selector(),
+        },
+        listener,
+        client_data,
+    ))
+}
+
+pub unsafe fn remove_listener<El: Element, Sc: Scope, Se: Selector>(
+    element: El,
+    _scope: Sc,
+    _selector: Se,
+    obj: AudioObjectID,
+    listener: AudioObjectPropertyListenerProc,
+    client_data: *mut c_void,
+) -> Result<(), CFError> {
+    check_os_status(AudioObjectRemovePropertyListener(
+        obj,
+        &AudioObjectPropertyAddress {
+            mElement: element.element(),
+            mScope: Sc::scope(),
+            mSelector: Se::selector(),
+        },
+        listener,
+        client_data,
+    ))
+}
+
+/// Gets a property in the input or output scope.
+pub unsafe fn get_in<El: Element, Se: Selector>(
+    element: El,
+    direction: Direction,
+    selector: Se,
+    obj: AudioObjectID,
+) -> Result<Se::Type, CFError>
+where
+    Se::Type: GettablePropertyType,
+{
+    match direction {
+        Direction::Input => get(element, scope::Input, selector, obj),
+        Direction::Output => get(element, scope::Output, selector, obj),
+    }
+}
+
+/// Sets a property in the input or output scope.
+pub unsafe fn set_in<El: Element, Se: Selector>(
+    element: El,
+    direction: Direction,
+    selector: Se,
+    obj: AudioObjectID,
+    value: &Se::Type,
+) -> Result<(), CFError>
+where
+    Se::Type: SettablePropertyType,
+{
+    match direction {
+        Direction::Input => set(element, scope::Input, selector, obj, value),
+        Direction::Output => set(element, scope::Output, selector, obj, value),
+    }
+}
+
 pub mod element {
     use coreaudio_sys::*;
 
@@ -144,10 +221,31 @@ pub mod element {
     pub struct Master;
 
     impl Element for Master {
-        fn element() -> AudioObjectPropertyElement {
+        fn element(&self) -> AudioObjectPropertyElement {
             kAudioObjectPropertyElementMaster
         }
     }
+
+    /// The element for a single channel of a device, counting from zero. The
+    /// HAL counts channels from one, with zero being the master element.
+    pub struct Channel(pub usize);
+
+    impl Element for Channel {
+        fn element(&self) -> AudioObjectPropertyElement {
+            self.0 as AudioObjectPropertyElement + 1
+        }
+    }
+
+    /// A single channel counting from zero, or the master element for None.
+    pub struct OptionalChannel(pub Option<usize>);
+
+    impl Element for OptionalChannel {
+        fn element(&self) -> AudioObjectPropertyElement {
+            self.0.map_or(kAudioObjectPropertyElementMaster, |channel| {
+                channel as AudioObjectPropertyElement + 1
+            })
+        }
+    }
 }
 
 pub mod scope {
@@ -242,6 +340,18 @@ pub mod selector {
         }
     }
 
+    /// The CFRunLoopRef the HAL is to use for its notifications. If NULL, the
+    /// HAL will create its own thread to handle notifications instead of
+    /// using the process's main run loop.
+    pub struct HardwarePropertyRunLoop;
+    impl Selector for HardwarePropertyRunLoop {
+        type Type = CFRunLoopRef;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioHardwarePropertyRunLoop
+        }
+    }
+
     /// A CFArray of CFStrings that contain the UIDs of all the devices, active
     /// or inactive, contained in the AudioAggregateDevice. The order of the
     /// items in the array is significant and is used to determine the order of
@@ -256,6 +366,76 @@ pub mod selector {
         }
     }
 
+    /// A CFString that contains the UID for the AudioDevice that is currently
+    /// serving as the master time base of the aggregate device.
+    pub struct AggregateDevicePropertyMasterSubDevice;
+    impl Selector for AggregateDevicePropertyMasterSubDevice {
+        type Type = CFString;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioAggregateDevicePropertyMasterSubDevice
+        }
+    }
+
+    /// A CFArray of CFStrings that contain the UIDs of all the taps contained
+    /// in the AudioAggregateDevice. The order of the items in the array is
+    /// significant and is used to determine the order of the streams of the
+    /// AudioAggregateDevice.
+    pub struct AggregateDevicePropertyTapList;
+    impl Selector for AggregateDevicePropertyTapList {
+        type Type = CFArray;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioAggregateDevicePropertyTapList
+        }
+    }
+
+    /// This property fetches the AudioObjectID that corresponds to the process
+    /// object that has the given PID. The PID is passed in via the qualifier
+    /// as a pid_t. This property will return kAudioObjectUnknown if the given
+    /// PID doesn't correspond to any process object.
+    pub struct HardwarePropertyTranslatePIDToProcessObject;
+    impl Selector for HardwarePropertyTranslatePIDToProcessObject {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioHardwarePropertyTranslatePIDToProcessObject
+        }
+    }
+
+    /// An array of AudioObjectIDs that represent the process objects of all
+    /// the clients currently using the system object.
+    pub struct HardwarePropertyProcessObjectList;
+    impl Selector for HardwarePropertyProcessObjectList {
+        type Type = Vec<u32>;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioHardwarePropertyProcessObjectList
+        }
+    }
+
+    /// A CFString that contains the bundle ID of the process. The caller is
+    /// responsible for releasing the returned CFObject.
+    pub struct ProcessPropertyBundleID;
+    impl Selector for ProcessPropertyBundleID {
+        type Type = CFString;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioProcessPropertyBundleID
+        }
+    }
+
+    /// A CFString that contains a persistent identifier for the tap. The
+    /// caller is responsible for releasing the returned CFObject.
+    pub struct TapPropertyUID;
+    impl Selector for TapPropertyUID {
+        type Type = CFString;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioTapPropertyUID
+        }
+    }
+
     /// This property is used to tell a plug-in to destroy an
     /// AudioAggregateDevice. Like kAudioPlugInCreateAggregateDevice, this
     /// property is read only. The value of the property is the AudioObjectID of
@@ -336,6 +516,17 @@ pub mod selector {
         }
     }
 
+    /// An array of AudioValueRange structs that indicates the valid ranges
+    /// for the nominal sample rate of the AudioDevice.
+    pub struct DevicePropertyAvailableNominalSampleRates;
+    impl Selector for DevicePropertyAvailableNominalSampleRates {
+        type Type = Vec<AudioValueRange>;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyAvailableNominalSampleRates
+        }
+    }
+
     /// A Float64 that indicates the current actual sample rate of the
     /// AudioDevice as measured by its time stamps.    
     pub struct DevicePropertyActualSampleRate;
@@ -346,6 +537,309 @@ pub mod selector {
             kAudioDevicePropertyActualSampleRate
         }
     }
+
+    /// A UInt32 whose value indicates the number of frames in the IO buffers.
+    pub struct DevicePropertyBufferFrameSize;
+    impl Selector for DevicePropertyBufferFrameSize {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyBufferFrameSize
+        }
+    }
+
+    /// An AudioValueRange indicating the minimum and maximum values, inclusive,
+    /// for kAudioDevicePropertyBufferFrameSize.
+    pub struct DevicePropertyBufferFrameSizeRange;
+    impl Selector for DevicePropertyBufferFrameSizeRange {
+        type Type = AudioValueRange;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyBufferFrameSizeRange
+        }
+    }
+
+    /// A UInt32 containing the number of frames of latency in the AudioDevice.
+    /// Note that stream latency is not included.
+    pub struct DevicePropertyLatency;
+    impl Selector for DevicePropertyLatency {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyLatency
+        }
+    }
+
+    /// A UInt32 whose value indicates the number for frames in ahead (for
+    /// output) or behind (for input) the current hardware position that is safe
+    /// to do IO.
+    pub struct DevicePropertySafetyOffset;
+    impl Selector for DevicePropertySafetyOffset {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertySafetyOffset
+        }
+    }
+
+    /// An array of AudioStreamIDs that represent the AudioStreams of the
+    /// AudioDevice. Note that if a notification is received for this property,
+    /// any cached AudioStreamIDs for the device become invalid and need to be
+    /// re-fetched.
+    pub struct DevicePropertyStreams;
+    impl Selector for DevicePropertyStreams {
+        type Type = Vec<u32>;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyStreams
+        }
+    }
+
+    /// An AudioStreamBasicDescription that describes the current data format
+    /// for the AudioStream. The physical format refers to the data format in
+    /// which the hardware for the owning AudioDevice performs its IO
+    /// transactions.
+    pub struct StreamPropertyPhysicalFormat;
+    impl Selector for StreamPropertyPhysicalFormat {
+        type Type = AudioStreamBasicDescription;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioStreamPropertyPhysicalFormat
+        }
+    }
+
+    /// A UInt32 containing the number of frames of latency in the AudioStream.
+    /// Note that the owning AudioDevice may have additional latency.
+    pub struct StreamPropertyLatency;
+    impl Selector for StreamPropertyLatency {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioStreamPropertyLatency
+        }
+    }
+
+    /// A UInt32 where a value of 1 means the device is ready and available and
+    /// 0 means the device is unusable and will most likely go away shortly.
+    pub struct DevicePropertyDeviceIsAlive;
+    impl Selector for DevicePropertyDeviceIsAlive {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyDeviceIsAlive
+        }
+    }
+
+    /// A UInt32 where a value of 0 means the device is not running and any
+    /// other value means it is running.
+    pub struct DevicePropertyDeviceIsRunning;
+    impl Selector for DevicePropertyDeviceIsRunning {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyDeviceIsRunning
+        }
+    }
+
+    /// A UInt32 where 1 means that the AudioDevice is running in at least one
+    /// process on the system and 0 means that it isn't running at all.
+    pub struct DevicePropertyDeviceIsRunningSomewhere;
+    impl Selector for DevicePropertyDeviceIsRunningSomewhere {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyDeviceIsRunningSomewhere
+        }
+    }
+
+    /// A pid_t indicating the process that currently owns exclusive access to
+    /// the AudioDevice or a value of -1 indicating that the device is
+    /// currently available to all processes.
+    pub struct DevicePropertyHogMode;
+    impl Selector for DevicePropertyHogMode {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyHogMode
+        }
+    }
+
+    /// A UInt32 where the value has no meaning. This property exists so that
+    /// clients can listen to it and be told when the IO thread for the
+    /// AudioDevice has overloaded.
+    pub struct DeviceProcessorOverload;
+    impl Selector for DeviceProcessorOverload {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDeviceProcessorOverload
+        }
+    }
+
+    /// A pid_t indicating the process ID associated with the process.
+    pub struct ProcessPropertyPID;
+    impl Selector for ProcessPropertyPID {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioProcessPropertyPID
+        }
+    }
+
+    /// An array of AudioObjectIDs that represent the devices currently used
+    /// by the process for input or output, depending on the scope.
+    pub struct ProcessPropertyDevices;
+    impl Selector for ProcessPropertyDevices {
+        type Type = Vec<u32>;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioProcessPropertyDevices
+        }
+    }
+
+    /// A CFString that contains a human readable name for the given element
+    /// in the given scope.
+    pub struct ObjectPropertyElementName;
+    impl Selector for ObjectPropertyElementName {
+        type Type = CFString;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioObjectPropertyElementName
+        }
+    }
+
+    /// An array of UInt32s whose values are the item IDs for the currently
+    /// selected data sources.
+    pub struct DevicePropertyDataSource;
+    impl Selector for DevicePropertyDataSource {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyDataSource
+        }
+    }
+
+    /// An array of UInt32s that are represent all the IDs of all the data
+    /// sources currently available.
+    pub struct DevicePropertyDataSources;
+    impl Selector for DevicePropertyDataSources {
+        type Type = Vec<u32>;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyDataSources
+        }
+    }
+
+    /// This property translates the given data source item ID into a human
+    /// readable name using an AudioValueTranslation structure. The input data
+    /// is the UInt32 containing data source item ID and the output data is the
+    /// CFString. The caller is responsible for releasing the returned
+    /// CFObject.
+    pub struct DevicePropertyDataSourceNameForIDCFString;
+    impl Selector for DevicePropertyDataSourceNameForIDCFString {
+        type Type = AudioValueTranslation;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyDataSourceNameForIDCFString
+        }
+    }
+
+    /// A UInt32 whose value is the item ID for the currently selected clock
+    /// source.
+    pub struct DevicePropertyClockSource;
+    impl Selector for DevicePropertyClockSource {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyClockSource
+        }
+    }
+
+    /// An array of UInt32s that are represent all the IDs of all the clock
+    /// sources currently available.
+    pub struct DevicePropertyClockSources;
+    impl Selector for DevicePropertyClockSources {
+        type Type = Vec<u32>;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyClockSources
+        }
+    }
+
+    /// This property translates the given clock source item ID into a human
+    /// readable name using an AudioValueTranslation structure. The input data
+    /// is the UInt32 containing clock source item ID and the output data is
+    /// the CFString. The caller is responsible for releasing the returned
+    /// CFObject.
+    pub struct DevicePropertyClockSourceNameForIDCFString;
+    impl Selector for DevicePropertyClockSourceNameForIDCFString {
+        type Type = AudioValueTranslation;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyClockSourceNameForIDCFString
+        }
+    }
+
+    /// A Float32 that represents the value of the volume control. The range
+    /// is between 0.0 and 1.0 (inclusive). This scale has a curve that is
+    /// appropriate for a user interface.
+    pub struct DevicePropertyVolumeScalar;
+    impl Selector for DevicePropertyVolumeScalar {
+        type Type = f32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyVolumeScalar
+        }
+    }
+
+    /// A UInt32 where a value of 1 means that mute is enabled making that
+    /// element inaudible. The property is implemented by an
+    /// AudioControl object.
+    pub struct DevicePropertyMute;
+    impl Selector for DevicePropertyMute {
+        type Type = u32;
+
+        fn selector() -> AudioObjectPropertySelector {
+            kAudioDevicePropertyMute
+        }
+    }
+}
+
+impl GettablePropertyType for f32 {
+    unsafe fn get(obj: AudioObjectID, addr: AudioObjectPropertyAddress) -> Result<Self, CFError> {
+        let mut value = mem::MaybeUninit::<f32>::uninit();
+        let mut size = mem::size_of::<Self>() as u32;
+
+        check_os_status(AudioObjectGetPropertyData(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            &mut size,
+            value.as_mut_ptr() as *mut c_void,
+        ))?;
+
+        Ok(value.assume_init())
+    }
+}
+
+impl SettablePropertyType for f32 {
+    unsafe fn set(
+        obj: AudioObjectID,
+        addr: AudioObjectPropertyAddress,
+        value: &Self,
+    ) -> Result<(), CFError> {
+        let size = mem::size_of::<Self>() as u32;
+
+        check_os_status(AudioObjectSetPropertyData(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            size,
+            value as *const Self as *const c_void,
+        ))
+    }
 }
 
 impl GettablePropertyType for f64 {
@@ -385,6 +879,160 @@ impl SettablePropertyType for f64 {
     }
 }
 
+impl GettablePropertyType for u32 {
+    unsafe fn get(obj: AudioObjectID, addr: AudioObjectPropertyAddress) -> Result<Self, CFError> {
+        let mut value = mem::MaybeUninit::<u32>::uninit();
+        let mut size = mem::size_of::<Self>() as u32;
+
+        check_os_status(AudioObjectGetPropertyData(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            &mut size,
+            value.as_mut_ptr() as *mut c_void,
+        ))?;
+
+        Ok(value.assume_init())
+    }
+}
+
+impl SettablePropertyType for u32 {
+    unsafe fn set(
+        obj: AudioObjectID,
+        addr: AudioObjectPropertyAddress,
+        value: &Self,
+    ) -> Result<(), CFError> {
+        let size = mem::size_of::<Self>() as u32;
+
+        check_os_status(AudioObjectSetPropertyData(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            size,
+            value as *const Self as *const c_void,
+        ))
+    }
+}
+
+impl GettablePropertyType for AudioValueRange {
+    unsafe fn get(obj: AudioObjectID, addr: AudioObjectPropertyAddress) -> Result<Self, CFError> {
+        let mut value = mem::MaybeUninit::<AudioValueRange>::uninit();
+        let mut size = mem::size_of::<Self>() as u32;
+
+        check_os_status(AudioObjectGetPropertyData(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            &mut size,
+            value.as_mut_ptr() as *mut c_void,
+        ))?;
+
+        Ok(value.assume_init())
+    }
+}
+
+impl GettablePropertyType for AudioStreamBasicDescription {
+    unsafe fn get(obj: AudioObjectID, addr: AudioObjectPropertyAddress) -> Result<Self, CFError> {
+        let mut value = mem::MaybeUninit::<AudioStreamBasicDescription>::uninit();
+        let mut size = mem::size_of::<Self>() as u32;
+
+        check_os_status(AudioObjectGetPropertyData(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            &mut size,
+            value.as_mut_ptr() as *mut c_void,
+        ))?;
+
+        Ok(value.assume_init())
+    }
+}
+
+impl SettablePropertyType for AudioStreamBasicDescription {
+    unsafe fn set(
+        obj: AudioObjectID,
+        addr: AudioObjectPropertyAddress,
+        value: &Self,
+    ) -> Result<(), CFError> {
+        let size = mem::size_of::<Self>() as u32;
+
+        check_os_status(AudioObjectSetPropertyData(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            size,
+            value as *const Self as *const c_void,
+        ))
+    }
+}
+
+impl GettablePropertyType for Vec<u32> {
+    unsafe fn get(obj: AudioObjectID, addr: AudioObjectPropertyAddress) -> Result<Self, CFError> {
+        let mut size = 0;
+        check_os_status(AudioObjectGetPropertyDataSize(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            &mut size,
+        ))?;
+
+        let mut values = vec![0u32; size as usize / mem::size_of::<u32>()];
+
+        check_os_status(AudioObjectGetPropertyData(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            &mut size,
+            values.as_mut_ptr() as *mut c_void,
+        ))?;
+
+        values.truncate(size as usize / mem::size_of::<u32>());
+
+        Ok(values)
+    }
+}
+
+impl GettablePropertyType for Vec<AudioValueRange> {
+    unsafe fn get(obj: AudioObjectID, addr: AudioObjectPropertyAddress) -> Result<Self, CFError> {
+        let mut size = 0;
+        check_os_status(AudioObjectGetPropertyDataSize(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            &mut size,
+        ))?;
+
+        let mut values = vec![
+            AudioValueRange {
+                mMinimum: 0.0,
+                mMaximum: 0.0,
+            };
+            size as usize / mem::size_of::<AudioValueRange>()
+        ];
+
+        check_os_status(AudioObjectGetPropertyData(
+            obj,
+            &addr,
+            0,
+            ptr::null(),
+            &mut size,
+            values.as_mut_ptr() as *mut c_void,
+        ))?;
+
+        values.truncate(size as usize / mem::size_of::<AudioValueRange>());
+
+        Ok(values)
+    }
+}
+
 impl GettablePropertyType for Vec<CADevice> {
     unsafe fn get(obj: AudioObjectID, addr: AudioObjectPropertyAddress) -> Result<Self, CFError> {
         let mut devices_size = 0;
@@ -471,6 +1119,28 @@ impl QualifiedGettablePropertyType<CFDictionary> for CADevice {
     }
 }
 
+impl QualifiedGettablePropertyType<i32> for u32 {
+    unsafe fn get_qualified(
+        obj: AudioObjectID,
+        addr: AudioObjectPropertyAddress,
+        qualifier: &i32,
+    ) -> Result<Self, CFError> {
+        let mut value = mem::MaybeUninit::<u32>::uninit();
+        let mut size = mem::size_of::<Self>() as u32;
+
+        check_os_status(AudioObjectGetPropertyData(
+            obj,
+            &addr,
+            mem::size_of::<i32>() as u32,
+            qualifier as *const i32 as *const c_void,
+            &mut size,
+            value.as_mut_ptr() as *mut c_void,
+        ))?;
+
+        Ok(value.assume_init())
+    }
+}
+
 impl GettablePropertyType for CFArray {
     unsafe fn get(obj: AudioObjectID, addr: AudioObjectPropertyAddress) -> Result<Self, CFError> {
         use coreaudio_sys::CFArrayRef;
@@ -506,6 +1176,40 @@ impl SettablePropertyType for CFArray {
     }
 }
 
+impl SettablePropertyType for CFString {
+    unsafe fn set(
+        obj: AudioObjectID,
+        addr: AudioObjectPropertyAddress,
+        value: &Self,
+    ) -> Result<(), CFError> {
+        check_os_status(AudioObjectSetPropertyData(
+            obj,
+            &addr,
+            0,
+            std::ptr::null(),
+            std::mem::size_of::<Self>() as u32,
+            (&value.as_void_ptr() as *const _) as *mut c_void,
+        ))
+    }
+}
+
+impl SettablePropertyType for CFRunLoopRef {
+    unsafe fn set(
+        obj: AudioObjectID,
+        addr: AudioObjectPropertyAddress,
+        value: &Self,
+    ) -> Result<(), CFError> {
+        check_os_status(AudioObjectSetPropertyData(
+            obj,
+            &addr,
+            0,
+            std::ptr::null(),
+            std::mem::size_of::<Self>() as u32,
+            value as *const Self as *const c_void,
+        ))
+    }
+}
+
 impl TranslatablePropertyType for AudioValueTranslation {
     unsafe fn translate(
         obj: AudioObjectID,
@@ -1,23 +1,220 @@
+use std::cell::Cell;
 use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use coreaudio_sys::{
-    noErr, AudioBuffer, AudioBufferList, AudioDeviceCreateIOProcID, AudioDeviceDestroyIOProcID,
-    AudioDeviceID, AudioDeviceIOProcID, AudioDeviceStart, AudioDeviceStop, AudioTimeStamp,
-    OSStatus,
+    kAudioFormatFlagIsFloat, kAudioFormatFlagIsPacked, kAudioFormatFlagIsSignedInteger,
+    kAudioFormatLinearPCM, kAudioTimeStampHostTimeValid, kAudioTimeStampSampleTimeValid, noErr,
+    AudioBuffer, AudioBufferList, AudioDeviceCreateIOProcID, AudioDeviceDestroyIOProcID,
+    AudioDeviceID, AudioDeviceIOProcID, AudioDeviceStart, AudioDeviceStartAtTime, AudioDeviceStop,
+    AudioObjectID, AudioObjectPropertyAddress, AudioStreamBasicDescription, AudioStreamID,
+    AudioTimeStamp, OSStatus,
 };
 
-use crate::traits::{AudioBuffers, Device, Session};
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::{OutputPolicy, PhysicalFormat};
+use crate::context::{AudioTimestamp, CycleTimes, RenderContext};
+use crate::deadline::DeadlineHistogram;
+use crate::direction::Direction;
+use crate::dropout::DropoutStats;
+use crate::engine::{zero_buffers, RenderEngine};
+use crate::events::{Events, SessionEvent, StopCause};
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+use crate::preroll::{delay_output, OutputDelay};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::routing::ResolvedRouting;
+use crate::scratch::Scratch;
+use crate::traits::{AudioBuffers, Backend, Device, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
 
 use super::aggregate_device::AggregateDevice;
 use super::backend::CABackend;
-use super::cf::{check_os_status, CFError};
+use super::cf::{check_os_status, CFError, CFMutableArray, StartupDiagnostics};
 use super::device::CADevice;
+use super::device_watcher::DeviceListeners;
+use super::live_sessions::DeviceClaim;
+use super::power::{PowerEvent, PowerWatcher};
+use super::properties::{self, element, scope, selector};
+use super::tap::ProcessTap;
+use super::validation::{BufferListError, BufferListValidator};
 
-pub type RenderCallback = dyn FnMut(&[InterleavedBuffer], &mut [InterleavedBuffer]) + Send;
+const VALIDATE_BUFFER_LISTS: bool = cfg!(any(debug_assertions, feature = "validate-buffers"));
+
+/// How long each buffer size is tried out for when tuning for low latency.
+const LATENCY_TRIAL_PERIOD: Duration = Duration::from_secs(1);
+
+/// Buffers smaller than this are never tried, whatever the device claims.
+const MIN_TUNED_BUFFER_SIZE: usize = 16;
+
+/// How long a running session can go without a callback before the watchdog
+/// reports it as stalled. Well above the longest buffer any device uses.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the watchdog checks on the IOProc.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(250);
+
+pub type RenderCallback =
+    dyn FnMut(&RenderContext<'_>, &[InterleavedBuffer], &mut [InterleavedBuffer]) + Send;
 
 pub struct CASession {
-    device: AggregateDevice,
-    callback: Option<(AudioDeviceIOProcID, Box<RenderCallback>)>,
+    /// The device the IOProc runs on. Stays the same when the devices making
+    /// it up change.
+    io_device: CADevice,
+    devices: Arc<Mutex<SessionDevices>>,
+    proc_id: AudioDeviceIOProcID,
+    shared: Arc<SharedState>,
+    /// Physical formats of streams changed by the session, to be restored on
+    /// teardown.
+    saved_formats: Vec<(AudioStreamID, AudioStreamBasicDescription)>,
+    /// Declared after `devices` so that the aggregate device is torn down
+    /// before the taps it contains.
+    taps: Vec<ProcessTap>,
+    power: Option<PowerWatcher>,
+    watchdog: Option<Watchdog>,
+    follower: Option<DefaultDeviceFollower>,
+    /// Released last, once the session no longer touches its devices.
+    claim: Arc<DeviceClaim>,
+}
+
+/// The devices making up a session, shared with the thread following the
+/// default devices.
+struct SessionDevices {
+    aggregate: AggregateDevice,
+    watched: Vec<CADevice>,
+}
+
+/// State shared between the control thread and the IOProc. The IOProc only
+/// ever gets a shared reference to this, never to the `CASession` itself.
+struct SharedState {
+    engine: RenderEngine<CABackend>,
+    validator: BufferListValidator,
+    interrupted: AtomicBool,
+    auto_resume: AtomicBool,
+    restart_on_wake: AtomicBool,
+    /// Set by the IOProc on every cycle, and cleared whenever the session
+    /// stops on purpose. A stop while it's set is unexpected.
+    io_running: AtomicBool,
+    /// Set while the IOProc is stopped for the system sleeping, until it's
+    /// restarted after waking.
+    asleep: AtomicBool,
+    /// Set by the hog mode listener for the watchdog to restart the IOProc,
+    /// since the listener runs on a HAL notification thread, which must not
+    /// wait for `io_proc`: whoever holds it may be waiting for the HAL.
+    resume_requested: AtomicBool,
+    /// The running IOProc, for restarting it after the system or another
+    /// process stopped it. Held while doing so, so it can't be restarted
+    /// after the session was stopped on purpose.
+    io_proc: Mutex<Option<(AudioDeviceID, AudioDeviceIOProcID)>>,
+}
+
+impl SharedState {
+    /// Reports the IOProc as stopped, unless it's already been reported or
+    /// was stopped on purpose.
+    fn report_stopped(&self, cause: StopCause) {
+        if self.io_running.swap(false, Ordering::AcqRel) {
+            self.engine
+                .events
+                .push(SessionEvent::StoppedUnexpectedly { cause });
+        }
+    }
+
+    fn reset_timeline(&self) {
+        self.engine.reset_timeline();
+    }
+
+    /// Starts the IOProc again after something outside of the session
+    /// stopped it, reporting it if the device refuses. Returns whether it
+    /// started.
+    fn restart(&self, device: AudioDeviceID, proc_id: AudioDeviceIOProcID) -> bool {
+        self.reset_timeline();
+        if check_os_status(unsafe { AudioDeviceStart(device, proc_id) }).is_err() {
+            self.engine.events.push(SessionEvent::StoppedUnexpectedly {
+                cause: StopCause::RestartFailed,
+            });
+            return false;
+        }
+
+        true
+    }
+
+    /// Restarts the IOProc after an interruption ended, unless the session
+    /// has been stopped since, and then reports the interruption as over.
+    /// Must not be called on a HAL notification thread.
+    fn resume(&self) {
+        let io_proc = self.io_proc.lock().unwrap();
+        if let Some((device, proc_id)) = *io_proc {
+            self.restart(device, proc_id);
+        }
+        self.engine.events.push(SessionEvent::Resumed);
+    }
+
+    fn refresh_stream_layout(&self, device: CADevice) -> Result<(), CFError> {
+        let (input, output) = unsafe {
+            (
+                properties::get(
+                    element::Master,
+                    scope::Input,
+                    selector::DevicePropertyStreamConfiguration,
+                    device.id(),
+                )?,
+                properties::get(
+                    element::Master,
+                    scope::Output,
+                    selector::DevicePropertyStreamConfiguration,
+                    device.id(),
+                )?,
+            )
+        };
+
+        self.validator.set_input_layout(&input);
+        self.validator.set_output_layout(&output);
+
+        self.resize_scratch(device)
+    }
+
+    /// Reallocates the scratch arena for the current buffer size and layout.
+    fn resize_scratch(&self, device: CADevice) -> Result<(), CFError> {
+        let (input, output) = buffer_channels(device)?;
+        let channels = input.iter().sum::<usize>().max(output.iter().sum());
+        self.engine
+            .resize_scratch(buffer_frame_size(device)?, channels);
+
+        Ok(())
+    }
+
+    /// Stops the IOProc before the system sleeps, since it often doesn't
+    /// come back by itself afterwards.
+    fn handle_power_event(&self, event: PowerEvent) {
+        let io_proc = self.io_proc.lock().unwrap();
+
+        match event {
+            PowerEvent::WillSleep => {
+                self.io_running.store(false, Ordering::Release);
+                if let Some((device, proc_id)) = *io_proc {
+                    self.asleep.store(true, Ordering::Release);
+                    unsafe { AudioDeviceStop(device, proc_id) };
+                }
+                self.engine.events.push(SessionEvent::SystemWillSleep);
+            }
+            PowerEvent::DidWake => {
+                self.engine.events.push(SessionEvent::SystemDidWake);
+                if self.restart_on_wake.load(Ordering::Relaxed) {
+                    if let Some((device, proc_id)) = *io_proc {
+                        if self.restart(device, proc_id) {
+                            self.asleep.store(false, Ordering::Release);
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl CASession {
@@ -28,118 +225,1414 @@ impl CASession {
         output_device: CADevice,
         callback: Box<RenderCallback>,
     ) -> Result<Box<Self>, CFError> {
-        let aggregate_device = AggregateDevice::new(backend, input_device, output_device)?;
-        let device = aggregate_device.device();
+        let mut session = CASession::new(backend, sample_rate, input_device, output_device)?;
+        session.start_with(callback)?;
+
+        Ok(session)
+    }
+
+    /// Sets up the aggregate device for a session without starting any audio
+    /// processing.
+    pub fn new(
+        _backend: &CABackend,
+        sample_rate: f64,
+        input_device: CADevice,
+        output_device: CADevice,
+    ) -> Result<Box<Self>, CFError> {
+        let claim = DeviceClaim::new(vec![input_device, output_device], sample_rate)?;
+        let aggregate_device = AggregateDevice::new(input_device, output_device)?;
+        CASession::with_device(aggregate_device, claim, sample_rate)
+    }
+
+    /// Sets up a session that runs directly on `output_device`, without an
+    /// aggregate device. Its devices can't be switched or added to.
+    pub fn new_playback(
+        _backend: &CABackend,
+        sample_rate: f64,
+        output_device: CADevice,
+    ) -> Result<Box<Self>, CFError> {
+        let claim = DeviceClaim::new(vec![output_device], sample_rate)?;
+        CASession::with_device(AggregateDevice::direct(output_device), claim, sample_rate)
+    }
+
+    fn with_device(
+        aggregate_device: AggregateDevice,
+        claim: DeviceClaim,
+        sample_rate: f64,
+    ) -> Result<Box<Self>, CFError> {
         let mut session = Box::new(CASession {
-            device: aggregate_device,
-            callback: None,
+            io_device: aggregate_device.device(),
+            devices: Arc::new(Mutex::new(SessionDevices {
+                aggregate: aggregate_device,
+                watched: Vec::new(),
+            })),
+            proc_id: None,
+            shared: Arc::new(SharedState {
+                engine: RenderEngine::new(sample_rate),
+                validator: BufferListValidator::new(),
+                interrupted: AtomicBool::new(false),
+                auto_resume: AtomicBool::new(false),
+                restart_on_wake: AtomicBool::new(true),
+                io_running: AtomicBool::new(false),
+                asleep: AtomicBool::new(false),
+                resume_requested: AtomicBool::new(false),
+                io_proc: Mutex::new(None),
+            }),
+            saved_formats: Vec::new(),
+            taps: Vec::new(),
+            power: None,
+            watchdog: None,
+            follower: None,
+            claim: Arc::new(claim),
         });
 
-        session.device.device().set_nominal_sample_rate(sample_rate)?;
+        let shared = session.shared.clone();
+        session.power = PowerWatcher::new(move |event| shared.handle_power_event(event));
+
+        session.io_device.set_nominal_sample_rate(sample_rate)?;
+        session.shared.refresh_stream_layout(session.io_device)?;
+        session.watch_devices()?;
+
+        Ok(session)
+    }
+
+    /// Registers the IOProc with `callback` and starts audio processing.
+    pub fn start_with(&mut self, callback: Box<RenderCallback>) -> Result<(), CFError> {
+        assert!(self.proc_id.is_none(), "Session already started");
+
+        let device = self.io_device;
+        self.shared.engine.set_callback(callback);
 
         let mut proc_id = std::mem::MaybeUninit::<AudioDeviceIOProcID>::uninit();
         unsafe {
             check_os_status(AudioDeviceCreateIOProcID(
                 device.id(),
                 Some(session_io_proc),
-                session.as_mut() as *mut CASession as *mut c_void,
+                Arc::as_ptr(&self.shared) as *mut c_void,
                 proc_id.as_mut_ptr(),
             ))?;
 
-            let proc_id = proc_id.assume_init();
-            session.callback = Some((proc_id, callback));
+            self.proc_id = proc_id.assume_init();
+            *self.shared.io_proc.lock().unwrap() = Some((device.id(), self.proc_id));
 
-            check_os_status(AudioDeviceStart(device.id(), proc_id))?;
+            check_os_status(AudioDeviceStart(device.id(), self.proc_id))?;
         }
 
-        Ok(session)
+        self.watchdog = Some(Watchdog::new(self.shared.clone()));
+        Ok(())
+    }
+
+    /// Renders `buffers` cycles of output ahead of time, with silent input,
+    /// and then starts the session playing that output first.
+    pub fn start_primed(
+        &mut self,
+        mut callback: Box<RenderCallback>,
+        buffers: usize,
+    ) -> Result<(), CFError> {
+        let max_frames = self.max_frames_per_callback()?;
+        let (input_channels, output_channels) = self.buffer_channels()?;
+        let mut delay = OutputDelay::new(
+            &output_channels,
+            max_frames * buffers,
+            self.largest_buffer_size()?,
+        );
+
+        let allocate = |channels: &[usize]| -> Vec<Vec<f32>> {
+            channels
+                .iter()
+                .map(|&channels| vec![0.0; max_frames * channels])
+                .collect()
+        };
+        let mut input_storage = allocate(&input_channels);
+        let mut output_storage = allocate(&output_channels);
+        let inputs = InterleavedBuffer::wrap(&mut input_storage, &input_channels);
+        let mut outputs = InterleavedBuffer::wrap(&mut output_storage, &output_channels);
+        let mut scratch = Scratch::new(
+            max_frames,
+            input_channels
+                .iter()
+                .sum::<usize>()
+                .max(output_channels.iter().sum()),
+        );
+
+        for cycle in 0..buffers {
+            scratch.reset();
+            let context = RenderContext {
+                valid: &self.shared.engine.valid,
+                discontinuity: cycle == 0,
+                dropout: None,
+                sample_rate: self.shared.engine.clock.sample_rate(),
+                frames: max_frames,
+                stream_frames: 0,
+                times: CycleTimes::default(),
+                output_silent: Cell::new(false),
+                scratch: &scratch,
+                #[cfg(feature = "profiling")]
+                profiler: None,
+                #[cfg(feature = "profiling")]
+                cycle: 0,
+            };
+
+            if self.shared.engine.zeroes_output() {
+                zero_buffers(&mut outputs);
+            }
+            callback(&context, &inputs, &mut outputs);
+            context.take_output_silence(&mut outputs);
+            delay.prime(&outputs);
+        }
+
+        self.start_with(delay_output::<CABackend>(callback, delay))
+    }
+
+    /// Wraps `callback` so that it sees the channels picked by `routing`
+    /// rather than the device's own buffers.
+    pub(crate) fn route(
+        &self,
+        mut callback: Box<RenderCallback>,
+        routing: ResolvedRouting,
+    ) -> Result<Box<RenderCallback>, CFError> {
+        let mut routed = RoutedBuffers::new(&routing, self.largest_buffer_size()?);
+
+        Ok(Box::new(move |ctx, input, output| {
+            let frames = output
+                .first()
+                .or(input.first())
+                .map_or(0, |buffer| buffer.num_frames());
+            routed.set_frames(frames);
+
+            if let Some(buffer) = routed.input.first_mut() {
+                routing.gather(input, buffer);
+            }
+            zero_buffers(&mut routed.output);
+            callback(ctx, &routed.input, &mut routed.output);
+            ctx.take_output_silence(&mut routed.output);
+            if let Some(buffer) = routed.output.first() {
+                routing.scatter(buffer, output);
+            } else {
+                zero_buffers(output);
+            }
+        }))
+    }
+
+    /// Whether to restart the IOProc by itself when an interruption ends.
+    pub fn set_auto_resume(&self, auto_resume: bool) {
+        self.shared
+            .auto_resume
+            .store(auto_resume, Ordering::Relaxed);
+    }
+
+    pub fn set_restart_on_wake(&self, restart_on_wake: bool) {
+        self.shared
+            .restart_on_wake
+            .store(restart_on_wake, Ordering::Relaxed);
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.shared.engine.set_output_policy(policy);
+    }
+
+    /// Restarts the IOProc so that its first cycle begins at `host_time`, and
+    /// returns the host time the device settled on.
+    pub fn start_at(&mut self, host_time: u64) -> Result<u64, CFError> {
+        assert!(self.proc_id.is_some(), "Session not started");
+
+        let device = self.io_device;
+        let mut time = AudioTimeStamp {
+            mHostTime: host_time,
+            mFlags: kAudioTimeStampHostTimeValid,
+            ..unsafe { std::mem::zeroed() }
+        };
+
+        unsafe {
+            self.shared.io_running.store(false, Ordering::Release);
+            check_os_status(AudioDeviceStop(device.id(), self.proc_id))?;
+            self.shared.reset_timeline();
+            check_os_status(AudioDeviceStartAtTime(
+                device.id(),
+                self.proc_id,
+                &mut time,
+                0,
+            ))?;
+        }
+        *self.shared.io_proc.lock().unwrap() = Some((device.id(), self.proc_id));
+        self.shared.asleep.store(false, Ordering::Release);
+
+        Ok(time.mHostTime)
+    }
+
+    /// Blocks until the IOProc has been called at least once, or fails with
+    /// a snapshot of the device's state if that doesn't happen in time.
+    pub fn wait_until_running(&self, timeout: Duration) -> Result<(), CFError> {
+        let deadline = Instant::now() + timeout;
+
+        while self.shared.engine.cycles.load(Ordering::Acquire) == 0 {
+            if Instant::now() >= deadline {
+                return Err(CFError::StartupTimeout(Box::new(
+                    self.startup_diagnostics(timeout),
+                )));
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+
+    fn startup_diagnostics(&self, timeout: Duration) -> StartupDiagnostics {
+        let device = self.io_device;
+        let (input, output) = self.main_devices();
+        let flag = |value: Result<u32, CFError>| value.ok().map(|value| value != 0);
+
+        unsafe {
+            StartupDiagnostics {
+                timeout,
+                device_name: device.name().ok(),
+                is_alive: flag(properties::get(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyDeviceIsAlive,
+                    device.id(),
+                )),
+                is_running: flag(properties::get(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyDeviceIsRunning,
+                    device.id(),
+                )),
+                is_running_somewhere: flag(properties::get(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyDeviceIsRunningSomewhere,
+                    device.id(),
+                )),
+                nominal_sample_rate: device.nominal_sample_rate().ok(),
+                actual_sample_rate: device.actual_sample_rate().ok(),
+                input_sample_rate: input.nominal_sample_rate().ok(),
+                output_sample_rate: output.nominal_sample_rate().ok(),
+            }
+        }
+    }
+
+    /// Makes the session span more devices than its main input and output.
+    pub fn set_additional_devices(
+        &mut self,
+        devices: Vec<CADevice>,
+        clock_master: Option<CADevice>,
+    ) -> Result<(), CFError> {
+        let mut session_devices = self.devices.lock().unwrap();
+        let mut claimed = vec![
+            session_devices.aggregate.input(),
+            session_devices.aggregate.output(),
+        ];
+        claimed.extend(&devices);
+        self.claim.update(claimed, self.claim.sample_rate())?;
+
+        session_devices
+            .aggregate
+            .set_additional(devices, clock_master)?;
+        self.shared.reset_timeline();
+        self.shared.refresh_stream_layout(self.io_device)?;
+        session_devices.watch(&self.shared)
+    }
+
+    /// The main input and output devices.
+    fn main_devices(&self) -> (CADevice, CADevice) {
+        let devices = self.devices.lock().unwrap();
+        (devices.aggregate.input(), devices.aggregate.output())
+    }
+
+    fn switcher(&self) -> DeviceSwitcher {
+        DeviceSwitcher {
+            io_device: self.io_device,
+            devices: self.devices.clone(),
+            shared: self.shared.clone(),
+            claim: self.claim.clone(),
+        }
+    }
+
+    /// Switches the main devices to the system defaults whenever those
+    /// change, right away if they aren't already. Reports each switch as
+    /// `SessionEvent::FollowedDefaultDevice`.
+    pub fn follow_default_devices(&mut self, follow: bool) -> Result<(), CFError> {
+        if !follow {
+            drop(self.follower.take());
+            return Ok(());
+        }
+
+        self.devices.lock().unwrap().aggregate.check_aggregate()?;
+        if self.follower.is_none() {
+            self.follower = Some(DefaultDeviceFollower::new(self.switcher())?);
+        }
+
+        Ok(())
+    }
+
+    /// Adds the audio of another process to the end of the session's input
+    /// streams.
+    pub fn add_process_tap(&mut self, tap: ProcessTap) -> Result<(), CFError> {
+        self.devices.lock().unwrap().aggregate.check_aggregate()?;
+        self.taps.push(tap);
+
+        let mut uids = CFMutableArray::new();
+        for tap in &self.taps {
+            uids.push(tap.uid()?.as_void_ptr());
+        }
+
+        unsafe {
+            properties::set(
+                element::Master,
+                scope::Global,
+                selector::AggregateDevicePropertyTapList,
+                self.io_device.id(),
+                &uids.clone_immutable(),
+            )?;
+        }
+
+        self.shared.reset_timeline();
+        self.shared.refresh_stream_layout(self.io_device)
+    }
+
+    /// Switches every stream of every device in the session to `format`.
+    pub fn set_physical_format(&mut self, format: PhysicalFormat) -> Result<(), CFError> {
+        let sub_devices = self.devices.lock().unwrap().aggregate.sub_devices();
+        self.claim.update(sub_devices.clone(), format.sample_rate)?;
+
+        for device in sub_devices {
+            for &direction in &[Direction::Input, Direction::Output] {
+                let streams = unsafe {
+                    properties::get_in(
+                        element::Master,
+                        direction,
+                        selector::DevicePropertyStreams,
+                        device.id(),
+                    )?
+                };
+
+                for stream in streams {
+                    let current = unsafe {
+                        properties::get(
+                            element::Master,
+                            scope::Global,
+                            selector::StreamPropertyPhysicalFormat,
+                            stream,
+                        )?
+                    };
+
+                    if !self.saved_formats.iter().any(|&(id, _)| id == stream) {
+                        self.saved_formats.push((stream, current));
+                    }
+
+                    unsafe {
+                        properties::set(
+                            element::Master,
+                            scope::Global,
+                            selector::StreamPropertyPhysicalFormat,
+                            stream,
+                            &stream_description(format, current.mChannelsPerFrame),
+                        )?;
+                    }
+                }
+            }
+        }
+
+        self.shared.engine.set_sample_rate(format.sample_rate);
+        self.shared.reset_timeline();
+        self.shared.refresh_stream_layout(self.io_device)
+    }
+
+    fn restore_physical_formats(&mut self) {
+        for (stream, format) in self.saved_formats.drain(..).rev() {
+            // Devices that have gone away don't need restoring.
+            let _ = unsafe {
+                properties::set(
+                    element::Master,
+                    scope::Global,
+                    selector::StreamPropertyPhysicalFormat,
+                    stream,
+                    &format,
+                )
+            };
+        }
+    }
+
+    /// Asks the device to call the IOProc with `frames` frames at a time.
+    /// The device may pick a different size if it doesn't support this one.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), CFError> {
+        unsafe {
+            properties::set(
+                element::Master,
+                scope::Global,
+                selector::DevicePropertyBufferFrameSize,
+                self.io_device.id(),
+                &(frames as u32),
+            )?;
+        }
+
+        self.shared.resize_scratch(self.io_device)
+    }
+
+    /// Frames of latency in one direction at the current buffer size: the
+    /// device's own latency and safety offset, the largest latency of its
+    /// streams, plus one buffer.
+    pub fn latency_frames(&self, direction: Direction) -> Result<usize, CFError> {
+        let device = self.io_device.id();
+        let (latency, safety_offset, streams) = unsafe {
+            (
+                properties::get_in(
+                    element::Master,
+                    direction,
+                    selector::DevicePropertyLatency,
+                    device,
+                )?,
+                properties::get_in(
+                    element::Master,
+                    direction,
+                    selector::DevicePropertySafetyOffset,
+                    device,
+                )?,
+                properties::get_in(
+                    element::Master,
+                    direction,
+                    selector::DevicePropertyStreams,
+                    device,
+                )?,
+            )
+        };
+
+        let mut stream_latency = 0;
+        for stream in streams {
+            let latency = unsafe {
+                properties::get(
+                    element::Master,
+                    scope::Global,
+                    selector::StreamPropertyLatency,
+                    stream,
+                )?
+            };
+            stream_latency = stream_latency.max(latency);
+        }
+
+        Ok(latency as usize
+            + safety_offset as usize
+            + stream_latency as usize
+            + self.max_frames_per_callback()?)
+    }
+
+    /// The largest number of frames the callback will be asked to render in
+    /// one go.
+    pub fn max_frames_per_callback(&self) -> Result<usize, CFError> {
+        buffer_frame_size(self.io_device)
+    }
+
+    /// The most frames the device could ever ask for, for buffers that live
+    /// inside the callback and so can't grow along with `set_buffer_size`.
+    fn largest_buffer_size(&self) -> Result<usize, CFError> {
+        let (_, max) = self.io_device.buffer_size_range()?;
+        Ok(max.max(self.max_frames_per_callback()?))
+    }
+
+    pub fn io_device(&self) -> CADevice {
+        self.io_device
+    }
+
+    /// Number of IOProc cycles whose buffer lists failed validation. Always
+    /// zero unless built with debug assertions or the `validate-buffers`
+    /// feature.
+    pub fn buffer_list_error_count(&self) -> u64 {
+        self.shared.validator.failure_count()
+    }
+
+    pub fn last_buffer_list_error(&self) -> Option<BufferListError> {
+        self.shared.validator.last_failure()
+    }
+
+    /// The channel count of each input and output buffer the IOProc gets.
+    pub fn buffer_channels(&self) -> Result<(Vec<usize>, Vec<usize>), CFError> {
+        buffer_channels(self.io_device)
+    }
+
+    fn watch_devices(&self) -> Result<(), CFError> {
+        self.devices.lock().unwrap().watch(&self.shared)
+    }
+
+    fn unwatch_devices(&self) {
+        self.devices.lock().unwrap().unwatch(&self.shared);
+    }
+}
+
+impl SessionDevices {
+    /// Listens for the session's devices dying, which invalidates the session
+    /// until it's pointed at devices that are alive again.
+    fn watch(&mut self, shared: &Arc<SharedState>) -> Result<(), CFError> {
+        self.unwatch(shared);
+
+        let mut devices = self.aggregate.sub_devices();
+        if self.aggregate.is_aggregate() {
+            devices.insert(0, self.aggregate.device());
+        }
+
+        let mut all_alive = true;
+        for device in devices {
+            unsafe {
+                properties::add_listener(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyDeviceIsAlive,
+                    device.id(),
+                    Some(device_alive_listener),
+                    Arc::as_ptr(shared) as *mut c_void,
+                )?;
+                properties::add_listener(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyHogMode,
+                    device.id(),
+                    Some(hog_mode_listener),
+                    Arc::as_ptr(shared) as *mut c_void,
+                )?;
+                properties::add_listener(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyDeviceIsRunning,
+                    device.id(),
+                    Some(device_running_listener),
+                    Arc::as_ptr(shared) as *mut c_void,
+                )?;
+                properties::add_listener(
+                    element::Master,
+                    scope::Global,
+                    selector::DeviceProcessorOverload,
+                    device.id(),
+                    Some(overload_listener),
+                    Arc::as_ptr(shared) as *mut c_void,
+                )?;
+            }
+            self.watched.push(device);
+
+            all_alive &= is_alive(device.id());
+        }
+
+        shared.engine.valid.store(all_alive, Ordering::Release);
+
+        Ok(())
+    }
+
+    fn unwatch(&mut self, shared: &Arc<SharedState>) {
+        for device in self.watched.drain(..) {
+            // The device may already be gone, in which case its listeners went
+            // with it.
+            let _ = unsafe {
+                properties::remove_listener(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyDeviceIsAlive,
+                    device.id(),
+                    Some(device_alive_listener),
+                    Arc::as_ptr(shared) as *mut c_void,
+                )
+            };
+            let _ = unsafe {
+                properties::remove_listener(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyHogMode,
+                    device.id(),
+                    Some(hog_mode_listener),
+                    Arc::as_ptr(shared) as *mut c_void,
+                )
+            };
+            let _ = unsafe {
+                properties::remove_listener(
+                    element::Master,
+                    scope::Global,
+                    selector::DevicePropertyDeviceIsRunning,
+                    device.id(),
+                    Some(device_running_listener),
+                    Arc::as_ptr(shared) as *mut c_void,
+                )
+            };
+            let _ = unsafe {
+                properties::remove_listener(
+                    element::Master,
+                    scope::Global,
+                    selector::DeviceProcessorOverload,
+                    device.id(),
+                    Some(overload_listener),
+                    Arc::as_ptr(shared) as *mut c_void,
+                )
+            };
+        }
+    }
+}
+
+/// The channel count of each input and output buffer the IOProc gets.
+fn buffer_channels(device: CADevice) -> Result<(Vec<usize>, Vec<usize>), CFError> {
+    let device = device.id();
+    let channels = |direction| -> Result<Vec<usize>, CFError> {
+        let config = unsafe {
+            properties::get_in(
+                element::Master,
+                direction,
+                selector::DevicePropertyStreamConfiguration,
+                device,
+            )?
+        };
+        let buffers = unsafe {
+            std::slice::from_raw_parts(config.mBuffers.as_ptr(), config.mNumberBuffers as usize)
+        };
+
+        Ok(buffers
+            .iter()
+            .map(|buffer| buffer.mNumberChannels as usize)
+            .collect())
+    };
+
+    Ok((channels(Direction::Input)?, channels(Direction::Output)?))
+}
+
+/// The number of frames the IOProc is called with.
+fn buffer_frame_size(device: CADevice) -> Result<usize, CFError> {
+    let frames = unsafe {
+        properties::get(
+            element::Master,
+            scope::Global,
+            selector::DevicePropertyBufferFrameSize,
+            device.id(),
+        )?
+    };
+
+    Ok(frames as usize)
+}
+
+fn stream_description(format: PhysicalFormat, channels: u32) -> AudioStreamBasicDescription {
+    let bytes_per_sample = format.bits_per_sample.div_ceil(8);
+    let sample_type = if format.is_float {
+        kAudioFormatFlagIsFloat
+    } else {
+        kAudioFormatFlagIsSignedInteger
+    };
+
+    AudioStreamBasicDescription {
+        mSampleRate: format.sample_rate,
+        mFormatID: kAudioFormatLinearPCM,
+        mFormatFlags: sample_type | kAudioFormatFlagIsPacked,
+        mBytesPerPacket: bytes_per_sample * channels,
+        mFramesPerPacket: 1,
+        mBytesPerFrame: bytes_per_sample * channels,
+        mChannelsPerFrame: channels,
+        mBitsPerChannel: format.bits_per_sample,
+        mReserved: 0,
+    }
+}
+
+fn is_alive(device: AudioObjectID) -> bool {
+    let alive = unsafe {
+        properties::get(
+            element::Master,
+            scope::Global,
+            selector::DevicePropertyDeviceIsAlive,
+            device,
+        )
+    };
+
+    alive.map(|alive| alive != 0).unwrap_or(false)
+}
+
+unsafe extern "C" fn device_alive_listener(
+    in_object_id: AudioObjectID,
+    _in_number_addresses: u32,
+    _in_addresses: *const AudioObjectPropertyAddress,
+    in_client_data: *mut c_void,
+) -> OSStatus {
+    if let Some(shared) = (in_client_data as *const SharedState).as_ref() {
+        if !is_alive(in_object_id) {
+            shared.engine.valid.store(false, Ordering::Release);
+            shared.report_stopped(StopCause::DeviceDied);
+        }
+    }
+
+    noErr as OSStatus
+}
+
+/// Reports another process taking exclusive access to one of the session's
+/// devices as an interruption, and restarts the session once it lets go if
+/// asked to.
+unsafe extern "C" fn hog_mode_listener(
+    in_object_id: AudioObjectID,
+    _in_number_addresses: u32,
+    _in_addresses: *const AudioObjectPropertyAddress,
+    in_client_data: *mut c_void,
+) -> OSStatus {
+    if let Some(shared) = (in_client_data as *const SharedState).as_ref() {
+        let owner = properties::get(
+            element::Master,
+            scope::Global,
+            selector::DevicePropertyHogMode,
+            in_object_id,
+        )
+        .map_or(-1, |pid| pid as i32);
+        let taken = owner != -1 && owner != std::process::id() as i32;
+
+        if taken {
+            if !shared.interrupted.swap(true, Ordering::AcqRel) {
+                shared.engine.events.push(SessionEvent::Interrupted);
+            }
+            shared.report_stopped(StopCause::TakenExclusively);
+        } else if shared.interrupted.swap(false, Ordering::AcqRel) {
+            if shared.auto_resume.load(Ordering::Relaxed) {
+                shared.resume_requested.store(true, Ordering::Release);
+            } else {
+                shared.engine.events.push(SessionEvent::Resumed);
+            }
+        }
     }
 
-    pub fn aggregate_device(&self) -> &AggregateDevice {
-        &self.device
+    noErr as OSStatus
+}
+
+/// Notices a device that stops running while the session still expects it
+/// to, and guesses why from what else is known about the session.
+unsafe extern "C" fn device_running_listener(
+    in_object_id: AudioObjectID,
+    _in_number_addresses: u32,
+    _in_addresses: *const AudioObjectPropertyAddress,
+    in_client_data: *mut c_void,
+) -> OSStatus {
+    if let Some(shared) = (in_client_data as *const SharedState).as_ref() {
+        let running = properties::get(
+            element::Master,
+            scope::Global,
+            selector::DevicePropertyDeviceIsRunning,
+            in_object_id,
+        )
+        .map_or(true, |running| running != 0);
+
+        if !running {
+            let cause = if !shared.engine.valid.load(Ordering::Acquire) || !is_alive(in_object_id) {
+                StopCause::DeviceDied
+            } else if shared.interrupted.load(Ordering::Acquire) {
+                StopCause::TakenExclusively
+            } else {
+                StopCause::Unknown
+            };
+            shared.report_stopped(cause);
+        }
     }
 
-    pub fn aggregate_device_mut(&mut self) -> &mut AggregateDevice {
-        &mut self.device
+    noErr as OSStatus
+}
+
+/// Counts the overloads the HAL notices on the IO thread, which include ones
+/// where the IOProc wasn't called in time at all.
+unsafe extern "C" fn overload_listener(
+    _in_object_id: AudioObjectID,
+    _in_number_addresses: u32,
+    _in_addresses: *const AudioObjectPropertyAddress,
+    in_client_data: *mut c_void,
+) -> OSStatus {
+    if let Some(shared) = (in_client_data as *const SharedState).as_ref() {
+        shared.engine.report_device_overload();
+    }
+
+    noErr as OSStatus
+}
+
+/// Reports the session as stalled when the IOProc stops being called without
+/// the device saying so, which some drivers do.
+struct Watchdog {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    fn new(shared: Arc<SharedState>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = thread::Builder::new()
+            .name("render_callback watchdog".to_owned())
+            .spawn(move || {
+                let mut last_cycles = shared.engine.cycles.load(Ordering::Acquire);
+                let mut last_progress = Instant::now();
+
+                loop {
+                    thread::park_timeout(WATCHDOG_INTERVAL);
+                    if thread_stop.load(Ordering::Acquire) {
+                        break;
+                    }
+
+                    if shared.resume_requested.swap(false, Ordering::AcqRel) {
+                        shared.resume();
+                        last_progress = Instant::now();
+                    }
+
+                    let cycles = shared.engine.cycles.load(Ordering::Acquire);
+                    if cycles != last_cycles {
+                        last_cycles = cycles;
+                        last_progress = Instant::now();
+                    } else if last_progress.elapsed() >= STALL_TIMEOUT
+                        && !shared.interrupted.load(Ordering::Acquire)
+                    {
+                        shared.report_stopped(StopCause::Stalled);
+                    }
+                }
+            })
+            .expect("Could not spawn watchdog thread");
+
+        Watchdog {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// What it takes to switch one of a session's main devices, shared by the
+/// session and the thread following the default devices.
+struct DeviceSwitcher {
+    io_device: CADevice,
+    devices: Arc<Mutex<SessionDevices>>,
+    shared: Arc<SharedState>,
+    claim: Arc<DeviceClaim>,
+}
+
+impl DeviceSwitcher {
+    fn current(&self, direction: Direction) -> CADevice {
+        let devices = self.devices.lock().unwrap();
+        match direction {
+            Direction::Input => devices.aggregate.input(),
+            Direction::Output => devices.aggregate.output(),
+        }
+    }
+
+    fn switch(&self, direction: Direction, device: CADevice) -> Result<(), CFError> {
+        let mut devices = self.devices.lock().unwrap();
+
+        // The new device is claimed alongside the current ones ahead of the
+        // switch, and the replaced one let go once it's done.
+        let mut claimed = devices.aggregate.sub_devices();
+        claimed.push(device);
+        self.claim.update(claimed, self.claim.sample_rate())?;
+
+        let result = match direction {
+            Direction::Input => devices.aggregate.set_input(device),
+            Direction::Output => devices.aggregate.set_output(device),
+        };
+        // Claiming fewer devices never conflicts.
+        let _ = self
+            .claim
+            .update(devices.aggregate.sub_devices(), self.claim.sample_rate());
+        result?;
+
+        self.shared.reset_timeline();
+        self.shared.refresh_stream_layout(self.io_device)?;
+        devices.watch(&self.shared)
+    }
+}
+
+/// Switches a session's main devices to the system defaults, on a thread of
+/// its own that listeners wake up whenever the defaults change.
+struct DefaultDeviceFollower {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    listeners: Option<DeviceListeners>,
+}
+
+impl DefaultDeviceFollower {
+    fn new(switcher: DeviceSwitcher) -> Result<Self, CFError> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = thread::Builder::new()
+            .name("render_callback default device follower".to_owned())
+            .spawn(move || {
+                let backend = CABackend;
+
+                while !thread_stop.load(Ordering::Acquire) {
+                    for &direction in &[Direction::Input, Direction::Output] {
+                        let default = match direction {
+                            Direction::Input => backend.default_input_device(),
+                            Direction::Output => backend.default_output_device(),
+                        };
+
+                        // A default that vanished again before it could be
+                        // looked up is left until the next change.
+                        if let Ok(default) = default {
+                            if default != switcher.current(direction) {
+                                let event = match switcher.switch(direction, default) {
+                                    Ok(()) => SessionEvent::FollowedDefaultDevice { direction },
+                                    Err(_) => SessionEvent::DeviceSwitchFailed { direction },
+                                };
+                                switcher.shared.engine.events.push(event);
+                            }
+                        }
+                    }
+
+                    thread::park();
+                }
+            })
+            .expect("Could not spawn default device follower thread");
+
+        let mut follower = DefaultDeviceFollower {
+            stop,
+            thread: Some(thread),
+            listeners: None,
+        };
+        let thread = follower.thread.as_ref().unwrap().thread().clone();
+        follower.listeners = Some(DeviceListeners::install(thread)?);
+
+        Ok(follower)
+    }
+}
+
+impl Drop for DefaultDeviceFollower {
+    fn drop(&mut self) {
+        drop(self.listeners.take());
+        self.stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
     }
 }
 
 impl Drop for CASession {
     fn drop(&mut self) {
-        if let Some((proc_id, _)) = &mut self.callback {
+        drop(self.follower.take());
+        drop(self.watchdog.take());
+        self.shared.io_running.store(false, Ordering::Release);
+        self.shared.engine.valid.store(false, Ordering::Release);
+        drop(self.power.take());
+        self.shared.io_proc.lock().unwrap().take();
+
+        if self.proc_id.is_some() {
             unsafe {
-                check_os_status(AudioDeviceStop(self.device.device().id(), *proc_id))
+                check_os_status(AudioDeviceStop(self.io_device.id(), self.proc_id))
                     .expect("Could not stop session");
                 check_os_status(AudioDeviceDestroyIOProcID(
-                    self.device.device().id(),
-                    *proc_id,
+                    self.io_device.id(),
+                    self.proc_id,
                 ))
                 .expect("Could not destroy IOProcID");
             }
         }
+
+        self.unwatch_devices();
+        self.restore_physical_formats();
+
+        // The IOProc is gone, so this is guaranteed to drop the callback here
+        // rather than on the real-time thread.
+        drop(self.shared.engine.take_callback());
     }
 }
 
 unsafe extern "C" fn session_io_proc(
     _in_device: AudioDeviceID,
-    _in_now: *const AudioTimeStamp,
+    in_now: *const AudioTimeStamp,
     in_input_data: *const AudioBufferList,
-    _in_input_time: *const AudioTimeStamp,
+    in_input_time: *const AudioTimeStamp,
     out_output_data: *mut AudioBufferList,
-    _in_output_time: *const AudioTimeStamp,
+    in_output_time: *const AudioTimeStamp,
     in_client_data: *mut c_void,
 ) -> OSStatus {
-    let session_ptr = in_client_data as *mut CASession;
-    if let (Some(session), Some(in_input_data), Some(out_output_data)) = (
-        session_ptr.as_mut(),
+    let shared_ptr = in_client_data as *const SharedState;
+    if let (Some(shared), Some(in_input_data), Some(out_output_data)) = (
+        shared_ptr.as_ref(),
         in_input_data.as_ref(),
         out_output_data.as_mut(),
     ) {
-        if let Some((_, callback)) = &mut session.callback {
-            let input_buffers = {
-                let ptr = in_input_data.mBuffers.as_ptr() as *const InterleavedBuffer;
-                let len = in_input_data.mNumberBuffers as usize;
+        shared.engine.cycles.fetch_add(1, Ordering::Release);
+        shared.io_running.store(true, Ordering::Release);
 
-                std::slice::from_raw_parts(ptr, len)
-            };
+        if VALIDATE_BUFFER_LISTS {
+            if let Err(error) = shared.validator.validate(in_input_data, out_output_data) {
+                shared.validator.record(error);
+                shared.engine.events.push(SessionEvent::InvalidBuffers {
+                    fatal: error.is_fatal(),
+                });
+                if error.is_fatal() {
+                    return noErr as OSStatus;
+                }
+            }
+        }
 
-            let output_buffers = {
-                let ptr = out_output_data.mBuffers.as_ptr() as *mut InterleavedBuffer;
-                let len = out_output_data.mNumberBuffers as usize;
+        let frames = buffer_list_frames(out_output_data)
+            .or_else(|| buffer_list_frames(in_input_data))
+            .unwrap_or(0);
+        let times = CycleTimes {
+            now: timestamp(in_now),
+            input: timestamp(in_input_time),
+            output: timestamp(in_output_time),
+        };
 
-                std::slice::from_raw_parts_mut(ptr, len)
-            };
+        let input_buffers = {
+            let ptr = in_input_data.mBuffers.as_ptr() as *const InterleavedBuffer;
+            let len = in_input_data.mNumberBuffers as usize;
 
-            callback(input_buffers, output_buffers);
-        }
+            std::slice::from_raw_parts(ptr, len)
+        };
+
+        let output_buffers = {
+            let ptr = out_output_data.mBuffers.as_ptr() as *mut InterleavedBuffer;
+            let len = out_output_data.mNumberBuffers as usize;
+
+            std::slice::from_raw_parts_mut(ptr, len)
+        };
+
+        shared
+            .engine
+            .render(input_buffers, output_buffers, frames, times);
     }
 
     noErr as OSStatus
 }
 
+/// The parts of `time` its flags say are valid.
+unsafe fn timestamp(time: *const AudioTimeStamp) -> AudioTimestamp {
+    match time.as_ref() {
+        Some(time) => AudioTimestamp {
+            sample_time: Some(time.mSampleTime)
+                .filter(|_| time.mFlags & kAudioTimeStampSampleTimeValid != 0),
+            host_time: Some(time.mHostTime)
+                .filter(|_| time.mFlags & kAudioTimeStampHostTimeValid != 0),
+        },
+        None => AudioTimestamp::default(),
+    }
+}
+
+fn buffer_list_frames(list: &AudioBufferList) -> Option<usize> {
+    if list.mNumberBuffers == 0 || list.mBuffers[0].mNumberChannels == 0 {
+        return None;
+    }
+
+    let buffer = &list.mBuffers[0];
+    Some((buffer.mDataByteSize / (4 * buffer.mNumberChannels)) as usize)
+}
+
 impl Session<CABackend> for Box<CASession> {
     fn input_device(&self) -> Result<CADevice, CFError> {
-        Ok(self.aggregate_device().input())
+        Ok(self.main_devices().0)
     }
 
     fn output_device(&self) -> Result<CADevice, CFError> {
-        Ok(self.aggregate_device().output())
+        Ok(self.main_devices().1)
     }
 
     fn set_input_device(&mut self, device: CADevice) -> Result<(), CFError> {
-        self.aggregate_device_mut().set_input(device)
+        self.switcher().switch(Direction::Input, device)
     }
 
     fn set_output_device(&mut self, device: CADevice) -> Result<(), CFError> {
-        self.aggregate_device_mut().set_output(device)
+        self.switcher().switch(Direction::Output, device)
+    }
+
+    fn prepare_device_switch(&mut self, devices: &[CADevice]) -> Result<(), CFError> {
+        self.devices.lock().unwrap().aggregate.prepare(devices)
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, CFError> {
+        CASession::max_frames_per_callback(self)
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, CFError> {
+        let mut map = ChannelMap::default();
+
+        let sub_devices = self.devices.lock().unwrap().aggregate.sub_devices();
+        for device in sub_devices {
+            let device_id = device.persistent_id()?;
+
+            for &direction in &[Direction::Input, Direction::Output] {
+                let config = unsafe {
+                    properties::get_in(
+                        element::Master,
+                        direction,
+                        selector::DevicePropertyStreamConfiguration,
+                        device.id(),
+                    )?
+                };
+                let streams = unsafe {
+                    std::slice::from_raw_parts(
+                        config.mBuffers.as_ptr(),
+                        config.mNumberBuffers as usize,
+                    )
+                };
+
+                let mappings = match direction {
+                    Direction::Input => &mut map.input,
+                    Direction::Output => &mut map.output,
+                };
+                let mut first_device_channel = 0;
+                for stream in streams {
+                    mappings.push(StreamMapping {
+                        device_id: device_id.clone(),
+                        first_device_channel,
+                        channels: stream.mNumberChannels as usize,
+                    });
+                    first_device_channel += stream.mNumberChannels as usize;
+                }
+            }
+        }
+
+        Ok(map)
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.shared.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.shared.engine.clock.clone()
+    }
+
+    /// The nominal rate of the aggregate device, which CoreAudio may have
+    /// rounded to one all of its sub-devices support.
+    fn sample_rate(&self) -> Result<f64, CFError> {
+        self.io_device.nominal_sample_rate()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.shared.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.shared.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.shared.engine.events.clone())
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.shared.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback>) {
+        self.shared.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.shared.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.shared.engine.is_bypassed()
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let shared = self.shared.clone();
+        let snapshot = move || {
+            shared
+                .engine
+                .warning_snapshot(shared.validator.failure_count())
+        };
+
+        warnings::watch(thresholds, snapshot, f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.shared.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.shared.engine.overloads.clone(), f)
+    }
+
+    fn start_at(&mut self, host_time: u64) -> Result<u64, CFError> {
+        CASession::start_at(self, host_time)
+    }
+
+    fn input_latency_frames(&self) -> Result<usize, CFError> {
+        self.latency_frames(Direction::Input)
+    }
+
+    fn output_latency_frames(&self) -> Result<usize, CFError> {
+        self.latency_frames(Direction::Output)
+    }
+
+    /// Stops the IOProc, but keeps it registered with the aggregate device.
+    fn stop(&mut self) -> Result<(), CFError> {
+        // The session restarts whichever IOProc is set here after the system
+        // or another process stopped it, which it mustn't do while stopped on
+        // purpose. The lock is let go of before stopping, since stopping can
+        // wait for the HAL.
+        let io_proc = self.shared.io_proc.lock().unwrap().take();
+        if let Some((device, proc_id)) = io_proc {
+            unsafe { check_os_status(AudioDeviceStop(device, proc_id))? };
+        }
+        self.shared.io_running.store(false, Ordering::Release);
+
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), CFError> {
+        assert!(self.proc_id.is_some(), "Session not started");
+        if self.is_running() {
+            return Ok(());
+        }
+
+        let device = self.io_device.id();
+        self.shared.reset_timeline();
+        unsafe { check_os_status(AudioDeviceStart(device, self.proc_id))? };
+        *self.shared.io_proc.lock().unwrap() = Some((device, self.proc_id));
+        self.shared.asleep.store(false, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// False while stopped for the system sleeping, even though the session
+    /// hasn't been stopped.
+    fn is_running(&self) -> bool {
+        self.shared.io_proc.lock().unwrap().is_some() && !self.shared.asleep.load(Ordering::Acquire)
+    }
+
+    fn tune_for_low_latency(&mut self, target_ms: f64) -> Result<Option<LatencyTuning>, CFError> {
+        let sample_rate = self.io_device.nominal_sample_rate()?;
+        let (min, max) = self.io_device.buffer_size_range()?;
+        let current = self.max_frames_per_callback()?;
+
+        // Latency that doesn't depend on the buffer size, measured at the
+        // current size.
+        let fixed = (self.latency_frames(Direction::Input)?
+            + self.latency_frames(Direction::Output)?)
+        .saturating_sub(2 * current);
+        let target_frames = (target_ms * sample_rate / 1000.0) as usize;
+
+        let mut candidates = Vec::new();
+        let mut size = min.max(MIN_TUNED_BUFFER_SIZE).next_power_of_two();
+        while size <= max {
+            candidates.push(size);
+            size *= 2;
+        }
+        if candidates.is_empty() {
+            candidates.push(current);
+        }
+
+        // Start from the largest size within the target: anything smaller
+        // only adds risk of dropouts.
+        let first = candidates
+            .iter()
+            .rposition(|&size| fixed + 2 * size <= target_frames)
+            .unwrap_or(0);
+
+        let mut stable = false;
+        let mut buffer_size = current;
+        for &size in &candidates[first..] {
+            self.set_buffer_size(size)?;
+            buffer_size = self.max_frames_per_callback()?;
+            self.shared.reset_timeline();
+
+            let before = self.shared.engine.dropouts.stats().count;
+            thread::sleep(LATENCY_TRIAL_PERIOD);
+
+            if self.shared.engine.dropouts.stats().count == before {
+                stable = true;
+                break;
+            }
+        }
+
+        if !stable {
+            // Nothing ran cleanly, so leave the session as it was found.
+            self.set_buffer_size(current)?;
+            self.shared.reset_timeline();
+            return Ok(None);
+        }
+
+        Ok(Some(LatencyTuning {
+            buffer_size,
+            input_latency_frames: self.latency_frames(Direction::Input)?,
+            output_latency_frames: self.latency_frames(Direction::Output)?,
+            sample_rate,
+        }))
     }
 }
 
+#[repr(transparent)]
 pub struct InterleavedBuffer(AudioBuffer);
 
+/// The buffers a routed callback sees in place of the device's, pointing
+/// into storage of their own.
+struct RoutedBuffers {
+    _storage: Vec<Vec<f32>>,
+    input: Vec<InterleavedBuffer>,
+    output: Vec<InterleavedBuffer>,
+    max_frames: usize,
+}
+
+// The buffers only point into the storage that moves along with them.
+unsafe impl Send for RoutedBuffers {}
+
+impl RoutedBuffers {
+    fn new(routing: &ResolvedRouting, max_frames: usize) -> Self {
+        let channels = [routing.input_channels, routing.output_channels];
+        let mut storage: Vec<_> = channels
+            .iter()
+            .map(|&channels| vec![0.0; max_frames * channels])
+            .collect();
+        let mut buffers = InterleavedBuffer::wrap(&mut storage, &channels).into_iter();
+        let mut side = |channels: usize| {
+            let buffer = buffers.next();
+            buffer.filter(|_| channels > 0).into_iter().collect()
+        };
+        let input = side(routing.input_channels);
+        let output = side(routing.output_channels);
+
+        RoutedBuffers {
+            _storage: storage,
+            input,
+            output,
+            max_frames,
+        }
+    }
+
+    fn set_frames(&mut self, frames: usize) {
+        let frames = frames.min(self.max_frames);
+        for buffer in self.input.iter_mut().chain(&mut self.output) {
+            buffer.0.mDataByteSize =
+                (frames * buffer.num_channels() * std::mem::size_of::<f32>()) as u32;
+        }
+    }
+}
+
+impl InterleavedBuffer {
+    /// Buffers pointing into `storage`, which must outlive them.
+    fn wrap(storage: &mut [Vec<f32>], channels: &[usize]) -> Vec<InterleavedBuffer> {
+        storage
+            .iter_mut()
+            .zip(channels)
+            .map(|(samples, &channels)| {
+                InterleavedBuffer(AudioBuffer {
+                    mNumberChannels: channels as u32,
+                    mDataByteSize: (samples.len() * std::mem::size_of::<f32>()) as u32,
+                    mData: samples.as_mut_ptr() as *mut c_void,
+                })
+            })
+            .collect()
+    }
+}
+
 impl AudioBuffers for InterleavedBuffer {
+    type Sample = f32;
+
     fn num_frames(&self) -> usize {
-        (self.0.mDataByteSize / (4 * self.0.mNumberChannels)) as usize
+        self.0.mDataByteSize as usize / (4 * self.0.mNumberChannels as usize)
     }
 
     fn num_channels(&self) -> usize {
@@ -149,6 +1642,11 @@ impl AudioBuffers for InterleavedBuffer {
     fn interleaved_frames(&self) -> &[f32] {
         let ptr = self.0.mData as *const f32;
         let len = self.num_frames() * self.num_channels();
+        // Drivers may leave the data null on empty buffers, which a slice
+        // can't point to even when it is empty.
+        if ptr.is_null() {
+            return &[];
+        }
 
         unsafe { std::slice::from_raw_parts(ptr, len) }
     }
@@ -156,6 +1654,9 @@ impl AudioBuffers for InterleavedBuffer {
     fn interleaved_frames_mut(&mut self) -> &mut [f32] {
         let ptr = self.0.mData as *mut f32;
         let len = self.num_frames() * self.num_channels();
+        if ptr.is_null() {
+            return &mut [];
+        }
 
         unsafe { std::slice::from_raw_parts_mut(ptr, len) }
     }
@@ -0,0 +1,176 @@
+use std::ffi::{c_void, CStr};
+use std::mem;
+use std::os::raw::c_char;
+
+use coreaudio_sys::{
+    kAudioHardwareBadObjectError, kAudioObjectSystemObject, kAudioObjectUnknown, AudioObjectID,
+    OSStatus,
+};
+
+use crate::capture::CaptureTarget;
+
+use super::cf::{check_os_status, CFError, CFMutableArray, CFNumber, CFString};
+use super::properties::{self, element, scope, selector};
+
+// Process taps are only exposed through CATapDescription, an Objective-C
+// class, so the handful of messages needed to build one are sent by hand.
+#[link(name = "objc")]
+extern "C" {
+    fn objc_getClass(name: *const c_char) -> *mut c_void;
+    fn sel_registerName(name: *const c_char) -> *mut c_void;
+    fn objc_msgSend();
+}
+
+#[link(name = "CoreAudio", kind = "framework")]
+extern "C" {
+    fn AudioHardwareCreateProcessTap(
+        description: *mut c_void,
+        out_tap_id: *mut AudioObjectID,
+    ) -> OSStatus;
+    fn AudioHardwareDestroyProcessTap(tap_id: AudioObjectID) -> OSStatus;
+}
+
+/// A stereo mixdown of everything one process plays, which can be added to
+/// an aggregate device as extra input streams. Requires macOS 14.2.
+pub struct ProcessTap {
+    id: AudioObjectID,
+}
+
+impl ProcessTap {
+    pub fn new(target: &CaptureTarget) -> Result<Self, CFError> {
+        // A system tap is a global tap that excludes our own process, so
+        // sessions don't record themselves.
+        let (process, init) = match target {
+            CaptureTarget::System => (
+                process_object(&CaptureTarget::Pid(std::process::id() as i32)).ok(),
+                &b"initStereoGlobalTapButExcludeProcesses:\0"[..],
+            ),
+            _ => (
+                Some(process_object(target)?),
+                &b"initStereoMixdownOfProcesses:\0"[..],
+            ),
+        };
+
+        let mut processes = CFMutableArray::new();
+        if let Some(process) = process {
+            processes.push(CFNumber::new(process as i32).as_void_ptr());
+        }
+        let processes = processes.clone_immutable();
+
+        let mut id = kAudioObjectUnknown;
+        unsafe {
+            let description = send_id(class(b"CATapDescription\0"), selector_named(b"alloc\0"));
+            let description = send_id_id(
+                description,
+                selector_named(init),
+                processes.as_void_ptr() as *mut c_void,
+            );
+            if description.is_null() {
+                return Err(CFError::Status(kAudioHardwareBadObjectError as OSStatus));
+            }
+
+            let status = AudioHardwareCreateProcessTap(description, &mut id);
+            send_id(description, selector_named(b"release\0"));
+            check_os_status(status)?;
+        }
+
+        Ok(ProcessTap { id })
+    }
+
+    pub fn uid(&self) -> Result<CFString, CFError> {
+        unsafe {
+            properties::get(
+                element::Master,
+                scope::Global,
+                selector::TapPropertyUID,
+                self.id,
+            )
+        }
+    }
+}
+
+impl Drop for ProcessTap {
+    fn drop(&mut self) {
+        unsafe {
+            AudioHardwareDestroyProcessTap(self.id);
+        }
+    }
+}
+
+/// Finds the HAL's process object for `target`. Only processes that have
+/// used audio since they launched have one.
+fn process_object(target: &CaptureTarget) -> Result<AudioObjectID, CFError> {
+    let not_found = CFError::Status(kAudioHardwareBadObjectError as OSStatus);
+
+    match target {
+        CaptureTarget::Pid(pid) => {
+            let process = unsafe {
+                properties::get_qualified(
+                    element::Master,
+                    scope::Global,
+                    selector::HardwarePropertyTranslatePIDToProcessObject,
+                    pid,
+                    kAudioObjectSystemObject,
+                )?
+            };
+
+            if process == kAudioObjectUnknown {
+                Err(not_found)
+            } else {
+                Ok(process)
+            }
+        }
+        CaptureTarget::System => Err(not_found),
+        CaptureTarget::BundleId(bundle_id) => {
+            let processes = unsafe {
+                properties::get(
+                    element::Master,
+                    scope::Global,
+                    selector::HardwarePropertyProcessObjectList,
+                    kAudioObjectSystemObject,
+                )?
+            };
+
+            for process in processes {
+                let process_bundle_id = unsafe {
+                    properties::get(
+                        element::Master,
+                        scope::Global,
+                        selector::ProcessPropertyBundleID,
+                        process,
+                    )
+                };
+
+                // Processes without a bundle fail rather than returning an
+                // empty string.
+                if let Ok(process_bundle_id) = process_bundle_id {
+                    if &process_bundle_id.to_string() == bundle_id {
+                        return Ok(process);
+                    }
+                }
+            }
+
+            Err(not_found)
+        }
+    }
+}
+
+unsafe fn class(name: &[u8]) -> *mut c_void {
+    objc_getClass(CStr::from_bytes_with_nul(name).unwrap().as_ptr())
+}
+
+unsafe fn selector_named(name: &[u8]) -> *mut c_void {
+    sel_registerName(CStr::from_bytes_with_nul(name).unwrap().as_ptr())
+}
+
+unsafe fn send_id(receiver: *mut c_void, sel: *mut c_void) -> *mut c_void {
+    let send: unsafe extern "C" fn(*mut c_void, *mut c_void) -> *mut c_void =
+        mem::transmute(objc_msgSend as unsafe extern "C" fn());
+    send(receiver, sel)
+}
+
+unsafe fn send_id_id(receiver: *mut c_void, sel: *mut c_void, arg: *mut c_void) -> *mut c_void {
+    let send: unsafe extern "C" fn(*mut c_void, *mut c_void, *mut c_void) -> *mut c_void =
+        mem::transmute(objc_msgSend as unsafe extern "C" fn());
+    send(receiver, sel, arg)
+}
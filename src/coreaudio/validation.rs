@@ -0,0 +1,337 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use coreaudio_sys::{AudioBuffer, AudioBufferList};
+
+/// Streams beyond this are not checked against the expected layout. Large
+/// enough for an aggregate of several 64 channel interfaces with mono streams.
+const MAX_STREAMS: usize = 256;
+const SAMPLE_SIZE: u32 = std::mem::size_of::<f32>() as u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferListSide {
+    Input,
+    Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferListErrorKind {
+    NullData,
+    ZeroChannels,
+    /// So many channels that the size of a frame doesn't fit in a `u32`.
+    FrameSizeOverflow(u32),
+    UnalignedByteSize(u32),
+    FrameCountMismatch(u32),
+    BufferCountMismatch {
+        expected: u32,
+    },
+    ChannelCountMismatch {
+        expected: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferListError {
+    pub side: BufferListSide,
+    pub buffer: u32,
+    pub kind: BufferListErrorKind,
+}
+
+impl BufferListError {
+    /// Whether the buffer list is malformed in a way that makes it unsafe to
+    /// hand to the render callback, as opposed to merely disagreeing with the
+    /// stream configuration we expected.
+    pub fn is_fatal(&self) -> bool {
+        match self.kind {
+            BufferListErrorKind::NullData
+            | BufferListErrorKind::ZeroChannels
+            | BufferListErrorKind::FrameSizeOverflow(_)
+            | BufferListErrorKind::UnalignedByteSize(_) => true,
+            BufferListErrorKind::FrameCountMismatch(_)
+            | BufferListErrorKind::BufferCountMismatch { .. }
+            | BufferListErrorKind::ChannelCountMismatch { .. } => false,
+        }
+    }
+
+    fn pack(&self) -> u64 {
+        let (kind, value) = match self.kind {
+            BufferListErrorKind::NullData => (0, 0),
+            BufferListErrorKind::ZeroChannels => (1, 0),
+            BufferListErrorKind::UnalignedByteSize(size) => (2, size),
+            BufferListErrorKind::FrameCountMismatch(frames) => (3, frames),
+            BufferListErrorKind::BufferCountMismatch { expected } => (4, expected),
+            BufferListErrorKind::ChannelCountMismatch { expected } => (5, expected),
+            BufferListErrorKind::FrameSizeOverflow(channels) => (6, channels),
+        };
+        let side = match self.side {
+            BufferListSide::Input => 0,
+            BufferListSide::Output => 1,
+        };
+
+        (u64::from(value) << 32)
+            | (side << 31)
+            | (kind << 28)
+            | u64::from(self.buffer & 0x0fff_ffff)
+    }
+
+    fn unpack(packed: u64) -> Self {
+        let value = (packed >> 32) as u32;
+        let side = if (packed >> 31) & 1 == 0 {
+            BufferListSide::Input
+        } else {
+            BufferListSide::Output
+        };
+        let kind = match (packed >> 28) & 0x7 {
+            0 => BufferListErrorKind::NullData,
+            1 => BufferListErrorKind::ZeroChannels,
+            2 => BufferListErrorKind::UnalignedByteSize(value),
+            3 => BufferListErrorKind::FrameCountMismatch(value),
+            4 => BufferListErrorKind::BufferCountMismatch { expected: value },
+            5 => BufferListErrorKind::ChannelCountMismatch { expected: value },
+            _ => BufferListErrorKind::FrameSizeOverflow(value),
+        };
+
+        BufferListError {
+            side,
+            buffer: (packed & 0x0fff_ffff) as u32,
+            kind,
+        }
+    }
+}
+
+/// Channel counts per stream as reported by the device's stream
+/// configuration. Kept in atomics so the control thread can update it while
+/// the IOProc is running.
+struct StreamLayout {
+    count: AtomicUsize,
+    channels: [AtomicU32; MAX_STREAMS],
+}
+
+impl StreamLayout {
+    fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU32 = AtomicU32::new(0);
+
+        StreamLayout {
+            count: AtomicUsize::new(0),
+            channels: [ZERO; MAX_STREAMS],
+        }
+    }
+
+    fn store(&self, config: &AudioBufferList) {
+        let buffers = unsafe { buffers(config) };
+        for (slot, buffer) in self.channels.iter().zip(buffers) {
+            slot.store(buffer.mNumberChannels, Ordering::Relaxed);
+        }
+        self.count.store(buffers.len(), Ordering::Release);
+    }
+
+    fn check(&self, side: BufferListSide, buffers: &[AudioBuffer]) -> Result<(), BufferListError> {
+        let count = self.count.load(Ordering::Acquire);
+        if count != buffers.len() {
+            return Err(BufferListError {
+                side,
+                buffer: buffers.len() as u32,
+                kind: BufferListErrorKind::BufferCountMismatch {
+                    expected: count as u32,
+                },
+            });
+        }
+
+        for (index, (slot, buffer)) in self.channels.iter().zip(buffers).enumerate() {
+            let expected = slot.load(Ordering::Relaxed);
+            if expected != buffer.mNumberChannels {
+                return Err(BufferListError {
+                    side,
+                    buffer: index as u32,
+                    kind: BufferListErrorKind::ChannelCountMismatch { expected },
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct BufferListValidator {
+    input: StreamLayout,
+    output: StreamLayout,
+    failures: AtomicU64,
+    last_failure: AtomicU64,
+}
+
+impl BufferListValidator {
+    pub fn new() -> Self {
+        BufferListValidator {
+            input: StreamLayout::new(),
+            output: StreamLayout::new(),
+            failures: AtomicU64::new(0),
+            last_failure: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_input_layout(&self, config: &AudioBufferList) {
+        self.input.store(config);
+    }
+
+    pub fn set_output_layout(&self, config: &AudioBufferList) {
+        self.output.store(config);
+    }
+
+    /// Checks the buffer lists handed to the IOProc. Fatal errors take
+    /// precedence over mismatches against the expected layout, so that the
+    /// caller can decide whether the buffers are safe to use. Must not
+    /// allocate or block: this runs on the real-time thread.
+    pub fn validate(
+        &self,
+        input: &AudioBufferList,
+        output: &AudioBufferList,
+    ) -> Result<(), BufferListError> {
+        let input = unsafe { buffers(input) };
+        let output = unsafe { buffers(output) };
+
+        let mut frames = None;
+        let mut mismatch = None;
+        check_buffers(BufferListSide::Input, input, &mut frames, &mut mismatch)?;
+        check_buffers(BufferListSide::Output, output, &mut frames, &mut mismatch)?;
+
+        if let Some(error) = mismatch {
+            return Err(error);
+        }
+
+        self.input.check(BufferListSide::Input, input)?;
+        self.output.check(BufferListSide::Output, output)
+    }
+
+    /// Real-time safe record of a failed validation, readable from the
+    /// control thread through `failure_count` and `last_failure`.
+    pub fn record(&self, error: BufferListError) {
+        self.last_failure.store(error.pack(), Ordering::Relaxed);
+        self.failures.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn failure_count(&self) -> u64 {
+        self.failures.load(Ordering::Acquire)
+    }
+
+    pub fn last_failure(&self) -> Option<BufferListError> {
+        if self.failure_count() == 0 {
+            None
+        } else {
+            Some(BufferListError::unpack(
+                self.last_failure.load(Ordering::Relaxed),
+            ))
+        }
+    }
+}
+
+fn check_buffers(
+    side: BufferListSide,
+    buffers: &[AudioBuffer],
+    frames: &mut Option<u32>,
+    mismatch: &mut Option<BufferListError>,
+) -> Result<(), BufferListError> {
+    for (index, buffer) in buffers.iter().enumerate() {
+        let error = |kind| BufferListError {
+            side,
+            buffer: index as u32,
+            kind,
+        };
+
+        if buffer.mNumberChannels == 0 {
+            return Err(error(BufferListErrorKind::ZeroChannels));
+        }
+
+        if buffer.mDataByteSize > 0 && buffer.mData.is_null() {
+            return Err(error(BufferListErrorKind::NullData));
+        }
+
+        let frame_size = match SAMPLE_SIZE.checked_mul(buffer.mNumberChannels) {
+            Some(frame_size) => frame_size,
+            None => {
+                return Err(error(BufferListErrorKind::FrameSizeOverflow(
+                    buffer.mNumberChannels,
+                )))
+            }
+        };
+        if buffer.mDataByteSize % frame_size != 0 {
+            return Err(error(BufferListErrorKind::UnalignedByteSize(
+                buffer.mDataByteSize,
+            )));
+        }
+
+        let buffer_frames = buffer.mDataByteSize / frame_size;
+        match *frames {
+            None => *frames = Some(buffer_frames),
+            Some(expected) if expected != buffer_frames => {
+                mismatch.get_or_insert(error(BufferListErrorKind::FrameCountMismatch(
+                    buffer_frames,
+                )));
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+unsafe fn buffers(list: &AudioBufferList) -> &[AudioBuffer] {
+    std::slice::from_raw_parts(list.mBuffers.as_ptr(), list.mNumberBuffers as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::c_void;
+
+    use super::*;
+
+    fn list(channels: u32, samples: &mut [f32]) -> AudioBufferList {
+        AudioBufferList {
+            mNumberBuffers: 1,
+            mBuffers: [AudioBuffer {
+                mNumberChannels: channels,
+                mDataByteSize: (samples.len() * SAMPLE_SIZE as usize) as u32,
+                mData: samples.as_mut_ptr() as *mut c_void,
+            }],
+        }
+    }
+
+    fn empty() -> AudioBufferList {
+        AudioBufferList {
+            mNumberBuffers: 0,
+            mBuffers: [AudioBuffer {
+                mNumberChannels: 0,
+                mDataByteSize: 0,
+                mData: std::ptr::null_mut(),
+            }],
+        }
+    }
+
+    #[test]
+    fn frame_size_overflow_is_fatal() {
+        let mut samples = [0.0; 16];
+        let validator = BufferListValidator::new();
+
+        // 4 * 2^30 wraps to 0, which used to divide by zero.
+        for &channels in &[1 << 30, u32::MAX] {
+            let input = list(channels, &mut samples);
+            let error = validator.validate(&input, &empty()).unwrap_err();
+
+            assert_eq!(error.kind, BufferListErrorKind::FrameSizeOverflow(channels));
+            assert!(error.is_fatal());
+
+            validator.record(error);
+            assert_eq!(validator.last_failure(), Some(error));
+        }
+    }
+
+    #[test]
+    fn well_formed_list_passes() {
+        let mut samples = [0.0; 16];
+        let validator = BufferListValidator::new();
+        let input = list(2, &mut samples);
+        validator.set_input_layout(&input);
+        validator.set_output_layout(&empty());
+
+        assert_eq!(validator.validate(&input, &empty()), Ok(()));
+    }
+}
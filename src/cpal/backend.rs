@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use ::cpal::traits::HostTrait;
+use ::cpal::{Host, HostId};
+
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::processor::{processor_callback, Processor};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::CpalDevice;
+use super::error::CpalError;
+use super::session::CpalSession;
+
+/// Bridges to cpal, for platforms without a native backend here. Uses
+/// cpal's default host unless created with `with_host`.
+pub struct CpalBackend {
+    host: Arc<Host>,
+}
+
+impl CpalBackend {
+    /// A backend on a specific cpal host, like JACK or ALSA on Linux.
+    pub fn with_host(id: HostId) -> Result<Self, CpalError> {
+        Ok(CpalBackend {
+            host: Arc::new(::cpal::host_from_id(id)?),
+        })
+    }
+
+    pub fn host_id(&self) -> HostId {
+        self.host.id()
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(&self, config: &SessionConfig<Self>) -> Result<CpalSession, CpalError> {
+        if config.physical_format.is_some() {
+            return Err(CpalError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(CpalError::Unsupported("preroll"));
+        }
+        if config.routing.is_some() {
+            return Err(CpalError::Unsupported("routing matrices"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(CpalError::Unsupported("more than one device per direction"));
+        }
+
+        let mut session = CpalSession::new(
+            config.retry,
+            config.sample_rate,
+            config.input_device.clone(),
+            config.output_device.clone(),
+        );
+        if let Some(frames) = config.buffer_size {
+            session.set_buffer_size(frames)?;
+        }
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for CpalBackend {
+    type Session = CpalSession;
+    type Error = CpalError;
+    type Device = CpalDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    fn new() -> Result<Self, CpalError> {
+        Ok(CpalBackend {
+            host: Arc::new(::cpal::default_host()),
+        })
+    }
+
+    fn is_available() -> bool {
+        !::cpal::available_hosts().is_empty()
+    }
+
+    fn all_devices(&self) -> Result<Vec<CpalDevice>, CpalError> {
+        self.host
+            .devices()?
+            .map(|device| CpalDevice::new(device, self.host.clone()))
+            .collect()
+    }
+
+    fn default_input_device(&self) -> Result<CpalDevice, CpalError> {
+        let device = self
+            .host
+            .default_input_device()
+            .ok_or(CpalError::NoDefaultDevice)?;
+
+        CpalDevice::new(device, self.host.clone())
+    }
+
+    fn default_output_device(&self) -> Result<CpalDevice, CpalError> {
+        let device = self
+            .host
+            .default_output_device()
+            .ok_or(CpalError::NoDefaultDevice)?;
+
+        CpalDevice::new(device, self.host.clone())
+    }
+
+    /// cpal opens each device at the session's rate in 32 bit float, so a
+    /// `physical_format` is refused, and so are preroll, routing and
+    /// additional devices. The clock master and application name are
+    /// ignored.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<CpalSession, CpalError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<CpalSession, CpalError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<CpalSession, CpalError> {
+        Err(CpalError::Unsupported("process taps"))
+    }
+}
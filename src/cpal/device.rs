@@ -0,0 +1,172 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use ::cpal::traits::{DeviceTrait, HostTrait};
+use ::cpal::{Host, SupportedBufferSize, SupportedStreamConfig};
+
+use crate::direction::Direction;
+use crate::traits::Device;
+
+use super::backend::CpalBackend;
+use super::error::CpalError;
+
+/// A device of the cpal host the backend was created with.
+///
+/// Devices compare and order by their cpal ID.
+#[derive(Clone)]
+pub struct CpalDevice {
+    device: ::cpal::Device,
+    id: String,
+    host: Arc<Host>,
+}
+
+impl CpalDevice {
+    pub(crate) fn new(device: ::cpal::Device, host: Arc<Host>) -> Result<Self, CpalError> {
+        let id = device.id()?.to_string();
+
+        Ok(CpalDevice { device, id, host })
+    }
+
+    fn is(&self, device: Option<::cpal::Device>) -> Result<bool, CpalError> {
+        match device {
+            Some(device) => Ok(device.id()?.to_string() == self.id),
+            None => Ok(false),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn cpal_device(&self) -> &::cpal::Device {
+        &self.device
+    }
+
+    /// The configuration the device runs at by default in `direction`, or
+    /// None if it has no streams that way.
+    pub(crate) fn default_config(&self, direction: Direction) -> Option<SupportedStreamConfig> {
+        match direction {
+            Direction::Input if self.device.supports_input() => {
+                self.device.default_input_config().ok()
+            }
+            Direction::Output if self.device.supports_output() => {
+                self.device.default_output_config().ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// The buffer sizes the device supports in `direction`, if it says.
+    pub(crate) fn buffer_size_range(&self, direction: Direction) -> Option<(usize, usize)> {
+        match self
+            .default_config(direction)
+            .map(|config| *config.buffer_size())
+        {
+            Some(SupportedBufferSize::Range { min, max }) => Some((min as usize, max as usize)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn channels(&self, direction: Direction) -> usize {
+        self.default_config(direction)
+            .map_or(0, |config| usize::from(config.channels()))
+    }
+}
+
+impl fmt::Debug for CpalDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CpalDevice").field(&self.id).finish()
+    }
+}
+
+impl PartialEq for CpalDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for CpalDevice {}
+
+impl Hash for CpalDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl PartialOrd for CpalDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CpalDevice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl Device<CpalBackend> for CpalDevice {
+    fn num_inputs(&self) -> Result<usize, CpalError> {
+        Ok(self.channels(Direction::Input))
+    }
+
+    fn num_outputs(&self) -> Result<usize, CpalError> {
+        Ok(self.channels(Direction::Output))
+    }
+
+    fn name(&self) -> Result<String, CpalError> {
+        Ok(self.device.description()?.name().to_owned())
+    }
+
+    /// The cpal ID, which starts with the name of the host.
+    fn persistent_id(&self) -> Result<String, CpalError> {
+        Ok(self.id.clone())
+    }
+
+    /// cpal opens streams at whatever rate they ask for, so sessions pick
+    /// their own rate instead.
+    fn is_default_input(&self) -> Result<bool, CpalError> {
+        self.is(self.host.default_input_device())
+    }
+
+    fn is_default_output(&self) -> Result<bool, CpalError> {
+        self.is(self.host.default_output_device())
+    }
+
+    fn set_nominal_sample_rate(&mut self, _sample_rate: f64) -> Result<(), CpalError> {
+        Err(CpalError::Unsupported(
+            "changing a device's sample rate outside of a session",
+        ))
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), CpalError> {
+        Err(CpalError::Unsupported("selecting clock sources"))
+    }
+
+    /// The rate of the device's default output configuration, or its input
+    /// one for input-only devices.
+    fn nominal_sample_rate(&self) -> Result<f64, CpalError> {
+        let config = match self.default_config(Direction::Output) {
+            Some(config) => config,
+            None => self.device.default_input_config()?,
+        };
+
+        Ok(f64::from(config.sample_rate()))
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, CpalError> {
+        self.nominal_sample_rate()
+    }
+
+    /// From the default output configuration, or the input one for
+    /// input-only devices.
+    fn buffer_frame_size_range(&self) -> Result<Option<RangeInclusive<usize>>, CpalError> {
+        Ok(self
+            .buffer_size_range(Direction::Output)
+            .or_else(|| self.buffer_size_range(Direction::Input))
+            .map(|(min, max)| min..=max))
+    }
+}
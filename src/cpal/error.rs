@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use ::cpal::{
+    BuildStreamError, DefaultStreamConfigError, DeviceIdError, DeviceNameError, DevicesError,
+    HostUnavailable, PauseStreamError, PlayStreamError,
+};
+
+#[derive(Debug)]
+pub enum CpalError {
+    HostUnavailable(HostUnavailable),
+    Devices(DevicesError),
+    DeviceId(DeviceIdError),
+    DeviceName(DeviceNameError),
+    DefaultConfig(DefaultStreamConfigError),
+    BuildStream(BuildStreamError),
+    PlayStream(PlayStreamError),
+    PauseStream(PauseStreamError),
+    /// The host has no default device in the direction one was asked for.
+    NoDefaultDevice,
+    /// The session was started, but the host never called it.
+    StartupTimeout(Duration),
+    /// The operation or session option has no cpal equivalent.
+    Unsupported(&'static str),
+}
+
+impl CpalError {
+    /// Whether the error is expected to clear up by itself, like the device
+    /// being busy.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            CpalError::BuildStream(BuildStreamError::DeviceNotAvailable)
+        )
+    }
+}
+
+impl From<HostUnavailable> for CpalError {
+    fn from(error: HostUnavailable) -> Self {
+        CpalError::HostUnavailable(error)
+    }
+}
+
+impl From<DevicesError> for CpalError {
+    fn from(error: DevicesError) -> Self {
+        CpalError::Devices(error)
+    }
+}
+
+impl From<DeviceIdError> for CpalError {
+    fn from(error: DeviceIdError) -> Self {
+        CpalError::DeviceId(error)
+    }
+}
+
+impl From<DeviceNameError> for CpalError {
+    fn from(error: DeviceNameError) -> Self {
+        CpalError::DeviceName(error)
+    }
+}
+
+impl From<DefaultStreamConfigError> for CpalError {
+    fn from(error: DefaultStreamConfigError) -> Self {
+        CpalError::DefaultConfig(error)
+    }
+}
+
+impl From<BuildStreamError> for CpalError {
+    fn from(error: BuildStreamError) -> Self {
+        CpalError::BuildStream(error)
+    }
+}
+
+impl From<PlayStreamError> for CpalError {
+    fn from(error: PlayStreamError) -> Self {
+        CpalError::PlayStream(error)
+    }
+}
+
+impl From<PauseStreamError> for CpalError {
+    fn from(error: PauseStreamError) -> Self {
+        CpalError::PauseStream(error)
+    }
+}
+
+impl fmt::Display for CpalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpalError::HostUnavailable(error) => write!(f, "cpal error: {}", error),
+            CpalError::Devices(error) => write!(f, "cpal error: {}", error),
+            CpalError::DeviceId(error) => write!(f, "cpal error: {}", error),
+            CpalError::DeviceName(error) => write!(f, "cpal error: {}", error),
+            CpalError::DefaultConfig(error) => write!(f, "cpal error: {}", error),
+            CpalError::BuildStream(error) => write!(f, "cpal error: {}", error),
+            CpalError::PlayStream(error) => write!(f, "cpal error: {}", error),
+            CpalError::PauseStream(error) => write!(f, "cpal error: {}", error),
+            CpalError::NoDefaultDevice => write!(f, "cpal host has no default device"),
+            CpalError::StartupTimeout(timeout) => {
+                write!(
+                    f,
+                    "cpal host did not start the session within {:?}",
+                    timeout
+                )
+            }
+            CpalError::Unsupported(what) => write!(f, "Not supported by cpal: {}", what),
+        }
+    }
+}
+
+impl Error for CpalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CpalError::HostUnavailable(error) => Some(error),
+            CpalError::Devices(error) => Some(error),
+            CpalError::DeviceId(error) => Some(error),
+            CpalError::DeviceName(error) => Some(error),
+            CpalError::DefaultConfig(error) => Some(error),
+            CpalError::BuildStream(error) => Some(error),
+            CpalError::PlayStream(error) => Some(error),
+            CpalError::PauseStream(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<CpalError> for crate::Error {
+    fn from(error: CpalError) -> Self {
+        let kind = match &error {
+            CpalError::DefaultConfig(DefaultStreamConfigError::DeviceNotAvailable)
+            | CpalError::BuildStream(BuildStreamError::DeviceNotAvailable)
+            | CpalError::PlayStream(PlayStreamError::DeviceNotAvailable)
+            | CpalError::PauseStream(PauseStreamError::DeviceNotAvailable) => {
+                Self::DeviceDisconnected
+            }
+            CpalError::BuildStream(BuildStreamError::StreamConfigNotSupported) => {
+                Self::FormatNotSupported
+            }
+            CpalError::HostUnavailable(_)
+            | CpalError::DeviceId(DeviceIdError::UnsupportedPlatform)
+            | CpalError::DefaultConfig(DefaultStreamConfigError::StreamTypeNotSupported) => {
+                Self::Unsupported
+            }
+            CpalError::NoDefaultDevice => Self::DeviceNotFound,
+            CpalError::StartupTimeout(_) => Self::StartupTimeout,
+            CpalError::Unsupported(_) => Self::Unsupported,
+            _ => Self::Backend,
+        };
+
+        kind(Box::new(error))
+    }
+}
@@ -0,0 +1,9 @@
+mod backend;
+mod device;
+mod error;
+mod session;
+
+pub use backend::CpalBackend as Backend;
+pub use device::CpalDevice;
+pub use error::CpalError;
+pub use session::CpalSession;
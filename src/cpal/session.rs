@@ -0,0 +1,506 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ::cpal::traits::{DeviceTrait, StreamTrait};
+use ::cpal::{BufferSize, FrameCount, Stream, StreamConfig, StreamError, SupportedBufferSize};
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::CycleTimes;
+use crate::deadline::DeadlineHistogram;
+use crate::direction::Direction;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::{Events, SessionEvent, StopCause};
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::retry::{retry, RetryPolicy};
+use crate::ring_buffer::{ring_buffer, Consumer};
+use crate::sample::OwnedBuffer;
+use crate::traits::{RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+
+use super::backend::CpalBackend;
+use super::device::CpalDevice;
+use super::error::CpalError;
+
+/// How long each buffer size is tried out for when tuning for low latency.
+const LATENCY_TRIAL_PERIOD: Duration = Duration::from_secs(1);
+
+/// The buffer sizes tried when tuning for low latency, within what the
+/// device supports.
+const MIN_TUNED_BUFFER_SIZE: usize = 16;
+const MAX_TUNED_BUFFER_SIZE: usize = 4096;
+
+/// The most frames a callback gets when the host picks the buffer size.
+/// Larger host buffers are rendered in several callbacks.
+const DEFAULT_MAX_FRAMES: usize = 4096;
+
+/// Input is kept this many callbacks ahead of the output at most, and the
+/// rest dropped, so drift between the two streams doesn't build up latency.
+const MAX_BUFFERED_INPUT: usize = 2;
+
+/// A cpal output stream, and an input stream if the input device has any
+/// input channels, both as 32 bit float. cpal has no duplex streams, so the
+/// input stream queues its audio for the output stream's callback, which
+/// renders. Each callback gets one buffer per direction, with the device's
+/// default channel count.
+///
+/// The sample time counts the frames rendered since the streams were built.
+pub struct CpalSession {
+    engine: Arc<RenderEngine<CpalBackend>>,
+    input: CpalDevice,
+    output: CpalDevice,
+    sample_rate: f64,
+    buffer_size: BufferSize,
+    /// How building the streams is retried when starting.
+    retry: RetryPolicy,
+    /// Underruns and overruns the host reported.
+    xruns: Arc<AtomicU64>,
+    streams: Option<Streams>,
+}
+
+struct Streams {
+    input: Option<Stream>,
+    output: Stream,
+}
+
+impl CpalSession {
+    pub(crate) fn new(
+        retry: RetryPolicy,
+        sample_rate: f64,
+        input: CpalDevice,
+        output: CpalDevice,
+    ) -> Self {
+        let engine = Arc::new(RenderEngine::new(sample_rate));
+
+        CpalSession {
+            engine,
+            input,
+            output,
+            sample_rate,
+            buffer_size: BufferSize::Default,
+            retry,
+            xruns: Arc::new(AtomicU64::new(0)),
+            streams: None,
+        }
+    }
+
+    /// Builds the streams, retrying while the devices are busy, and plays
+    /// them with `callback`.
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<CpalBackend>>,
+    ) -> Result<(), CpalError> {
+        self.engine.set_callback(callback);
+        retry(self.retry, CpalError::is_transient, || self.run())
+    }
+
+    fn layout(&self) -> (usize, usize) {
+        (
+            self.input.channels(Direction::Input),
+            self.output.channels(Direction::Output),
+        )
+    }
+
+    /// Builds and plays the streams, unless they already are.
+    fn run(&mut self) -> Result<(), CpalError> {
+        if self.streams.is_some() {
+            return Ok(());
+        }
+
+        let (input_channels, output_channels) = self.layout();
+        if output_channels == 0 {
+            return Err(CpalError::Unsupported("output devices without outputs"));
+        }
+        let max_frames = self.max_frames_per_callback();
+        self.engine
+            .resize_scratch(max_frames, input_channels.max(output_channels));
+
+        let (producer, consumer) =
+            ring_buffer((MAX_BUFFERED_INPUT + 2) * max_frames * input_channels.max(1));
+        let config = |channels: usize| StreamConfig {
+            channels: channels as u16,
+            sample_rate: self.sample_rate as u32,
+            buffer_size: self.buffer_size,
+        };
+
+        let input = if input_channels > 0 {
+            let mut producer = producer;
+            let stream = self.input.cpal_device().build_input_stream(
+                &config(input_channels),
+                move |data: &[f32], _: &_| {
+                    // Dropped when the output side stops taking input.
+                    producer.try_push(data);
+                },
+                self.error_callback(),
+                None,
+            )?;
+            Some(stream)
+        } else {
+            None
+        };
+
+        let mut process = Process {
+            engine: self.engine.clone(),
+            input: consumer,
+            input_buffers: vec![buffer(max_frames, input_channels)],
+            output_buffers: vec![buffer(max_frames, output_channels)],
+            max_frames,
+            sample_time: 0,
+        };
+        let output = self.output.cpal_device().build_output_stream(
+            &config(output_channels),
+            move |data: &mut [f32], _: &_| process.process(data),
+            self.error_callback(),
+            None,
+        )?;
+
+        self.engine.reset_timeline();
+        self.engine.valid.store(true, Ordering::Release);
+        if let Some(input) = &input {
+            input.play()?;
+        }
+        output.play()?;
+        self.streams = Some(Streams { input, output });
+
+        Ok(())
+    }
+
+    /// Returns whether the streams were running.
+    fn halt(&mut self) -> Result<bool, CpalError> {
+        match self.streams.take() {
+            Some(streams) => {
+                self.engine.valid.store(false, Ordering::Release);
+                streams.output.pause()?;
+                if let Some(input) = &streams.input {
+                    input.pause()?;
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Builds new streams for the current devices and buffer size.
+    fn rebuild(&mut self) -> Result<(), CpalError> {
+        if self.halt()? {
+            self.run()?;
+        }
+
+        Ok(())
+    }
+
+    fn error_callback(&self) -> impl FnMut(StreamError) + Send + 'static {
+        let engine = self.engine.clone();
+        let xruns = self.xruns.clone();
+
+        move |error| match error {
+            StreamError::BufferUnderrun => {
+                xruns.fetch_add(1, Ordering::Relaxed);
+            }
+            StreamError::DeviceNotAvailable | StreamError::StreamInvalidated => {
+                if engine.valid.swap(false, Ordering::AcqRel) {
+                    let cause = match error {
+                        StreamError::DeviceNotAvailable => StopCause::DeviceDied,
+                        _ => StopCause::Unknown,
+                    };
+                    engine
+                        .events
+                        .push(SessionEvent::StoppedUnexpectedly { cause });
+                }
+            }
+            StreamError::BackendSpecific { .. } => {}
+        }
+    }
+
+    /// Asks the host for `frames` frames per callback, rebuilding the
+    /// streams if they're running.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), CpalError> {
+        self.buffer_size = BufferSize::Fixed(frames as FrameCount);
+        self.rebuild()
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        match self.buffer_size {
+            BufferSize::Fixed(frames) => frames as usize,
+            BufferSize::Default => match self
+                .output
+                .default_config(Direction::Output)
+                .map(|config| *config.buffer_size())
+            {
+                Some(SupportedBufferSize::Range { max, .. }) => {
+                    (max as usize).clamp(1, DEFAULT_MAX_FRAMES)
+                }
+                _ => DEFAULT_MAX_FRAMES,
+            },
+        }
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        let (input, output) = self.layout();
+        (vec![input], vec![output])
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.engine.set_output_policy(policy);
+    }
+
+    /// Blocks until the host has called the session at least once.
+    pub fn wait_until_running(&self, timeout: Duration) -> Result<(), CpalError> {
+        let deadline = Instant::now() + timeout;
+
+        while self.engine.cycles.load(Ordering::Acquire) == 0 {
+            if Instant::now() >= deadline {
+                return Err(CpalError::StartupTimeout(timeout));
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for CpalSession {
+    fn drop(&mut self) {
+        let _ = self.halt();
+
+        // Dropping the streams joins their callbacks, so this drops the
+        // render callback here rather than on the real-time thread.
+        drop(self.engine.take_callback());
+    }
+}
+
+/// The output stream's side of the session.
+struct Process {
+    engine: Arc<RenderEngine<CpalBackend>>,
+    input: Consumer,
+    input_buffers: Vec<OwnedBuffer<f32>>,
+    output_buffers: Vec<OwnedBuffer<f32>>,
+    max_frames: usize,
+    sample_time: u64,
+}
+
+impl Process {
+    fn process(&mut self, data: &mut [f32]) {
+        let input_channels = self.input_buffers[0].num_channels();
+        let output_channels = self.output_buffers[0].num_channels();
+
+        for chunk in data.chunks_mut(self.max_frames * output_channels) {
+            self.engine.cycles.fetch_add(1, Ordering::Release);
+            let frames = chunk.len() / output_channels;
+            self.input_buffers[0].reshape(frames, input_channels);
+            self.output_buffers[0].reshape(frames, output_channels);
+
+            let input = self.input_buffers[0].interleaved_frames_mut();
+            while self.input.len() > MAX_BUFFERED_INPUT * input.len().max(1) {
+                self.input.pop(input);
+            }
+            let read = self.input.pop(input);
+            // Silence until the input stream catches up.
+            input[read..].fill(0.0);
+
+            unsafe {
+                self.engine.render(
+                    &self.input_buffers,
+                    &mut self.output_buffers,
+                    frames,
+                    CycleTimes::at(self.sample_time as f64),
+                );
+            }
+            self.sample_time += frames as u64;
+
+            chunk.copy_from_slice(self.output_buffers[0].interleaved_frames());
+        }
+    }
+}
+
+fn buffer(frames: usize, channels: usize) -> OwnedBuffer<f32> {
+    let mut buffer = OwnedBuffer::with_capacity(frames, channels);
+    buffer.reshape(frames, channels);
+    buffer
+}
+
+impl Session<CpalBackend> for CpalSession {
+    fn input_device(&self) -> Result<CpalDevice, CpalError> {
+        Ok(self.input.clone())
+    }
+
+    fn output_device(&self) -> Result<CpalDevice, CpalError> {
+        Ok(self.output.clone())
+    }
+
+    fn set_input_device(&mut self, device: CpalDevice) -> Result<(), CpalError> {
+        self.input = device;
+        self.rebuild()
+    }
+
+    fn set_output_device(&mut self, device: CpalDevice) -> Result<(), CpalError> {
+        self.output = device;
+        self.rebuild()
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, CpalError> {
+        Ok(CpalSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, CpalError> {
+        let (input_channels, output_channels) = self.layout();
+        let side = |device: &CpalDevice, channels: usize| {
+            vec![StreamMapping {
+                device_id: device.id().to_owned(),
+                first_device_channel: 0,
+                channels,
+            }]
+        };
+
+        Ok(ChannelMap {
+            input: side(&self.input, input_channels),
+            output: side(&self.output, output_channels),
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.engine.clock.clone()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<CpalBackend>>) {
+        self.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.engine.is_bypassed()
+    }
+
+    /// Tries fixed buffer sizes within what the output device supports,
+    /// watching for the underruns and overruns the host reports. cpal
+    /// doesn't say how much latency the hardware adds, so only the buffers
+    /// are counted.
+    fn tune_for_low_latency(&mut self, target_ms: f64) -> Result<Option<LatencyTuning>, CpalError> {
+        let target_frames = (target_ms * self.sample_rate / 1000.0) as usize;
+        let (min, max) = self
+            .output
+            .buffer_size_range(Direction::Output)
+            .unwrap_or((MIN_TUNED_BUFFER_SIZE, MAX_TUNED_BUFFER_SIZE));
+
+        let mut candidates = Vec::new();
+        let mut size = MIN_TUNED_BUFFER_SIZE;
+        while size <= MAX_TUNED_BUFFER_SIZE {
+            if size >= min && size <= max {
+                candidates.push(size);
+            }
+            size *= 2;
+        }
+        if candidates.is_empty() {
+            return Err(CpalError::Unsupported(
+                "tuning devices without power of two buffer sizes",
+            ));
+        }
+
+        // Start from the largest size within the target: anything smaller
+        // only adds risk of dropouts.
+        let first = candidates
+            .iter()
+            .rposition(|&size| 2 * size <= target_frames)
+            .unwrap_or(0);
+
+        let original = self.buffer_size;
+        let mut stable = false;
+        let mut buffer_size = CpalSession::max_frames_per_callback(self);
+        for &size in &candidates[first..] {
+            self.set_buffer_size(size)?;
+            buffer_size = size;
+
+            let before = self.xruns.load(Ordering::Relaxed);
+            thread::sleep(LATENCY_TRIAL_PERIOD);
+
+            if self.xruns.load(Ordering::Relaxed) == before {
+                stable = true;
+                break;
+            }
+        }
+
+        if !stable {
+            // Nothing ran cleanly, so leave the session as it was found.
+            self.buffer_size = original;
+            self.rebuild()?;
+            return Ok(None);
+        }
+
+        Ok(Some(LatencyTuning {
+            buffer_size,
+            input_latency_frames: buffer_size,
+            output_latency_frames: buffer_size,
+            sample_rate: self.sample_rate,
+        }))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, CpalError> {
+        Err(CpalError::Unsupported("starting at a host time"))
+    }
+
+    fn stop(&mut self) -> Result<(), CpalError> {
+        self.halt()?;
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), CpalError> {
+        retry(self.retry, CpalError::is_transient, || self.run())
+    }
+
+    fn is_running(&self) -> bool {
+        self.streams.is_some()
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+pub const DEADLINE_BUCKETS: usize = 10;
+
+/// How much of each callback's time budget was left when it returned, where
+/// the budget is the duration of audio the callback rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeadlineHistogram {
+    /// Callbacks that took longer than their budget.
+    pub overruns: u64,
+    /// `buckets[n]` counts callbacks that returned with between `n` and
+    /// `n + 1` tenths of their budget left.
+    pub buckets: [u64; DEADLINE_BUCKETS],
+    /// The smallest fraction of the budget left by any callback, negative for
+    /// overruns. One if nothing has been recorded.
+    pub worst_margin: f64,
+}
+
+impl DeadlineHistogram {
+    pub fn total(&self) -> u64 {
+        self.overruns + self.buckets.iter().sum::<u64>()
+    }
+}
+
+/// Real-time safe accumulator behind `DeadlineHistogram`. `record` must only
+/// be called from the real-time thread.
+pub struct DeadlineMonitor {
+    overruns: AtomicU64,
+    buckets: [AtomicU64; DEADLINE_BUCKETS],
+    worst_margin: AtomicU64,
+}
+
+impl DeadlineMonitor {
+    pub fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+
+        DeadlineMonitor {
+            overruns: AtomicU64::new(0),
+            buckets: [ZERO; DEADLINE_BUCKETS],
+            worst_margin: AtomicU64::new(1.0f64.to_bits()),
+        }
+    }
+
+    pub fn record(&self, elapsed: Duration, budget: Duration) {
+        if budget.is_zero() {
+            return;
+        }
+
+        let margin = 1.0 - elapsed.as_secs_f64() / budget.as_secs_f64();
+        if margin < 0.0 {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        } else {
+            let bucket = ((margin * DEADLINE_BUCKETS as f64) as usize).min(DEADLINE_BUCKETS - 1);
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+
+        if margin < f64::from_bits(self.worst_margin.load(Ordering::Relaxed)) {
+            self.worst_margin.store(margin.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn histogram(&self) -> DeadlineHistogram {
+        let mut buckets = [0; DEADLINE_BUCKETS];
+        for (bucket, count) in buckets.iter_mut().zip(&self.buckets) {
+            *bucket = count.load(Ordering::Relaxed);
+        }
+
+        DeadlineHistogram {
+            overruns: self.overruns.load(Ordering::Relaxed),
+            buckets,
+            worst_margin: f64::from_bits(self.worst_margin.load(Ordering::Relaxed)),
+        }
+    }
+}
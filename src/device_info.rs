@@ -0,0 +1,109 @@
+use crate::traits::{Backend, Device};
+
+/// One of the selectable sources of a device stream, like "Internal
+/// Microphone" or "Line In". The ID is stable, the name is for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DataSource {
+    pub id: u32,
+    pub name: String,
+}
+
+/// One of the clocks a device can sync to, like its internal clock, S/PDIF
+/// or word clock. The ID is stable, the name is for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClockSource {
+    pub id: u32,
+    pub name: String,
+}
+
+/// A process using a device, as reported by `Device::users`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceUser {
+    /// None if the device is known to be in use, but not by whom.
+    pub pid: Option<i32>,
+    pub bundle_id: Option<String>,
+    /// Whether the process has taken exclusive access to the device.
+    pub exclusive: bool,
+}
+
+/// A snapshot of a device's properties, detached from the device handle so it
+/// can be kept around, compared and displayed freely. With the `serde`
+/// feature it can be saved as well, and matched up with a device again
+/// through `Backend::device_by_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceInfo {
+    pub persistent_id: String,
+    pub name: String,
+    pub num_inputs: usize,
+    pub num_outputs: usize,
+    pub is_default_input: bool,
+    pub is_default_output: bool,
+}
+
+impl DeviceInfo {
+    pub(crate) fn collect<B: Backend>(backend: &B) -> Result<Vec<DeviceInfo>, B::Error> {
+        let (default_input, default_output) = backend.default_devices()?;
+        let default_input = default_input.persistent_id()?;
+        let default_output = default_output.persistent_id()?;
+
+        backend
+            .all_devices()?
+            .into_iter()
+            .map(|device| {
+                let persistent_id = device.persistent_id()?;
+
+                Ok(DeviceInfo {
+                    is_default_input: persistent_id == default_input,
+                    is_default_output: persistent_id == default_output,
+                    persistent_id,
+                    name: device.name()?,
+                    num_inputs: device.num_inputs()?,
+                    num_outputs: device.num_outputs()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The difference between two device listings, matched up by persistent ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceListDiff {
+    pub added: Vec<DeviceInfo>,
+    pub removed: Vec<DeviceInfo>,
+    /// The new info for devices in both listings whose properties differ.
+    pub changed: Vec<DeviceInfo>,
+}
+
+impl DeviceListDiff {
+    pub fn compute(old: &[DeviceInfo], new: &[DeviceInfo]) -> Self {
+        let find = |list: &[DeviceInfo], id: &str| {
+            list.iter().find(|info| info.persistent_id == id).cloned()
+        };
+
+        let mut diff = DeviceListDiff::default();
+        for info in new {
+            match find(old, &info.persistent_id) {
+                None => diff.added.push(info.clone()),
+                Some(old_info) if old_info != *info => diff.changed.push(info.clone()),
+                Some(_) => {}
+            }
+        }
+
+        diff.removed = old
+            .iter()
+            .filter(|info| find(new, &info.persistent_id).is_none())
+            .cloned()
+            .collect();
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle, Thread};
+use std::time::Duration;
+
+use crate::device_info::{DeviceInfo, DeviceListDiff};
+use crate::traits::Backend;
+
+/// How often devices are listed again. Backends that are told when the list
+/// changes list them right away as well.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change to the devices a backend lists, as reported by
+/// `Backend::watch_devices`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Added(DeviceInfo),
+    Removed(DeviceInfo),
+    /// A device whose properties changed, like when it became the default.
+    Changed(DeviceInfo),
+}
+
+/// Reports devices coming and going on a background thread, until dropped.
+pub struct DeviceWatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    /// Wakes the thread up when the backend says the devices changed. Dropped
+    /// before the thread is stopped.
+    notifier: Option<Box<dyn Send>>,
+}
+
+impl DeviceWatcher {
+    /// Lists the devices of `backend`, and then keeps listing them on a
+    /// background thread with a backend of its own, calling `f` with what
+    /// changed each time.
+    pub(crate) fn spawn<B, F>(backend: &B, mut f: F) -> Result<Self, B::Error>
+    where
+        B: Backend + 'static,
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        let mut known = backend.device_infos()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = thread::Builder::new()
+            .name("render_callback device watcher".to_owned())
+            .spawn(move || {
+                let backend = match B::new() {
+                    Ok(backend) => backend,
+                    Err(_) => return,
+                };
+
+                loop {
+                    thread::park_timeout(POLL_INTERVAL);
+                    if thread_stop.load(Ordering::Acquire) {
+                        break;
+                    }
+
+                    // Devices that vanish while they're being listed make it
+                    // fail, and are picked up on the next round.
+                    let current = match backend.device_infos() {
+                        Ok(current) => current,
+                        Err(_) => continue,
+                    };
+
+                    let diff = DeviceListDiff::compute(&known, &current);
+                    let events = diff
+                        .removed
+                        .into_iter()
+                        .map(DeviceEvent::Removed)
+                        .chain(diff.added.into_iter().map(DeviceEvent::Added))
+                        .chain(diff.changed.into_iter().map(DeviceEvent::Changed));
+                    for event in events {
+                        f(event);
+                    }
+                    known = current;
+                }
+            })
+            .expect("Could not spawn device watcher thread");
+
+        Ok(DeviceWatcher {
+            stop,
+            thread: Some(thread),
+            notifier: None,
+        })
+    }
+}
+
+/// For backends that are notified when devices change, which aren't built on
+/// every platform.
+#[allow(dead_code)]
+impl DeviceWatcher {
+    /// The watcher's thread, to unpark when the devices change.
+    pub(crate) fn thread(&self) -> Thread {
+        self.thread
+            .as_ref()
+            .expect("Device watcher already stopped")
+            .thread()
+            .clone()
+    }
+
+    /// Keeps `notifier` alive for as long as the watcher runs.
+    pub(crate) fn set_notifier<N: Send + 'static>(&mut self, notifier: N) {
+        self.notifier = Some(Box::new(notifier));
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        drop(self.notifier.take());
+        self.stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DropoutStats {
+    /// Number of gaps detected in the device's sample timeline.
+    pub count: u64,
+    /// Size in frames of the most recent gap.
+    pub last_dropout_frames: u64,
+    /// Size in frames of the largest gap.
+    pub largest_dropout_frames: u64,
+    /// Total frames lost to gaps.
+    pub total_dropped_frames: u64,
+}
+
+/// Detects gaps between the sample time a render cycle was expected to start
+/// at, given the previous cycle, and the time it actually started at.
+///
+/// `observe` is meant to be called from the real-time thread only; the rest
+/// may be called from anywhere.
+pub struct DropoutDetector {
+    expected_next: AtomicU64,
+    count: AtomicU64,
+    last_dropout: AtomicU64,
+    largest_dropout: AtomicU64,
+    total_dropped: AtomicU64,
+}
+
+const UNKNOWN: u64 = u64::MAX;
+
+impl DropoutDetector {
+    pub fn new() -> Self {
+        DropoutDetector {
+            expected_next: AtomicU64::new(UNKNOWN),
+            count: AtomicU64::new(0),
+            last_dropout: AtomicU64::new(0),
+            largest_dropout: AtomicU64::new(0),
+            total_dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a cycle starting at `sample_time` covering `frames` frames, and
+    /// returns the size of the gap before it, if any.
+    pub fn observe(&self, sample_time: f64, frames: usize) -> Option<u64> {
+        let start = sample_time.round().max(0.0) as u64;
+        let expected = self
+            .expected_next
+            .swap(start + frames as u64, Ordering::Relaxed);
+
+        // A timeline that moves backwards means the device was restarted or
+        // reconfigured, which is not a dropout.
+        if expected == UNKNOWN || start <= expected {
+            return None;
+        }
+
+        let gap = start - expected;
+        self.last_dropout.store(gap, Ordering::Relaxed);
+        self.largest_dropout.fetch_max(gap, Ordering::Relaxed);
+        self.total_dropped.fetch_add(gap, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Release);
+
+        Some(gap)
+    }
+
+    /// Forgets the expected next sample time, e.g. after a device change.
+    pub fn reset(&self) {
+        self.expected_next.store(UNKNOWN, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> DropoutStats {
+        let count = self.count.load(Ordering::Acquire);
+
+        DropoutStats {
+            count,
+            last_dropout_frames: self.last_dropout.load(Ordering::Relaxed),
+            largest_dropout_frames: self.largest_dropout.load(Ordering::Relaxed),
+            total_dropped_frames: self.total_dropped.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,846 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use crate::channel_map::ChannelMap;
+use crate::clock::SampleClock;
+use crate::config::SessionConfig;
+use crate::deadline::DeadlineHistogram;
+use crate::device_info::{ClockSource, DeviceInfo};
+use crate::direction::Direction;
+use crate::dropout::DropoutStats;
+use crate::events::Events;
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::OverloadSubscription;
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::sample::{ConvertingProcessor, SampleRenderCallback};
+use crate::traits::{Backend, Device, Session};
+use crate::warnings::{Warning, WarningSubscription, WarningThresholds};
+
+/// The error type of every dynamic backend, session and device.
+#[derive(Debug)]
+pub enum DynError {
+    /// The backend failed with its own error, which is kept as the source,
+    /// already converted to a crate `Error`.
+    Backend {
+        backend: &'static str,
+        error: Box<dyn Error>,
+    },
+    /// A device listed by one backend was handed to another.
+    ForeignDevice { backend: &'static str },
+}
+
+impl DynError {
+    fn backend<E: Into<crate::Error>>(backend: &'static str, error: E) -> Self {
+        DynError::Backend {
+            backend,
+            error: Box::new(error.into()),
+        }
+    }
+
+    /// The name of the backend the error came from.
+    pub fn backend_name(&self) -> &'static str {
+        match self {
+            DynError::Backend { backend, .. } | DynError::ForeignDevice { backend } => backend,
+        }
+    }
+}
+
+impl fmt::Display for DynError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DynError::Backend { backend, error } => write!(f, "{}: {}", backend, error),
+            DynError::ForeignDevice { backend } => {
+                write!(f, "Device does not belong to the {} backend", backend)
+            }
+        }
+    }
+}
+
+impl Error for DynError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DynError::Backend { error, .. } => Some(&**error),
+            DynError::ForeignDevice { .. } => None,
+        }
+    }
+}
+
+/// Recovers the kind of error the backend failed with.
+impl From<DynError> for crate::Error {
+    fn from(error: DynError) -> Self {
+        match error {
+            DynError::Backend { error, .. } => match error.downcast::<crate::Error>() {
+                Ok(error) => *error,
+                Err(error) => crate::Error::Backend(error),
+            },
+            DynError::ForeignDevice { .. } => crate::Error::DeviceNotFound(Box::new(error)),
+        }
+    }
+}
+
+/// A backend picked at runtime, like from a configuration file, rather than
+/// at compile time through the `Backend` trait's type parameter.
+///
+/// Callbacks always work in interleaved `f32`, converted from and to the
+/// backend's own buffers, and all errors are `DynError`s.
+pub struct DynBackend {
+    inner: Box<dyn ErasedBackend>,
+}
+
+impl DynBackend {
+    /// Creates a `B` and wraps it. `name` identifies the backend in errors
+    /// and is returned by `name`.
+    pub fn new<B>(name: &'static str) -> Result<Self, DynError>
+    where
+        B: Backend + 'static,
+    {
+        let backend = B::new().map_err(|error| DynError::backend(name, error))?;
+
+        Ok(DynBackend::from_backend(name, backend))
+    }
+
+    pub fn from_backend<B>(name: &'static str, backend: B) -> Self
+    where
+        B: Backend + 'static,
+    {
+        DynBackend {
+            inner: Box::new(BackendOf { name, backend }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    pub fn all_devices(&self) -> Result<Vec<DynDevice>, DynError> {
+        self.inner.all_devices()
+    }
+
+    pub fn default_input_device(&self) -> Result<DynDevice, DynError> {
+        self.inner.default_input_device()
+    }
+
+    pub fn default_output_device(&self) -> Result<DynDevice, DynError> {
+        self.inner.default_output_device()
+    }
+
+    /// Looks up a device by the ID returned from `DynDevice::persistent_id`.
+    pub fn device_by_id(&self, id: &str) -> Result<Option<DynDevice>, DynError> {
+        self.inner.device_by_id(id)
+    }
+
+    pub fn device_infos(&self) -> Result<Vec<DeviceInfo>, DynError> {
+        self.inner.device_infos()
+    }
+
+    /// Fails with `DynError::ForeignDevice` if either device came from a
+    /// different backend.
+    pub fn start_session(
+        &self,
+        sample_rate: f64,
+        input_device: &DynDevice,
+        output_device: &DynDevice,
+        callback: Box<SampleRenderCallback<f32>>,
+    ) -> Result<DynSession, DynError> {
+        self.inner
+            .start_session(sample_rate, input_device, output_device, callback)
+    }
+}
+
+/// A device of a `DynBackend`. Devices compare equal when they're the same
+/// device of the same backend.
+pub struct DynDevice {
+    inner: Box<dyn ErasedDevice>,
+}
+
+impl DynDevice {
+    /// The name of the backend the device belongs to.
+    pub fn backend_name(&self) -> &'static str {
+        self.inner.backend_name()
+    }
+
+    pub fn num_inputs(&self) -> Result<usize, DynError> {
+        self.inner.num_inputs()
+    }
+
+    pub fn num_outputs(&self) -> Result<usize, DynError> {
+        self.inner.num_outputs()
+    }
+
+    pub fn name(&self) -> Result<String, DynError> {
+        self.inner.name()
+    }
+
+    pub fn channel_name(
+        &self,
+        direction: Direction,
+        channel: usize,
+    ) -> Result<Option<String>, DynError> {
+        self.inner.channel_name(direction, channel)
+    }
+
+    pub fn persistent_id(&self) -> Result<String, DynError> {
+        self.inner.persistent_id()
+    }
+
+    pub fn is_default_input(&self) -> Result<bool, DynError> {
+        self.inner.is_default_input()
+    }
+
+    pub fn is_default_output(&self) -> Result<bool, DynError> {
+        self.inner.is_default_output()
+    }
+
+    pub fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), DynError> {
+        self.inner.set_nominal_sample_rate(sample_rate)
+    }
+
+    pub fn nominal_sample_rate(&self) -> Result<f64, DynError> {
+        self.inner.nominal_sample_rate()
+    }
+
+    pub fn actual_sample_rate(&self) -> Result<f64, DynError> {
+        self.inner.actual_sample_rate()
+    }
+
+    pub fn available_sample_rates(&self) -> Result<Vec<RangeInclusive<f64>>, DynError> {
+        self.inner.available_sample_rates()
+    }
+
+    pub fn buffer_frame_size_range(&self) -> Result<Option<RangeInclusive<usize>>, DynError> {
+        self.inner.buffer_frame_size_range()
+    }
+
+    pub fn clock_sources(&self) -> Result<Vec<ClockSource>, DynError> {
+        self.inner.clock_sources()
+    }
+
+    pub fn current_clock_source(&self) -> Result<Option<ClockSource>, DynError> {
+        self.inner.current_clock_source()
+    }
+
+    pub fn set_clock_source(&mut self, id: u32) -> Result<(), DynError> {
+        self.inner.set_clock_source(id)
+    }
+}
+
+impl Clone for DynDevice {
+    fn clone(&self) -> Self {
+        DynDevice {
+            inner: self.inner.clone_box(),
+        }
+    }
+}
+
+impl fmt::Debug for DynDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl PartialEq for DynDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.eq(&*other.inner)
+    }
+}
+
+impl Eq for DynDevice {}
+
+/// A session started through a `DynBackend`. Dropping it stops the session.
+pub struct DynSession {
+    inner: Box<dyn ErasedSession>,
+}
+
+impl DynSession {
+    pub fn input_device(&self) -> Result<DynDevice, DynError> {
+        self.inner.input_device()
+    }
+
+    pub fn output_device(&self) -> Result<DynDevice, DynError> {
+        self.inner.output_device()
+    }
+
+    pub fn set_input_device(&mut self, device: &DynDevice) -> Result<(), DynError> {
+        self.inner.set_input_device(device)
+    }
+
+    pub fn set_output_device(&mut self, device: &DynDevice) -> Result<(), DynError> {
+        self.inner.set_output_device(device)
+    }
+
+    pub fn max_frames_per_callback(&self) -> Result<usize, DynError> {
+        self.inner.max_frames_per_callback()
+    }
+
+    pub fn channel_map(&self) -> Result<ChannelMap, DynError> {
+        self.inner.channel_map()
+    }
+
+    pub fn dropouts(&self) -> DropoutStats {
+        self.inner.dropouts()
+    }
+
+    pub fn sample_clock(&self) -> SampleClock {
+        self.inner.sample_clock()
+    }
+
+    pub fn sample_rate(&self) -> Result<f64, DynError> {
+        self.inner.sample_rate()
+    }
+
+    pub fn deadline_margins(&self) -> DeadlineHistogram {
+        self.inner.deadline_margins()
+    }
+
+    pub fn meters(&self) -> Meters {
+        self.inner.meters()
+    }
+
+    pub fn events(&self) -> Events {
+        self.inner.events()
+    }
+
+    pub fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        self.inner.warnings(thresholds, Box::new(f))
+    }
+
+    pub fn overloads(&self) -> u64 {
+        self.inner.overloads()
+    }
+
+    pub fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        self.inner.on_overload(Box::new(f))
+    }
+
+    #[cfg(feature = "profiling")]
+    pub fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.inner.take_profile()
+    }
+
+    /// Replaces the callback without stopping the session, converting
+    /// samples to and from `f32` around it as `DynBackend::start_session`
+    /// does.
+    pub fn set_callback(
+        &mut self,
+        callback: Box<SampleRenderCallback<f32>>,
+    ) -> Result<(), DynError> {
+        self.inner.set_callback(callback)
+    }
+
+    pub fn set_bypassed(&self, bypassed: bool) {
+        self.inner.set_bypassed(bypassed);
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.inner.is_bypassed()
+    }
+
+    pub fn input_latency_frames(&self) -> Result<usize, DynError> {
+        self.inner.input_latency_frames()
+    }
+
+    pub fn output_latency_frames(&self) -> Result<usize, DynError> {
+        self.inner.output_latency_frames()
+    }
+
+    pub fn round_trip_latency(&self) -> Result<Duration, DynError> {
+        self.inner.round_trip_latency()
+    }
+
+    pub fn tune_for_low_latency(
+        &mut self,
+        target_ms: f64,
+    ) -> Result<Option<LatencyTuning>, DynError> {
+        self.inner.tune_for_low_latency(target_ms)
+    }
+
+    pub fn start_at(&mut self, host_time: u64) -> Result<u64, DynError> {
+        self.inner.start_at(host_time)
+    }
+
+    pub fn stop(&mut self) -> Result<(), DynError> {
+        self.inner.stop()
+    }
+
+    pub fn start(&mut self) -> Result<(), DynError> {
+        self.inner.start()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+}
+
+// The object-safe traits behind the wrappers, each implemented once for
+// any backend.
+
+trait ErasedBackend {
+    fn name(&self) -> &'static str;
+    fn all_devices(&self) -> Result<Vec<DynDevice>, DynError>;
+    fn default_input_device(&self) -> Result<DynDevice, DynError>;
+    fn default_output_device(&self) -> Result<DynDevice, DynError>;
+    fn device_by_id(&self, id: &str) -> Result<Option<DynDevice>, DynError>;
+    fn device_infos(&self) -> Result<Vec<DeviceInfo>, DynError>;
+    fn start_session(
+        &self,
+        sample_rate: f64,
+        input_device: &DynDevice,
+        output_device: &DynDevice,
+        callback: Box<SampleRenderCallback<f32>>,
+    ) -> Result<DynSession, DynError>;
+}
+
+trait ErasedDevice {
+    fn as_any(&self) -> &dyn Any;
+    fn clone_box(&self) -> Box<dyn ErasedDevice>;
+    fn eq(&self, other: &dyn ErasedDevice) -> bool;
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+    fn backend_name(&self) -> &'static str;
+    fn num_inputs(&self) -> Result<usize, DynError>;
+    fn num_outputs(&self) -> Result<usize, DynError>;
+    fn name(&self) -> Result<String, DynError>;
+    fn channel_name(
+        &self,
+        direction: Direction,
+        channel: usize,
+    ) -> Result<Option<String>, DynError>;
+    fn persistent_id(&self) -> Result<String, DynError>;
+    fn is_default_input(&self) -> Result<bool, DynError>;
+    fn is_default_output(&self) -> Result<bool, DynError>;
+    fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), DynError>;
+    fn nominal_sample_rate(&self) -> Result<f64, DynError>;
+    fn actual_sample_rate(&self) -> Result<f64, DynError>;
+    fn available_sample_rates(&self) -> Result<Vec<RangeInclusive<f64>>, DynError>;
+    fn buffer_frame_size_range(&self) -> Result<Option<RangeInclusive<usize>>, DynError>;
+    fn clock_sources(&self) -> Result<Vec<ClockSource>, DynError>;
+    fn current_clock_source(&self) -> Result<Option<ClockSource>, DynError>;
+    fn set_clock_source(&mut self, id: u32) -> Result<(), DynError>;
+}
+
+trait ErasedSession {
+    fn input_device(&self) -> Result<DynDevice, DynError>;
+    fn output_device(&self) -> Result<DynDevice, DynError>;
+    fn set_input_device(&mut self, device: &DynDevice) -> Result<(), DynError>;
+    fn set_output_device(&mut self, device: &DynDevice) -> Result<(), DynError>;
+    fn max_frames_per_callback(&self) -> Result<usize, DynError>;
+    fn channel_map(&self) -> Result<ChannelMap, DynError>;
+    fn dropouts(&self) -> DropoutStats;
+    fn sample_clock(&self) -> SampleClock;
+    fn sample_rate(&self) -> Result<f64, DynError>;
+    fn deadline_margins(&self) -> DeadlineHistogram;
+    fn meters(&self) -> Meters;
+    fn events(&self) -> Events;
+    fn warnings(
+        &self,
+        thresholds: WarningThresholds,
+        f: Box<dyn FnMut(Warning) + Send>,
+    ) -> WarningSubscription;
+    fn overloads(&self) -> u64;
+    fn on_overload(&self, f: Box<dyn FnMut(u64) + Send>) -> OverloadSubscription;
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord>;
+    fn set_callback(&mut self, callback: Box<SampleRenderCallback<f32>>) -> Result<(), DynError>;
+    fn set_bypassed(&self, bypassed: bool);
+    fn is_bypassed(&self) -> bool;
+    fn input_latency_frames(&self) -> Result<usize, DynError>;
+    fn output_latency_frames(&self) -> Result<usize, DynError>;
+    fn round_trip_latency(&self) -> Result<Duration, DynError>;
+    fn tune_for_low_latency(&mut self, target_ms: f64) -> Result<Option<LatencyTuning>, DynError>;
+    fn start_at(&mut self, host_time: u64) -> Result<u64, DynError>;
+    fn stop(&mut self) -> Result<(), DynError>;
+    fn start(&mut self) -> Result<(), DynError>;
+    fn is_running(&self) -> bool;
+}
+
+fn wrap<B: Backend + 'static>(backend: &'static str, device: B::Device) -> DynDevice {
+    DynDevice {
+        inner: Box::new(DeviceOf::<B> { backend, device }),
+    }
+}
+
+/// The backend's own device, if `device` belongs to the backend.
+fn unwrap<B: Backend + 'static>(
+    backend: &'static str,
+    device: &DynDevice,
+) -> Result<B::Device, DynError> {
+    device
+        .inner
+        .as_any()
+        .downcast_ref::<DeviceOf<B>>()
+        .filter(|device| device.backend == backend)
+        .map(|device| device.device.clone())
+        .ok_or(DynError::ForeignDevice { backend })
+}
+
+struct BackendOf<B> {
+    name: &'static str,
+    backend: B,
+}
+
+impl<B: Backend + 'static> ErasedBackend for BackendOf<B> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn all_devices(&self) -> Result<Vec<DynDevice>, DynError> {
+        let devices = self
+            .backend
+            .all_devices()
+            .map_err(|error| DynError::backend(self.name, error))?;
+
+        Ok(devices
+            .into_iter()
+            .map(|device| wrap::<B>(self.name, device))
+            .collect())
+    }
+
+    fn default_input_device(&self) -> Result<DynDevice, DynError> {
+        self.backend
+            .default_input_device()
+            .map(|device| wrap::<B>(self.name, device))
+            .map_err(|error| DynError::backend(self.name, error))
+    }
+
+    fn default_output_device(&self) -> Result<DynDevice, DynError> {
+        self.backend
+            .default_output_device()
+            .map(|device| wrap::<B>(self.name, device))
+            .map_err(|error| DynError::backend(self.name, error))
+    }
+
+    fn device_by_id(&self, id: &str) -> Result<Option<DynDevice>, DynError> {
+        self.backend
+            .device_by_id(id)
+            .map(|device| device.map(|device| wrap::<B>(self.name, device)))
+            .map_err(|error| DynError::backend(self.name, error))
+    }
+
+    fn device_infos(&self) -> Result<Vec<DeviceInfo>, DynError> {
+        self.backend
+            .device_infos()
+            .map_err(|error| DynError::backend(self.name, error))
+    }
+
+    fn start_session(
+        &self,
+        sample_rate: f64,
+        input_device: &DynDevice,
+        output_device: &DynDevice,
+        callback: Box<SampleRenderCallback<f32>>,
+    ) -> Result<DynSession, DynError> {
+        let session = self
+            .backend
+            .start_session_with_sample_type(
+                SessionConfig::new(
+                    sample_rate,
+                    unwrap::<B>(self.name, input_device)?,
+                    unwrap::<B>(self.name, output_device)?,
+                ),
+                callback,
+            )
+            .map_err(|error| DynError::backend(self.name, error))?;
+
+        Ok(DynSession {
+            inner: Box::new(SessionOf::<B> {
+                backend: self.name,
+                session,
+            }),
+        })
+    }
+}
+
+struct DeviceOf<B: Backend> {
+    backend: &'static str,
+    device: B::Device,
+}
+
+impl<B: Backend + 'static> ErasedDevice for DeviceOf<B> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_box(&self) -> Box<dyn ErasedDevice> {
+        Box::new(DeviceOf::<B> {
+            backend: self.backend,
+            device: self.device.clone(),
+        })
+    }
+
+    fn eq(&self, other: &dyn ErasedDevice) -> bool {
+        other
+            .as_any()
+            .downcast_ref::<DeviceOf<B>>()
+            .is_some_and(|other| other.backend == self.backend && other.device == self.device)
+    }
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.device, f)
+    }
+
+    fn backend_name(&self) -> &'static str {
+        self.backend
+    }
+
+    fn num_inputs(&self) -> Result<usize, DynError> {
+        self.device
+            .num_inputs()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn num_outputs(&self) -> Result<usize, DynError> {
+        self.device
+            .num_outputs()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn name(&self) -> Result<String, DynError> {
+        self.device
+            .name()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn channel_name(
+        &self,
+        direction: Direction,
+        channel: usize,
+    ) -> Result<Option<String>, DynError> {
+        self.device
+            .channel_name(direction, channel)
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn persistent_id(&self) -> Result<String, DynError> {
+        self.device
+            .persistent_id()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn is_default_input(&self) -> Result<bool, DynError> {
+        self.device
+            .is_default_input()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn is_default_output(&self) -> Result<bool, DynError> {
+        self.device
+            .is_default_output()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), DynError> {
+        self.device
+            .set_nominal_sample_rate(sample_rate)
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn nominal_sample_rate(&self) -> Result<f64, DynError> {
+        self.device
+            .nominal_sample_rate()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, DynError> {
+        self.device
+            .actual_sample_rate()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn available_sample_rates(&self) -> Result<Vec<RangeInclusive<f64>>, DynError> {
+        self.device
+            .available_sample_rates()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn buffer_frame_size_range(&self) -> Result<Option<RangeInclusive<usize>>, DynError> {
+        self.device
+            .buffer_frame_size_range()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn clock_sources(&self) -> Result<Vec<ClockSource>, DynError> {
+        self.device
+            .clock_sources()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn current_clock_source(&self) -> Result<Option<ClockSource>, DynError> {
+        self.device
+            .current_clock_source()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn set_clock_source(&mut self, id: u32) -> Result<(), DynError> {
+        self.device
+            .set_clock_source(id)
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+}
+
+struct SessionOf<B: Backend> {
+    backend: &'static str,
+    session: B::Session,
+}
+
+impl<B: Backend + 'static> ErasedSession for SessionOf<B> {
+    fn input_device(&self) -> Result<DynDevice, DynError> {
+        let device = self
+            .session
+            .input_device()
+            .map_err(|error| DynError::backend(self.backend, error))?;
+
+        Ok(wrap::<B>(self.backend, device))
+    }
+
+    fn output_device(&self) -> Result<DynDevice, DynError> {
+        let device = self
+            .session
+            .output_device()
+            .map_err(|error| DynError::backend(self.backend, error))?;
+
+        Ok(wrap::<B>(self.backend, device))
+    }
+
+    fn set_input_device(&mut self, device: &DynDevice) -> Result<(), DynError> {
+        let device = unwrap::<B>(self.backend, device)?;
+        self.session
+            .set_input_device(device)
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn set_output_device(&mut self, device: &DynDevice) -> Result<(), DynError> {
+        let device = unwrap::<B>(self.backend, device)?;
+        self.session
+            .set_output_device(device)
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, DynError> {
+        self.session
+            .max_frames_per_callback()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, DynError> {
+        self.session
+            .channel_map()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.session.dropouts()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.session.sample_clock()
+    }
+
+    fn sample_rate(&self) -> Result<f64, DynError> {
+        self.session
+            .sample_rate()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.session.deadline_margins()
+    }
+
+    fn meters(&self) -> Meters {
+        self.session.meters()
+    }
+
+    fn events(&self) -> Events {
+        self.session.events()
+    }
+
+    fn warnings(
+        &self,
+        thresholds: WarningThresholds,
+        f: Box<dyn FnMut(Warning) + Send>,
+    ) -> WarningSubscription {
+        self.session.warnings(thresholds, f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.session.overloads()
+    }
+
+    fn on_overload(&self, f: Box<dyn FnMut(u64) + Send>) -> OverloadSubscription {
+        self.session.on_overload(f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.session.take_profile()
+    }
+
+    fn set_callback(&mut self, callback: Box<SampleRenderCallback<f32>>) -> Result<(), DynError> {
+        self.session
+            .set_processor(ConvertingProcessor::<f32, B>::new(callback))
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.session.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.session.is_bypassed()
+    }
+
+    fn input_latency_frames(&self) -> Result<usize, DynError> {
+        self.session
+            .input_latency_frames()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn output_latency_frames(&self) -> Result<usize, DynError> {
+        self.session
+            .output_latency_frames()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn round_trip_latency(&self) -> Result<Duration, DynError> {
+        self.session
+            .round_trip_latency()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn tune_for_low_latency(&mut self, target_ms: f64) -> Result<Option<LatencyTuning>, DynError> {
+        self.session
+            .tune_for_low_latency(target_ms)
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn start_at(&mut self, host_time: u64) -> Result<u64, DynError> {
+        self.session
+            .start_at(host_time)
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn stop(&mut self) -> Result<(), DynError> {
+        self.session
+            .stop()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn start(&mut self) -> Result<(), DynError> {
+        self.session
+            .start()
+            .map_err(|error| DynError::backend(self.backend, error))
+    }
+
+    fn is_running(&self) -> bool {
+        self.session.is_running()
+    }
+}
@@ -0,0 +1,241 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::{CycleTimes, RenderContext};
+use crate::deadline::DeadlineMonitor;
+use crate::dropout::DropoutDetector;
+use crate::event_log::{self, HardwareEventKind};
+use crate::events::{EventQueue, SessionEvent};
+use crate::host_time;
+use crate::meters::MeterBank;
+use crate::overload::OverloadCounter;
+use crate::passthrough::passthrough;
+#[cfg(feature = "profiling")]
+use crate::profiling::Profiler;
+use crate::rt_cell::RtCell;
+use crate::scratch::Scratch;
+use crate::traits::{AudioBuffers, Backend, RenderCallback};
+use crate::warnings::WarningSnapshot;
+
+/// Everything a session does around its callback on the real-time thread
+/// that doesn't depend on the platform: timeline tracking, bypass and
+/// silence handling, metering, clipping and overload detection. Backends
+/// share one of these between the control thread and their real-time
+/// callback, and call `render` from it once they have the cycle's buffers.
+pub(crate) struct RenderEngine<B: Backend> {
+    callback: RtCell<Box<RenderCallback<B>>>,
+    /// Only ever touched by `render` while it holds the callback.
+    scratch: RtCell<Scratch>,
+    pub valid: AtomicBool,
+    pub dropouts: DropoutDetector,
+    discontinuity: AtomicBool,
+    pub meters: Arc<MeterBank>,
+    /// Every call the backend got from the platform, including ones it
+    /// rejected before rendering. Backends count these themselves.
+    pub cycles: AtomicU64,
+    pub clock: SampleClock,
+    pub deadlines: DeadlineMonitor,
+    pub events: Arc<EventQueue>,
+    pub overloads: Arc<OverloadCounter>,
+    zero_output: AtomicBool,
+    bypassed: AtomicBool,
+    #[cfg(feature = "profiling")]
+    pub profiler: Profiler,
+}
+
+impl<B: Backend> RenderEngine<B> {
+    pub fn new(sample_rate: f64) -> Self {
+        RenderEngine {
+            callback: RtCell::new(None),
+            scratch: RtCell::new(None),
+            valid: AtomicBool::new(false),
+            dropouts: DropoutDetector::new(),
+            discontinuity: AtomicBool::new(false),
+            meters: Arc::new(MeterBank::new(sample_rate)),
+            cycles: AtomicU64::new(0),
+            clock: SampleClock::new(sample_rate),
+            deadlines: DeadlineMonitor::new(),
+            events: Arc::new(EventQueue::new()),
+            overloads: Arc::new(OverloadCounter::new()),
+            zero_output: AtomicBool::new(true),
+            bypassed: AtomicBool::new(false),
+            #[cfg(feature = "profiling")]
+            profiler: Profiler::new(),
+        }
+    }
+
+    pub fn set_callback(&self, callback: Box<RenderCallback<B>>) {
+        self.callback.replace(Some(Box::new(callback)));
+    }
+
+    /// Must only be called once the real-time callback can no longer run, so
+    /// that the callback is dropped on the calling thread.
+    pub fn take_callback(&self) -> Option<Box<Box<RenderCallback<B>>>> {
+        self.callback.take()
+    }
+
+    /// Reallocates the scratch arena for callbacks of up to `max_frames`
+    /// frames across `channels` channels.
+    pub fn resize_scratch(&self, max_frames: usize, channels: usize) {
+        let scratch = Scratch::new(max_frames, channels);
+        self.scratch.replace(Some(Box::new(scratch)));
+    }
+
+    /// Marks the next cycle as not following on from the previous one.
+    pub fn reset_timeline(&self) {
+        self.dropouts.reset();
+        self.discontinuity.store(true, Ordering::Relaxed);
+    }
+
+    /// For backends whose devices change sample rate while running, which
+    /// aren't built on every platform.
+    #[allow(dead_code)]
+    pub fn set_sample_rate(&self, sample_rate: f64) {
+        self.clock.set_sample_rate(sample_rate);
+        self.meters.set_sample_rate(sample_rate);
+    }
+
+    /// For backends whose devices report overloads themselves, which aren't
+    /// built on every platform. Never blocks or allocates.
+    #[allow(dead_code)]
+    pub fn report_device_overload(&self) {
+        self.overloads.record();
+        self.events.push(SessionEvent::DeviceOverload);
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.zero_output
+            .store(policy == OutputPolicy::Zeroed, Ordering::Relaxed);
+    }
+
+    pub fn zeroes_output(&self) -> bool {
+        self.zero_output.load(Ordering::Relaxed)
+    }
+
+    pub fn set_bypassed(&self, bypassed: bool) {
+        self.bypassed.store(bypassed, Ordering::Relaxed);
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed.load(Ordering::Relaxed)
+    }
+
+    /// The counters `warnings::watch` works from.
+    pub fn warning_snapshot(&self, invalid_buffers: u64) -> WarningSnapshot {
+        WarningSnapshot {
+            overruns: self.deadlines.histogram().overruns,
+            dropouts: self.dropouts.stats(),
+            invalid_buffers,
+            frames: self.clock.frames(),
+            sample_rate: self.clock.sample_rate(),
+        }
+    }
+
+    /// Runs one cycle of `frames` frames. `times` are the cycle's time
+    /// stamps, as far as the platform reports them, and their sample time is
+    /// used to detect dropouts. Never blocks or allocates.
+    ///
+    /// # Safety
+    ///
+    /// Must only ever be called from one thread at a time, like the
+    /// platform's real-time thread, and never from within the callback.
+    pub unsafe fn render(
+        &self,
+        input: &[B::AudioBuffers],
+        output: &mut [B::AudioBuffers],
+        frames: usize,
+        mut times: CycleTimes,
+    ) {
+        if times.now.host_time.is_none() {
+            times.now.host_time = Some(host_time::now());
+        }
+
+        let dropout = times
+            .sample_time()
+            .and_then(|sample_time| self.dropouts.observe(sample_time, frames));
+        if let Some(frames) = dropout {
+            self.events.push(SessionEvent::Dropout { frames });
+        }
+        let discontinuity = dropout.is_some() | self.discontinuity.swap(false, Ordering::Relaxed);
+
+        let elapsed = stopwatch();
+
+        // The real-time thread is the only reader of the callback and scratch
+        // cells. The scratch arena is set up along with the session, so it's
+        // always there by the time the callback is.
+        self.callback.with(|callback| {
+            self.scratch.with(|scratch| {
+                scratch.reset();
+
+                let context = RenderContext {
+                    valid: &self.valid,
+                    discontinuity,
+                    dropout,
+                    sample_rate: self.clock.sample_rate(),
+                    frames,
+                    stream_frames: self.clock.frames(),
+                    times,
+                    output_silent: Cell::new(false),
+                    scratch,
+                    #[cfg(feature = "profiling")]
+                    profiler: Some(&self.profiler),
+                    // Only the real-time thread advances the count.
+                    #[cfg(feature = "profiling")]
+                    cycle: self.cycles.load(Ordering::Relaxed).saturating_sub(1),
+                };
+
+                if self.is_bypassed() {
+                    passthrough(input, output);
+                } else {
+                    if self.zeroes_output() {
+                        zero_buffers(output);
+                    }
+                    let _scope = context.scope("callback");
+                    callback(&context, input, output);
+                }
+                let silent = context.take_output_silence(output);
+                self.meters.process(input, output);
+                if !silent {
+                    self.events.check_clipping(output);
+                }
+                self.clock.advance(frames);
+            });
+        });
+
+        let elapsed = elapsed();
+        let budget = Duration::try_from_secs_f64(frames as f64 / self.clock.sample_rate())
+            .unwrap_or_default();
+        self.deadlines.record(elapsed, budget);
+        if !budget.is_zero() && elapsed > budget {
+            self.overloads.record();
+            self.events.push(SessionEvent::Overload { elapsed, budget });
+            event_log::record(HardwareEventKind::Overload { elapsed, budget });
+        }
+    }
+}
+
+/// Starts timing, and returns a function giving the time since. `Instant`
+/// panics on wasm32-unknown-unknown, where the page's clock stands in.
+#[cfg(not(target_arch = "wasm32"))]
+fn stopwatch() -> impl Fn() -> Duration {
+    let started = Instant::now();
+    move || started.elapsed()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn stopwatch() -> impl Fn() -> Duration {
+    let started = js_sys::Date::now();
+    move || Duration::from_secs_f64((js_sys::Date::now() - started).max(0.0) / 1000.0)
+}
+
+pub(crate) fn zero_buffers<A: AudioBuffers>(buffers: &mut [A]) {
+    for buffer in buffers {
+        buffer.interleaved_frames_mut().fill(Default::default());
+    }
+}
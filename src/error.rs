@@ -0,0 +1,67 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+/// Any backend's error, sorted into the kinds of failure that code working
+/// across backends usually needs to tell apart. Each variant holds the
+/// backend's own error, which it displays as and shares its source with.
+///
+/// Every backend's error converts into this, so `?` works on any of them in
+/// functions returning it.
+#[derive(Debug)]
+pub enum Error {
+    /// The device asked for isn't there, or there's no default device in
+    /// the direction one was asked for.
+    DeviceNotFound(Box<dyn StdError>),
+    /// The device went away, or stopped working, while it was in use.
+    DeviceDisconnected(Box<dyn StdError>),
+    /// Another session or process is using the device and won't share it.
+    DeviceBusy(Box<dyn StdError>),
+    /// The device can't run at the sample rate, buffer size, sample format
+    /// or number of channels asked for.
+    FormatNotSupported(Box<dyn StdError>),
+    /// The session was started, but the device never called it.
+    StartupTimeout(Box<dyn StdError>),
+    /// The backend can't do what was asked of it.
+    Unsupported(Box<dyn StdError>),
+    /// Any other failure.
+    Backend(Box<dyn StdError>),
+}
+
+impl Error {
+    /// The backend's own error.
+    pub fn inner(&self) -> &(dyn StdError + 'static) {
+        match self {
+            Error::DeviceNotFound(error)
+            | Error::DeviceDisconnected(error)
+            | Error::DeviceBusy(error)
+            | Error::FormatNotSupported(error)
+            | Error::StartupTimeout(error)
+            | Error::Unsupported(error)
+            | Error::Backend(error) => &**error,
+        }
+    }
+
+    pub fn into_inner(self) -> Box<dyn StdError> {
+        match self {
+            Error::DeviceNotFound(error)
+            | Error::DeviceDisconnected(error)
+            | Error::DeviceBusy(error)
+            | Error::FormatNotSupported(error)
+            | Error::StartupTimeout(error)
+            | Error::Unsupported(error)
+            | Error::Backend(error) => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.inner(), f)
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.inner().source()
+    }
+}
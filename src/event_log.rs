@@ -0,0 +1,160 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::direction::Direction;
+use crate::queue::Queue;
+
+/// Events that haven't been drained by the time the log holds this many are
+/// dropped and counted instead.
+const EVENT_LOG_CAPACITY: usize = 1024;
+
+/// How often subscribers check for new events.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Devices are identified by their `Device::persistent_id`, since a device
+/// that was just removed can't be asked for anything.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HardwareEventKind {
+    DeviceAdded {
+        device: String,
+    },
+    DeviceRemoved {
+        device: String,
+    },
+    DefaultInputChanged {
+        device: String,
+    },
+    DefaultOutputChanged {
+        device: String,
+    },
+    SampleRateChanged {
+        device: String,
+        sample_rate: f64,
+    },
+    DataSourceChanged {
+        device: String,
+        direction: Direction,
+    },
+    /// A render callback in one of this process's sessions took longer than
+    /// the duration of audio it rendered.
+    Overload {
+        elapsed: Duration,
+        budget: Duration,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HardwareEvent {
+    pub at: SystemTime,
+    pub kind: HardwareEventKind,
+}
+
+struct EventLogQueue {
+    enabled: AtomicBool,
+    queue: Queue<HardwareEvent>,
+    lost: AtomicU64,
+}
+
+static LOG: OnceLock<EventLogQueue> = OnceLock::new();
+
+/// Adds an event to the log, if anyone has opened it. Never blocks or
+/// allocates, so it's safe to call from the real-time thread as long as
+/// `kind` doesn't need allocating either.
+pub(crate) fn record(kind: HardwareEventKind) {
+    let log = match LOG.get() {
+        Some(log) if log.enabled.load(Ordering::Relaxed) => log,
+        _ => return,
+    };
+
+    let event = HardwareEvent {
+        at: SystemTime::now(),
+        kind,
+    };
+    if log.queue.push(event).is_err() {
+        log.lost.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A handle to the process-wide log of hardware notifications, returned by
+/// `Backend::event_log`.
+///
+/// Events are only collected once the log has been opened. All handles drain
+/// the same log, so each event is seen by exactly one of them.
+#[derive(Clone, Copy)]
+pub struct EventLog {
+    log: &'static EventLogQueue,
+}
+
+impl EventLog {
+    pub(crate) fn open() -> Self {
+        let log = LOG.get_or_init(|| EventLogQueue {
+            enabled: AtomicBool::new(false),
+            queue: Queue::new(EVENT_LOG_CAPACITY),
+            lost: AtomicU64::new(0),
+        });
+        log.enabled.store(true, Ordering::Relaxed);
+
+        EventLog { log }
+    }
+
+    pub fn pop(&self) -> Option<HardwareEvent> {
+        self.log.queue.pop()
+    }
+
+    /// Removes and returns every event currently in the log.
+    pub fn drain(&self) -> Vec<HardwareEvent> {
+        std::iter::from_fn(|| self.pop()).collect()
+    }
+
+    /// Number of events dropped because the log was full.
+    pub fn lost(&self) -> u64 {
+        self.log.lost.load(Ordering::Relaxed)
+    }
+
+    /// Calls `f` on a background thread for every event, until the returned
+    /// subscription is dropped.
+    pub fn subscribe<F>(&self, mut f: F) -> EventLogSubscription
+    where
+        F: FnMut(HardwareEvent) + Send + 'static,
+    {
+        let log = *self;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = thread::Builder::new()
+            .name("render_callback event log".to_owned())
+            .spawn(move || {
+                while !thread_stop.load(Ordering::Acquire) {
+                    while let Some(event) = log.pop() {
+                        f(event);
+                    }
+
+                    thread::park_timeout(POLL_INTERVAL);
+                }
+            })
+            .expect("Could not spawn event log thread");
+
+        EventLogSubscription {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+pub struct EventLogSubscription {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for EventLogSubscription {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+
+/// Events from one of the crate's background threads, as a stream for async
+/// code to await. The stream never ends; drop it to stop the thread feeding
+/// it. Events wait in the stream until polled, however many pile up.
+pub struct EventStream<T> {
+    pending: Arc<Mutex<Pending<T>>>,
+    /// The subscription or watcher feeding the stream, dropped with it.
+    _source: Option<Box<dyn Send>>,
+}
+
+struct Pending<T> {
+    events: VecDeque<T>,
+    waker: Option<Waker>,
+}
+
+impl<T: Send + 'static> EventStream<T> {
+    pub(crate) fn new() -> Self {
+        EventStream {
+            pending: Arc::new(Mutex::new(Pending {
+                events: VecDeque::new(),
+                waker: None,
+            })),
+            _source: None,
+        }
+    }
+
+    /// A function that adds an event to the stream and wakes up the task
+    /// waiting for it, to be called from the source's thread.
+    pub(crate) fn sender(&self) -> impl FnMut(T) + Send + 'static {
+        let pending = self.pending.clone();
+
+        move |event| {
+            let waker = {
+                let mut pending = pending.lock().unwrap();
+                pending.events.push_back(event);
+                pending.waker.take()
+            };
+
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Keeps `source` alive for as long as the stream is.
+    pub(crate) fn set_source<S: Send + 'static>(&mut self, source: S) {
+        self._source = Some(Box::new(source));
+    }
+}
+
+impl<T> Stream for EventStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut pending = self.pending.lock().unwrap();
+
+        match pending.events.pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => {
+                pending.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::direction::Direction;
+#[cfg(feature = "async")]
+use crate::event_stream::EventStream;
+use crate::queue::Queue;
+use crate::sample::Sample;
+use crate::traits::AudioBuffers;
+
+/// Events that haven't been drained by the time the queue holds this many are
+/// dropped and counted instead.
+const EVENT_CAPACITY: usize = 256;
+
+/// How often subscribers check for new events.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Something that happened to a session, mostly on its real-time thread, that
+/// the application may want to know about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionEvent {
+    /// A callback took longer than the duration of audio it rendered.
+    Overload { elapsed: Duration, budget: Duration },
+    /// The device's timeline skipped ahead by `frames` frames.
+    Dropout { frames: u64 },
+    /// The buffers handed over by the device didn't match the expected stream
+    /// layout. If `fatal`, the cycle was skipped entirely.
+    InvalidBuffers { fatal: bool },
+    /// An output sample on `channel` was outside of [-1, 1] and will be
+    /// clipped by the device. Reported at most once per cycle.
+    Clipped { channel: usize, peak: f32 },
+    /// Something outside of the application took the session's devices away,
+    /// like another process taking exclusive access on macOS. No callbacks
+    /// are made until the interruption ends.
+    Interrupted,
+    /// The interruption ended. Sessions configured to resume automatically
+    /// have already restarted; others stay stopped until `Session::start_at`
+    /// restarts them.
+    Resumed,
+    /// The system is about to sleep, and the session has been stopped.
+    SystemWillSleep,
+    /// The system woke up again. Sessions set to restart on wake, which is
+    /// the default, have already restarted; others stay stopped until
+    /// `Session::start_at` restarts them.
+    SystemDidWake,
+    /// The device stopped calling the session even though nothing asked it
+    /// to stop. Reported once per stoppage.
+    StoppedUnexpectedly { cause: StopCause },
+    /// The device reported the session missing a deadline, which can happen
+    /// even when the callback itself was quick enough, like when the system
+    /// didn't get around to calling it in time.
+    DeviceOverload,
+    /// The session switched its main device in `direction` to the new
+    /// system default, as asked to by `SessionConfig::follow_default_devices`.
+    FollowedDefaultDevice { direction: Direction },
+    /// Switching to the new default device in `direction` failed, and the
+    /// session stayed on its current device.
+    DeviceSwitchFailed { direction: Direction },
+}
+
+/// The best guess at why a session stopped by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCause {
+    /// One of the session's devices went away.
+    DeviceDied,
+    /// Another process took exclusive access to one of the devices.
+    TakenExclusively,
+    /// The device says it's running, but hasn't called the session for a
+    /// while. Usually a driver problem.
+    Stalled,
+    /// The session tried to restart by itself, like after the system woke
+    /// up, but the device refused to start.
+    RestartFailed,
+    /// The device stopped without saying why.
+    Unknown,
+}
+
+impl SessionEvent {
+    /// Whether the event means the session stopped or failed to do something
+    /// it was asked to, as opposed to a glitch it recovers from by itself.
+    /// Subscribing to the session's events and picking these out gives the
+    /// control thread a channel of errors.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            SessionEvent::InvalidBuffers { fatal: true }
+                | SessionEvent::StoppedUnexpectedly { .. }
+                | SessionEvent::DeviceSwitchFailed { .. }
+        )
+    }
+}
+
+/// Events pushed from the real-time thread for `Events` handles to drain.
+pub(crate) struct EventQueue {
+    enabled: AtomicBool,
+    queue: Queue<SessionEvent>,
+    lost: AtomicU64,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        EventQueue {
+            enabled: AtomicBool::new(false),
+            queue: Queue::new(EVENT_CAPACITY),
+            lost: AtomicU64::new(0),
+        }
+    }
+
+    /// Never blocks or allocates. Does nothing until someone has asked for
+    /// the events.
+    pub fn push(&self, event: SessionEvent) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if self.queue.push(event).is_err() {
+            self.lost.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Reports the first output channel whose samples went out of range, if
+    /// any. Must only be called from the real-time thread.
+    pub fn check_clipping<A: AudioBuffers>(&self, output: &[A]) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut channel = 0;
+        for buffer in output {
+            let stride = buffer.num_channels();
+            let samples = buffer.interleaved_frames();
+
+            for offset in 0..stride {
+                let peak = samples
+                    .iter()
+                    .skip(offset)
+                    .step_by(stride)
+                    .fold(0.0f32, |peak, sample| peak.max(sample.to_f32().abs()));
+                if peak > 1.0 {
+                    self.push(SessionEvent::Clipped { channel, peak });
+                    return;
+                }
+
+                channel += 1;
+            }
+        }
+    }
+}
+
+/// A handle to the events reported by a session.
+///
+/// Events are only collected once the first handle has been created. All
+/// handles drain the same queue, so each event is seen by exactly one of them.
+#[derive(Clone)]
+pub struct Events {
+    queue: Arc<EventQueue>,
+}
+
+impl Events {
+    pub(crate) fn new(queue: Arc<EventQueue>) -> Self {
+        queue.enabled.store(true, Ordering::Relaxed);
+        Events { queue }
+    }
+
+    pub fn pop(&self) -> Option<SessionEvent> {
+        self.queue.queue.pop()
+    }
+
+    /// Removes and returns every event currently in the queue.
+    pub fn drain(&self) -> Vec<SessionEvent> {
+        std::iter::from_fn(|| self.pop()).collect()
+    }
+
+    /// Number of events dropped because the queue was full.
+    pub fn lost(&self) -> u64 {
+        self.queue.lost.load(Ordering::Relaxed)
+    }
+
+    /// Calls `f` on a background thread for every event, until the returned
+    /// subscription is dropped.
+    pub fn subscribe<F>(&self, mut f: F) -> EventSubscription
+    where
+        F: FnMut(SessionEvent) + Send + 'static,
+    {
+        let events = self.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = thread::Builder::new()
+            .name("render_callback events".to_owned())
+            .spawn(move || {
+                while !thread_stop.load(Ordering::Acquire) {
+                    while let Some(event) = events.pop() {
+                        f(event);
+                    }
+
+                    thread::park_timeout(POLL_INTERVAL);
+                }
+            })
+            .expect("Could not spawn event thread");
+
+        EventSubscription {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// The events as a stream, fed by a subscription that lasts as long as
+    /// the stream does.
+    #[cfg(feature = "async")]
+    pub fn stream(&self) -> EventStream<SessionEvent> {
+        let mut stream = EventStream::new();
+        stream.set_source(self.subscribe(stream.sender()));
+        stream
+    }
+}
+
+pub struct EventSubscription {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for EventSubscription {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{EventQueue, Events, SessionEvent};
+    use crate::sample::testing::wide_layout;
+
+    #[test]
+    fn reports_clipping_on_channels_past_64() {
+        // 128 channels in 16 streams of 8, with only channel 100 too loud.
+        let output = wide_layout(
+            16,
+            8,
+            64,
+            |channel, _| {
+                if channel == 100 {
+                    1.5
+                } else {
+                    0.5
+                }
+            },
+        );
+
+        let queue = Arc::new(EventQueue::new());
+        let events = Events::new(queue.clone());
+        queue.check_clipping(&output);
+
+        assert_eq!(
+            events.drain(),
+            [SessionEvent::Clipped {
+                channel: 100,
+                peak: 1.5
+            }]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::processor::{processor_callback, Processor};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::FileDevice;
+use super::error::FileError;
+use super::session::FileSession;
+
+/// Renders offline, from WAV files to WAV files, for deterministic tests of
+/// DSP code. Sessions can be started on any `FileDevice`; the backend only
+/// lists the files it was created with.
+pub struct FileBackend {
+    devices: Option<(FileDevice, FileDevice)>,
+}
+
+impl FileBackend {
+    /// A backend whose default input and output are `source` and
+    /// `destination`, for code that starts sessions on the default devices.
+    pub fn with_files(source: FileDevice, destination: FileDevice) -> Self {
+        FileBackend {
+            devices: Some((source, destination)),
+        }
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(&self, config: &SessionConfig<Self>) -> Result<FileSession, FileError> {
+        if config.physical_format.is_some() {
+            return Err(FileError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(FileError::Unsupported("preroll"));
+        }
+        if config.routing.is_some() {
+            return Err(FileError::Unsupported("routing matrices"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(FileError::Unsupported("more than one file per direction"));
+        }
+
+        let session = FileSession::new(
+            config.sample_rate,
+            config.input_device.clone(),
+            config.output_device.clone(),
+            config.buffer_size,
+        )?;
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for FileBackend {
+    type Session = FileSession;
+    type Error = FileError;
+    type Device = FileDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    /// A backend without files, which has no default devices.
+    fn new() -> Result<Self, FileError> {
+        Ok(FileBackend { devices: None })
+    }
+
+    fn is_available() -> bool {
+        true
+    }
+
+    fn all_devices(&self) -> Result<Vec<FileDevice>, FileError> {
+        Ok(self
+            .devices
+            .iter()
+            .flat_map(|(source, destination)| vec![source.clone(), destination.clone()])
+            .collect())
+    }
+
+    fn default_input_device(&self) -> Result<FileDevice, FileError> {
+        self.devices
+            .as_ref()
+            .map(|(source, _)| source.clone())
+            .ok_or(FileError::NoDefaultDevice)
+    }
+
+    fn default_output_device(&self) -> Result<FileDevice, FileError> {
+        self.devices
+            .as_ref()
+            .map(|(_, destination)| destination.clone())
+            .ok_or(FileError::NoDefaultDevice)
+    }
+
+    /// The buffer size sets the block size, and defaults to 512 frames.
+    /// Retries, the clock master and the startup timeout are ignored, since
+    /// rendering starts right away. Additional devices, physical formats,
+    /// preroll and routing are refused.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<FileSession, FileError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<FileSession, FileError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<FileSession, FileError> {
+        Err(FileError::Unsupported("process taps"))
+    }
+}
@@ -0,0 +1,161 @@
+use std::cmp::Ordering;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use crate::direction::Direction;
+use crate::traits::Device;
+use crate::wav::WavReader;
+
+use super::backend::FileBackend;
+use super::error::FileError;
+
+/// A WAV file standing in for a device: either a source the session reads
+/// its input from, or a destination it writes its output to.
+///
+/// Devices compare and order by path and direction.
+#[derive(Debug, Clone)]
+pub struct FileDevice {
+    path: PathBuf,
+    direction: Direction,
+    channels: usize,
+    sample_rate: f64,
+}
+
+impl FileDevice {
+    /// A WAV file to read input from. Its header is read right away, for its
+    /// channel count and sample rate, which sessions have to match.
+    pub fn source(path: impl Into<PathBuf>) -> Result<Self, FileError> {
+        let path = path.into();
+        let reader = WavReader::new(BufReader::new(File::open(&path)?))?;
+
+        Ok(FileDevice {
+            channels: usize::from(reader.channels()),
+            sample_rate: f64::from(reader.sample_rate()),
+            path,
+            direction: Direction::Input,
+        })
+    }
+
+    /// A WAV file to write output to, in 32 bit float. It's created, or
+    /// truncated, when a session is started on it, and written at the
+    /// session's sample rate.
+    pub fn destination(path: impl Into<PathBuf>, channels: u16) -> Self {
+        FileDevice {
+            path: path.into(),
+            direction: Direction::Output,
+            channels: usize::from(channels),
+            sample_rate: 0.0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub(crate) fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn key(&self) -> (&Path, bool) {
+        (&self.path, self.direction == Direction::Output)
+    }
+}
+
+impl PartialEq for FileDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for FileDevice {}
+
+impl Hash for FileDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for FileDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FileDevice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Device<FileBackend> for FileDevice {
+    fn num_inputs(&self) -> Result<usize, FileError> {
+        Ok(match self.direction {
+            Direction::Input => self.channels,
+            Direction::Output => 0,
+        })
+    }
+
+    fn num_outputs(&self) -> Result<usize, FileError> {
+        Ok(match self.direction {
+            Direction::Input => 0,
+            Direction::Output => self.channels,
+        })
+    }
+
+    fn name(&self) -> Result<String, FileError> {
+        Ok(self
+            .path
+            .file_name()
+            .unwrap_or(self.path.as_os_str())
+            .to_string_lossy()
+            .into_owned())
+    }
+
+    fn persistent_id(&self) -> Result<String, FileError> {
+        Ok(self.path.to_string_lossy().into_owned())
+    }
+
+    /// Destinations take whatever rate the session runs at, so this only
+    /// records the rate for `nominal_sample_rate`. A source's rate is that
+    /// of its file.
+    /// A backend lists only the source and destination it was created
+    /// with, which are its default input and output.
+    fn is_default_input(&self) -> Result<bool, FileError> {
+        Ok(self.direction == Direction::Input)
+    }
+
+    fn is_default_output(&self) -> Result<bool, FileError> {
+        Ok(self.direction == Direction::Output)
+    }
+
+    fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), FileError> {
+        match self.direction {
+            Direction::Input if sample_rate != self.sample_rate => Err(FileError::Unsupported(
+                "changing a source file's sample rate",
+            )),
+            _ => {
+                self.sample_rate = sample_rate;
+                Ok(())
+            }
+        }
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), FileError> {
+        Err(FileError::Unsupported("selecting clock sources"))
+    }
+
+    /// Zero for destinations whose rate hasn't been set.
+    fn nominal_sample_rate(&self) -> Result<f64, FileError> {
+        Ok(self.sample_rate)
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, FileError> {
+        self.nominal_sample_rate()
+    }
+}
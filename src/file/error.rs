@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum FileError {
+    /// Reading or writing one of the files failed, or the source isn't a
+    /// WAV file this crate can read.
+    Io(io::Error),
+    /// A destination was given where the session needs a source.
+    NotASource(PathBuf),
+    /// A source was given where the session needs a destination.
+    NotADestination(PathBuf),
+    /// The source file is at a different sample rate than the session.
+    SampleRateMismatch { requested: f64, file: f64 },
+    /// The callback panicked, which stopped rendering.
+    CallbackPanicked,
+    /// The backend was created without files.
+    NoDefaultDevice,
+    /// Blocks have to have at least one frame.
+    InvalidBufferSize(usize),
+    /// The operation or session option makes no sense when rendering
+    /// offline.
+    Unsupported(&'static str),
+}
+
+impl From<io::Error> for FileError {
+    fn from(error: io::Error) -> Self {
+        FileError::Io(error)
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io(error) => write!(f, "I/O error: {}", error),
+            FileError::NotASource(path) => {
+                write!(f, "{} is a destination, not a source", path.display())
+            }
+            FileError::NotADestination(path) => {
+                write!(f, "{} is a source, not a destination", path.display())
+            }
+            FileError::SampleRateMismatch { requested, file } => write!(
+                f,
+                "Requested {} Hz, but the source file is at {} Hz",
+                requested, file
+            ),
+            FileError::CallbackPanicked => write!(f, "Render callback panicked"),
+            FileError::NoDefaultDevice => write!(f, "File backend has no files"),
+            FileError::InvalidBufferSize(frames) => {
+                write!(f, "Invalid buffer size: {} frames", frames)
+            }
+            FileError::Unsupported(what) => {
+                write!(f, "Not supported when rendering offline: {}", what)
+            }
+        }
+    }
+}
+
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FileError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<FileError> for crate::Error {
+    fn from(error: FileError) -> Self {
+        let kind = match &error {
+            FileError::Io(error) if error.kind() == io::ErrorKind::NotFound => Self::DeviceNotFound,
+            FileError::Io(_) | FileError::CallbackPanicked => Self::Backend,
+            FileError::NotASource(_) | FileError::NotADestination(_) => Self::Unsupported,
+            FileError::SampleRateMismatch { .. } | FileError::InvalidBufferSize(_) => {
+                Self::FormatNotSupported
+            }
+            FileError::NoDefaultDevice => Self::DeviceNotFound,
+            FileError::Unsupported(_) => Self::Unsupported,
+        };
+
+        kind(Box::new(error))
+    }
+}
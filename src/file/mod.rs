@@ -0,0 +1,9 @@
+mod backend;
+mod device;
+mod error;
+mod session;
+
+pub use backend::FileBackend as Backend;
+pub use device::FileDevice;
+pub use error::FileError;
+pub use session::FileSession;
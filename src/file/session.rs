@@ -0,0 +1,410 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::UNIX_EPOCH;
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::CycleTimes;
+use crate::deadline::DeadlineHistogram;
+use crate::direction::Direction;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::{Events, SessionEvent, StopCause};
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::sample::OwnedBuffer;
+use crate::traits::{RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+use crate::wav::{WavReader, WavWriter};
+
+use super::backend::FileBackend;
+use super::device::FileDevice;
+use super::error::FileError;
+
+/// The frames per callback unless the session is configured otherwise.
+pub(crate) const DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// Renders a source file through the callback into a destination file, on
+/// a thread of its own and as fast as the callback allows. Each callback
+/// gets one buffer per direction, shaped like the files, and the sample
+/// time counts the frames rendered so far. The last callback gets however
+/// many frames are left in the source.
+///
+/// The output is the same for the same input and callback, down to the
+/// bytes of the destination file, whose broadcast extension chunk is dated
+/// at the Unix epoch rather than when the session ran.
+pub struct FileSession {
+    engine: Arc<RenderEngine<FileBackend>>,
+    source: FileDevice,
+    destination: FileDevice,
+    block_size: usize,
+    state: State,
+}
+
+enum State {
+    Ready(Pump),
+    Rendering {
+        stop: Arc<AtomicBool>,
+        thread: JoinHandle<Result<(), FileError>>,
+    },
+    Finished,
+}
+
+impl FileSession {
+    pub(crate) fn new(
+        sample_rate: f64,
+        source: FileDevice,
+        destination: FileDevice,
+        block_size: Option<usize>,
+    ) -> Result<Self, FileError> {
+        if source.direction() != Direction::Input {
+            return Err(FileError::NotASource(source.path().to_owned()));
+        }
+        if destination.direction() != Direction::Output {
+            return Err(FileError::NotADestination(destination.path().to_owned()));
+        }
+        let block_size = block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+        if block_size == 0 {
+            return Err(FileError::InvalidBufferSize(block_size));
+        }
+
+        let reader = WavReader::new(BufReader::new(File::open(source.path())?))?;
+        let file_rate = f64::from(reader.sample_rate());
+        if file_rate != sample_rate {
+            return Err(FileError::SampleRateMismatch {
+                requested: sample_rate,
+                file: file_rate,
+            });
+        }
+        let engine = Arc::new(RenderEngine::new(sample_rate));
+        let pump = Pump {
+            engine: engine.clone(),
+            block_size,
+            stop: Arc::new(AtomicBool::new(false)),
+            input_buffers: vec![buffer(block_size, source.channels())],
+            output_buffers: vec![buffer(block_size, destination.channels())],
+            reader,
+        };
+        engine.resize_scratch(block_size, source.channels().max(destination.channels()));
+
+        Ok(FileSession {
+            engine,
+            source,
+            destination,
+            block_size,
+            state: State::Ready(pump),
+        })
+    }
+
+    /// Starts rendering with `callback`. Sessions render their files once,
+    /// so starting a session that has already started does nothing.
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<FileBackend>>,
+    ) -> Result<(), FileError> {
+        let pump = match std::mem::replace(&mut self.state, State::Finished) {
+            State::Ready(pump) => pump,
+            state => {
+                self.state = state;
+                return Ok(());
+            }
+        };
+
+        // The destination is only created now, so a session that never
+        // starts leaves it alone.
+        let writer = File::create(self.destination.path()).and_then(|file| {
+            WavWriter::new(
+                BufWriter::new(file),
+                f64::from(pump.reader.sample_rate()),
+                self.destination.channels() as u16,
+                UNIX_EPOCH,
+            )
+        });
+        let writer = match writer {
+            Ok(writer) => writer,
+            Err(err) => {
+                self.state = State::Ready(pump);
+                return Err(err.into());
+            }
+        };
+
+        self.engine.set_callback(callback);
+        self.engine.valid.store(true, Ordering::Release);
+
+        let stop = pump.stop.clone();
+        let thread = thread::Builder::new()
+            .name("render_callback file".to_owned())
+            .spawn(move || pump.run(writer))?;
+        self.state = State::Rendering { stop, thread };
+
+        Ok(())
+    }
+
+    /// Blocks until the whole source has been rendered and the destination
+    /// written, and returns the first error either ran into. Returns right
+    /// away if that already happened, or the session was never started.
+    pub fn wait_until_finished(&mut self) -> Result<(), FileError> {
+        match std::mem::replace(&mut self.state, State::Finished) {
+            State::Rendering { thread, .. } => {
+                thread.join().unwrap_or(Err(FileError::CallbackPanicked))
+            }
+            state => {
+                self.state = state;
+                Ok(())
+            }
+        }
+    }
+
+    /// Changes how many frames each callback gets. Only possible before the
+    /// session starts.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), FileError> {
+        if frames == 0 {
+            return Err(FileError::InvalidBufferSize(frames));
+        }
+
+        match &mut self.state {
+            State::Ready(pump) => {
+                pump.block_size = frames;
+                pump.input_buffers = vec![buffer(frames, self.source.channels())];
+                pump.output_buffers = vec![buffer(frames, self.destination.channels())];
+                self.block_size = frames;
+                self.engine.resize_scratch(
+                    frames,
+                    self.source.channels().max(self.destination.channels()),
+                );
+                Ok(())
+            }
+            _ => Err(FileError::Unsupported(
+                "changing the buffer size while rendering",
+            )),
+        }
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        self.block_size
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        (
+            vec![self.source.channels()],
+            vec![self.destination.channels()],
+        )
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.engine.set_output_policy(policy);
+    }
+}
+
+impl Drop for FileSession {
+    /// Stops rendering where it is. The destination is still finished, so
+    /// it's a valid, shorter file.
+    fn drop(&mut self) {
+        if let State::Rendering { stop, .. } = &self.state {
+            stop.store(true, Ordering::Release);
+        }
+        let _ = self.wait_until_finished();
+
+        // The thread has been joined, so this drops the callback here.
+        drop(self.engine.take_callback());
+    }
+}
+
+/// The rendering thread's state.
+struct Pump {
+    engine: Arc<RenderEngine<FileBackend>>,
+    block_size: usize,
+    stop: Arc<AtomicBool>,
+    input_buffers: Vec<OwnedBuffer<f32>>,
+    output_buffers: Vec<OwnedBuffer<f32>>,
+    reader: WavReader<BufReader<File>>,
+}
+
+impl Pump {
+    fn run(mut self, mut writer: WavWriter<BufWriter<File>>) -> Result<(), FileError> {
+        let rendered = self.render(&mut writer);
+        let finished = writer.finish();
+        self.engine.valid.store(false, Ordering::Release);
+
+        let result = rendered.and(finished.map(drop));
+        if result.is_err() {
+            self.engine.events.push(SessionEvent::StoppedUnexpectedly {
+                cause: StopCause::Unknown,
+            });
+        }
+
+        Ok(result?)
+    }
+
+    fn render(&mut self, writer: &mut WavWriter<BufWriter<File>>) -> std::io::Result<()> {
+        let mut sample_time = 0u64;
+
+        while !self.stop.load(Ordering::Acquire) {
+            let input = &mut self.input_buffers[0];
+            let (input_channels, output_channels) =
+                (input.num_channels(), self.output_buffers[0].num_channels());
+            input.reshape(self.block_size, input_channels);
+
+            let frames = self.reader.read(input.interleaved_frames_mut())?;
+            if frames == 0 {
+                break;
+            }
+            input.reshape(frames, input_channels);
+            self.output_buffers[0].reshape(frames, output_channels);
+
+            self.engine.cycles.fetch_add(1, Ordering::Release);
+            // Only this thread renders, and the session joins it before
+            // touching the callback.
+            unsafe {
+                self.engine.render(
+                    &self.input_buffers,
+                    &mut self.output_buffers,
+                    frames,
+                    CycleTimes::at(sample_time as f64),
+                );
+            }
+            sample_time += frames as u64;
+
+            writer.write(self.output_buffers[0].interleaved_frames())?;
+        }
+
+        Ok(())
+    }
+}
+
+fn buffer(frames: usize, channels: usize) -> OwnedBuffer<f32> {
+    let mut buffer = OwnedBuffer::with_capacity(frames, channels);
+    buffer.reshape(frames, channels);
+    buffer
+}
+
+impl Session<FileBackend> for FileSession {
+    fn input_device(&self) -> Result<FileDevice, FileError> {
+        Ok(self.source.clone())
+    }
+
+    fn output_device(&self) -> Result<FileDevice, FileError> {
+        Ok(self.destination.clone())
+    }
+
+    fn set_input_device(&mut self, _device: FileDevice) -> Result<(), FileError> {
+        Err(FileError::Unsupported("switching files"))
+    }
+
+    fn set_output_device(&mut self, _device: FileDevice) -> Result<(), FileError> {
+        Err(FileError::Unsupported("switching files"))
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, FileError> {
+        Ok(FileSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, FileError> {
+        let side = |device: &FileDevice| {
+            vec![StreamMapping {
+                device_id: device.path().to_string_lossy().into_owned(),
+                first_device_channel: 0,
+                channels: device.channels(),
+            }]
+        };
+
+        Ok(ChannelMap {
+            input: side(&self.source),
+            output: side(&self.destination),
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.engine.clock.clone()
+    }
+
+    /// Callbacks are never late offline, but the margins show how much
+    /// faster than real time they run.
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<FileBackend>>) {
+        self.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.engine.is_bypassed()
+    }
+
+    fn tune_for_low_latency(
+        &mut self,
+        _target_ms: f64,
+    ) -> Result<Option<LatencyTuning>, FileError> {
+        Err(FileError::Unsupported("tuning for low latency"))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, FileError> {
+        Err(FileError::Unsupported("starting at a host time"))
+    }
+
+    /// Files are rendered in one go, so sessions can't be stopped part way
+    /// through. Dropping the session ends the file early instead.
+    fn stop(&mut self) -> Result<(), FileError> {
+        Err(FileError::Unsupported("stopping while rendering"))
+    }
+
+    /// Sessions are started as they're created, and never stop, so there's
+    /// nothing to do.
+    fn start(&mut self) -> Result<(), FileError> {
+        Ok(())
+    }
+
+    /// Whether the source is still being rendered.
+    fn is_running(&self) -> bool {
+        matches!(self.state, State::Rendering { .. }) && self.engine.valid.load(Ordering::Acquire)
+    }
+}
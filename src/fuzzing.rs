@@ -0,0 +1,109 @@
+//! Entry points for the fuzz targets in `fuzz/`, taking `arbitrary` inputs.
+//! Each one runs an input through the same code the backends use and checks
+//! what it can about the result.
+
+use std::convert::TryInto;
+
+use arbitrary::Arbitrary;
+
+#[cfg(all(feature = "coreaudio", target_os = "macos"))]
+pub use crate::coreaudio::fuzzing::*;
+
+use crate::sample::{OwnedBuffer, Sample, SampleFormat, I24};
+use crate::traits::AudioBuffers;
+
+/// Raw device memory in some sample format, the way a driver hands it over.
+#[derive(Debug, Arbitrary)]
+pub struct SampleConversionInput {
+    pub format: SampleFormat,
+    pub channels: u8,
+    pub data: Vec<u8>,
+}
+
+/// Reads `data` as samples in `format` and converts the buffer to every
+/// sample type and back, checking that conversions to a type able to hold
+/// every value lose nothing and that integers stay within full scale.
+pub fn sample_conversion(input: &SampleConversionInput) {
+    let channels = usize::from(input.channels.max(1));
+    let data = &input.data;
+
+    match input.format {
+        SampleFormat::I16 => convert_all(decode(data, i16::from_ne_bytes), channels),
+        SampleFormat::I24 => convert_all(
+            decode(data, |[a, b, c]| {
+                I24::new(i32::from_le_bytes([0, a, b, c]) >> 8)
+            }),
+            channels,
+        ),
+        SampleFormat::I32 => convert_all(decode(data, i32::from_ne_bytes), channels),
+        SampleFormat::F32 => convert_all(decode(data, f32::from_ne_bytes), channels),
+        SampleFormat::F64 => convert_all(decode(data, f64::from_ne_bytes), channels),
+    }
+}
+
+fn decode<S, const N: usize>(data: &[u8], sample: impl Fn([u8; N]) -> S) -> Vec<S> {
+    data.chunks_exact(N)
+        .map(|bytes| sample(bytes.try_into().unwrap()))
+        .collect()
+}
+
+fn convert_all<S: Sample>(samples: Vec<S>, channels: usize) {
+    let buffer = owned_buffer(&samples, channels);
+
+    convert_and_back::<S, i16>(&buffer);
+    convert_and_back::<S, I24>(&buffer);
+    convert_and_back::<S, i32>(&buffer);
+    convert_and_back::<S, f32>(&buffer);
+    convert_and_back::<S, f64>(&buffer);
+}
+
+fn owned_buffer<S: Sample>(samples: &[S], channels: usize) -> OwnedBuffer<S> {
+    let frames = samples.len() / channels;
+    let mut buffer = OwnedBuffer::with_capacity(frames, channels);
+    assert!(buffer.reshape(frames, channels));
+    buffer
+        .interleaved_frames_mut()
+        .copy_from_slice(&samples[..frames * channels]);
+    buffer
+}
+
+fn convert_and_back<S: Sample, T: Sample>(buffer: &OwnedBuffer<S>) {
+    let converted = buffer.frames_as::<T>().collect::<Vec<_>>();
+    assert_eq!(converted.len(), buffer.interleaved_frames().len());
+
+    if T::FORMAT.is_float() && !S::FORMAT.is_float() {
+        for sample in &converted {
+            assert!(sample.to_f64().abs() <= 1.0, "{:?}", sample.to_f64());
+        }
+    }
+
+    let mut back = owned_buffer(buffer.interleaved_frames(), buffer.num_channels());
+    back.copy_frames_from(&converted);
+    assert_eq!(back.frames().count(), buffer.num_frames());
+
+    if holds_every_value(S::FORMAT, T::FORMAT) {
+        for (&before, &after) in buffer
+            .interleaved_frames()
+            .iter()
+            .zip(back.interleaved_frames())
+        {
+            let (before, after) = (before.to_f64(), after.to_f64());
+            assert!(
+                before == after || (before.is_nan() && after.is_nan()),
+                "{:?} became {:?} going through {:?}",
+                before,
+                after,
+                T::FORMAT
+            );
+        }
+    }
+}
+
+/// Whether every `from` sample converts to `to` exactly.
+fn holds_every_value(from: SampleFormat, to: SampleFormat) -> bool {
+    match (from, to) {
+        (_, SampleFormat::F64) | (SampleFormat::I16, _) => true,
+        (SampleFormat::I24, SampleFormat::I24 | SampleFormat::I32 | SampleFormat::F32) => true,
+        (from, to) => from == to,
+    }
+}
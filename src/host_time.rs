@@ -0,0 +1,72 @@
+//! The platform's host clock. Apple platforms count `mach_absolute_time`
+//! ticks, like CoreAudio time stamps do. Elsewhere a tick is a nanosecond.
+
+pub use platform::{now, timebase};
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod platform {
+    use std::sync::OnceLock;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct MachTimebaseInfo {
+        numer: u32,
+        denom: u32,
+    }
+
+    extern "C" {
+        fn mach_absolute_time() -> u64;
+        fn mach_timebase_info(info: *mut MachTimebaseInfo) -> i32;
+    }
+
+    /// The current host time, in the ticks CoreAudio time stamps use.
+    pub fn now() -> u64 {
+        unsafe { mach_absolute_time() }
+    }
+
+    /// Nanoseconds per tick, as a fraction. The timebase never changes while
+    /// the process runs, so it's only asked for once.
+    pub fn timebase() -> (u32, u32) {
+        static TIMEBASE: OnceLock<(u32, u32)> = OnceLock::new();
+
+        *TIMEBASE.get_or_init(|| {
+            let mut info = MachTimebaseInfo::default();
+            if unsafe { mach_timebase_info(&mut info) } != 0 || info.denom == 0 {
+                return (1, 1);
+            }
+            (info.numer, info.denom)
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_arch = "wasm32")))]
+mod platform {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    /// Nanoseconds since the clock was first read.
+    pub fn now() -> u64 {
+        static ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+        let nanos = ORIGIN.get_or_init(Instant::now).elapsed().as_nanos();
+        nanos.min(u128::from(u64::MAX)) as u64
+    }
+
+    pub fn timebase() -> (u32, u32) {
+        (1, 1)
+    }
+}
+
+/// `Instant` panics on wasm32-unknown-unknown, so the page's clock stands in.
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    /// Nanoseconds since the Unix epoch, at the millisecond resolution
+    /// browsers allow.
+    pub fn now() -> u64 {
+        (js_sys::Date::now().max(0.0) * 1_000_000.0) as u64
+    }
+
+    pub fn timebase() -> (u32, u32) {
+        (1, 1)
+    }
+}
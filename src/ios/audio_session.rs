@@ -0,0 +1,239 @@
+//! The parts of AVAudioSession the backend needs, over the Objective-C
+//! runtime. Every call goes to the app's shared session.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr;
+
+use objc::rc::autoreleasepool;
+use objc::runtime::{Object, BOOL, NO, YES};
+use objc::{class, msg_send, sel, sel_impl};
+
+use super::error::IosError;
+
+#[link(name = "AVFAudio", kind = "framework")]
+extern "C" {
+    static AVAudioSessionCategoryPlayAndRecord: *mut Object;
+    static AVAudioSessionModeDefault: *mut Object;
+    static AVAudioSessionModeVoiceChat: *mut Object;
+}
+
+/// `AVAudioSessionCategoryOptionDefaultToSpeaker`, so output goes to the
+/// speaker rather than the receiver meant for phone calls.
+const DEFAULT_TO_SPEAKER: usize = 0x8;
+/// `AVAudioSessionCategoryOptionAllowBluetoothA2DP`.
+const ALLOW_BLUETOOTH_A2DP: usize = 0x20;
+
+/// `AVAudioSessionPortOverrideNone` and `AVAudioSessionPortOverrideSpeaker`.
+const OVERRIDE_NONE: usize = 0;
+const OVERRIDE_SPEAKER: usize = 0x73706b72;
+
+/// The port type of the built-in speaker, `AVAudioSessionPortBuiltInSpeaker`.
+pub(crate) const BUILT_IN_SPEAKER: &str = "Speaker";
+
+/// A snapshot of an `AVAudioSessionPortDescription`.
+#[derive(Debug, Clone)]
+pub(crate) struct Port {
+    pub uid: String,
+    pub name: String,
+    pub port_type: String,
+    pub channel_names: Vec<String>,
+}
+
+fn shared() -> *mut Object {
+    unsafe { msg_send![class!(AVAudioSession), sharedInstance] }
+}
+
+unsafe fn string(object: *mut Object) -> String {
+    if object.is_null() {
+        return String::new();
+    }
+
+    let utf8: *const c_char = msg_send![object, UTF8String];
+    CStr::from_ptr(utf8).to_string_lossy().into_owned()
+}
+
+unsafe fn objects(array: *mut Object) -> Vec<*mut Object> {
+    if array.is_null() {
+        return Vec::new();
+    }
+
+    let count: usize = msg_send![array, count];
+    (0..count)
+        .map(|index| -> *mut Object { msg_send![array, objectAtIndex: index] })
+        .collect()
+}
+
+unsafe fn port(description: *mut Object) -> Port {
+    let uid: *mut Object = msg_send![description, UID];
+    let name: *mut Object = msg_send![description, portName];
+    let port_type: *mut Object = msg_send![description, portType];
+    let channels: *mut Object = msg_send![description, channels];
+
+    Port {
+        uid: string(uid),
+        name: string(name),
+        port_type: string(port_type),
+        channel_names: objects(channels)
+            .into_iter()
+            .map(|channel| {
+                let name: *mut Object = msg_send![channel, channelName];
+                string(name)
+            })
+            .collect(),
+    }
+}
+
+/// Calls an AVAudioSession method that reports failure through an
+/// `NSError` out parameter.
+unsafe fn check(call: impl FnOnce(*mut *mut Object) -> BOOL) -> Result<(), IosError> {
+    let mut error: *mut Object = ptr::null_mut();
+    if call(&mut error) != NO {
+        return Ok(());
+    }
+
+    let (code, description) = if error.is_null() {
+        (0, String::new())
+    } else {
+        let code: isize = msg_send![error, code];
+        let description: *mut Object = msg_send![error, localizedDescription];
+        (code, string(description))
+    };
+
+    Err(IosError::AudioSession { code, description })
+}
+
+/// Puts the app in the play and record category, which it needs to see
+/// any inputs at all.
+pub(crate) fn set_play_and_record() -> Result<(), IosError> {
+    autoreleasepool(|| unsafe {
+        let options = DEFAULT_TO_SPEAKER | ALLOW_BLUETOOTH_A2DP;
+        check(|error| {
+            msg_send![shared(), setCategory: AVAudioSessionCategoryPlayAndRecord
+                                       mode: AVAudioSessionModeDefault
+                                    options: options
+                                      error: error]
+        })
+    })
+}
+
+/// Switches between the default mode and the one for voice over IP, which
+/// goes with the voice processing audio unit.
+pub(crate) fn set_voice_chat_mode(voice_chat: bool) -> Result<(), IosError> {
+    autoreleasepool(|| unsafe {
+        let mode = if voice_chat {
+            AVAudioSessionModeVoiceChat
+        } else {
+            AVAudioSessionModeDefault
+        };
+        check(|error| msg_send![shared(), setMode: mode error: error])
+    })
+}
+
+pub(crate) fn set_active(active: bool) -> Result<(), IosError> {
+    let active = if active { YES } else { NO };
+    autoreleasepool(|| unsafe {
+        check(|error| msg_send![shared(), setActive: active error: error])
+    })
+}
+
+/// The inputs the app could switch to, including ones not in the route.
+pub(crate) fn available_inputs() -> Vec<Port> {
+    autoreleasepool(|| unsafe {
+        let inputs: *mut Object = msg_send![shared(), availableInputs];
+        objects(inputs)
+            .into_iter()
+            .map(|input| port(input))
+            .collect()
+    })
+}
+
+/// The ports audio currently goes in and out through.
+pub(crate) fn current_route() -> (Vec<Port>, Vec<Port>) {
+    autoreleasepool(|| unsafe {
+        let route: *mut Object = msg_send![shared(), currentRoute];
+        let inputs: *mut Object = msg_send![route, inputs];
+        let outputs: *mut Object = msg_send![route, outputs];
+
+        (
+            objects(inputs)
+                .into_iter()
+                .map(|input| port(input))
+                .collect(),
+            objects(outputs)
+                .into_iter()
+                .map(|output| port(output))
+                .collect(),
+        )
+    })
+}
+
+/// Routes input through the available input with `uid`.
+pub(crate) fn set_preferred_input(uid: &str) -> Result<(), IosError> {
+    autoreleasepool(|| unsafe {
+        let inputs: *mut Object = msg_send![shared(), availableInputs];
+        let input = objects(inputs)
+            .into_iter()
+            .find(|&input| port(input).uid == uid);
+
+        match input {
+            Some(input) => {
+                check(|error| msg_send![shared(), setPreferredInput: input error: error])
+            }
+            None => Err(IosError::Unsupported("inputs that aren't available")),
+        }
+    })
+}
+
+/// Forces output to the built-in speaker, or lets the system pick the
+/// route again.
+pub(crate) fn override_speaker(speaker: bool) -> Result<(), IosError> {
+    let port = if speaker {
+        OVERRIDE_SPEAKER
+    } else {
+        OVERRIDE_NONE
+    };
+    autoreleasepool(|| unsafe {
+        check(|error| msg_send![shared(), overrideOutputAudioPort: port error: error])
+    })
+}
+
+pub(crate) fn sample_rate() -> f64 {
+    unsafe { msg_send![shared(), sampleRate] }
+}
+
+pub(crate) fn set_preferred_sample_rate(sample_rate: f64) -> Result<(), IosError> {
+    autoreleasepool(|| unsafe {
+        check(|error| msg_send![shared(), setPreferredSampleRate: sample_rate error: error])
+    })
+}
+
+/// The hardware buffer duration in seconds.
+pub(crate) fn io_buffer_duration() -> f64 {
+    unsafe { msg_send![shared(), IOBufferDuration] }
+}
+
+pub(crate) fn set_preferred_io_buffer_duration(seconds: f64) -> Result<(), IosError> {
+    autoreleasepool(|| unsafe {
+        check(|error| msg_send![shared(), setPreferredIOBufferDuration: seconds error: error])
+    })
+}
+
+/// The input and output latency of the route in seconds, not counting the
+/// I/O buffer.
+pub(crate) fn latencies() -> (f64, f64) {
+    unsafe {
+        let input: f64 = msg_send![shared(), inputLatency];
+        let output: f64 = msg_send![shared(), outputLatency];
+        (input, output)
+    }
+}
+
+/// The channel counts of the current route.
+pub(crate) fn channels() -> (usize, usize) {
+    unsafe {
+        let input: isize = msg_send![shared(), inputNumberOfChannels];
+        let output: isize = msg_send![shared(), outputNumberOfChannels];
+        (input.max(0) as usize, output.max(0) as usize)
+    }
+}
@@ -0,0 +1,168 @@
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::direction::Direction;
+use crate::processor::{processor_callback, Processor};
+use crate::retry::{retry, RetryPolicy};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::audio_session;
+use super::device::IosDevice;
+use super::error::IosError;
+use super::session::IosSession;
+
+/// Plays and records through the RemoteIO audio unit, with AVAudioSession
+/// choosing the route. Creating the backend puts the app's audio session
+/// in the play and record category.
+pub struct IosBackend;
+
+impl IosBackend {
+    fn new_session(
+        &self,
+        policy: RetryPolicy,
+        sample_rate: f64,
+        input_device: IosDevice,
+        output_device: IosDevice,
+        buffer_size: Option<usize>,
+        voice_processing: bool,
+    ) -> Result<IosSession, IosError> {
+        retry(policy, IosError::is_transient, || {
+            IosSession::new(
+                sample_rate,
+                input_device.clone(),
+                output_device.clone(),
+                buffer_size,
+                voice_processing,
+            )
+        })
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(&self, config: &SessionConfig<Self>) -> Result<IosSession, IosError> {
+        if config.physical_format.is_some() {
+            return Err(IosError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(IosError::Unsupported("preroll"));
+        }
+        if config.routing.is_some() {
+            return Err(IosError::Unsupported("routing matrices"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(IosError::Unsupported("more than one device per direction"));
+        }
+
+        let session = self.new_session(
+            config.retry,
+            config.sample_rate,
+            config.input_device.clone(),
+            config.output_device.clone(),
+            config.buffer_size,
+            config.voice_processing,
+        )?;
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for IosBackend {
+    type Session = IosSession;
+    type Error = IosError;
+    type Device = IosDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    fn new() -> Result<Self, IosError> {
+        audio_session::set_play_and_record()?;
+        Ok(IosBackend)
+    }
+
+    fn is_available() -> bool {
+        true
+    }
+
+    /// The inputs the app could record from, and the outputs of the current
+    /// route. iOS doesn't let apps pick outputs other than the speaker, so
+    /// those are the only ones a session can use.
+    fn all_devices(&self) -> Result<Vec<IosDevice>, IosError> {
+        let inputs = audio_session::available_inputs()
+            .into_iter()
+            .map(|port| IosDevice::new(port, Direction::Input));
+        let outputs = audio_session::current_route()
+            .1
+            .into_iter()
+            .map(|port| IosDevice::new(port, Direction::Output));
+
+        Ok(inputs.chain(outputs).collect())
+    }
+
+    fn default_input_device(&self) -> Result<IosDevice, IosError> {
+        audio_session::current_route()
+            .0
+            .into_iter()
+            .next()
+            .map(|port| IosDevice::new(port, Direction::Input))
+            .ok_or(IosError::NoDefaultDevice)
+    }
+
+    fn default_output_device(&self) -> Result<IosDevice, IosError> {
+        audio_session::current_route()
+            .1
+            .into_iter()
+            .next()
+            .map(|port| IosDevice::new(port, Direction::Output))
+            .ok_or(IosError::NoDefaultDevice)
+    }
+
+    /// RemoteIO converts to and from the hardware format itself, and an app
+    /// only has one route, so a `physical_format` and additional devices
+    /// are refused, along with preroll and routing, which only CoreAudio
+    /// sessions implement so far. `clock_master` and the metadata are
+    /// ignored. `voice_processing` swaps RemoteIO for the voice processing
+    /// unit and puts the audio session in voice chat mode.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<IosSession, IosError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<IosSession, IosError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<IosSession, IosError> {
+        Err(IosError::Unsupported("capturing other apps"))
+    }
+}
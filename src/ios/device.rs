@@ -0,0 +1,145 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+use crate::direction::Direction;
+use crate::traits::Device;
+
+use super::audio_session::{self, Port};
+use super::backend::IosBackend;
+use super::error::IosError;
+
+/// An AVAudioSession port, like the built-in microphone, the speaker or a
+/// headset, as it was when the device was listed. Ports only go one way,
+/// so each device has either inputs or outputs.
+///
+/// Devices compare and order by port UID and direction.
+#[derive(Debug, Clone)]
+pub struct IosDevice {
+    port: Port,
+    direction: Direction,
+}
+
+impl IosDevice {
+    pub(crate) fn new(port: Port, direction: Direction) -> Self {
+        IosDevice { port, direction }
+    }
+
+    pub fn uid(&self) -> &str {
+        &self.port.uid
+    }
+
+    /// The AVAudioSession port type, like `MicrophoneBuiltIn` or
+    /// `Headphones`.
+    pub fn port_type(&self) -> &str {
+        &self.port.port_type
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn key(&self) -> (&str, bool) {
+        (&self.port.uid, self.direction == Direction::Output)
+    }
+
+    fn channels(&self, direction: Direction) -> usize {
+        if direction == self.direction {
+            self.port.channel_names.len()
+        } else {
+            0
+        }
+    }
+}
+
+impl PartialEq for IosDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for IosDevice {}
+
+impl Hash for IosDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for IosDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IosDevice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Device<IosBackend> for IosDevice {
+    fn num_inputs(&self) -> Result<usize, IosError> {
+        Ok(self.channels(Direction::Input))
+    }
+
+    fn num_outputs(&self) -> Result<usize, IosError> {
+        Ok(self.channels(Direction::Output))
+    }
+
+    fn name(&self) -> Result<String, IosError> {
+        Ok(self.port.name.clone())
+    }
+
+    fn channel_name(
+        &self,
+        direction: Direction,
+        channel: usize,
+    ) -> Result<Option<String>, IosError> {
+        if direction != self.direction {
+            return Ok(None);
+        }
+
+        Ok(self.port.channel_names.get(channel).cloned())
+    }
+
+    /// Built-in ports keep their UID for good, and external ones for as
+    /// long as the accessory reports the same one, which most do.
+    fn persistent_id(&self) -> Result<String, IosError> {
+        Ok(self.port.uid.clone())
+    }
+
+    /// The default ports are the first of the current route's, as the
+    /// backend's `default_input_device` picks them.
+    fn is_default_input(&self) -> Result<bool, IosError> {
+        let (inputs, _) = audio_session::current_route();
+        Ok(self.direction == Direction::Input && is_first(&inputs, &self.port))
+    }
+
+    fn is_default_output(&self) -> Result<bool, IosError> {
+        let (_, outputs) = audio_session::current_route();
+        Ok(self.direction == Direction::Output && is_first(&outputs, &self.port))
+    }
+
+    /// The app's audio session has a single sample rate for every port, so
+    /// this asks for it to change for all of them. The system may pick a
+    /// different rate anyway.
+    fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), IosError> {
+        audio_session::set_preferred_sample_rate(sample_rate)
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), IosError> {
+        Err(IosError::Unsupported("selecting clock sources"))
+    }
+
+    fn nominal_sample_rate(&self) -> Result<f64, IosError> {
+        Ok(audio_session::sample_rate())
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, IosError> {
+        self.nominal_sample_rate()
+    }
+}
+
+fn is_first(ports: &[Port], port: &Port) -> bool {
+    ports.first().is_some_and(|first| first.uid == port.uid)
+}
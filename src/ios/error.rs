@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use coreaudio_sys::OSStatus;
+
+#[derive(Debug)]
+pub enum IosError {
+    Status(OSStatus),
+    /// AVAudioSession refused a request, with the `NSError` it gave.
+    AudioSession {
+        code: isize,
+        description: String,
+    },
+    /// The system has no RemoteIO audio unit, which should never happen.
+    NoRemoteIO,
+    /// The current route has no port in the direction a default device was
+    /// asked for.
+    NoDefaultDevice,
+    /// The session was started, but the audio unit never called it.
+    StartupTimeout(Duration),
+    /// The operation or session option has no iOS equivalent.
+    Unsupported(&'static str),
+}
+
+pub(crate) fn check_os_status(status: OSStatus) -> Result<(), IosError> {
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(IosError::Status(status))
+    }
+}
+
+impl IosError {
+    /// Whether the error is expected to clear up by itself. Activating the
+    /// audio session fails while a phone call or another app with priority
+    /// holds the audio hardware.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, IosError::AudioSession { .. })
+    }
+}
+
+impl fmt::Display for IosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IosError::Status(status) => write!(f, "CoreAudio error {}", status),
+            IosError::AudioSession { code, description } => {
+                write!(f, "AVAudioSession error {}: {}", code, description)
+            }
+            IosError::NoRemoteIO => write!(f, "RemoteIO audio unit not found"),
+            IosError::NoDefaultDevice => write!(f, "Current audio route has no port"),
+            IosError::StartupTimeout(timeout) => {
+                write!(f, "RemoteIO did not start the session within {:?}", timeout)
+            }
+            IosError::Unsupported(what) => write!(f, "Not supported on iOS: {}", what),
+        }
+    }
+}
+
+impl Error for IosError {}
+
+impl From<IosError> for crate::Error {
+    fn from(error: IosError) -> Self {
+        let kind = match &error {
+            IosError::Status(_) | IosError::AudioSession { .. } | IosError::NoRemoteIO => {
+                Self::Backend
+            }
+            IosError::NoDefaultDevice => Self::DeviceNotFound,
+            IosError::StartupTimeout(_) => Self::StartupTimeout,
+            IosError::Unsupported(_) => Self::Unsupported,
+        };
+
+        kind(Box::new(error))
+    }
+}
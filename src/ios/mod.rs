@@ -0,0 +1,10 @@
+mod audio_session;
+mod backend;
+mod device;
+mod error;
+mod session;
+
+pub use backend::IosBackend as Backend;
+pub use device::IosDevice;
+pub use error::IosError;
+pub use session::IosSession;
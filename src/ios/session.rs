@@ -0,0 +1,681 @@
+use std::ffi::c_void;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use coreaudio_sys::{
+    kAudioFormatFlagIsFloat, kAudioFormatFlagIsPacked, kAudioFormatLinearPCM,
+    kAudioOutputUnitProperty_EnableIO, kAudioTimeStampHostTimeValid,
+    kAudioTimeStampSampleTimeValid, kAudioUnitManufacturer_Apple,
+    kAudioUnitProperty_MaximumFramesPerSlice, kAudioUnitProperty_SetRenderCallback,
+    kAudioUnitProperty_StreamFormat, kAudioUnitScope_Global, kAudioUnitScope_Input,
+    kAudioUnitScope_Output, kAudioUnitSubType_RemoteIO, kAudioUnitSubType_VoiceProcessingIO,
+    kAudioUnitType_Output, AURenderCallbackStruct, AudioBuffer, AudioBufferList,
+    AudioComponentDescription, AudioComponentFindNext, AudioComponentInstanceDispose,
+    AudioComponentInstanceNew, AudioOutputUnitStart, AudioOutputUnitStop,
+    AudioStreamBasicDescription, AudioTimeStamp, AudioUnit, AudioUnitElement, AudioUnitInitialize,
+    AudioUnitPropertyID, AudioUnitRender, AudioUnitRenderActionFlags, AudioUnitScope,
+    AudioUnitSetProperty, AudioUnitUninitialize, OSStatus,
+};
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::{AudioTimestamp, CycleTimes};
+use crate::deadline::DeadlineHistogram;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::Events;
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::rt_cell::RtCell;
+use crate::sample::OwnedBuffer;
+use crate::traits::{RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+
+use super::audio_session::{self, BUILT_IN_SPEAKER};
+use super::backend::IosBackend;
+use super::device::IosDevice;
+use super::error::{check_os_status, IosError};
+
+/// RemoteIO's element for the hardware input, and for the hardware output.
+const INPUT_BUS: AudioUnitElement = 1;
+const OUTPUT_BUS: AudioUnitElement = 0;
+
+/// The most frames RemoteIO is allowed to ask for at once. Apple recommends
+/// 4096 so audio keeps running while the screen is locked, when the system
+/// switches to larger buffers to save power.
+const MAX_FRAMES_PER_SLICE: usize = 4096;
+
+/// How long each buffer size is tried out for when tuning for low latency.
+const LATENCY_TRIAL_PERIOD: Duration = Duration::from_secs(1);
+
+/// The buffer sizes tried when tuning for low latency. The system rounds
+/// the requested duration to what the hardware supports.
+const MIN_TUNED_BUFFER_SIZE: usize = 32;
+const MAX_TUNED_BUFFER_SIZE: usize = MAX_FRAMES_PER_SLICE;
+
+/// A RemoteIO audio unit playing and recording through the app's audio
+/// route. The callback gets one interleaved buffer per direction, with as
+/// many channels as the route has, and no input buffer if the route has no
+/// input. RemoteIO converts to the session's sample rate if the hardware
+/// runs at another one.
+///
+/// Route changes and interruptions, like phone calls, aren't followed: the
+/// callback just stops being called until the system resumes the unit.
+pub struct IosSession {
+    shared: Box<SharedState>,
+    input: IosDevice,
+    output: IosDevice,
+    /// The route's input and output channel counts.
+    channels: (usize, usize),
+    running: bool,
+}
+
+/// What the render callback gets as its refcon. Boxed so its address stays
+/// the same for the lifetime of the session.
+struct SharedState {
+    engine: Arc<RenderEngine<IosBackend>>,
+    unit: AudioUnit,
+    buffers: RtCell<RenderBuffers>,
+}
+
+struct RenderBuffers {
+    input: Vec<OwnedBuffer<f32>>,
+    output: Vec<OwnedBuffer<f32>>,
+}
+
+impl IosSession {
+    pub(crate) fn new(
+        sample_rate: f64,
+        input: IosDevice,
+        output: IosDevice,
+        buffer_size: Option<usize>,
+        voice_processing: bool,
+    ) -> Result<Self, IosError> {
+        audio_session::set_voice_chat_mode(voice_processing)?;
+        audio_session::set_preferred_sample_rate(sample_rate)?;
+        if let Some(frames) = buffer_size {
+            audio_session::set_preferred_io_buffer_duration(frames as f64 / sample_rate)?;
+        }
+        audio_session::set_active(true)?;
+        route_input(&input)?;
+        route_output(&output)?;
+
+        let unit = unsafe { remote_io(voice_processing)? };
+        let mut session = IosSession {
+            shared: Box::new(SharedState {
+                engine: Arc::new(RenderEngine::new(sample_rate)),
+                unit,
+                buffers: RtCell::new(None),
+            }),
+            input,
+            output,
+            channels: (0, 0),
+            running: false,
+        };
+        session.configure()?;
+
+        Ok(session)
+    }
+
+    /// Starts the audio unit with `callback`.
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<IosBackend>>,
+    ) -> Result<(), IosError> {
+        self.shared.engine.set_callback(callback);
+        self.run()
+    }
+
+    /// Sets the audio unit up for the channels of the current route.
+    fn configure(&mut self) -> Result<(), IosError> {
+        let (ins, outs) = audio_session::channels();
+        self.channels = (ins, outs);
+
+        let unit = self.shared.unit;
+        let sample_rate = self.shared.engine.clock.sample_rate();
+        let callback = AURenderCallbackStruct {
+            inputProc: Some(render),
+            inputProcRefCon: &*self.shared as *const SharedState as *mut c_void,
+        };
+
+        unsafe {
+            check_os_status(AudioUnitUninitialize(unit))?;
+            set_property(
+                unit,
+                kAudioOutputUnitProperty_EnableIO,
+                kAudioUnitScope_Input,
+                INPUT_BUS,
+                &u32::from(ins > 0),
+            )?;
+            if ins > 0 {
+                set_property(
+                    unit,
+                    kAudioUnitProperty_StreamFormat,
+                    kAudioUnitScope_Output,
+                    INPUT_BUS,
+                    &stream_description(sample_rate, ins),
+                )?;
+            }
+            set_property(
+                unit,
+                kAudioUnitProperty_StreamFormat,
+                kAudioUnitScope_Input,
+                OUTPUT_BUS,
+                &stream_description(sample_rate, outs),
+            )?;
+            set_property(
+                unit,
+                kAudioUnitProperty_MaximumFramesPerSlice,
+                kAudioUnitScope_Global,
+                0,
+                &(MAX_FRAMES_PER_SLICE as u32),
+            )?;
+            set_property(
+                unit,
+                kAudioUnitProperty_SetRenderCallback,
+                kAudioUnitScope_Input,
+                OUTPUT_BUS,
+                &callback,
+            )?;
+            check_os_status(AudioUnitInitialize(unit))?;
+        }
+
+        let side = |channels: usize| {
+            (channels > 0)
+                .then(|| buffer(MAX_FRAMES_PER_SLICE, channels))
+                .into_iter()
+                .collect()
+        };
+        let buffers = RenderBuffers {
+            input: side(ins),
+            output: side(outs),
+        };
+        self.shared.buffers.replace(Some(Box::new(buffers)));
+        self.shared
+            .engine
+            .resize_scratch(MAX_FRAMES_PER_SLICE, ins.max(outs));
+        self.shared.engine.reset_timeline();
+
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), IosError> {
+        if self.running {
+            return Ok(());
+        }
+
+        self.shared.engine.valid.store(true, Ordering::Release);
+        check_os_status(unsafe { AudioOutputUnitStart(self.shared.unit) })?;
+        self.running = true;
+
+        Ok(())
+    }
+
+    /// Returns whether the unit was running.
+    fn halt(&mut self) -> Result<bool, IosError> {
+        if !self.running {
+            return Ok(false);
+        }
+
+        self.shared.engine.valid.store(false, Ordering::Release);
+        self.running = false;
+        check_os_status(unsafe { AudioOutputUnitStop(self.shared.unit) })?;
+
+        Ok(true)
+    }
+
+    /// Reconfigures the unit for a new route.
+    fn rebuild(&mut self) -> Result<(), IosError> {
+        let was_running = self.halt()?;
+        self.configure()?;
+
+        if was_running {
+            self.run()?;
+        }
+
+        Ok(())
+    }
+
+    /// Asks for hardware buffers of `frames` frames. The system rounds it
+    /// to a size the hardware supports, and may change it again at any
+    /// time, like when the screen locks.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), IosError> {
+        let sample_rate = self.shared.engine.clock.sample_rate();
+        audio_session::set_preferred_io_buffer_duration(frames as f64 / sample_rate)?;
+        self.shared.engine.reset_timeline();
+
+        Ok(())
+    }
+
+    /// The hardware buffer size the system is using right now.
+    pub fn buffer_size(&self) -> usize {
+        let sample_rate = self.shared.engine.clock.sample_rate();
+        (audio_session::io_buffer_duration() * sample_rate).round() as usize
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        MAX_FRAMES_PER_SLICE
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        let side = |channels: usize| (channels > 0).then_some(channels).into_iter().collect();
+        (side(self.channels.0), side(self.channels.1))
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.shared.engine.set_output_policy(policy);
+    }
+
+    /// Blocks until the audio unit has called the session at least once.
+    pub fn wait_until_running(&self, timeout: Duration) -> Result<(), IosError> {
+        let deadline = Instant::now() + timeout;
+
+        while self.shared.engine.cycles.load(Ordering::Acquire) == 0 {
+            if Instant::now() >= deadline {
+                return Err(IosError::StartupTimeout(timeout));
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for IosSession {
+    fn drop(&mut self) {
+        let _ = self.halt();
+
+        unsafe {
+            AudioUnitUninitialize(self.shared.unit);
+            AudioComponentInstanceDispose(self.shared.unit);
+        }
+
+        // The unit is gone, so this is guaranteed to drop the callback here
+        // rather than on the real-time thread.
+        drop(self.shared.engine.take_callback());
+    }
+}
+
+fn route_input(device: &IosDevice) -> Result<(), IosError> {
+    audio_session::set_preferred_input(device.uid())
+}
+
+/// Apps can't pick outputs on iOS, other than forcing audio to the speaker,
+/// so any other output has to be part of the current route already.
+fn route_output(device: &IosDevice) -> Result<(), IosError> {
+    audio_session::override_speaker(device.port_type() == BUILT_IN_SPEAKER)?;
+
+    let (_, outputs) = audio_session::current_route();
+    if outputs.iter().any(|port| port.uid == device.uid()) {
+        Ok(())
+    } else {
+        Err(IosError::Unsupported("outputs outside the current route"))
+    }
+}
+
+/// RemoteIO, or its voice processing variant, which cancels the output's
+/// echo from the input and evens out its level. Both have the same buses
+/// and properties.
+unsafe fn remote_io(voice_processing: bool) -> Result<AudioUnit, IosError> {
+    let description = AudioComponentDescription {
+        componentType: kAudioUnitType_Output,
+        componentSubType: if voice_processing {
+            kAudioUnitSubType_VoiceProcessingIO
+        } else {
+            kAudioUnitSubType_RemoteIO
+        },
+        componentManufacturer: kAudioUnitManufacturer_Apple,
+        componentFlags: 0,
+        componentFlagsMask: 0,
+    };
+
+    let component = AudioComponentFindNext(ptr::null_mut(), &description);
+    if component.is_null() {
+        return Err(IosError::NoRemoteIO);
+    }
+
+    let mut unit = ptr::null_mut();
+    check_os_status(AudioComponentInstanceNew(component, &mut unit))?;
+
+    Ok(unit)
+}
+
+unsafe fn set_property<T>(
+    unit: AudioUnit,
+    id: AudioUnitPropertyID,
+    scope: AudioUnitScope,
+    element: AudioUnitElement,
+    value: &T,
+) -> Result<(), IosError> {
+    check_os_status(AudioUnitSetProperty(
+        unit,
+        id,
+        scope,
+        element,
+        value as *const T as *const c_void,
+        mem::size_of::<T>() as u32,
+    ))
+}
+
+/// Interleaved 32 bit floats, which RemoteIO converts to and from whatever
+/// the hardware uses.
+fn stream_description(sample_rate: f64, channels: usize) -> AudioStreamBasicDescription {
+    let bytes_per_frame = (mem::size_of::<f32>() * channels) as u32;
+
+    AudioStreamBasicDescription {
+        mSampleRate: sample_rate,
+        mFormatID: kAudioFormatLinearPCM,
+        mFormatFlags: kAudioFormatFlagIsFloat | kAudioFormatFlagIsPacked,
+        mBytesPerPacket: bytes_per_frame,
+        mFramesPerPacket: 1,
+        mBytesPerFrame: bytes_per_frame,
+        mChannelsPerFrame: channels as u32,
+        mBitsPerChannel: 32,
+        mReserved: 0,
+    }
+}
+
+fn buffer(frames: usize, channels: usize) -> OwnedBuffer<f32> {
+    let mut buffer = OwnedBuffer::with_capacity(frames, channels);
+    buffer.reshape(frames, channels);
+    buffer
+}
+
+unsafe fn silence(data: *mut AudioBufferList) {
+    let destination = &mut (*data).mBuffers[0];
+    ptr::write_bytes(
+        destination.mData as *mut u8,
+        0,
+        destination.mDataByteSize as usize,
+    );
+}
+
+/// Pulls the input from RemoteIO, then renders the output it asked for.
+unsafe extern "C" fn render(
+    ref_con: *mut c_void,
+    flags: *mut AudioUnitRenderActionFlags,
+    time_stamp: *const AudioTimeStamp,
+    _bus: u32,
+    frames: u32,
+    data: *mut AudioBufferList,
+) -> OSStatus {
+    let shared = &*(ref_con as *const SharedState);
+    shared.engine.cycles.fetch_add(1, Ordering::Release);
+
+    // Only the real-time thread reads the buffers, and the control thread
+    // only replaces them while the unit is stopped.
+    let rendered = shared.buffers.with(|buffers| {
+        let frames = frames as usize;
+        let fits = buffers
+            .input
+            .iter_mut()
+            .chain(&mut buffers.output)
+            .all(|buffer| {
+                let channels = buffer.num_channels();
+                buffer.reshape(frames, channels)
+            });
+        if !fits {
+            return false;
+        }
+
+        for input in &mut buffers.input {
+            let channels = input.num_channels() as u32;
+            let samples = input.interleaved_frames_mut();
+            let mut list = AudioBufferList {
+                mNumberBuffers: 1,
+                mBuffers: [AudioBuffer {
+                    mNumberChannels: channels,
+                    mDataByteSize: mem::size_of_val(samples) as u32,
+                    mData: samples.as_mut_ptr() as *mut c_void,
+                }],
+            };
+
+            // Input isn't ready for the first few cycles after starting.
+            let status = AudioUnitRender(
+                shared.unit,
+                flags,
+                time_stamp,
+                INPUT_BUS,
+                frames as u32,
+                &mut list,
+            );
+            if status != 0 {
+                samples.fill(0.0);
+            }
+        }
+
+        // RemoteIO renders input and output against the same time stamp.
+        let time = &*time_stamp;
+        let timestamp = AudioTimestamp {
+            sample_time: Some(time.mSampleTime)
+                .filter(|_| time.mFlags & kAudioTimeStampSampleTimeValid != 0),
+            host_time: Some(time.mHostTime)
+                .filter(|_| time.mFlags & kAudioTimeStampHostTimeValid != 0),
+        };
+        shared.engine.render(
+            &buffers.input,
+            &mut buffers.output,
+            frames,
+            CycleTimes {
+                now: AudioTimestamp::default(),
+                input: timestamp,
+                output: timestamp,
+            },
+        );
+
+        if let Some(output) = buffers.output.first() {
+            let destination = &mut (*data).mBuffers[0];
+            let samples = slice::from_raw_parts_mut(
+                destination.mData as *mut f32,
+                destination.mDataByteSize as usize / mem::size_of::<f32>(),
+            );
+            let source = output.interleaved_frames();
+            let len = samples.len().min(source.len());
+            samples[..len].copy_from_slice(&source[..len]);
+        }
+
+        true
+    });
+
+    if rendered != Some(true) {
+        silence(data);
+    }
+
+    0
+}
+
+impl Session<IosBackend> for IosSession {
+    fn input_device(&self) -> Result<IosDevice, IosError> {
+        Ok(self.input.clone())
+    }
+
+    fn output_device(&self) -> Result<IosDevice, IosError> {
+        Ok(self.output.clone())
+    }
+
+    fn set_input_device(&mut self, device: IosDevice) -> Result<(), IosError> {
+        route_input(&device)?;
+        self.input = device;
+        self.rebuild()
+    }
+
+    fn set_output_device(&mut self, device: IosDevice) -> Result<(), IosError> {
+        route_output(&device)?;
+        self.output = device;
+        self.rebuild()
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, IosError> {
+        Ok(IosSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, IosError> {
+        let side = |device: &IosDevice, channels: usize| {
+            (channels > 0)
+                .then(|| StreamMapping {
+                    device_id: device.uid().to_owned(),
+                    first_device_channel: 0,
+                    channels,
+                })
+                .into_iter()
+                .collect()
+        };
+
+        Ok(ChannelMap {
+            input: side(&self.input, self.channels.0),
+            output: side(&self.output, self.channels.1),
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.shared.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.shared.engine.clock.clone()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.shared.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.shared.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.shared.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.shared.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.shared.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.shared.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.shared.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<IosBackend>>) {
+        self.shared.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.shared.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.shared.engine.is_bypassed()
+    }
+
+    /// The route's latency as reported by AVAudioSession, plus the hardware
+    /// buffer.
+    fn input_latency_frames(&self) -> Result<usize, IosError> {
+        let sample_rate = self.shared.engine.clock.sample_rate();
+        let (input_latency, _) = audio_session::latencies();
+        Ok(self.buffer_size() + (input_latency * sample_rate) as usize)
+    }
+
+    fn output_latency_frames(&self) -> Result<usize, IosError> {
+        let sample_rate = self.shared.engine.clock.sample_rate();
+        let (_, output_latency) = audio_session::latencies();
+        Ok(self.buffer_size() + (output_latency * sample_rate) as usize)
+    }
+
+    /// Asks for ever larger hardware buffers, starting from the largest one
+    /// within the target, until one runs without dropouts. The route's own
+    /// latency, as reported by AVAudioSession, counts towards the target.
+    fn tune_for_low_latency(&mut self, target_ms: f64) -> Result<Option<LatencyTuning>, IosError> {
+        let sample_rate = self.shared.engine.clock.sample_rate();
+        let (input_latency, output_latency) = audio_session::latencies();
+        let route_frames = ((input_latency + output_latency) * sample_rate) as usize;
+        let target_frames =
+            ((target_ms * sample_rate / 1000.0) as usize).saturating_sub(route_frames);
+
+        let mut candidates = Vec::new();
+        let mut size = MIN_TUNED_BUFFER_SIZE;
+        while size <= MAX_TUNED_BUFFER_SIZE {
+            candidates.push(size);
+            size *= 2;
+        }
+
+        let first = candidates
+            .iter()
+            .rposition(|&size| 2 * size <= target_frames)
+            .unwrap_or(0);
+
+        let original = self.buffer_size();
+        let mut stable = false;
+        for &size in &candidates[first..] {
+            self.set_buffer_size(size)?;
+
+            let before = self.shared.engine.dropouts.stats().count;
+            thread::sleep(LATENCY_TRIAL_PERIOD);
+
+            if self.shared.engine.dropouts.stats().count == before {
+                stable = true;
+                break;
+            }
+        }
+
+        if !stable {
+            // Nothing ran cleanly, so leave the session as it was found.
+            self.set_buffer_size(original)?;
+            return Ok(None);
+        }
+
+        let buffer_size = self.buffer_size();
+        Ok(Some(LatencyTuning {
+            buffer_size,
+            input_latency_frames: buffer_size + (input_latency * sample_rate) as usize,
+            output_latency_frames: buffer_size + (output_latency * sample_rate) as usize,
+            sample_rate,
+        }))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, IosError> {
+        Err(IosError::Unsupported("starting at a host time"))
+    }
+
+    fn stop(&mut self) -> Result<(), IosError> {
+        self.halt()?;
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), IosError> {
+        if !self.running {
+            self.shared.engine.reset_timeline();
+        }
+        self.run()
+    }
+
+    fn is_running(&self) -> bool {
+        self.running
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use ::jack::{Client, ClientOptions, PortFlags};
+
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::direction::Direction;
+use crate::processor::{processor_callback, Processor};
+use crate::retry::{retry, RetryPolicy};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::{default_client_name, JackDevice, AUDIO_PORT_TYPE};
+use super::error::JackError;
+use super::session::JackSession;
+
+/// The name sessions register under unless their config gives an
+/// application name. The server appends a number if it's taken.
+pub(crate) const CLIENT_NAME: &str = "render_callback";
+
+/// Talks to a running JACK server. Never starts one itself.
+pub struct JackBackend {
+    /// A client without ports, only used to look around the graph.
+    server: Arc<Client>,
+}
+
+impl JackBackend {
+    fn device(&self, client_name: &str) -> JackDevice {
+        JackDevice::new(client_name.to_owned(), self.server.clone())
+    }
+
+    /// The client owning the first physical port carrying audio in
+    /// `direction`, which is the sound card on most setups.
+    fn default_device(&self, direction: Direction) -> Result<JackDevice, JackError> {
+        default_client_name(&self.server, direction)
+            .map(|client| self.device(&client))
+            .ok_or(JackError::NoDefaultDevice)
+    }
+
+    fn new_session(
+        &self,
+        policy: RetryPolicy,
+        client_name: &str,
+        sample_rate: f64,
+        inputs: Vec<JackDevice>,
+        outputs: Vec<JackDevice>,
+    ) -> Result<JackSession, JackError> {
+        retry(policy, JackError::is_transient, || {
+            JackSession::new(client_name, sample_rate, inputs.clone(), outputs.clone())
+        })
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(&self, config: &SessionConfig<Self>) -> Result<JackSession, JackError> {
+        if config.physical_format.is_some() {
+            return Err(JackError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(JackError::Unsupported("preroll"));
+        }
+        if config.routing.is_some() {
+            return Err(JackError::Unsupported("routing matrices"));
+        }
+
+        let mut inputs = vec![config.input_device.clone()];
+        inputs.extend(config.additional_input_devices.iter().cloned());
+        let mut outputs = vec![config.output_device.clone()];
+        outputs.extend(config.additional_output_devices.iter().cloned());
+
+        let client_name = config
+            .metadata
+            .application_name
+            .as_deref()
+            .unwrap_or(CLIENT_NAME);
+        let mut session = self.new_session(
+            config.retry,
+            client_name,
+            config.sample_rate,
+            inputs,
+            outputs,
+        )?;
+
+        if let Some(frames) = config.buffer_size {
+            session.set_buffer_size(frames)?;
+        }
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for JackBackend {
+    type Session = JackSession;
+    type Error = JackError;
+    type Device = JackDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    fn new() -> Result<Self, JackError> {
+        let (client, _status) = Client::new(CLIENT_NAME, ClientOptions::NO_START_SERVER)?;
+
+        Ok(JackBackend {
+            server: Arc::new(client),
+        })
+    }
+
+    fn is_available() -> bool {
+        Self::new().is_ok()
+    }
+
+    fn all_devices(&self) -> Result<Vec<JackDevice>, JackError> {
+        let clients: BTreeSet<_> = self
+            .server
+            .ports(None, Some(AUDIO_PORT_TYPE), PortFlags::empty())
+            .iter()
+            .filter_map(|port| port.split_once(':'))
+            .map(|(client, _)| client.to_owned())
+            .collect();
+
+        Ok(clients
+            .into_iter()
+            .map(|client| self.device(&client))
+            .collect())
+    }
+
+    fn default_input_device(&self) -> Result<JackDevice, JackError> {
+        self.default_device(Direction::Input)
+    }
+
+    fn default_output_device(&self) -> Result<JackDevice, JackError> {
+        self.default_device(Direction::Output)
+    }
+
+    /// The server has a single clock and picks the sample format, so
+    /// `clock_master` is ignored and a `physical_format` is refused. So are
+    /// preroll and routing, which only CoreAudio sessions implement so far.
+    /// The application name, if any, names the session's client.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<JackSession, JackError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<JackSession, JackError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    /// JACK clients can be captured by connecting to their output ports like
+    /// any other device, which `start_session_with_config` does.
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<JackSession, JackError> {
+        Err(JackError::Unsupported("process taps"))
+    }
+}
@@ -0,0 +1,176 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use ::jack::{Client, PortFlags};
+
+use crate::direction::Direction;
+use crate::traits::Device;
+
+use super::backend::JackBackend;
+use super::error::JackError;
+
+/// JACK's name for the type of audio ports.
+pub(crate) const AUDIO_PORT_TYPE: &str = "32 bit float mono audio";
+
+/// A JACK client, like `system` for the sound card, whose audio ports a
+/// session can connect to. Its output ports are the device's inputs, and the
+/// other way around.
+///
+/// Devices compare and order by client name.
+#[derive(Clone)]
+pub struct JackDevice {
+    client_name: String,
+    server: Arc<Client>,
+}
+
+impl JackDevice {
+    pub(crate) fn new(client_name: String, server: Arc<Client>) -> Self {
+        JackDevice {
+            client_name,
+            server,
+        }
+    }
+
+    pub fn client_name(&self) -> &str {
+        &self.client_name
+    }
+
+    /// The full names of the ports carrying audio in `direction`, as seen
+    /// from a session.
+    pub(crate) fn ports(&self, direction: Direction) -> Vec<String> {
+        let flags = match direction {
+            Direction::Input => PortFlags::IS_OUTPUT,
+            Direction::Output => PortFlags::IS_INPUT,
+        };
+        let pattern = format!("^{}:", escape_regex(&self.client_name));
+
+        self.server
+            .ports(Some(&pattern), Some(AUDIO_PORT_TYPE), flags)
+    }
+}
+
+/// The name of the client `JackBackend` treats as the default in
+/// `direction`.
+pub(super) fn default_client_name(server: &Client, direction: Direction) -> Option<String> {
+    let flags = match direction {
+        Direction::Input => PortFlags::IS_OUTPUT,
+        Direction::Output => PortFlags::IS_INPUT,
+    };
+    let ports = server.ports(None, Some(AUDIO_PORT_TYPE), flags | PortFlags::IS_PHYSICAL);
+
+    ports
+        .first()
+        .and_then(|port| port.split_once(':'))
+        .map(|(client, _)| client.to_owned())
+}
+
+/// Port name patterns are regular expressions, and client names often have
+/// parentheses or dots in them.
+fn escape_regex(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+impl fmt::Debug for JackDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("JackDevice")
+            .field(&self.client_name)
+            .finish()
+    }
+}
+
+impl PartialEq for JackDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.client_name == other.client_name
+    }
+}
+
+impl Eq for JackDevice {}
+
+impl Hash for JackDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.client_name.hash(state);
+    }
+}
+
+impl PartialOrd for JackDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JackDevice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.client_name.cmp(&other.client_name)
+    }
+}
+
+impl Device<JackBackend> for JackDevice {
+    fn num_inputs(&self) -> Result<usize, JackError> {
+        Ok(self.ports(Direction::Input).len())
+    }
+
+    fn num_outputs(&self) -> Result<usize, JackError> {
+        Ok(self.ports(Direction::Output).len())
+    }
+
+    fn name(&self) -> Result<String, JackError> {
+        Ok(self.client_name.clone())
+    }
+
+    /// The port's name without the client prefix, like `capture_1`.
+    fn channel_name(
+        &self,
+        direction: Direction,
+        channel: usize,
+    ) -> Result<Option<String>, JackError> {
+        Ok(self.ports(direction).get(channel).map(|port| {
+            port.split_once(':')
+                .map_or(port.as_str(), |(_, short)| short)
+                .to_owned()
+        }))
+    }
+
+    /// JACK client names are chosen by the clients themselves, and stay the
+    /// same across restarts for most of them.
+    fn persistent_id(&self) -> Result<String, JackError> {
+        Ok(self.client_name.clone())
+    }
+
+    fn is_default_input(&self) -> Result<bool, JackError> {
+        let default = default_client_name(&self.server, Direction::Input);
+        Ok(default.as_deref() == Some(self.client_name.as_str()))
+    }
+
+    fn is_default_output(&self) -> Result<bool, JackError> {
+        let default = default_client_name(&self.server, Direction::Output);
+        Ok(default.as_deref() == Some(self.client_name.as_str()))
+    }
+
+    fn set_nominal_sample_rate(&mut self, _sample_rate: f64) -> Result<(), JackError> {
+        Err(JackError::Unsupported(
+            "changing the sample rate, which is set when the server starts",
+        ))
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), JackError> {
+        Err(JackError::Unsupported("selecting clock sources"))
+    }
+
+    fn nominal_sample_rate(&self) -> Result<f64, JackError> {
+        Ok(self.server.sample_rate() as f64)
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, JackError> {
+        self.nominal_sample_rate()
+    }
+}
@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum JackError {
+    Jack(::jack::Error),
+    /// The JACK server runs every client at its own sample rate, and the
+    /// session asked for a different one.
+    SampleRateMismatch {
+        requested: f64,
+        server: f64,
+    },
+    /// The server has no physical ports in the direction a default device
+    /// was asked for.
+    NoDefaultDevice,
+    /// The session was started, but the server never called it.
+    StartupTimeout(Duration),
+    /// The operation or session option has no JACK equivalent.
+    Unsupported(&'static str),
+}
+
+impl JackError {
+    /// Whether the error is expected to clear up by itself, like the server
+    /// still starting up.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, JackError::Jack(::jack::Error::ClientError(_)))
+    }
+}
+
+impl From<::jack::Error> for JackError {
+    fn from(error: ::jack::Error) -> Self {
+        JackError::Jack(error)
+    }
+}
+
+impl fmt::Display for JackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JackError::Jack(error) => write!(f, "JACK error: {}", error),
+            JackError::SampleRateMismatch { requested, server } => write!(
+                f,
+                "Requested {} Hz, but the JACK server runs at {} Hz",
+                requested, server
+            ),
+            JackError::NoDefaultDevice => write!(f, "JACK server has no physical ports"),
+            JackError::StartupTimeout(timeout) => {
+                write!(
+                    f,
+                    "JACK server did not start the session within {:?}",
+                    timeout
+                )
+            }
+            JackError::Unsupported(what) => write!(f, "Not supported by JACK: {}", what),
+        }
+    }
+}
+
+impl Error for JackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            JackError::Jack(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<JackError> for crate::Error {
+    fn from(error: JackError) -> Self {
+        let kind = match &error {
+            // Most often the server isn't running.
+            JackError::Jack(::jack::Error::ClientError(_)) => Self::DeviceNotFound,
+            JackError::Jack(::jack::Error::ClientIsNoLongerAlive) => Self::DeviceDisconnected,
+            JackError::Jack(::jack::Error::SetBufferSizeError)
+            | JackError::SampleRateMismatch { .. } => Self::FormatNotSupported,
+            JackError::Jack(_) => Self::Backend,
+            JackError::NoDefaultDevice => Self::DeviceNotFound,
+            JackError::StartupTimeout(_) => Self::StartupTimeout,
+            JackError::Unsupported(_) => Self::Unsupported,
+        };
+
+        kind(Box::new(error))
+    }
+}
@@ -0,0 +1,9 @@
+mod backend;
+mod device;
+mod error;
+mod session;
+
+pub use backend::JackBackend as Backend;
+pub use device::JackDevice;
+pub use error::JackError;
+pub use session::JackSession;
@@ -0,0 +1,611 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ::jack::{
+    AsyncClient, AudioIn, AudioOut, Client, ClientOptions, ClientStatus, Control, Frames,
+    LatencyType, NotificationHandler, Port, ProcessHandler, ProcessScope,
+};
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::CycleTimes;
+use crate::deadline::DeadlineHistogram;
+use crate::direction::Direction;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::{Events, SessionEvent, StopCause};
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::sample::OwnedBuffer;
+use crate::traits::{RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+
+use super::backend::JackBackend;
+use super::device::JackDevice;
+use super::error::JackError;
+
+/// How long each buffer size is tried out for when tuning for low latency.
+const LATENCY_TRIAL_PERIOD: Duration = Duration::from_secs(1);
+
+/// The buffer sizes tried when tuning for low latency. JACK doesn't say
+/// which sizes the driver supports, but these are the usual ones.
+const MIN_TUNED_BUFFER_SIZE: usize = 16;
+const MAX_TUNED_BUFFER_SIZE: usize = 4096;
+
+/// A JACK client of its own, with one port per channel of the session's
+/// devices, connected to the devices' ports. Each device is one buffer in
+/// the callback, with the device's ports as its channels in the order the
+/// server lists them.
+pub struct JackSession {
+    engine: Arc<RenderEngine<JackBackend>>,
+    inputs: Vec<JackDevice>,
+    outputs: Vec<JackDevice>,
+    /// The channel count of each input and output buffer.
+    layout: (Vec<usize>, Vec<usize>),
+    state: Option<ClientState>,
+}
+
+enum ClientState {
+    Inactive(Client, Process),
+    Active(AsyncClient<Notifications, Process>),
+}
+
+impl JackSession {
+    pub(crate) fn new(
+        client_name: &str,
+        sample_rate: f64,
+        inputs: Vec<JackDevice>,
+        outputs: Vec<JackDevice>,
+    ) -> Result<Self, JackError> {
+        let (client, _status) = Client::new(client_name, ClientOptions::NO_START_SERVER)?;
+        let server_rate = client.sample_rate() as f64;
+        if server_rate != sample_rate {
+            return Err(JackError::SampleRateMismatch {
+                requested: sample_rate,
+                server: server_rate,
+            });
+        }
+
+        let engine = Arc::new(RenderEngine::new(sample_rate));
+        let mut process = Process::new(engine.clone());
+        let layout = process.register(&client, &inputs, &outputs)?;
+
+        Ok(JackSession {
+            engine,
+            inputs,
+            outputs,
+            layout,
+            state: Some(ClientState::Inactive(client, process)),
+        })
+    }
+
+    /// Activates the session's client with `callback`, and connects its
+    /// ports to the devices.
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<JackBackend>>,
+    ) -> Result<(), JackError> {
+        self.engine.set_callback(callback);
+        self.activate()
+    }
+
+    fn client(&self) -> &Client {
+        match self.state.as_ref().expect("Session client is gone") {
+            ClientState::Inactive(client, _) => client,
+            ClientState::Active(active) => active.as_client(),
+        }
+    }
+
+    fn activate(&mut self) -> Result<(), JackError> {
+        let (client, mut process) = match self.state.take() {
+            Some(ClientState::Inactive(client, process)) => (client, process),
+            state => {
+                self.state = state;
+                return Ok(());
+            }
+        };
+
+        let max_frames = client.buffer_size() as usize;
+        process.allocate(max_frames);
+        let (input, output) = &self.layout;
+        let channels = input.iter().sum::<usize>().max(output.iter().sum());
+        self.engine.resize_scratch(max_frames, channels);
+
+        let connections = process.connections(&self.inputs, &self.outputs)?;
+        let notifications = Notifications {
+            engine: self.engine.clone(),
+        };
+        let active = client.activate_async(notifications, process)?;
+        self.engine.valid.store(true, Ordering::Release);
+
+        let result = connections
+            .iter()
+            .try_for_each(|(from, to)| active.as_client().connect_ports_by_name(from, to));
+        self.state = Some(ClientState::Active(active));
+
+        Ok(result?)
+    }
+
+    /// Returns whether the client was active.
+    fn deactivate(&mut self) -> Result<bool, JackError> {
+        match self.state.take() {
+            Some(ClientState::Active(active)) => {
+                self.engine.valid.store(false, Ordering::Release);
+                let (client, _, process) = active.deactivate()?;
+                self.state = Some(ClientState::Inactive(client, process));
+                Ok(true)
+            }
+            state => {
+                self.state = state;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Registers ports for the current devices in place of the old ones.
+    fn rebuild(&mut self) -> Result<(), JackError> {
+        let was_active = self.deactivate()?;
+
+        if let Some(ClientState::Inactive(client, process)) = &mut self.state {
+            self.layout = process.register(client, &self.inputs, &self.outputs)?;
+        }
+        self.engine.reset_timeline();
+
+        if was_active {
+            self.activate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Changes the server's buffer size, for every client connected to it,
+    /// not just this session.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), JackError> {
+        Ok(self.client().set_buffer_size(frames as Frames)?)
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        self.client().buffer_size() as usize
+    }
+
+    /// The largest latency JACK has worked out for the session's ports named
+    /// `prefix`, from what they're connected to, plus one buffer.
+    fn port_latency(&self, prefix: &str, ports: usize, mode: LatencyType) -> usize {
+        let client = self.client();
+        let latency = (1..=ports)
+            .filter_map(|n| client.port_by_name(&format!("{}:{}_{}", client.name(), prefix, n)))
+            .map(|port| port.get_latency_range(mode).1 as usize)
+            .max()
+            .unwrap_or(0);
+
+        latency + self.max_frames_per_callback()
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        self.layout.clone()
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.engine.set_output_policy(policy);
+    }
+
+    /// Blocks until the server has called the session at least once.
+    pub fn wait_until_running(&self, timeout: Duration) -> Result<(), JackError> {
+        let deadline = Instant::now() + timeout;
+
+        while self.engine.cycles.load(Ordering::Acquire) == 0 {
+            if Instant::now() >= deadline {
+                return Err(JackError::StartupTimeout(timeout));
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for JackSession {
+    fn drop(&mut self) {
+        // The client closes along with its ports either way.
+        let _ = self.deactivate();
+        drop(self.state.take());
+
+        // The process thread is gone, so this is guaranteed to drop the
+        // callback here rather than on the real-time thread.
+        drop(self.engine.take_callback());
+    }
+}
+
+/// The session's side of the process callback: its ports, and the
+/// interleaved buffers the render callback sees.
+struct Process {
+    engine: Arc<RenderEngine<JackBackend>>,
+    input_ports: Vec<Vec<Port<AudioIn>>>,
+    output_ports: Vec<Vec<Port<AudioOut>>>,
+    input_buffers: Vec<OwnedBuffer<f32>>,
+    output_buffers: Vec<OwnedBuffer<f32>>,
+    /// The server's frame time at the start of the previous cycle, and the
+    /// same time counted in 64 bits so it doesn't wrap.
+    frame_time: Option<(Frames, u64)>,
+}
+
+impl Process {
+    fn new(engine: Arc<RenderEngine<JackBackend>>) -> Self {
+        Process {
+            engine,
+            input_ports: Vec::new(),
+            output_ports: Vec::new(),
+            input_buffers: Vec::new(),
+            output_buffers: Vec::new(),
+            frame_time: None,
+        }
+    }
+
+    /// Replaces the ports with one per channel of each device, and returns
+    /// the resulting channel counts.
+    fn register(
+        &mut self,
+        client: &Client,
+        inputs: &[JackDevice],
+        outputs: &[JackDevice],
+    ) -> Result<(Vec<usize>, Vec<usize>), JackError> {
+        for port in self.input_ports.drain(..).flatten() {
+            client.unregister_port(port)?;
+        }
+        for port in self.output_ports.drain(..).flatten() {
+            client.unregister_port(port)?;
+        }
+
+        let mut count = 0;
+        for device in inputs {
+            let ports = (0..device.ports(Direction::Input).len())
+                .map(|_| {
+                    count += 1;
+                    client.register_port(&format!("in_{}", count), AudioIn)
+                })
+                .collect::<Result<_, _>>()?;
+            self.input_ports.push(ports);
+        }
+
+        let mut count = 0;
+        for device in outputs {
+            let ports = (0..device.ports(Direction::Output).len())
+                .map(|_| {
+                    count += 1;
+                    client.register_port(&format!("out_{}", count), AudioOut)
+                })
+                .collect::<Result<_, _>>()?;
+            self.output_ports.push(ports);
+        }
+
+        Ok((
+            self.input_ports.iter().map(Vec::len).collect(),
+            self.output_ports.iter().map(Vec::len).collect(),
+        ))
+    }
+
+    /// The pairs of source and destination ports linking the session's
+    /// ports to the devices.
+    fn connections(
+        &self,
+        inputs: &[JackDevice],
+        outputs: &[JackDevice],
+    ) -> Result<Vec<(String, String)>, JackError> {
+        let mut connections = Vec::new();
+
+        for (device, ports) in inputs.iter().zip(&self.input_ports) {
+            for (source, port) in device.ports(Direction::Input).into_iter().zip(ports) {
+                connections.push((source, port.name()?));
+            }
+        }
+        for (device, ports) in outputs.iter().zip(&self.output_ports) {
+            for (destination, port) in device.ports(Direction::Output).into_iter().zip(ports) {
+                connections.push((port.name()?, destination));
+            }
+        }
+
+        Ok(connections)
+    }
+
+    /// Makes room in the buffers for cycles of up to `max_frames` frames.
+    fn allocate(&mut self, max_frames: usize) {
+        self.input_buffers = buffers_for(&self.input_ports, max_frames);
+        self.output_buffers = buffers_for(&self.output_ports, max_frames);
+    }
+
+    fn silence(&mut self, scope: &ProcessScope) {
+        for port in self.output_ports.iter_mut().flatten() {
+            port.as_mut_slice(scope).fill(0.0);
+        }
+    }
+}
+
+impl ProcessHandler for Process {
+    fn process(&mut self, _: &Client, scope: &ProcessScope) -> Control {
+        self.engine.cycles.fetch_add(1, Ordering::Release);
+
+        let frames = scope.n_frames() as usize;
+        let now = scope.last_frame_time();
+        let sample_time = match self.frame_time {
+            Some((last, total)) => total + u64::from(now.wrapping_sub(last)),
+            None => u64::from(now),
+        };
+        self.frame_time = Some((now, sample_time));
+
+        let input = self.input_ports.iter().zip(&mut self.input_buffers);
+        let output = self.output_ports.iter().zip(&mut self.output_buffers);
+        let fits = input
+            .map(|(ports, buffer)| buffer.reshape(frames, ports.len()))
+            .chain(output.map(|(ports, buffer)| buffer.reshape(frames, ports.len())))
+            .all(|reshaped| reshaped);
+        if !fits {
+            self.silence(scope);
+            return Control::Continue;
+        }
+
+        for (ports, buffer) in self.input_ports.iter().zip(&mut self.input_buffers) {
+            let channels = ports.len();
+            for (channel, port) in ports.iter().enumerate() {
+                let frames = buffer.interleaved_frames_mut().chunks_mut(channels);
+                for (frame, &sample) in frames.zip(port.as_slice(scope)) {
+                    frame[channel] = sample;
+                }
+            }
+        }
+
+        unsafe {
+            self.engine.render(
+                &self.input_buffers,
+                &mut self.output_buffers,
+                frames,
+                CycleTimes::at(sample_time as f64),
+            );
+        }
+
+        for (ports, buffer) in self.output_ports.iter_mut().zip(&self.output_buffers) {
+            let channels = ports.len();
+            for (channel, port) in ports.iter_mut().enumerate() {
+                let frames = buffer.interleaved_frames().chunks(channels);
+                for (sample, frame) in port.as_mut_slice(scope).iter_mut().zip(frames) {
+                    *sample = frame[channel];
+                }
+            }
+        }
+
+        Control::Continue
+    }
+
+    /// Called between cycles, outside the real-time context, so it's free to
+    /// allocate.
+    fn buffer_size(&mut self, _: &Client, size: Frames) -> Control {
+        let channels = self
+            .input_ports
+            .iter()
+            .map(Vec::len)
+            .sum::<usize>()
+            .max(self.output_ports.iter().map(Vec::len).sum());
+
+        self.allocate(size as usize);
+        self.engine.resize_scratch(size as usize, channels);
+        self.engine.reset_timeline();
+        Control::Continue
+    }
+}
+
+fn buffers_for<P>(ports: &[Vec<Port<P>>], max_frames: usize) -> Vec<OwnedBuffer<f32>> {
+    ports
+        .iter()
+        .map(|ports| OwnedBuffer::with_capacity(max_frames, ports.len()))
+        .collect()
+}
+
+struct Notifications {
+    engine: Arc<RenderEngine<JackBackend>>,
+}
+
+impl NotificationHandler for Notifications {
+    fn shutdown(&mut self, _status: ClientStatus, _reason: &str) {
+        self.engine.valid.store(false, Ordering::Release);
+        self.engine.events.push(SessionEvent::StoppedUnexpectedly {
+            cause: StopCause::DeviceDied,
+        });
+    }
+
+    fn sample_rate(&mut self, _: &Client, sample_rate: Frames) -> Control {
+        self.engine.set_sample_rate(sample_rate as f64);
+        self.engine.reset_timeline();
+        Control::Continue
+    }
+}
+
+impl Session<JackBackend> for JackSession {
+    fn input_device(&self) -> Result<JackDevice, JackError> {
+        Ok(self.inputs[0].clone())
+    }
+
+    fn output_device(&self) -> Result<JackDevice, JackError> {
+        Ok(self.outputs[0].clone())
+    }
+
+    fn set_input_device(&mut self, device: JackDevice) -> Result<(), JackError> {
+        self.inputs[0] = device;
+        self.rebuild()
+    }
+
+    fn set_output_device(&mut self, device: JackDevice) -> Result<(), JackError> {
+        self.outputs[0] = device;
+        self.rebuild()
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, JackError> {
+        Ok(JackSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, JackError> {
+        let side = |devices: &[JackDevice], channels: &[usize]| {
+            devices
+                .iter()
+                .zip(channels)
+                .map(|(device, &channels)| StreamMapping {
+                    device_id: device.client_name().to_owned(),
+                    first_device_channel: 0,
+                    channels,
+                })
+                .collect()
+        };
+
+        Ok(ChannelMap {
+            input: side(&self.inputs, &self.layout.0),
+            output: side(&self.outputs, &self.layout.1),
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.engine.clock.clone()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<JackBackend>>) {
+        self.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.engine.is_bypassed()
+    }
+
+    fn input_latency_frames(&self) -> Result<usize, JackError> {
+        let ports = self.layout.0.iter().sum();
+        Ok(self.port_latency("in", ports, LatencyType::Capture))
+    }
+
+    fn output_latency_frames(&self) -> Result<usize, JackError> {
+        let ports = self.layout.1.iter().sum();
+        Ok(self.port_latency("out", ports, LatencyType::Playback))
+    }
+
+    /// Changes the server's buffer size, which affects every other client
+    /// too. Only the session's own buffer is counted as latency, since JACK
+    /// leaves the driver's latency to the ports of the devices.
+    fn tune_for_low_latency(&mut self, target_ms: f64) -> Result<Option<LatencyTuning>, JackError> {
+        let sample_rate = self.engine.clock.sample_rate();
+        let target_frames = (target_ms * sample_rate / 1000.0) as usize;
+
+        let mut candidates = Vec::new();
+        let mut size = MIN_TUNED_BUFFER_SIZE;
+        while size <= MAX_TUNED_BUFFER_SIZE {
+            candidates.push(size);
+            size *= 2;
+        }
+
+        // Start from the largest size within the target: anything smaller
+        // only adds risk of dropouts.
+        let first = candidates
+            .iter()
+            .rposition(|&size| 2 * size <= target_frames)
+            .unwrap_or(0);
+
+        let original = JackSession::max_frames_per_callback(self);
+        let mut stable = false;
+        let mut buffer_size = original;
+        for &size in &candidates[first..] {
+            self.set_buffer_size(size)?;
+            buffer_size = JackSession::max_frames_per_callback(self);
+            self.engine.reset_timeline();
+
+            let before = self.engine.dropouts.stats().count;
+            thread::sleep(LATENCY_TRIAL_PERIOD);
+
+            if self.engine.dropouts.stats().count == before {
+                stable = true;
+                break;
+            }
+        }
+
+        if !stable {
+            // Nothing ran cleanly, so leave the session as it was found.
+            self.set_buffer_size(original)?;
+            self.engine.reset_timeline();
+            return Ok(None);
+        }
+
+        Ok(Some(LatencyTuning {
+            buffer_size,
+            input_latency_frames: buffer_size,
+            output_latency_frames: buffer_size,
+            sample_rate,
+        }))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, JackError> {
+        Err(JackError::Unsupported("starting at a host time"))
+    }
+
+    /// Deactivates the client, which disconnects its ports. Starting again
+    /// reconnects them to the session's devices.
+    fn stop(&mut self) -> Result<(), JackError> {
+        self.deactivate()?;
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), JackError> {
+        if !self.is_running() {
+            self.engine.reset_timeline();
+        }
+        self.activate()
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(self.state, Some(ClientState::Active(_)))
+    }
+}
@@ -0,0 +1,18 @@
+/// The outcome of `Session::tune_for_low_latency`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyTuning {
+    /// The buffer size the session settled on, in frames. It ran without
+    /// dropouts for the whole trial.
+    pub buffer_size: usize,
+    pub input_latency_frames: usize,
+    pub output_latency_frames: usize,
+    pub sample_rate: f64,
+}
+
+impl LatencyTuning {
+    /// The time it takes for audio to go from the input, through the
+    /// callback, and out again.
+    pub fn round_trip_ms(&self) -> f64 {
+        (self.input_latency_frames + self.output_latency_frames) as f64 * 1000.0 / self.sample_rate
+    }
+}
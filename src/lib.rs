@@ -1,11 +1,158 @@
+#[cfg(all(feature = "asio", target_os = "windows"))]
+mod asio;
+mod capture;
+mod chain;
+mod channel_map;
+mod clock;
+mod config;
+mod context;
+mod continuous_recorder;
+#[cfg(all(feature = "coreaudio", target_os = "macos"))]
 mod coreaudio;
+#[cfg(feature = "cpal")]
+mod cpal;
+mod deadline;
+mod device_info;
+mod device_watcher;
+mod direction;
+mod dropout;
+mod dyn_backend;
+mod engine;
+mod error;
+mod event_log;
+#[cfg(feature = "async")]
+mod event_stream;
+mod events;
+mod file;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+mod host_time;
+#[cfg(all(feature = "ios", target_os = "ios"))]
+mod ios;
+#[cfg(feature = "jack")]
+mod jack;
+mod latency;
+mod loopback;
+mod meters;
+mod mixer;
+mod network;
+mod null;
+#[cfg(all(
+    feature = "oss",
+    any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd")
+))]
+mod oss;
+mod overload;
+mod passthrough;
+mod planar;
+mod platform;
+#[cfg(feature = "portaudio")]
+mod portaudio;
+// Only some of the platform backends use preroll, retry and routing, so
+// they go unused on platforms where none of those are built.
+#[allow(dead_code)]
+mod preroll;
+mod processor;
+mod profiling;
+mod queue;
+mod recorder;
+mod registry;
+#[allow(dead_code)]
+mod retry;
+mod ring_buffer;
+#[allow(dead_code)]
+mod routing;
+mod rt_cell;
+mod sample;
+mod scratch;
+pub mod time;
 mod traits;
+mod voice_chat;
+mod warnings;
+mod wav;
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+mod web;
 
+pub use capture::CaptureTarget;
+pub use chain::{CallbackChain, CallbackId, ChainProcessor, MAX_CHAINED_CALLBACKS};
+pub use channel_map::{ChannelMap, StreamMapping};
+pub use clock::SampleClock;
+pub use config::{
+    ConfigError, MediaRole, OutputPolicy, PersistedSessionConfig, PhysicalFormat, SessionConfig,
+    StreamMetadata,
+};
+pub use context::{AudioTimestamp, RenderContext};
+pub use continuous_recorder::{ContinuousRecorder, RetentionPolicy, Segment};
+pub use deadline::{DeadlineHistogram, DEADLINE_BUCKETS};
+pub use device_info::{ClockSource, DataSource, DeviceInfo, DeviceListDiff, DeviceUser};
+pub use device_watcher::{DeviceEvent, DeviceWatcher};
+pub use direction::Direction;
+pub use dropout::DropoutStats;
+pub use dyn_backend::{DynBackend, DynDevice, DynError, DynSession};
+pub use error::Error;
+pub use event_log::{EventLog, EventLogSubscription, HardwareEvent, HardwareEventKind};
+#[cfg(feature = "async")]
+pub use event_stream::EventStream;
+pub use events::{EventSubscription, Events, SessionEvent, StopCause};
+pub use latency::LatencyTuning;
+pub use meters::{Level, MeterReadings, MeterSubscription, Meters, MAX_METERED_CHANNELS};
+pub use mixer::{
+    sound_stream, Mixer, MixerProcessor, SoundBuffer, SoundId, SoundSource, SoundStream,
+    StreamSource, MAX_VOICES,
+};
+// Empty on platforms without a backend of their own.
+pub use overload::OverloadSubscription;
+pub use planar::{Channel, ChannelMut, Channels};
+#[allow(unused_imports)]
+pub use platform::*;
+pub use processor::Processor;
+pub use profiling::ProfileScope;
+#[cfg(feature = "profiling")]
+pub use profiling::{ProfileRecord, PROFILE_CAPACITY};
+pub use recorder::{Recorder, RecorderError, Recording, MAX_RECORDED_CHANNELS};
+pub use registry::{BackendRegistry, Detected, NoBackendAvailable, ProbeError, ProbeFailure};
+pub use retry::RetryPolicy;
+pub use routing::{ChannelRef, Route, RoutingMatrix};
+pub use sample::{FramesAs, OwnedBuffer, Sample, SampleFormat, SampleRenderCallback, I24};
+pub use scratch::{Scratch, SCRATCH_BUFFERS};
 pub use traits::*;
+pub use voice_chat::{VoiceChatError, VoiceChatSession, VOICE_CHAT_SAMPLE_RATE};
+pub use warnings::{Warning, WarningSubscription, WarningThresholds};
 
-pub use coreaudio::Backend as CurrentPlatformBackend;
+#[cfg(all(feature = "coreaudio", target_os = "macos"))]
+pub use coreaudio::{MultiOutputDevice, StartupDiagnostics};
 
-pub type CurrentPlatformSession = <CurrentPlatformBackend as traits::Backend>::Session;
-pub type CurrentPlatformDevice = <CurrentPlatformBackend as traits::Backend>::Device;
-pub type CurrentPlatformError = <CurrentPlatformBackend as traits::Backend>::Error;
-pub type CurrentPlatformAudioBuffers = <CurrentPlatformBackend as traits::Backend>::AudioBuffers;
+#[cfg(all(feature = "asio", target_os = "windows"))]
+pub use asio::{AsioDevice, AsioError, AsioSession, Backend as AsioBackend};
+#[cfg(feature = "cpal")]
+pub use cpal::{Backend as CpalBackend, CpalDevice, CpalError, CpalSession};
+pub use file::{Backend as FileBackend, FileDevice, FileError, FileSession};
+#[cfg(all(feature = "ios", target_os = "ios"))]
+pub use ios::{Backend as IosBackend, IosDevice, IosError, IosSession};
+#[cfg(feature = "jack")]
+pub use jack::{Backend as JackBackend, JackDevice, JackError, JackSession};
+pub use loopback::{Backend as LoopbackBackend, LoopbackDevice, LoopbackError, LoopbackSession};
+pub use network::{
+    Backend as NetworkBackend, NetworkDevice, NetworkError, NetworkSession, NetworkStats,
+};
+pub use null::{Backend as NullBackend, NullDevice, NullError, NullSession};
+#[cfg(all(
+    feature = "oss",
+    any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd")
+))]
+pub use oss::{Backend as OssBackend, OssDevice, OssError, OssSession};
+#[cfg(feature = "portaudio")]
+pub use portaudio::{
+    Backend as PortAudioBackend, PortAudioDevice, PortAudioError, PortAudioSession,
+};
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+pub use web::{Backend as WebBackend, WebDevice, WebError, WebSession};
+
+#[cfg(all(
+    feature = "cf-leak-tracking",
+    feature = "coreaudio",
+    target_os = "macos"
+))]
+pub mod cf {
+    pub use crate::coreaudio::{leak_report, LeakReport, LiveObjects};
+}
@@ -0,0 +1,137 @@
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::processor::{processor_callback, Processor};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::LoopbackDevice;
+use super::error::LoopbackError;
+use super::session::LoopbackSession;
+
+/// A backend without hardware whose sessions hear their own output, for
+/// testing echo cancellation and latency measurement. It has a single
+/// device, used for both input and output, and its sessions call the
+/// callback at the pace a real device would.
+pub struct LoopbackBackend {
+    device: LoopbackDevice,
+    /// Frames added to the one block output takes to come back as input.
+    delay: usize,
+}
+
+impl LoopbackBackend {
+    /// A backend whose sessions get their output back `delay` frames later
+    /// than the next callback would.
+    pub fn with_delay(delay: usize) -> Self {
+        LoopbackBackend {
+            device: LoopbackDevice::new(),
+            delay,
+        }
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(
+        &self,
+        config: &SessionConfig<Self>,
+    ) -> Result<LoopbackSession, LoopbackError> {
+        if config.physical_format.is_some() {
+            return Err(LoopbackError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(LoopbackError::Unsupported("preroll"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(LoopbackError::Unsupported(
+                "more than one device per direction",
+            ));
+        }
+
+        let session = LoopbackSession::new(
+            config.sample_rate,
+            config.output_device.clone(),
+            config.buffer_size,
+            self.delay,
+        )?;
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for LoopbackBackend {
+    type Session = LoopbackSession;
+    type Error = LoopbackError;
+    type Device = LoopbackDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    /// A backend without extra delay.
+    fn new() -> Result<Self, LoopbackError> {
+        Ok(LoopbackBackend::with_delay(0))
+    }
+
+    fn is_available() -> bool {
+        true
+    }
+
+    fn all_devices(&self) -> Result<Vec<LoopbackDevice>, LoopbackError> {
+        Ok(vec![self.device.clone()])
+    }
+
+    fn default_input_device(&self) -> Result<LoopbackDevice, LoopbackError> {
+        Ok(self.device.clone())
+    }
+
+    fn default_output_device(&self) -> Result<LoopbackDevice, LoopbackError> {
+        Ok(self.device.clone())
+    }
+
+    /// The buffer size sets the block size, and defaults to 512 frames.
+    /// There's nothing to fail transiently or to route, so retries and
+    /// routing are ignored, and so is the clock master since there's only
+    /// one device. Additional devices, physical formats and preroll are
+    /// refused.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<LoopbackSession, LoopbackError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<LoopbackSession, LoopbackError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<LoopbackSession, LoopbackError> {
+        Err(LoopbackError::Unsupported("process taps"))
+    }
+}
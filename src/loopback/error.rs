@@ -0,0 +1,65 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum LoopbackError {
+    /// Sample rates have to be positive and finite.
+    InvalidSampleRate(f64),
+    /// Blocks have to have at least one frame.
+    InvalidBufferSize(usize),
+    /// The session's thread could not be spawned.
+    Thread(std::io::Error),
+    /// The session was started, but its thread never called it.
+    StartupTimeout(Duration),
+    /// The operation or session option makes no sense without hardware.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for LoopbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopbackError::InvalidSampleRate(sample_rate) => {
+                write!(f, "Invalid sample rate: {} Hz", sample_rate)
+            }
+            LoopbackError::InvalidBufferSize(frames) => {
+                write!(f, "Invalid buffer size: {} frames", frames)
+            }
+            LoopbackError::Thread(error) => {
+                write!(f, "Could not spawn render thread: {}", error)
+            }
+            LoopbackError::StartupTimeout(timeout) => write!(
+                f,
+                "Render thread did not start the session within {:?}",
+                timeout
+            ),
+            LoopbackError::Unsupported(what) => {
+                write!(f, "Not supported by the loopback backend: {}", what)
+            }
+        }
+    }
+}
+
+impl Error for LoopbackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoopbackError::Thread(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<LoopbackError> for crate::Error {
+    fn from(error: LoopbackError) -> Self {
+        let kind = match &error {
+            LoopbackError::InvalidSampleRate(_) | LoopbackError::InvalidBufferSize(_) => {
+                Self::FormatNotSupported
+            }
+            LoopbackError::Thread(_) => Self::Backend,
+            LoopbackError::StartupTimeout(_) => Self::StartupTimeout,
+            LoopbackError::Unsupported(_) => Self::Unsupported,
+        };
+
+        kind(Box::new(error))
+    }
+}
@@ -0,0 +1,9 @@
+mod backend;
+mod device;
+mod error;
+mod session;
+
+pub use backend::LoopbackBackend as Backend;
+pub use device::LoopbackDevice;
+pub use error::LoopbackError;
+pub use session::LoopbackSession;
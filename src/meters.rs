@@ -0,0 +1,301 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::sample::Sample;
+use crate::traits::AudioBuffers;
+
+/// Channels beyond this are not metered.
+pub const MAX_METERED_CHANNELS: usize = 256;
+
+/// How often levels are published, and how often subscribers are called.
+const UPDATE_RATE: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Level {
+    /// Largest absolute sample value in the last metering window.
+    pub peak: f32,
+    pub rms: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeterReadings {
+    pub input: Vec<Level>,
+    pub output: Vec<Level>,
+    /// Increases by one every time new levels are published.
+    pub generation: u64,
+}
+
+/// Per-channel accumulators and published levels for one direction. Only the
+/// real-time thread stores to the accumulators.
+struct ChannelMeters {
+    count: AtomicUsize,
+    peak: [AtomicU32; MAX_METERED_CHANNELS],
+    sum_of_squares: [AtomicU32; MAX_METERED_CHANNELS],
+    published_peak: [AtomicU32; MAX_METERED_CHANNELS],
+    published_rms: [AtomicU32; MAX_METERED_CHANNELS],
+}
+
+impl ChannelMeters {
+    fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU32 = AtomicU32::new(0);
+
+        ChannelMeters {
+            count: AtomicUsize::new(0),
+            peak: [ZERO; MAX_METERED_CHANNELS],
+            sum_of_squares: [ZERO; MAX_METERED_CHANNELS],
+            published_peak: [ZERO; MAX_METERED_CHANNELS],
+            published_rms: [ZERO; MAX_METERED_CHANNELS],
+        }
+    }
+
+    fn accumulate<A: AudioBuffers>(&self, buffers: &[A]) {
+        let mut channel = 0;
+        for buffer in buffers {
+            let stride = buffer.num_channels();
+            let samples = buffer.interleaved_frames();
+
+            for offset in 0..stride {
+                if channel >= MAX_METERED_CHANNELS {
+                    break;
+                }
+
+                let mut peak = load_f32(&self.peak[channel]);
+                let mut sum_of_squares = load_f32(&self.sum_of_squares[channel]);
+                for &sample in samples.iter().skip(offset).step_by(stride) {
+                    let sample = sample.to_f32();
+                    peak = peak.max(sample.abs());
+                    sum_of_squares += sample * sample;
+                }
+                store_f32(&self.peak[channel], peak);
+                store_f32(&self.sum_of_squares[channel], sum_of_squares);
+
+                channel += 1;
+            }
+        }
+
+        self.count.store(channel, Ordering::Relaxed);
+    }
+
+    fn publish(&self, frames: usize) {
+        for channel in 0..self.count.load(Ordering::Relaxed) {
+            let rms = (load_f32(&self.sum_of_squares[channel]) / frames as f32).sqrt();
+            store_f32(&self.published_peak[channel], load_f32(&self.peak[channel]));
+            store_f32(&self.published_rms[channel], rms);
+            store_f32(&self.peak[channel], 0.0);
+            store_f32(&self.sum_of_squares[channel], 0.0);
+        }
+    }
+
+    fn read(&self) -> Vec<Level> {
+        (0..self.count.load(Ordering::Relaxed))
+            .map(|channel| Level {
+                peak: load_f32(&self.published_peak[channel]),
+                rms: load_f32(&self.published_rms[channel]),
+            })
+            .collect()
+    }
+}
+
+/// Level metering state shared between a session's real-time thread and any
+/// number of `Meters` handles.
+pub(crate) struct MeterBank {
+    enabled: AtomicBool,
+    window_frames: AtomicUsize,
+    accumulated_frames: AtomicUsize,
+    generation: AtomicU64,
+    input: ChannelMeters,
+    output: ChannelMeters,
+}
+
+impl MeterBank {
+    pub fn new(sample_rate: f64) -> Self {
+        let bank = MeterBank {
+            enabled: AtomicBool::new(false),
+            window_frames: AtomicUsize::new(0),
+            accumulated_frames: AtomicUsize::new(0),
+            generation: AtomicU64::new(0),
+            input: ChannelMeters::new(),
+            output: ChannelMeters::new(),
+        };
+        bank.set_sample_rate(sample_rate);
+        bank
+    }
+
+    pub fn set_sample_rate(&self, sample_rate: f64) {
+        let window = (sample_rate / UPDATE_RATE).round().max(1.0) as usize;
+        self.window_frames.store(window, Ordering::Relaxed);
+    }
+
+    /// Measures one cycle's worth of buffers, after the render callback has
+    /// filled in the output. Does nothing until someone has asked for the
+    /// meters. Must only be called from the real-time thread.
+    pub fn process<A: AudioBuffers>(&self, input: &[A], output: &[A]) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let frames = input
+            .first()
+            .or_else(|| output.first())
+            .map_or(0, |buffer| buffer.num_frames());
+        if frames == 0 {
+            return;
+        }
+
+        self.input.accumulate(input);
+        self.output.accumulate(output);
+
+        let accumulated = self.accumulated_frames.load(Ordering::Relaxed) + frames;
+        if accumulated >= self.window_frames.load(Ordering::Relaxed) {
+            self.input.publish(accumulated);
+            self.output.publish(accumulated);
+            self.accumulated_frames.store(0, Ordering::Relaxed);
+            self.generation.fetch_add(1, Ordering::Release);
+        } else {
+            self.accumulated_frames
+                .store(accumulated, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A handle to a session's input and output levels.
+///
+/// Levels are measured over windows of roughly 1/30th of a second. Metering
+/// only starts once the first handle has been created, so sessions nobody
+/// watches don't pay for it.
+#[derive(Clone)]
+pub struct Meters {
+    bank: Arc<MeterBank>,
+}
+
+impl Meters {
+    pub(crate) fn new(bank: Arc<MeterBank>) -> Self {
+        bank.enabled.store(true, Ordering::Relaxed);
+        Meters { bank }
+    }
+
+    /// The levels of the most recently completed window.
+    pub fn read(&self) -> MeterReadings {
+        let generation = self.bank.generation.load(Ordering::Acquire);
+
+        MeterReadings {
+            input: self.bank.input.read(),
+            output: self.bank.output.read(),
+            generation,
+        }
+    }
+
+    pub fn input(&self, channel: usize) -> Option<Level> {
+        self.bank.input.read().get(channel).copied()
+    }
+
+    pub fn output(&self, channel: usize) -> Option<Level> {
+        self.bank.output.read().get(channel).copied()
+    }
+
+    /// Calls `f` on a background thread whenever new levels are published,
+    /// until the returned subscription is dropped.
+    pub fn subscribe<F>(&self, mut f: F) -> MeterSubscription
+    where
+        F: FnMut(&MeterReadings) + Send + 'static,
+    {
+        let meters = self.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = thread::Builder::new()
+            .name("render_callback meters".to_owned())
+            .spawn(move || {
+                let interval = Duration::from_secs_f64(1.0 / UPDATE_RATE);
+                let mut last_generation = None;
+
+                while !thread_stop.load(Ordering::Acquire) {
+                    let readings = meters.read();
+                    if last_generation != Some(readings.generation) {
+                        last_generation = Some(readings.generation);
+                        f(&readings);
+                    }
+
+                    thread::park_timeout(interval);
+                }
+            })
+            .expect("Could not spawn meter thread");
+
+        MeterSubscription {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+pub struct MeterSubscription {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for MeterSubscription {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn load_f32(atomic: &AtomicU32) -> f32 {
+    f32::from_bits(atomic.load(Ordering::Relaxed))
+}
+
+fn store_f32(atomic: &AtomicU32, value: f32) {
+    atomic.store(value.to_bits(), Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{MeterBank, Meters, MAX_METERED_CHANNELS};
+    use crate::sample::testing::wide_layout;
+
+    fn channel_level(channel: usize) -> f32 {
+        (channel + 1) as f32 / 512.0
+    }
+
+    #[test]
+    fn meters_every_channel_of_a_wide_layout() {
+        // 128 channels in 16 streams of 8.
+        let buffers = wide_layout(16, 8, 64, |channel, _| channel_level(channel));
+
+        // One frame per window, so every cycle publishes.
+        let bank = Arc::new(MeterBank::new(1.0));
+        let meters = Meters::new(bank.clone());
+        bank.process(&buffers, &buffers);
+
+        let readings = meters.read();
+        assert_eq!(readings.input.len(), 128);
+        assert_eq!(readings.output.len(), 128);
+        for (channel, level) in readings.input.iter().enumerate() {
+            assert_eq!(level.peak, channel_level(channel));
+        }
+    }
+
+    #[test]
+    fn stops_metering_after_the_last_metered_channel() {
+        let streams = MAX_METERED_CHANNELS / 8 + 2;
+        let buffers = wide_layout(streams, 8, 16, |channel, _| channel_level(channel));
+
+        let bank = Arc::new(MeterBank::new(1.0));
+        let meters = Meters::new(bank.clone());
+        bank.process(&buffers, &buffers);
+
+        let readings = meters.read();
+        assert_eq!(readings.input.len(), MAX_METERED_CHANNELS);
+        let last = MAX_METERED_CHANNELS - 1;
+        assert_eq!(readings.input[last].peak, channel_level(last));
+    }
+}
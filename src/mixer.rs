@@ -0,0 +1,380 @@
+use std::f32::consts::FRAC_PI_4;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::context::RenderContext;
+use crate::processor::Processor;
+use crate::queue::Queue;
+use crate::ring_buffer::{ring_buffer, Consumer, Producer};
+use crate::sample::Sample;
+use crate::traits::{AudioBuffers, Backend};
+
+/// Sounds playing at the same time beyond this are rejected.
+pub const MAX_VOICES: usize = 64;
+
+const COMMAND_QUEUE_SIZE: usize = 256;
+
+/// Sources are rendered in chunks of at most this many frames.
+const CHUNK_FRAMES: usize = 512;
+
+/// Audio that can be played through a `Mixer`. Sources must produce mono or
+/// stereo audio at the session's sample rate.
+///
+/// `fill` is called on the real-time thread and must not allocate or block.
+pub trait SoundSource: Send {
+    fn channels(&self) -> usize;
+
+    /// Fills `out` with interleaved frames and returns how many were written.
+    /// Writing fewer frames than fit means the sound has ended.
+    fn fill(&mut self, out: &mut [f32]) -> usize;
+}
+
+/// A sound that's fully decoded in memory. Clones share the samples, so the
+/// same buffer can be played many times at once.
+#[derive(Clone)]
+pub struct SoundBuffer {
+    samples: Arc<[f32]>,
+    channels: usize,
+    position: usize,
+}
+
+impl SoundBuffer {
+    pub fn new(samples: impl Into<Arc<[f32]>>, channels: usize) -> Self {
+        assert!(
+            channels == 1 || channels == 2,
+            "Only mono and stereo sounds are supported"
+        );
+
+        SoundBuffer {
+            samples: samples.into(),
+            channels,
+            position: 0,
+        }
+    }
+}
+
+impl SoundSource for SoundBuffer {
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn fill(&mut self, out: &mut [f32]) -> usize {
+        let remaining = &self.samples[self.position..];
+        let len = remaining.len().min(out.len()) / self.channels * self.channels;
+
+        out[..len].copy_from_slice(&remaining[..len]);
+        self.position += len;
+
+        len / self.channels
+    }
+}
+
+/// The writing end of a streamed sound, for audio that's generated or
+/// decoded while it plays.
+pub struct SoundStream {
+    producer: Producer,
+    finished: Arc<AtomicBool>,
+}
+
+impl SoundStream {
+    /// Queues interleaved frames for playback. Returns false, without queueing
+    /// anything, if there isn't room for all of them yet.
+    pub fn write(&mut self, samples: &[f32]) -> bool {
+        self.producer.try_push(samples)
+    }
+
+    /// Number of samples that can currently be written.
+    pub fn free_len(&self) -> usize {
+        self.producer.free_len()
+    }
+
+    /// Lets the sound end once everything written so far has been played.
+    pub fn finish(self) {}
+}
+
+impl Drop for SoundStream {
+    fn drop(&mut self) {
+        self.finished.store(true, Ordering::Release);
+    }
+}
+
+/// The playing end of a `SoundStream`. Plays silence if the stream can't
+/// keep up.
+pub struct StreamSource {
+    consumer: Consumer,
+    channels: usize,
+    finished: Arc<AtomicBool>,
+}
+
+/// Creates a stream buffering up to `capacity_frames` frames.
+pub fn sound_stream(channels: usize, capacity_frames: usize) -> (SoundStream, StreamSource) {
+    assert!(
+        channels == 1 || channels == 2,
+        "Only mono and stereo sounds are supported"
+    );
+
+    let (producer, consumer) = ring_buffer(capacity_frames * channels);
+    let finished = Arc::new(AtomicBool::new(false));
+
+    (
+        SoundStream {
+            producer,
+            finished: finished.clone(),
+        },
+        StreamSource {
+            consumer,
+            channels,
+            finished,
+        },
+    )
+}
+
+impl SoundSource for StreamSource {
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn fill(&mut self, out: &mut [f32]) -> usize {
+        // Read the flag first, so samples written just before finishing are
+        // never mistaken for the end.
+        let finished = self.finished.load(Ordering::Acquire);
+        let available = self.consumer.len() / self.channels * self.channels;
+        let len = available.min(out.len() / self.channels * self.channels);
+        self.consumer.pop(&mut out[..len]);
+
+        if finished {
+            len / self.channels
+        } else {
+            for sample in &mut out[len..] {
+                *sample = 0.0;
+            }
+            out.len() / self.channels
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SoundId(u64);
+
+enum Command {
+    Play(Voice),
+    SetGain(SoundId, f32),
+    SetPan(SoundId, f32),
+    Stop(SoundId),
+}
+
+struct Voice {
+    id: SoundId,
+    source: Box<dyn SoundSource>,
+    gain: f32,
+    pan: f32,
+}
+
+struct MixerQueues {
+    commands: Queue<Command>,
+    /// Voices that are done, sent back so they're dropped off the real-time
+    /// thread.
+    finished: Queue<Voice>,
+    next_id: AtomicU64,
+}
+
+/// Plays any number of sounds at once, each with its own gain and pan.
+///
+/// `Mixer` is the control handle that application threads use to start and
+/// control sounds; the `MixerProcessor` it comes with renders them and is
+/// meant to be the session's processor. Sounds are mixed into the first two
+/// channels of the first output buffer, and every other output channel is
+/// silenced.
+#[derive(Clone)]
+pub struct Mixer {
+    queues: Arc<MixerQueues>,
+}
+
+impl Mixer {
+    pub fn new() -> (Mixer, MixerProcessor) {
+        let queues = Arc::new(MixerQueues {
+            commands: Queue::new(COMMAND_QUEUE_SIZE),
+            finished: Queue::new(MAX_VOICES + COMMAND_QUEUE_SIZE),
+            next_id: AtomicU64::new(0),
+        });
+
+        (
+            Mixer {
+                queues: queues.clone(),
+            },
+            MixerProcessor {
+                queues,
+                voices: Vec::with_capacity(MAX_VOICES),
+                scratch: vec![0.0; CHUNK_FRAMES * 2],
+            },
+        )
+    }
+
+    /// Starts playing `source`. `pan` goes from -1 (left) to 1 (right).
+    /// Returns `None` if the mixer is too busy to accept more commands.
+    pub fn play(&self, source: impl SoundSource + 'static, gain: f32, pan: f32) -> Option<SoundId> {
+        let id = SoundId(self.queues.next_id.fetch_add(1, Ordering::Relaxed));
+        let voice = Voice {
+            id,
+            source: Box::new(source),
+            gain,
+            pan: pan.clamp(-1.0, 1.0),
+        };
+
+        self.send(Command::Play(voice)).then_some(id)
+    }
+
+    pub fn set_gain(&self, id: SoundId, gain: f32) -> bool {
+        self.send(Command::SetGain(id, gain))
+    }
+
+    pub fn set_pan(&self, id: SoundId, pan: f32) -> bool {
+        self.send(Command::SetPan(id, pan.clamp(-1.0, 1.0)))
+    }
+
+    pub fn stop(&self, id: SoundId) -> bool {
+        self.send(Command::Stop(id))
+    }
+
+    fn send(&self, command: Command) -> bool {
+        while self.queues.finished.pop().is_some() {}
+
+        self.queues.commands.push(command).is_ok()
+    }
+}
+
+pub struct MixerProcessor {
+    queues: Arc<MixerQueues>,
+    voices: Vec<Voice>,
+    scratch: Vec<f32>,
+}
+
+impl MixerProcessor {
+    fn retire(&self, voice: Voice) {
+        // Only fails if the application hasn't touched the mixer in a long
+        // time, in which case dropping here is the lesser evil.
+        let _ = self.queues.finished.push(voice);
+    }
+
+    fn handle_commands(&mut self) {
+        while let Some(command) = self.queues.commands.pop() {
+            match command {
+                Command::Play(voice) => {
+                    if self.voices.len() < MAX_VOICES {
+                        self.voices.push(voice);
+                    } else {
+                        self.retire(voice);
+                    }
+                }
+                Command::SetGain(id, gain) => {
+                    if let Some(voice) = self.voices.iter_mut().find(|voice| voice.id == id) {
+                        voice.gain = gain;
+                    }
+                }
+                Command::SetPan(id, pan) => {
+                    if let Some(voice) = self.voices.iter_mut().find(|voice| voice.id == id) {
+                        voice.pan = pan;
+                    }
+                }
+                Command::Stop(id) => {
+                    if let Some(index) = self.voices.iter().position(|voice| voice.id == id) {
+                        let voice = self.voices.swap_remove(index);
+                        self.retire(voice);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Mixes every voice into `out`, which has `channels` interleaved
+    /// channels of which only the first two are used.
+    fn mix<S: Sample>(&mut self, out: &mut [S], channels: usize) {
+        let frames = out.len() / channels;
+
+        let mut index = 0;
+        while index < self.voices.len() {
+            let mut done = false;
+            let mut frame = 0;
+
+            while frame < frames && !done {
+                let voice = &mut self.voices[index];
+                let source_channels = voice.source.channels();
+                let chunk = (frames - frame).min(CHUNK_FRAMES);
+
+                let scratch = &mut self.scratch[..chunk * source_channels];
+                let written = voice.source.fill(scratch);
+                done = written < chunk;
+
+                let (left, right) = gains(voice.gain, voice.pan, source_channels);
+                for (source, target) in scratch[..written * source_channels]
+                    .chunks(source_channels)
+                    .zip(out[frame * channels..].chunks_mut(channels))
+                {
+                    let (l, r) = match *source {
+                        [mono] => (mono * left, mono * right),
+                        [l, r] => (l * left, r * right),
+                        _ => (0.0, 0.0),
+                    };
+
+                    if channels == 1 {
+                        target[0] = S::from_f32(target[0].to_f32() + (l + r) * 0.5);
+                    } else {
+                        target[0] = S::from_f32(target[0].to_f32() + l);
+                        target[1] = S::from_f32(target[1].to_f32() + r);
+                    }
+                }
+
+                frame += chunk;
+            }
+
+            if done {
+                let voice = self.voices.swap_remove(index);
+                self.retire(voice);
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+/// Equal power panning for mono sources, and balance for stereo ones.
+fn gains(gain: f32, pan: f32, channels: usize) -> (f32, f32) {
+    if channels == 1 {
+        let angle = (pan + 1.0) * FRAC_PI_4;
+        (gain * angle.cos(), gain * angle.sin())
+    } else {
+        (gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0))
+    }
+}
+
+impl<B: Backend> Processor<B> for MixerProcessor {
+    fn prepare(&mut self, _sample_rate: f64, _max_frames: usize) {}
+
+    fn process(
+        &mut self,
+        _input: &[B::AudioBuffers],
+        output: &mut [B::AudioBuffers],
+        _ctx: &RenderContext<'_>,
+    ) {
+        self.handle_commands();
+
+        for buffer in output.iter_mut() {
+            buffer.interleaved_frames_mut().fill(Default::default());
+        }
+
+        if let Some(buffer) = output.first_mut() {
+            let channels = buffer.num_channels();
+            if channels > 0 {
+                self.mix(buffer.interleaved_frames_mut(), channels);
+            }
+        }
+    }
+
+    fn reset(&mut self) {}
+}
+
+impl Drop for MixerProcessor {
+    fn drop(&mut self) {
+        while self.queues.commands.pop().is_some() {}
+    }
+}
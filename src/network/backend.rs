@@ -0,0 +1,137 @@
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::processor::{processor_callback, Processor};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::NetworkDevice;
+use super::error::NetworkError;
+use super::session::NetworkSession;
+
+/// Streams audio to and from a peer over UDP, as uncompressed RTP. Each
+/// device is one peer, used for both input and output: the session's output
+/// is sent to it, and what it sends back becomes the session's input.
+///
+/// Both ends pace themselves with their own clocks, and nothing corrects
+/// for the drift between them, so the jitter buffer eventually under- or
+/// overflows on long sessions and resynchronizes with a glitch.
+pub struct NetworkBackend {
+    peer: Option<NetworkDevice>,
+}
+
+impl NetworkBackend {
+    /// A backend whose default input and output is `peer`, for code that
+    /// starts sessions on the default devices.
+    pub fn with_peer(peer: NetworkDevice) -> Self {
+        NetworkBackend { peer: Some(peer) }
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(
+        &self,
+        config: &SessionConfig<Self>,
+    ) -> Result<NetworkSession, NetworkError> {
+        if config.physical_format.is_some() {
+            return Err(NetworkError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(NetworkError::Unsupported("preroll"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(NetworkError::Unsupported("more than one peer"));
+        }
+
+        let device = same_peer(config.input_device.clone(), config.output_device.clone())?;
+        let session = NetworkSession::new(config.sample_rate, device, config.buffer_size)?;
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for NetworkBackend {
+    type Session = NetworkSession;
+    type Error = NetworkError;
+    type Device = NetworkDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    /// A backend without a peer, which has no default devices.
+    fn new() -> Result<Self, NetworkError> {
+        Ok(NetworkBackend { peer: None })
+    }
+
+    fn is_available() -> bool {
+        true
+    }
+
+    fn all_devices(&self) -> Result<Vec<NetworkDevice>, NetworkError> {
+        Ok(self.peer.iter().cloned().collect())
+    }
+
+    fn default_input_device(&self) -> Result<NetworkDevice, NetworkError> {
+        self.peer.clone().ok_or(NetworkError::NoDefaultDevice)
+    }
+
+    fn default_output_device(&self) -> Result<NetworkDevice, NetworkError> {
+        self.peer.clone().ok_or(NetworkError::NoDefaultDevice)
+    }
+
+    /// The buffer size sets the block size, and defaults to 480 frames.
+    /// Additional devices, physical formats and preroll are refused.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<NetworkSession, NetworkError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<NetworkSession, NetworkError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<NetworkSession, NetworkError> {
+        Err(NetworkError::Unsupported("process taps"))
+    }
+}
+
+/// Both directions go over the same socket, so they need the same peer.
+fn same_peer(input: NetworkDevice, output: NetworkDevice) -> Result<NetworkDevice, NetworkError> {
+    if input != output {
+        return Err(NetworkError::Unsupported(
+            "different input and output peers",
+        ));
+    }
+
+    Ok(output)
+}
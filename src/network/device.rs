@@ -0,0 +1,142 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::traits::Device;
+
+use super::backend::NetworkBackend;
+use super::error::NetworkError;
+
+const DEFAULT_SAMPLE_RATE: f64 = 48_000.0;
+
+/// How long received audio is held back by default before it's played, to
+/// smooth out packets arriving unevenly.
+const DEFAULT_JITTER_DELAY: Duration = Duration::from_millis(40);
+
+/// A peer on the network, sending and receiving audio over RTP on UDP. The
+/// session's output is sent to `peer`, and its input is whatever `peer`
+/// sends to `local`.
+///
+/// Both ends need to agree on the sample rate and channel count, since
+/// nothing is negotiated. Devices compare and order by their addresses.
+#[derive(Debug, Clone)]
+pub struct NetworkDevice {
+    local: SocketAddr,
+    peer: SocketAddr,
+    channels: u16,
+    jitter_delay: Duration,
+    sample_rate: f64,
+}
+
+impl NetworkDevice {
+    pub fn new(local: SocketAddr, peer: SocketAddr, channels: u16) -> Self {
+        NetworkDevice {
+            local,
+            peer,
+            channels,
+            jitter_delay: DEFAULT_JITTER_DELAY,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }
+    }
+
+    /// Holds received audio back by `delay` before playing it, 40 ms by
+    /// default. Longer delays survive worse networks.
+    pub fn with_jitter_delay(mut self, delay: Duration) -> Self {
+        self.jitter_delay = delay;
+        self
+    }
+
+    pub fn local(&self) -> SocketAddr {
+        self.local
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn channels(&self) -> usize {
+        usize::from(self.channels)
+    }
+
+    pub fn jitter_delay(&self) -> Duration {
+        self.jitter_delay
+    }
+
+    fn key(&self) -> (SocketAddr, SocketAddr) {
+        (self.local, self.peer)
+    }
+}
+
+impl PartialEq for NetworkDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for NetworkDevice {}
+
+impl Hash for NetworkDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for NetworkDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NetworkDevice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Device<NetworkBackend> for NetworkDevice {
+    fn num_inputs(&self) -> Result<usize, NetworkError> {
+        Ok(self.channels())
+    }
+
+    fn num_outputs(&self) -> Result<usize, NetworkError> {
+        Ok(self.channels())
+    }
+
+    fn name(&self) -> Result<String, NetworkError> {
+        Ok(self.peer.to_string())
+    }
+
+    fn persistent_id(&self) -> Result<String, NetworkError> {
+        Ok(format!("rtp:{}-{}", self.local, self.peer))
+    }
+
+    /// Only recorded for `nominal_sample_rate`: sessions send and expect
+    /// audio at their own rate.
+    /// A backend only knows the peer it was created with, so whichever
+    /// device it lists is its default both ways.
+    fn is_default_input(&self) -> Result<bool, NetworkError> {
+        Ok(true)
+    }
+
+    fn is_default_output(&self) -> Result<bool, NetworkError> {
+        Ok(true)
+    }
+
+    fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), NetworkError> {
+        self.sample_rate = sample_rate;
+        Ok(())
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), NetworkError> {
+        Err(NetworkError::Unsupported("selecting clock sources"))
+    }
+
+    fn nominal_sample_rate(&self) -> Result<f64, NetworkError> {
+        Ok(self.sample_rate)
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, NetworkError> {
+        self.nominal_sample_rate()
+    }
+}
@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum NetworkError {
+    /// Binding the socket, or spawning the session's threads, failed.
+    Io(io::Error),
+    /// Sample rates have to be positive and fit an RTP clock.
+    InvalidSampleRate(f64),
+    /// Blocks have to have at least one frame.
+    InvalidBufferSize(usize),
+    /// The backend was created without a peer.
+    NoDefaultDevice,
+    /// The session was started, but its thread never called it.
+    StartupTimeout(Duration),
+    /// The operation or session option has no meaning for a network peer.
+    Unsupported(&'static str),
+}
+
+impl From<io::Error> for NetworkError {
+    fn from(error: io::Error) -> Self {
+        NetworkError::Io(error)
+    }
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Io(error) => write!(f, "I/O error: {}", error),
+            NetworkError::InvalidSampleRate(sample_rate) => {
+                write!(f, "Invalid sample rate: {} Hz", sample_rate)
+            }
+            NetworkError::InvalidBufferSize(frames) => {
+                write!(f, "Invalid buffer size: {} frames", frames)
+            }
+            NetworkError::NoDefaultDevice => write!(f, "Network backend has no peer"),
+            NetworkError::StartupTimeout(timeout) => write!(
+                f,
+                "Render thread did not start the session within {:?}",
+                timeout
+            ),
+            NetworkError::Unsupported(what) => {
+                write!(f, "Not supported by the network backend: {}", what)
+            }
+        }
+    }
+}
+
+impl Error for NetworkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NetworkError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<NetworkError> for crate::Error {
+    fn from(error: NetworkError) -> Self {
+        let kind = match &error {
+            NetworkError::Io(_) => Self::Backend,
+            NetworkError::InvalidSampleRate(_) | NetworkError::InvalidBufferSize(_) => {
+                Self::FormatNotSupported
+            }
+            NetworkError::NoDefaultDevice => Self::DeviceNotFound,
+            NetworkError::StartupTimeout(_) => Self::StartupTimeout,
+            NetworkError::Unsupported(_) => Self::Unsupported,
+        };
+
+        kind(Box::new(error))
+    }
+}
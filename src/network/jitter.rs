@@ -0,0 +1,125 @@
+use super::rtp;
+
+/// Puts received audio back in order by RTP timestamp, and plays it `delay`
+/// frames after the first packet's timestamp, so packets arriving up to
+/// that much later than the first still make it. Frames that haven't
+/// arrived by the time they're played are played as silence.
+///
+/// Playback follows the local clock, so a peer whose clock runs faster or
+/// slower eventually gets too far ahead or behind, at which point the
+/// buffer starts over from the next packet.
+pub(crate) struct JitterBuffer {
+    channels: usize,
+    samples: Vec<f32>,
+    filled: Vec<bool>,
+    /// In frames, a power of two so positions survive timestamps wrapping.
+    capacity: usize,
+    delay: u32,
+    /// The timestamp of the next frame to play, once a packet has arrived.
+    next: Option<u32>,
+    /// Frames of silence left to play before the first packet's audio.
+    lead_in: u32,
+    ssrc: Option<u32>,
+    pub received: u64,
+    pub late: u64,
+    pub concealed: u64,
+}
+
+impl JitterBuffer {
+    pub fn new(channels: usize, delay: usize) -> Self {
+        let capacity = (4 * delay.max(1)).next_power_of_two();
+
+        JitterBuffer {
+            channels,
+            samples: vec![0.0; capacity * channels],
+            filled: vec![false; capacity],
+            capacity,
+            delay: delay as u32,
+            next: None,
+            lead_in: 0,
+            ssrc: None,
+            received: 0,
+            late: 0,
+            concealed: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.filled.iter_mut().for_each(|filled| *filled = false);
+        self.next = None;
+    }
+
+    fn position(&self, timestamp: u32) -> usize {
+        timestamp as usize & (self.capacity - 1)
+    }
+
+    /// Stores an L16 payload starting at `timestamp`.
+    pub fn write(&mut self, ssrc: u32, timestamp: u32, payload: &[u8]) {
+        self.received += 1;
+
+        // A new source means the peer restarted, with a new timeline.
+        if self.ssrc != Some(ssrc) {
+            self.ssrc = Some(ssrc);
+            self.reset();
+        }
+
+        let frames = payload.len() / (2 * self.channels);
+        let delay = self.delay;
+        let next = match self.next {
+            Some(next) => next,
+            None => {
+                self.lead_in = delay;
+                *self.next.insert(timestamp.wrapping_sub(delay))
+            }
+        };
+        let ahead = timestamp.wrapping_sub(next) as i32;
+        if ahead < 0 {
+            self.late += 1;
+            return;
+        }
+        if ahead as usize + frames > self.capacity {
+            self.reset();
+            self.next = Some(timestamp.wrapping_sub(delay));
+            self.lead_in = delay;
+        }
+
+        for (frame, bytes) in payload.chunks_exact(2 * self.channels).enumerate() {
+            let position = self.position(timestamp.wrapping_add(frame as u32));
+            let samples = &mut self.samples[position * self.channels..][..self.channels];
+            for (sample, bytes) in samples.iter_mut().zip(bytes.chunks_exact(2)) {
+                *sample = rtp::decode([bytes[0], bytes[1]]);
+            }
+            self.filled[position] = true;
+        }
+    }
+
+    /// Fills `out`, which must hold whole frames, with the next frames to
+    /// play.
+    pub fn read(&mut self, out: &mut [f32]) {
+        let next = match self.next {
+            Some(next) => next,
+            None => {
+                out.fill(0.0);
+                return;
+            }
+        };
+
+        let frames = out.chunks_exact_mut(self.channels);
+        let count = frames.len();
+        for (frame, out) in frames.enumerate() {
+            let position = self.position(next.wrapping_add(frame as u32));
+            if self.filled[position] {
+                out.copy_from_slice(&self.samples[position * self.channels..][..self.channels]);
+                self.filled[position] = false;
+            } else {
+                out.fill(0.0);
+                if self.lead_in == 0 {
+                    self.concealed += 1;
+                }
+            }
+            self.lead_in = self.lead_in.saturating_sub(1);
+        }
+
+        self.next = Some(next.wrapping_add(count as u32));
+    }
+}
@@ -0,0 +1,11 @@
+mod backend;
+mod device;
+mod error;
+mod jitter;
+mod rtp;
+mod session;
+
+pub use backend::NetworkBackend as Backend;
+pub use device::NetworkDevice;
+pub use error::NetworkError;
+pub use session::{NetworkSession, NetworkStats};
@@ -0,0 +1,70 @@
+//! Just enough RTP (RFC 3550) to carry uncompressed audio: L16 payloads,
+//! big endian 16 bit samples interleaved like any other, as described in
+//! RFC 3551.
+
+/// A dynamic payload type, since the static L16 ones are fixed to 44.1 kHz.
+pub(crate) const PAYLOAD_TYPE: u8 = 96;
+
+pub(crate) const HEADER_LEN: usize = 12;
+
+/// Keeps packets within the usual 1500 byte Ethernet MTU, with room for
+/// IP and UDP headers.
+pub(crate) const MAX_PAYLOAD_LEN: usize = 1200;
+
+const VERSION: u8 = 2;
+
+pub(crate) struct Header {
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+}
+
+impl Header {
+    pub fn write(&self, packet: &mut Vec<u8>) {
+        packet.push(VERSION << 6);
+        packet.push(PAYLOAD_TYPE);
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&self.timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+    }
+
+    /// The header and payload of an L16 packet, or None for anything else.
+    pub fn parse(packet: &[u8]) -> Option<(Header, &[u8])> {
+        if packet.len() < HEADER_LEN
+            || packet[0] >> 6 != VERSION
+            || packet[1] & 0x7f != PAYLOAD_TYPE
+        {
+            return None;
+        }
+
+        let csrc_count = usize::from(packet[0] & 0x0f);
+        let mut start = HEADER_LEN + 4 * csrc_count;
+        if packet[0] & 0x10 != 0 {
+            let extension = packet.get(start..start + 4)?;
+            start += 4 + 4 * usize::from(u16::from_be_bytes([extension[2], extension[3]]));
+        }
+        let mut end = packet.len();
+        if packet[0] & 0x20 != 0 {
+            end = end.checked_sub(usize::from(packet[end - 1]))?;
+        }
+
+        let header = Header {
+            sequence: u16::from_be_bytes([packet[2], packet[3]]),
+            timestamp: u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]),
+            ssrc: u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]),
+        };
+
+        Some((header, packet.get(start..end)?))
+    }
+}
+
+pub(crate) fn encode(samples: &[f32], packet: &mut Vec<u8>) {
+    for &sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * 32_767.0) as i16;
+        packet.extend_from_slice(&sample.to_be_bytes());
+    }
+}
+
+pub(crate) fn decode(bytes: [u8; 2]) -> f32 {
+    f32::from(i16::from_be_bytes(bytes)) / 32_768.0
+}
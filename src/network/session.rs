@@ -0,0 +1,520 @@
+use std::net::UdpSocket;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::CycleTimes;
+use crate::deadline::DeadlineHistogram;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::Events;
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::sample::OwnedBuffer;
+use crate::traits::{RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+
+use super::backend::NetworkBackend;
+use super::device::NetworkDevice;
+use super::error::NetworkError;
+use super::jitter::JitterBuffer;
+use super::rtp::{self, Header, HEADER_LEN, MAX_PAYLOAD_LEN};
+
+/// The frames per callback unless the session is configured otherwise.
+pub(crate) const DEFAULT_BLOCK_SIZE: usize = 480;
+
+/// How often the receiving thread checks whether it should stop.
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Big enough for any UDP datagram.
+const MAX_PACKET_LEN: usize = 65_536;
+
+/// Counters for the audio received from the peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    pub packets_received: u64,
+    /// Packets that arrived after their audio should have been played.
+    pub packets_late: u64,
+    /// Frames played as silence because their packet hadn't arrived.
+    pub frames_concealed: u64,
+}
+
+/// Two threads of its own around a UDP socket: one receiving packets into a
+/// jitter buffer, and one calling the callback once per block, paced by the
+/// local clock. Each block's input comes out of the jitter buffer, and its
+/// output is sent to the peer right away, in as many packets as it takes.
+/// The callback gets one buffer per direction with the device's channels.
+///
+/// The sample time counts the frames rendered so far, and the RTP
+/// timestamps count the frames sent.
+pub struct NetworkSession {
+    engine: Arc<RenderEngine<NetworkBackend>>,
+    device: NetworkDevice,
+    socket: Arc<UdpSocket>,
+    block_size: usize,
+    jitter: Arc<Mutex<JitterBuffer>>,
+    /// The next packet's sequence number and timestamp, kept across
+    /// restarts so the peer sees one continuous stream.
+    sender: Arc<Mutex<Sender>>,
+    runner: Option<Runner>,
+}
+
+struct Runner {
+    stop: Arc<AtomicBool>,
+    render: JoinHandle<()>,
+    receive: JoinHandle<()>,
+}
+
+struct Sender {
+    ssrc: u32,
+    sequence: u16,
+    timestamp: u32,
+    packet: Vec<u8>,
+}
+
+impl NetworkSession {
+    pub(crate) fn new(
+        sample_rate: f64,
+        device: NetworkDevice,
+        block_size: Option<usize>,
+    ) -> Result<Self, NetworkError> {
+        if !(sample_rate.is_finite() && sample_rate >= 1.0 && sample_rate <= f64::from(u32::MAX)) {
+            return Err(NetworkError::InvalidSampleRate(sample_rate));
+        }
+        let block_size = block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+        if block_size == 0 {
+            return Err(NetworkError::InvalidBufferSize(block_size));
+        }
+        if device.channels() == 0 || 2 * device.channels() > MAX_PAYLOAD_LEN {
+            return Err(NetworkError::Unsupported("this many channels"));
+        }
+
+        let socket = UdpSocket::bind(device.local())?;
+        socket.connect(device.peer())?;
+        socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+
+        let jitter_frames = (device.jitter_delay().as_secs_f64() * sample_rate) as usize;
+        let jitter = JitterBuffer::new(device.channels(), jitter_frames);
+
+        // RFC 3550 wants both of these to be random, so different runs
+        // don't get mistaken for each other.
+        let seed = random_seed();
+        let sender = Sender {
+            ssrc: seed as u32,
+            sequence: (seed >> 32) as u16,
+            timestamp: (seed >> 48) as u32 ^ (seed as u32).rotate_left(16),
+            packet: Vec::with_capacity(HEADER_LEN + MAX_PAYLOAD_LEN),
+        };
+
+        let engine = Arc::new(RenderEngine::new(sample_rate));
+        engine.resize_scratch(block_size, device.channels());
+
+        Ok(NetworkSession {
+            engine,
+            device,
+            socket: Arc::new(socket),
+            block_size,
+            jitter: Arc::new(Mutex::new(jitter)),
+            sender: Arc::new(Mutex::new(sender)),
+            runner: None,
+        })
+    }
+
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<NetworkBackend>>,
+    ) -> Result<(), NetworkError> {
+        self.engine.set_callback(callback);
+        self.run()
+    }
+
+    fn run(&mut self) -> Result<(), NetworkError> {
+        if self.runner.is_some() {
+            return Ok(());
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let receiver = Receiver {
+            socket: self.socket.clone(),
+            jitter: self.jitter.clone(),
+            stop: stop.clone(),
+        };
+        let receive = thread::Builder::new()
+            .name("render_callback network receive".to_owned())
+            .spawn(move || receiver.run())?;
+
+        let mut pump = Pump {
+            engine: self.engine.clone(),
+            block_size: self.block_size,
+            stop: stop.clone(),
+            socket: self.socket.clone(),
+            jitter: self.jitter.clone(),
+            sender: self.sender.clone(),
+            input_buffers: vec![buffer(self.block_size, self.device.channels())],
+            output_buffers: vec![buffer(self.block_size, self.device.channels())],
+        };
+        let render = thread::Builder::new()
+            .name("render_callback network".to_owned())
+            .spawn(move || pump.run());
+        let render = match render {
+            Ok(render) => render,
+            Err(error) => {
+                stop.store(true, Ordering::Release);
+                let _ = receive.join();
+                return Err(error.into());
+            }
+        };
+
+        self.engine.valid.store(true, Ordering::Release);
+        self.runner = Some(Runner {
+            stop,
+            render,
+            receive,
+        });
+
+        Ok(())
+    }
+
+    /// Returns whether the threads were running.
+    fn halt(&mut self) -> bool {
+        match self.runner.take() {
+            Some(runner) => {
+                self.engine.valid.store(false, Ordering::Release);
+                runner.stop.store(true, Ordering::Release);
+                let _ = runner.render.join();
+                let _ = runner.receive.join();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Changes how many frames each callback gets, restarting the threads.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), NetworkError> {
+        if frames == 0 {
+            return Err(NetworkError::InvalidBufferSize(frames));
+        }
+
+        let was_running = self.halt();
+        self.block_size = frames;
+        self.engine.resize_scratch(frames, self.device.channels());
+        self.engine.reset_timeline();
+
+        if was_running {
+            self.run()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        self.block_size
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        (vec![self.device.channels()], vec![self.device.channels()])
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.engine.set_output_policy(policy);
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        let jitter = self.jitter.lock().unwrap();
+
+        NetworkStats {
+            packets_received: jitter.received,
+            packets_late: jitter.late,
+            frames_concealed: jitter.concealed,
+        }
+    }
+
+    /// Blocks until the thread has called the session at least once.
+    pub fn wait_until_running(&self, timeout: Duration) -> Result<(), NetworkError> {
+        let deadline = Instant::now() + timeout;
+
+        while self.engine.cycles.load(Ordering::Acquire) == 0 {
+            if Instant::now() >= deadline {
+                return Err(NetworkError::StartupTimeout(timeout));
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for NetworkSession {
+    fn drop(&mut self) {
+        self.halt();
+
+        // The threads have been joined, so this drops the callback here.
+        drop(self.engine.take_callback());
+    }
+}
+
+/// Not cryptographically random, but different between runs and processes.
+fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+
+    (nanos ^ u64::from(process::id()).rotate_left(32)).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// The receiving thread's state.
+struct Receiver {
+    socket: Arc<UdpSocket>,
+    jitter: Arc<Mutex<JitterBuffer>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Receiver {
+    fn run(&self) {
+        let mut packet = vec![0; MAX_PACKET_LEN];
+
+        while !self.stop.load(Ordering::Acquire) {
+            // Timeouts, and errors like the peer's port being closed, are
+            // retried until the session stops.
+            let len = match self.socket.recv(&mut packet) {
+                Ok(len) => len,
+                Err(_) => continue,
+            };
+
+            if let Some((header, payload)) = Header::parse(&packet[..len]) {
+                self.jitter
+                    .lock()
+                    .unwrap()
+                    .write(header.ssrc, header.timestamp, payload);
+            }
+        }
+    }
+}
+
+/// The render thread's state.
+struct Pump {
+    engine: Arc<RenderEngine<NetworkBackend>>,
+    block_size: usize,
+    stop: Arc<AtomicBool>,
+    socket: Arc<UdpSocket>,
+    jitter: Arc<Mutex<JitterBuffer>>,
+    sender: Arc<Mutex<Sender>>,
+    input_buffers: Vec<OwnedBuffer<f32>>,
+    output_buffers: Vec<OwnedBuffer<f32>>,
+}
+
+impl Pump {
+    fn run(&mut self) {
+        let period =
+            Duration::from_secs_f64(self.block_size as f64 / self.engine.clock.sample_rate());
+        let mut rendered = 0u64;
+        let mut next = Instant::now();
+
+        while !self.stop.load(Ordering::Acquire) {
+            self.engine.cycles.fetch_add(1, Ordering::Release);
+
+            self.jitter
+                .lock()
+                .unwrap()
+                .read(self.input_buffers[0].interleaved_frames_mut());
+
+            // Only this thread renders, and the session joins it before
+            // touching the callback.
+            unsafe {
+                self.engine.render(
+                    &self.input_buffers,
+                    &mut self.output_buffers,
+                    self.block_size,
+                    CycleTimes::at(rendered as f64),
+                );
+            }
+            rendered += self.block_size as u64;
+
+            self.send();
+
+            next += period;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            } else if now - next > period {
+                // Fell behind, like after the machine slept. Carry on from
+                // now rather than rendering the missed blocks in a burst.
+                next = now;
+            }
+        }
+    }
+
+    /// Sends the block's output, split into packets that fit the MTU.
+    fn send(&mut self) {
+        let mut sender = self.sender.lock().unwrap();
+        let output = &self.output_buffers[0];
+        let channels = output.num_channels();
+        let frames_per_packet = MAX_PAYLOAD_LEN / (2 * channels);
+
+        for samples in output
+            .interleaved_frames()
+            .chunks(frames_per_packet * channels)
+        {
+            let Sender {
+                ssrc,
+                sequence,
+                timestamp,
+                packet,
+            } = &mut *sender;
+
+            packet.clear();
+            Header {
+                sequence: *sequence,
+                timestamp: *timestamp,
+                ssrc: *ssrc,
+            }
+            .write(packet);
+            rtp::encode(samples, packet);
+
+            // Packets the network or the peer refuses are lost like any
+            // other, and the timeline carries on regardless.
+            let _ = self.socket.send(packet);
+            *sequence = sequence.wrapping_add(1);
+            *timestamp = timestamp.wrapping_add((samples.len() / channels) as u32);
+        }
+    }
+}
+
+fn buffer(frames: usize, channels: usize) -> OwnedBuffer<f32> {
+    let mut buffer = OwnedBuffer::with_capacity(frames, channels);
+    buffer.reshape(frames, channels);
+    buffer
+}
+
+impl Session<NetworkBackend> for NetworkSession {
+    fn input_device(&self) -> Result<NetworkDevice, NetworkError> {
+        Ok(self.device.clone())
+    }
+
+    fn output_device(&self) -> Result<NetworkDevice, NetworkError> {
+        Ok(self.device.clone())
+    }
+
+    fn set_input_device(&mut self, _device: NetworkDevice) -> Result<(), NetworkError> {
+        Err(NetworkError::Unsupported("switching peers"))
+    }
+
+    fn set_output_device(&mut self, _device: NetworkDevice) -> Result<(), NetworkError> {
+        Err(NetworkError::Unsupported("switching peers"))
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, NetworkError> {
+        Ok(NetworkSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, NetworkError> {
+        let side = || {
+            vec![StreamMapping {
+                device_id: self.device.peer().to_string(),
+                first_device_channel: 0,
+                channels: self.device.channels(),
+            }]
+        };
+
+        Ok(ChannelMap {
+            input: side(),
+            output: side(),
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.engine.clock.clone()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<NetworkBackend>>) {
+        self.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.engine.is_bypassed()
+    }
+
+    /// The network's latency is outside of the session's control, so the
+    /// jitter delay is what to tune instead.
+    fn tune_for_low_latency(
+        &mut self,
+        _target_ms: f64,
+    ) -> Result<Option<LatencyTuning>, NetworkError> {
+        Err(NetworkError::Unsupported("tuning for low latency"))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, NetworkError> {
+        Err(NetworkError::Unsupported("starting at a host time"))
+    }
+
+    fn stop(&mut self) -> Result<(), NetworkError> {
+        self.halt();
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), NetworkError> {
+        if self.runner.is_none() {
+            self.engine.reset_timeline();
+        }
+        self.run()
+    }
+
+    fn is_running(&self) -> bool {
+        self.runner.is_some()
+    }
+}
@@ -0,0 +1,121 @@
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::processor::{processor_callback, Processor};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::NullDevice;
+use super::error::NullError;
+use super::session::NullSession;
+
+/// A backend without hardware, for running code that opens sessions on
+/// machines without sound cards, like CI runners. It has a single device,
+/// used for both input and output, and its sessions call the callback with
+/// silence at the pace a real device would.
+pub struct NullBackend {
+    device: NullDevice,
+}
+
+impl NullBackend {
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(&self, config: &SessionConfig<Self>) -> Result<NullSession, NullError> {
+        if config.physical_format.is_some() {
+            return Err(NullError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(NullError::Unsupported("preroll"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(NullError::Unsupported("more than one device per direction"));
+        }
+
+        let session = NullSession::new(
+            config.sample_rate,
+            config.output_device.clone(),
+            config.buffer_size,
+        )?;
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for NullBackend {
+    type Session = NullSession;
+    type Error = NullError;
+    type Device = NullDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    fn new() -> Result<Self, NullError> {
+        Ok(NullBackend {
+            device: NullDevice::new(),
+        })
+    }
+
+    fn is_available() -> bool {
+        true
+    }
+
+    fn all_devices(&self) -> Result<Vec<NullDevice>, NullError> {
+        Ok(vec![self.device.clone()])
+    }
+
+    fn default_input_device(&self) -> Result<NullDevice, NullError> {
+        Ok(self.device.clone())
+    }
+
+    fn default_output_device(&self) -> Result<NullDevice, NullError> {
+        Ok(self.device.clone())
+    }
+
+    /// The buffer size sets the block size, and defaults to 512 frames.
+    /// There's nothing to fail transiently or to route, so retries and
+    /// routing are ignored, and so is the clock master since there's only
+    /// one device. Additional devices, physical formats and preroll are
+    /// refused.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<NullSession, NullError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<NullSession, NullError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<NullSession, NullError> {
+        Err(NullError::Unsupported("process taps"))
+    }
+}
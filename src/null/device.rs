@@ -0,0 +1,139 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::Arc;
+
+use crate::direction::Direction;
+use crate::traits::Device;
+
+use super::backend::NullBackend;
+use super::error::NullError;
+
+pub(crate) const DEVICE_NAME: &str = "Null";
+pub(crate) const CHANNELS: usize = 2;
+pub(crate) const DEFAULT_SAMPLE_RATE: f64 = 48_000.0;
+
+/// The null backend's only device, with two input channels that are always
+/// silent and two output channels that go nowhere. Its sample rate is
+/// whatever the last session started on it asked for.
+///
+/// All handles to the device from the same backend share its sample rate,
+/// and compare equal.
+#[derive(Clone)]
+pub struct NullDevice {
+    sample_rate: Arc<AtomicU64>,
+}
+
+impl NullDevice {
+    pub(crate) fn new() -> Self {
+        NullDevice {
+            sample_rate: Arc::new(AtomicU64::new(DEFAULT_SAMPLE_RATE.to_bits())),
+        }
+    }
+
+    pub(crate) fn store_sample_rate(&self, sample_rate: f64) {
+        self.sample_rate
+            .store(sample_rate.to_bits(), atomic::Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn check_sample_rate(sample_rate: f64) -> Result<(), NullError> {
+    if sample_rate.is_finite() && sample_rate > 0.0 {
+        Ok(())
+    } else {
+        Err(NullError::InvalidSampleRate(sample_rate))
+    }
+}
+
+impl fmt::Debug for NullDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NullDevice").finish()
+    }
+}
+
+impl PartialEq for NullDevice {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for NullDevice {}
+
+impl Hash for NullDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        DEVICE_NAME.hash(state);
+    }
+}
+
+impl PartialOrd for NullDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NullDevice {
+    fn cmp(&self, _other: &Self) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+impl Device<NullBackend> for NullDevice {
+    fn num_inputs(&self) -> Result<usize, NullError> {
+        Ok(CHANNELS)
+    }
+
+    fn num_outputs(&self) -> Result<usize, NullError> {
+        Ok(CHANNELS)
+    }
+
+    fn name(&self) -> Result<String, NullError> {
+        Ok(DEVICE_NAME.to_owned())
+    }
+
+    fn channel_name(
+        &self,
+        direction: Direction,
+        channel: usize,
+    ) -> Result<Option<String>, NullError> {
+        let prefix = match direction {
+            Direction::Input => "in",
+            Direction::Output => "out",
+        };
+
+        Ok((channel < CHANNELS).then(|| format!("{}_{}", prefix, channel + 1)))
+    }
+
+    fn persistent_id(&self) -> Result<String, NullError> {
+        Ok(DEVICE_NAME.to_lowercase())
+    }
+
+    /// The backend's only device is its default both ways.
+    fn is_default_input(&self) -> Result<bool, NullError> {
+        Ok(true)
+    }
+
+    fn is_default_output(&self) -> Result<bool, NullError> {
+        Ok(true)
+    }
+
+    fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), NullError> {
+        check_sample_rate(sample_rate)?;
+        self.store_sample_rate(sample_rate);
+        Ok(())
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), NullError> {
+        Err(NullError::Unsupported("selecting clock sources"))
+    }
+
+    fn nominal_sample_rate(&self) -> Result<f64, NullError> {
+        Ok(f64::from_bits(
+            self.sample_rate.load(atomic::Ordering::Relaxed),
+        ))
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, NullError> {
+        self.nominal_sample_rate()
+    }
+}
@@ -0,0 +1,63 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum NullError {
+    /// Sample rates have to be positive and finite.
+    InvalidSampleRate(f64),
+    /// Blocks have to have at least one frame.
+    InvalidBufferSize(usize),
+    /// The session's thread could not be spawned.
+    Thread(std::io::Error),
+    /// The session was started, but its thread never called it.
+    StartupTimeout(Duration),
+    /// The operation or session option makes no sense without hardware.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for NullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NullError::InvalidSampleRate(sample_rate) => {
+                write!(f, "Invalid sample rate: {} Hz", sample_rate)
+            }
+            NullError::InvalidBufferSize(frames) => {
+                write!(f, "Invalid buffer size: {} frames", frames)
+            }
+            NullError::Thread(error) => write!(f, "Could not spawn render thread: {}", error),
+            NullError::StartupTimeout(timeout) => write!(
+                f,
+                "Render thread did not start the session within {:?}",
+                timeout
+            ),
+            NullError::Unsupported(what) => {
+                write!(f, "Not supported by the null backend: {}", what)
+            }
+        }
+    }
+}
+
+impl Error for NullError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NullError::Thread(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<NullError> for crate::Error {
+    fn from(error: NullError) -> Self {
+        let kind = match &error {
+            NullError::InvalidSampleRate(_) | NullError::InvalidBufferSize(_) => {
+                Self::FormatNotSupported
+            }
+            NullError::Thread(_) => Self::Backend,
+            NullError::StartupTimeout(_) => Self::StartupTimeout,
+            NullError::Unsupported(_) => Self::Unsupported,
+        };
+
+        kind(Box::new(error))
+    }
+}
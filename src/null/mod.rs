@@ -0,0 +1,9 @@
+mod backend;
+mod device;
+mod error;
+mod session;
+
+pub use backend::NullBackend as Backend;
+pub use device::NullDevice;
+pub use error::NullError;
+pub use session::NullSession;
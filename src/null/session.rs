@@ -0,0 +1,362 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::CycleTimes;
+use crate::deadline::DeadlineHistogram;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::Events;
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::sample::OwnedBuffer;
+use crate::traits::{Device, RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+
+use super::backend::NullBackend;
+use super::device::{check_sample_rate, NullDevice, CHANNELS};
+use super::error::NullError;
+
+/// The frames per callback unless the session is configured otherwise.
+pub(crate) const DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// A thread of its own that calls the callback once per block, paced by
+/// the clock so blocks come at the rate a sound card at the session's
+/// sample rate would ask for them. Input is silent and output is thrown
+/// away. The sample time counts the frames rendered so far.
+pub struct NullSession {
+    engine: Arc<RenderEngine<NullBackend>>,
+    device: NullDevice,
+    block_size: usize,
+    runner: Option<Runner>,
+}
+
+struct Runner {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl NullSession {
+    pub(crate) fn new(
+        sample_rate: f64,
+        device: NullDevice,
+        block_size: Option<usize>,
+    ) -> Result<Self, NullError> {
+        check_sample_rate(sample_rate)?;
+        let block_size = block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+        if block_size == 0 {
+            return Err(NullError::InvalidBufferSize(block_size));
+        }
+        device.store_sample_rate(sample_rate);
+
+        let engine = Arc::new(RenderEngine::new(sample_rate));
+        engine.resize_scratch(block_size, CHANNELS);
+
+        Ok(NullSession {
+            engine,
+            device,
+            block_size,
+            runner: None,
+        })
+    }
+
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<NullBackend>>,
+    ) -> Result<(), NullError> {
+        self.engine.set_callback(callback);
+        self.run()
+    }
+
+    fn run(&mut self) -> Result<(), NullError> {
+        if self.runner.is_some() {
+            return Ok(());
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut pump = Pump::new(self.engine.clone(), self.block_size, stop.clone());
+        let thread = thread::Builder::new()
+            .name("render_callback null".to_owned())
+            .spawn(move || pump.run())
+            .map_err(NullError::Thread)?;
+
+        self.engine.valid.store(true, Ordering::Release);
+        self.runner = Some(Runner { stop, thread });
+
+        Ok(())
+    }
+
+    /// Returns whether the thread was running.
+    fn halt(&mut self) -> bool {
+        match self.runner.take() {
+            Some(runner) => {
+                self.engine.valid.store(false, Ordering::Release);
+                runner.stop.store(true, Ordering::Release);
+                let _ = runner.thread.join();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Changes how many frames each callback gets, restarting the thread.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), NullError> {
+        if frames == 0 {
+            return Err(NullError::InvalidBufferSize(frames));
+        }
+
+        let was_running = self.halt();
+        self.block_size = frames;
+        self.engine.resize_scratch(frames, CHANNELS);
+        self.engine.reset_timeline();
+
+        if was_running {
+            self.run()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        self.block_size
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        (vec![CHANNELS], vec![CHANNELS])
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.engine.set_output_policy(policy);
+    }
+
+    /// Blocks until the thread has called the session at least once.
+    pub fn wait_until_running(&self, timeout: Duration) -> Result<(), NullError> {
+        let deadline = Instant::now() + timeout;
+
+        while self.engine.cycles.load(Ordering::Acquire) == 0 {
+            if Instant::now() >= deadline {
+                return Err(NullError::StartupTimeout(timeout));
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for NullSession {
+    fn drop(&mut self) {
+        self.halt();
+
+        // The thread has been joined, so this drops the callback here.
+        drop(self.engine.take_callback());
+    }
+}
+
+/// The session thread's state.
+struct Pump {
+    engine: Arc<RenderEngine<NullBackend>>,
+    block_size: usize,
+    stop: Arc<AtomicBool>,
+    input_buffers: Vec<OwnedBuffer<f32>>,
+    output_buffers: Vec<OwnedBuffer<f32>>,
+}
+
+impl Pump {
+    fn new(
+        engine: Arc<RenderEngine<NullBackend>>,
+        block_size: usize,
+        stop: Arc<AtomicBool>,
+    ) -> Self {
+        Pump {
+            engine,
+            block_size,
+            stop,
+            input_buffers: vec![buffer(block_size)],
+            output_buffers: vec![buffer(block_size)],
+        }
+    }
+
+    fn run(&mut self) {
+        let period =
+            Duration::from_secs_f64(self.block_size as f64 / self.engine.clock.sample_rate());
+        let mut rendered = 0u64;
+        let mut next = Instant::now();
+
+        while !self.stop.load(Ordering::Acquire) {
+            self.engine.cycles.fetch_add(1, Ordering::Release);
+
+            // Only this thread renders, and the session joins it before
+            // touching the callback.
+            unsafe {
+                self.engine.render(
+                    &self.input_buffers,
+                    &mut self.output_buffers,
+                    self.block_size,
+                    CycleTimes::at(rendered as f64),
+                );
+            }
+            rendered += self.block_size as u64;
+
+            next += period;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            } else if now - next > period {
+                // Fell behind, like after the machine slept. Carry on from
+                // now rather than rendering the missed blocks in a burst.
+                next = now;
+            }
+        }
+    }
+}
+
+fn buffer(frames: usize) -> OwnedBuffer<f32> {
+    let mut buffer = OwnedBuffer::with_capacity(frames, CHANNELS);
+    buffer.reshape(frames, CHANNELS);
+    buffer
+}
+
+impl Session<NullBackend> for NullSession {
+    fn input_device(&self) -> Result<NullDevice, NullError> {
+        Ok(self.device.clone())
+    }
+
+    fn output_device(&self) -> Result<NullDevice, NullError> {
+        Ok(self.device.clone())
+    }
+
+    /// There's only the one device, so this does nothing.
+    fn set_input_device(&mut self, _device: NullDevice) -> Result<(), NullError> {
+        Ok(())
+    }
+
+    /// There's only the one device, so this does nothing.
+    fn set_output_device(&mut self, _device: NullDevice) -> Result<(), NullError> {
+        Ok(())
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, NullError> {
+        Ok(NullSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, NullError> {
+        let side = || {
+            Ok(vec![StreamMapping {
+                device_id: self.device.persistent_id()?,
+                first_device_channel: 0,
+                channels: CHANNELS,
+            }])
+        };
+
+        Ok(ChannelMap {
+            input: side()?,
+            output: side()?,
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.engine.clock.clone()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<NullBackend>>) {
+        self.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.engine.is_bypassed()
+    }
+
+    /// Without hardware to keep up with, any size is stable, so this picks
+    /// the largest power of two within the target right away.
+    fn tune_for_low_latency(&mut self, target_ms: f64) -> Result<Option<LatencyTuning>, NullError> {
+        let sample_rate = self.engine.clock.sample_rate();
+        let target_frames = (target_ms * sample_rate / 1000.0) as usize;
+
+        let mut buffer_size = 1;
+        while 4 * buffer_size <= target_frames {
+            buffer_size *= 2;
+        }
+        self.set_buffer_size(buffer_size)?;
+
+        Ok(Some(LatencyTuning {
+            buffer_size,
+            input_latency_frames: buffer_size,
+            output_latency_frames: buffer_size,
+            sample_rate,
+        }))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, NullError> {
+        Err(NullError::Unsupported("starting at a host time"))
+    }
+
+    fn stop(&mut self) -> Result<(), NullError> {
+        self.halt();
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), NullError> {
+        if self.runner.is_none() {
+            self.engine.reset_timeline();
+        }
+        self.run()
+    }
+
+    fn is_running(&self) -> bool {
+        self.runner.is_some()
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::processor::{processor_callback, Processor};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::{self, OssDevice};
+use super::error::OssError;
+use super::session::OssSession;
+
+/// The device node that follows the system's default sound card.
+const DEFAULT_DEVICE: &str = "/dev/dsp";
+
+/// The Open Sound System, as found on FreeBSD, DragonFly and NetBSD. Each
+/// session opens a single device node for both directions, so input and
+/// output have to be the same device.
+pub struct OssBackend;
+
+impl OssBackend {
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(&self, config: &SessionConfig<Self>) -> Result<OssSession, OssError> {
+        if config.physical_format.is_some() {
+            return Err(OssError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(OssError::Unsupported("preroll"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(OssError::Unsupported("more than one device per direction"));
+        }
+
+        let device = same_device(config.input_device.clone(), config.output_device.clone())?;
+        let session =
+            OssSession::new(config.retry, config.sample_rate, device, config.buffer_size)?;
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for OssBackend {
+    type Session = OssSession;
+    type Error = OssError;
+    type Device = OssDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    fn new() -> Result<Self, OssError> {
+        Ok(OssBackend)
+    }
+
+    fn is_available() -> bool {
+        Path::new(DEFAULT_DEVICE).exists()
+    }
+
+    fn all_devices(&self) -> Result<Vec<OssDevice>, OssError> {
+        Ok(device::scan().0)
+    }
+
+    fn default_input_device(&self) -> Result<OssDevice, OssError> {
+        default_device()
+    }
+
+    fn default_output_device(&self) -> Result<OssDevice, OssError> {
+        default_device()
+    }
+
+    /// The buffer size sets the block size, and defaults to 512 frames.
+    /// Additional devices, physical formats and preroll are refused.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<OssSession, OssError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<OssSession, OssError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<OssSession, OssError> {
+        Err(OssError::Unsupported("process taps"))
+    }
+}
+
+/// The device /dev/sndstat marks as the default, or /dev/dsp if nothing
+/// is marked.
+pub(super) fn default_device() -> Result<OssDevice, OssError> {
+    let (devices, default) = device::scan();
+    if let Some(index) = default {
+        return Ok(devices[index].clone());
+    }
+
+    if Path::new(DEFAULT_DEVICE).exists() {
+        Ok(OssDevice::new(DEFAULT_DEVICE))
+    } else {
+        Err(OssError::NoDefaultDevice)
+    }
+}
+
+fn same_device(input: OssDevice, output: OssDevice) -> Result<OssDevice, OssError> {
+    if input != output {
+        return Err(OssError::Unsupported("different input and output devices"));
+    }
+
+    Ok(output)
+}
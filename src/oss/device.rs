@@ -0,0 +1,249 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::Arc;
+
+use crate::direction::Direction;
+use crate::traits::Device;
+
+use super::backend::{self, OssBackend};
+use super::error::OssError;
+
+pub(crate) const DEFAULT_CHANNELS: usize = 2;
+pub(crate) const DEFAULT_SAMPLE_RATE: f64 = 48_000.0;
+
+/// Lists the sound cards and which of them is the default, on FreeBSD.
+const SNDSTAT: &str = "/dev/sndstat";
+
+/// A `/dev/dsp` device node. OSS doesn't say how many channels a device
+/// has without opening it, so devices claim two until told otherwise with
+/// `with_channels`, and sessions fail to start if the driver disagrees.
+///
+/// Devices are identified by their path.
+#[derive(Clone)]
+pub struct OssDevice {
+    path: PathBuf,
+    name: String,
+    playback: bool,
+    recording: bool,
+    channels: usize,
+    sample_rate: Arc<AtomicU64>,
+}
+
+impl OssDevice {
+    /// A device at `path`, which is assumed to do both playback and
+    /// recording.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+
+        OssDevice {
+            name: path.display().to_string(),
+            path,
+            playback: true,
+            recording: true,
+            channels: DEFAULT_CHANNELS,
+            sample_rate: Arc::new(AtomicU64::new(DEFAULT_SAMPLE_RATE.to_bits())),
+        }
+    }
+
+    /// The same device with `channels` channels in each direction it
+    /// supports.
+    pub fn with_channels(mut self, channels: usize) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn channels(&self, direction: Direction) -> usize {
+        let supported = match direction {
+            Direction::Input => self.recording,
+            Direction::Output => self.playback,
+        };
+
+        if supported {
+            self.channels
+        } else {
+            0
+        }
+    }
+
+    pub(crate) fn store_sample_rate(&self, sample_rate: f64) {
+        self.sample_rate
+            .store(sample_rate.to_bits(), atomic::Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn check_sample_rate(sample_rate: f64) -> Result<(), OssError> {
+    if sample_rate.is_finite() && sample_rate >= 1.0 && sample_rate <= f64::from(i32::MAX) {
+        Ok(())
+    } else {
+        Err(OssError::InvalidSampleRate(sample_rate))
+    }
+}
+
+/// The devices on the system, and the index of the default one if known.
+///
+/// FreeBSD describes its cards in /dev/sndstat, with lines like
+/// `pcm0: <Realtek ALC892 (Analog)> (play/rec) default`. Elsewhere, every
+/// /dev/dspN node is assumed to be a duplex device.
+pub(crate) fn scan() -> (Vec<OssDevice>, Option<usize>) {
+    if let Ok(sndstat) = fs::read_to_string(SNDSTAT) {
+        let mut devices = vec![];
+        let mut default = None;
+
+        for line in sndstat.lines() {
+            if let Some((device, is_default)) = parse_sndstat_line(line) {
+                if is_default {
+                    default = Some(devices.len());
+                }
+                devices.push(device);
+            }
+        }
+
+        return (devices, default);
+    }
+
+    let mut paths = fs::read_dir("/dev")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("dsp"))
+                .is_some_and(is_unit)
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    (paths.into_iter().map(OssDevice::new).collect(), None)
+}
+
+fn parse_sndstat_line(line: &str) -> Option<(OssDevice, bool)> {
+    let (unit, rest) = line.strip_prefix("pcm")?.split_once(':')?;
+    if !is_unit(unit) {
+        return None;
+    }
+
+    let (_, rest) = rest.split_once('<')?;
+    let (description, flags) = rest.rsplit_once('>')?;
+
+    let mut device = OssDevice::new(format!("/dev/dsp{}", unit));
+    device.name = description.to_owned();
+    device.playback = flags.contains("play");
+    device.recording = flags.contains("rec");
+
+    Some((
+        device,
+        flags.split_whitespace().any(|flag| flag == "default"),
+    ))
+}
+
+/// Whether `unit` is a unit number, like the 0 in pcm0 and dsp0.
+fn is_unit(unit: &str) -> bool {
+    !unit.is_empty() && unit.bytes().all(|b| b.is_ascii_digit())
+}
+
+impl fmt::Debug for OssDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OssDevice")
+            .field("path", &self.path)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl PartialEq for OssDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+impl Eq for OssDevice {}
+
+impl Hash for OssDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+    }
+}
+
+impl PartialOrd for OssDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OssDevice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.path.cmp(&other.path)
+    }
+}
+
+impl Device<OssBackend> for OssDevice {
+    fn num_inputs(&self) -> Result<usize, OssError> {
+        Ok(self.channels(Direction::Input))
+    }
+
+    fn num_outputs(&self) -> Result<usize, OssError> {
+        Ok(self.channels(Direction::Output))
+    }
+
+    fn name(&self) -> Result<String, OssError> {
+        Ok(self.name.clone())
+    }
+
+    fn channel_name(
+        &self,
+        direction: Direction,
+        channel: usize,
+    ) -> Result<Option<String>, OssError> {
+        let prefix = match direction {
+            Direction::Input => "in",
+            Direction::Output => "out",
+        };
+
+        Ok((channel < self.channels(direction)).then(|| format!("{}_{}", prefix, channel + 1)))
+    }
+
+    fn persistent_id(&self) -> Result<String, OssError> {
+        Ok(self.path.display().to_string())
+    }
+
+    /// Takes effect the next time a session opens the device.
+    /// Devices are full duplex, so the default device is the default both
+    /// ways.
+    fn is_default_input(&self) -> Result<bool, OssError> {
+        Ok(matches!(backend::default_device(), Ok(default) if default == *self))
+    }
+
+    fn is_default_output(&self) -> Result<bool, OssError> {
+        self.is_default_input()
+    }
+
+    fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), OssError> {
+        check_sample_rate(sample_rate)?;
+        self.store_sample_rate(sample_rate);
+        Ok(())
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), OssError> {
+        Err(OssError::Unsupported("selecting clock sources"))
+    }
+
+    fn nominal_sample_rate(&self) -> Result<f64, OssError> {
+        Ok(f64::from_bits(
+            self.sample_rate.load(atomic::Ordering::Relaxed),
+        ))
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, OssError> {
+        self.nominal_sample_rate()
+    }
+}
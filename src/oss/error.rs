@@ -0,0 +1,120 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum OssError {
+    /// The device node could not be opened.
+    Open(PathBuf, io::Error),
+    /// The named ioctl failed.
+    Ioctl(&'static str, io::Error),
+    /// The session's thread could not be spawned.
+    Thread(io::Error),
+    /// Sample rates have to be positive and fit in an int.
+    InvalidSampleRate(f64),
+    /// Blocks have to have at least one frame.
+    InvalidBufferSize(usize),
+    /// The driver settled for a different sample rate than the session asked
+    /// for.
+    SampleRateRejected { requested: f64, actual: f64 },
+    /// The driver settled for a different number of channels than the device
+    /// was set up with.
+    ChannelsRejected { requested: usize, actual: usize },
+    /// The driver doesn't do 16 bit samples in native byte order.
+    FormatRejected,
+    /// There's no /dev/dsp.
+    NoDefaultDevice,
+    /// The session was started, but the device never called it.
+    StartupTimeout(Duration),
+    /// The operation or session option has no OSS equivalent.
+    Unsupported(&'static str),
+}
+
+impl OssError {
+    /// Whether the error is expected to clear up by itself, like another
+    /// process still holding the device.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            OssError::Open(_, error) => error.raw_os_error() == Some(libc::EBUSY),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for OssError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OssError::Open(path, error) => {
+                write!(f, "Could not open {}: {}", path.display(), error)
+            }
+            OssError::Ioctl(name, error) => write!(f, "{} failed: {}", name, error),
+            OssError::Thread(error) => write!(f, "Could not spawn render thread: {}", error),
+            OssError::InvalidSampleRate(sample_rate) => {
+                write!(f, "Invalid sample rate: {} Hz", sample_rate)
+            }
+            OssError::InvalidBufferSize(frames) => {
+                write!(f, "Invalid buffer size: {} frames", frames)
+            }
+            OssError::SampleRateRejected { requested, actual } => write!(
+                f,
+                "Device runs at {} Hz instead of the requested {} Hz",
+                actual, requested
+            ),
+            OssError::ChannelsRejected { requested, actual } => write!(
+                f,
+                "Device has {} channels instead of the requested {}",
+                actual, requested
+            ),
+            OssError::FormatRejected => {
+                write!(f, "Device does not support 16 bit native endian samples")
+            }
+            OssError::NoDefaultDevice => write!(f, "No default device"),
+            OssError::StartupTimeout(timeout) => write!(
+                f,
+                "Device did not start calling the session within {:?}",
+                timeout
+            ),
+            OssError::Unsupported(what) => write!(f, "Not supported by OSS: {}", what),
+        }
+    }
+}
+
+impl Error for OssError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OssError::Open(_, error) | OssError::Ioctl(_, error) | OssError::Thread(error) => {
+                Some(error)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<OssError> for crate::Error {
+    fn from(error: OssError) -> Self {
+        let kind = match &error {
+            OssError::Open(_, error) if error.kind() == io::ErrorKind::NotFound => {
+                Self::DeviceNotFound
+            }
+            OssError::Open(_, error) if error.raw_os_error() == Some(libc::EBUSY) => {
+                Self::DeviceBusy
+            }
+            OssError::Ioctl(_, error) if error.raw_os_error() == Some(libc::ENXIO) => {
+                Self::DeviceDisconnected
+            }
+            OssError::Open(..) | OssError::Ioctl(..) | OssError::Thread(_) => Self::Backend,
+            OssError::InvalidSampleRate(_)
+            | OssError::InvalidBufferSize(_)
+            | OssError::SampleRateRejected { .. }
+            | OssError::ChannelsRejected { .. }
+            | OssError::FormatRejected => Self::FormatNotSupported,
+            OssError::NoDefaultDevice => Self::DeviceNotFound,
+            OssError::StartupTimeout(_) => Self::StartupTimeout,
+            OssError::Unsupported(_) => Self::Unsupported,
+        };
+
+        kind(Box::new(error))
+    }
+}
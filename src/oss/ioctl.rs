@@ -0,0 +1,68 @@
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+use libc::{c_int, c_ulong};
+
+use super::error::OssError;
+
+// From <sys/soundcard.h>, which the libc crate doesn't cover. The request
+// numbers use the BSD encoding: direction in the top three bits, then the
+// argument size, group and number.
+
+const IOC_VOID: c_ulong = 0x2000_0000;
+const IOC_INOUT: c_ulong = 0xc000_0000;
+const GROUP: c_ulong = b'P' as c_ulong;
+
+const fn io(number: c_ulong) -> c_ulong {
+    IOC_VOID | (GROUP << 8) | number
+}
+
+const fn iowr_int(number: c_ulong) -> c_ulong {
+    IOC_INOUT | ((std::mem::size_of::<c_int>() as c_ulong) << 16) | (GROUP << 8) | number
+}
+
+pub(super) const SNDCTL_DSP_RESET: c_ulong = io(0);
+pub(super) const SNDCTL_DSP_SPEED: c_ulong = iowr_int(2);
+pub(super) const SNDCTL_DSP_SETFMT: c_ulong = iowr_int(5);
+pub(super) const SNDCTL_DSP_CHANNELS: c_ulong = iowr_int(6);
+pub(super) const SNDCTL_DSP_SETFRAGMENT: c_ulong = iowr_int(10);
+pub(super) const SNDCTL_DSP_SETDUPLEX: c_ulong = io(22);
+
+#[cfg(target_endian = "little")]
+pub(super) const AFMT_S16_NE: c_int = 0x10;
+#[cfg(target_endian = "big")]
+pub(super) const AFMT_S16_NE: c_int = 0x20;
+
+/// Calls an ioctl that takes no argument.
+pub(super) fn call(
+    file: &impl AsRawFd,
+    request: c_ulong,
+    name: &'static str,
+) -> Result<(), OssError> {
+    // The request takes no argument, so there's nothing for the kernel to
+    // read or write.
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), request) };
+    if result < 0 {
+        return Err(OssError::Ioctl(name, io::Error::last_os_error()));
+    }
+
+    Ok(())
+}
+
+/// Calls an ioctl that reads and writes an int, returning what the driver
+/// wrote back, which is what it settled for rather than what was asked.
+pub(super) fn call_with(
+    file: &impl AsRawFd,
+    request: c_ulong,
+    name: &'static str,
+    value: c_int,
+) -> Result<c_int, OssError> {
+    let mut value = value;
+    // All the requests used with this take a pointer to an int.
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), request, &mut value as *mut c_int) };
+    if result < 0 {
+        return Err(OssError::Ioctl(name, io::Error::last_os_error()));
+    }
+
+    Ok(value)
+}
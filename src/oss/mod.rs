@@ -0,0 +1,10 @@
+mod backend;
+mod device;
+mod error;
+mod ioctl;
+mod session;
+
+pub use backend::OssBackend as Backend;
+pub use device::OssDevice;
+pub use error::OssError;
+pub use session::OssSession;
@@ -0,0 +1,499 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::CycleTimes;
+use crate::deadline::DeadlineHistogram;
+use crate::direction::Direction;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::{Events, SessionEvent, StopCause};
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::retry::{retry, RetryPolicy};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Device, RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+
+use super::backend::OssBackend;
+use super::device::{check_sample_rate, OssDevice};
+use super::error::OssError;
+use super::ioctl;
+
+/// The frames per callback unless the session is configured otherwise.
+pub(crate) const DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// Fragments the driver is asked to keep, each one block long.
+const FRAGMENTS: libc::c_int = 2;
+
+/// A thread of its own that blocks on the device node, reading a block of
+/// input, rendering it and writing the output, so the device's clock paces
+/// the callbacks. Samples go to and from the driver as 16 bit integers, and
+/// the callback gets one interleaved buffer per direction the device
+/// supports. The sample time counts the frames rendered so far.
+///
+/// Duplex devices get a block of silence written ahead, so output doesn't
+/// underrun while waiting for the first input.
+pub struct OssSession {
+    engine: Arc<RenderEngine<OssBackend>>,
+    device: OssDevice,
+    retry: RetryPolicy,
+    block_size: usize,
+    runner: Option<Runner>,
+}
+
+struct Runner {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl OssSession {
+    pub(crate) fn new(
+        retry: RetryPolicy,
+        sample_rate: f64,
+        device: OssDevice,
+        block_size: Option<usize>,
+    ) -> Result<Self, OssError> {
+        check_sample_rate(sample_rate)?;
+        let block_size = block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+        if block_size == 0 {
+            return Err(OssError::InvalidBufferSize(block_size));
+        }
+        if device.channels(Direction::Input) == 0 && device.channels(Direction::Output) == 0 {
+            return Err(OssError::Unsupported("devices without channels"));
+        }
+        device.store_sample_rate(sample_rate);
+
+        let engine = Arc::new(RenderEngine::new(sample_rate));
+        engine.resize_scratch(block_size, device.channels(Direction::Output));
+
+        Ok(OssSession {
+            engine,
+            device,
+            retry,
+            block_size,
+            runner: None,
+        })
+    }
+
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<OssBackend>>,
+    ) -> Result<(), OssError> {
+        self.engine.set_callback(callback);
+        self.run()
+    }
+
+    fn run(&mut self) -> Result<(), OssError> {
+        if self.runner.is_some() {
+            return Ok(());
+        }
+
+        let file = retry(self.retry, OssError::is_transient, || self.open())?;
+        self.configure(&file)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut pump = Pump::new(
+            self.engine.clone(),
+            file,
+            self.block_size,
+            self.device.channels(Direction::Input),
+            self.device.channels(Direction::Output),
+            stop.clone(),
+        );
+        let thread = thread::Builder::new()
+            .name("render_callback oss".to_owned())
+            .spawn(move || pump.run())
+            .map_err(OssError::Thread)?;
+
+        self.engine.valid.store(true, Ordering::Release);
+        self.runner = Some(Runner { stop, thread });
+
+        Ok(())
+    }
+
+    fn open(&self) -> Result<File, OssError> {
+        OpenOptions::new()
+            .read(self.device.channels(Direction::Input) > 0)
+            .write(self.device.channels(Direction::Output) > 0)
+            .open(self.device.path())
+            .map_err(|error| OssError::Open(self.device.path().to_owned(), error))
+    }
+
+    /// Sets the device up for the session. The order matters: drivers only
+    /// take the fragment size before anything else has been set.
+    fn configure(&self, file: &File) -> Result<(), OssError> {
+        let input_channels = self.device.channels(Direction::Input);
+        let output_channels = self.device.channels(Direction::Output);
+        let channels = input_channels.max(output_channels);
+
+        let fragment_bytes = (self.block_size * channels * 2).next_power_of_two();
+        let fragment = (FRAGMENTS << 16) | fragment_bytes.trailing_zeros() as libc::c_int;
+        // Only a hint, which drivers are free to round or ignore.
+        let _ = ioctl::call_with(
+            file,
+            ioctl::SNDCTL_DSP_SETFRAGMENT,
+            "SNDCTL_DSP_SETFRAGMENT",
+            fragment,
+        );
+
+        if input_channels > 0 && output_channels > 0 {
+            // FreeBSD devices are always duplex, and fail this.
+            let _ = ioctl::call(file, ioctl::SNDCTL_DSP_SETDUPLEX, "SNDCTL_DSP_SETDUPLEX");
+        }
+
+        let format = ioctl::call_with(
+            file,
+            ioctl::SNDCTL_DSP_SETFMT,
+            "SNDCTL_DSP_SETFMT",
+            ioctl::AFMT_S16_NE,
+        )?;
+        if format != ioctl::AFMT_S16_NE {
+            return Err(OssError::FormatRejected);
+        }
+
+        let actual = ioctl::call_with(
+            file,
+            ioctl::SNDCTL_DSP_CHANNELS,
+            "SNDCTL_DSP_CHANNELS",
+            channels as libc::c_int,
+        )?;
+        if actual as usize != channels {
+            return Err(OssError::ChannelsRejected {
+                requested: channels,
+                actual: actual as usize,
+            });
+        }
+
+        let sample_rate = self.engine.clock.sample_rate();
+        let actual = ioctl::call_with(
+            file,
+            ioctl::SNDCTL_DSP_SPEED,
+            "SNDCTL_DSP_SPEED",
+            sample_rate as libc::c_int,
+        )?;
+        if f64::from(actual) != sample_rate {
+            return Err(OssError::SampleRateRejected {
+                requested: sample_rate,
+                actual: f64::from(actual),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the thread was running.
+    fn halt(&mut self) -> bool {
+        match self.runner.take() {
+            Some(runner) => {
+                self.engine.valid.store(false, Ordering::Release);
+                runner.stop.store(true, Ordering::Release);
+                let _ = runner.thread.join();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Changes how many frames each callback gets, reopening the device.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), OssError> {
+        if frames == 0 {
+            return Err(OssError::InvalidBufferSize(frames));
+        }
+
+        let was_running = self.halt();
+        self.block_size = frames;
+        self.engine
+            .resize_scratch(frames, self.device.channels(Direction::Output));
+        self.engine.reset_timeline();
+
+        if was_running {
+            self.run()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        self.block_size
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        let side = |direction| match self.device.channels(direction) {
+            0 => vec![],
+            channels => vec![channels],
+        };
+
+        (side(Direction::Input), side(Direction::Output))
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.engine.set_output_policy(policy);
+    }
+
+    /// Blocks until the device has called the session at least once.
+    pub fn wait_until_running(&self, timeout: Duration) -> Result<(), OssError> {
+        let deadline = Instant::now() + timeout;
+
+        while self.engine.cycles.load(Ordering::Acquire) == 0 {
+            if Instant::now() >= deadline {
+                return Err(OssError::StartupTimeout(timeout));
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for OssSession {
+    fn drop(&mut self) {
+        self.halt();
+
+        // The thread has been joined, so this drops the callback here.
+        drop(self.engine.take_callback());
+    }
+}
+
+/// The session thread's state.
+struct Pump {
+    engine: Arc<RenderEngine<OssBackend>>,
+    file: File,
+    block_size: usize,
+    stop: Arc<AtomicBool>,
+    input_buffers: Vec<OwnedBuffer<f32>>,
+    output_buffers: Vec<OwnedBuffer<f32>>,
+    /// Samples on their way to or from the driver.
+    bytes: Vec<u8>,
+}
+
+impl Pump {
+    fn new(
+        engine: Arc<RenderEngine<OssBackend>>,
+        file: File,
+        block_size: usize,
+        input_channels: usize,
+        output_channels: usize,
+        stop: Arc<AtomicBool>,
+    ) -> Self {
+        let buffers = |channels| match channels {
+            0 => vec![],
+            channels => vec![buffer(block_size, channels)],
+        };
+
+        Pump {
+            engine,
+            file,
+            block_size,
+            stop,
+            input_buffers: buffers(input_channels),
+            output_buffers: buffers(output_channels),
+            bytes: vec![0; block_size * input_channels.max(output_channels) * 2],
+        }
+    }
+
+    fn run(&mut self) {
+        if let Err(error) = self.pump() {
+            let cause = match error.raw_os_error() {
+                Some(libc::ENXIO) | Some(libc::ENODEV) | Some(libc::EIO) => StopCause::DeviceDied,
+                _ => StopCause::Unknown,
+            };
+
+            self.engine.valid.store(false, Ordering::Release);
+            self.engine
+                .events
+                .push(SessionEvent::StoppedUnexpectedly { cause });
+        }
+
+        // Drops whatever output is still queued, so stopping is immediate.
+        let _ = ioctl::call(&self.file, ioctl::SNDCTL_DSP_RESET, "SNDCTL_DSP_RESET");
+    }
+
+    fn pump(&mut self) -> io::Result<()> {
+        if !self.input_buffers.is_empty() && !self.output_buffers.is_empty() {
+            let silence =
+                &mut self.bytes[..self.block_size * self.output_buffers[0].num_channels() * 2];
+            silence.fill(0);
+            self.file.write_all(silence)?;
+        }
+
+        let mut rendered = 0u64;
+        while !self.stop.load(Ordering::Acquire) {
+            if let Some(input) = self.input_buffers.first_mut() {
+                let samples = input.interleaved_frames_mut();
+                let bytes = &mut self.bytes[..samples.len() * 2];
+                self.file.read_exact(bytes)?;
+
+                for (sample, bytes) in samples.iter_mut().zip(bytes.chunks_exact(2)) {
+                    *sample = f32::from(i16::from_ne_bytes([bytes[0], bytes[1]])) / 32768.0;
+                }
+            }
+
+            self.engine.cycles.fetch_add(1, Ordering::Release);
+
+            // Only this thread renders, and the session joins it before
+            // touching the callback.
+            unsafe {
+                self.engine.render(
+                    &self.input_buffers,
+                    &mut self.output_buffers,
+                    self.block_size,
+                    CycleTimes::at(rendered as f64),
+                );
+            }
+            rendered += self.block_size as u64;
+
+            if let Some(output) = self.output_buffers.first() {
+                let samples = output.interleaved_frames();
+                let bytes = &mut self.bytes[..samples.len() * 2];
+
+                for (sample, bytes) in samples.iter().zip(bytes.chunks_exact_mut(2)) {
+                    let sample = (sample.clamp(-1.0, 1.0) * 32767.0) as i16;
+                    bytes.copy_from_slice(&sample.to_ne_bytes());
+                }
+                self.file.write_all(bytes)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn buffer(frames: usize, channels: usize) -> OwnedBuffer<f32> {
+    let mut buffer = OwnedBuffer::with_capacity(frames, channels);
+    buffer.reshape(frames, channels);
+    buffer
+}
+
+impl Session<OssBackend> for OssSession {
+    fn input_device(&self) -> Result<OssDevice, OssError> {
+        Ok(self.device.clone())
+    }
+
+    fn output_device(&self) -> Result<OssDevice, OssError> {
+        Ok(self.device.clone())
+    }
+
+    fn set_input_device(&mut self, _device: OssDevice) -> Result<(), OssError> {
+        Err(OssError::Unsupported("switching devices"))
+    }
+
+    fn set_output_device(&mut self, _device: OssDevice) -> Result<(), OssError> {
+        Err(OssError::Unsupported("switching devices"))
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, OssError> {
+        Ok(OssSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, OssError> {
+        let side = |direction| {
+            let channels = self.device.channels(direction);
+            if channels == 0 {
+                return Ok(vec![]);
+            }
+
+            Ok(vec![StreamMapping {
+                device_id: self.device.persistent_id()?,
+                first_device_channel: 0,
+                channels,
+            }])
+        };
+
+        Ok(ChannelMap {
+            input: side(Direction::Input)?,
+            output: side(Direction::Output)?,
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.engine.clock.clone()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<OssBackend>>) {
+        self.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.engine.is_bypassed()
+    }
+
+    fn tune_for_low_latency(&mut self, _target_ms: f64) -> Result<Option<LatencyTuning>, OssError> {
+        Err(OssError::Unsupported("tuning for low latency"))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, OssError> {
+        Err(OssError::Unsupported("starting at a host time"))
+    }
+
+    fn stop(&mut self) -> Result<(), OssError> {
+        self.halt();
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), OssError> {
+        if self.runner.is_none() {
+            self.engine.reset_timeline();
+        }
+        self.run()
+    }
+
+    fn is_running(&self) -> bool {
+        self.runner.is_some()
+    }
+}
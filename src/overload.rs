@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often handlers check for new overloads.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Counts the overloads of a session, bumped from the real-time thread or
+/// from wherever the platform reports them.
+pub(crate) struct OverloadCounter {
+    count: AtomicU64,
+}
+
+impl OverloadCounter {
+    pub fn new() -> Self {
+        OverloadCounter {
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Never blocks or allocates.
+    pub fn record(&self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+/// Calls `f` on a background thread with the number of overloads since the
+/// last call, whenever there were any, until the returned subscription is
+/// dropped. On targets without threads, like wasm32-unknown-unknown, `f` is
+/// never called.
+pub(crate) fn watch<F>(counter: Arc<OverloadCounter>, mut f: F) -> OverloadSubscription
+where
+    F: FnMut(u64) + Send + 'static,
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+
+    let thread = thread::Builder::new()
+        .name("render_callback overloads".to_owned())
+        .spawn(move || {
+            let mut seen = counter.count();
+
+            loop {
+                thread::park_timeout(POLL_INTERVAL);
+                if thread_stop.load(Ordering::Acquire) {
+                    break;
+                }
+
+                let count = counter.count();
+                if count != seen {
+                    f(count - seen);
+                    seen = count;
+                }
+            }
+        })
+        .ok();
+
+    OverloadSubscription { stop, thread }
+}
+
+pub struct OverloadSubscription {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for OverloadSubscription {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
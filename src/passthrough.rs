@@ -0,0 +1,28 @@
+use crate::routing::find_channel;
+use crate::traits::AudioBuffers;
+
+/// Copies input channels to the output channels with the same index, counting
+/// across all buffers, and silences outputs without a matching input. Used
+/// instead of the callback while a session is bypassed.
+pub(crate) fn passthrough<A: AudioBuffers>(input: &[A], output: &mut [A]) {
+    let mut channel = 0;
+    for buffer in output {
+        let stride = buffer.num_channels();
+        let samples = buffer.interleaved_frames_mut();
+        samples.fill(Default::default());
+
+        for offset in 0..stride {
+            if let Some((source, source_offset)) = find_channel(input, channel + offset) {
+                let source_stride = source.num_channels();
+                let frames = samples
+                    .chunks_mut(stride)
+                    .zip(source.interleaved_frames().chunks(source_stride));
+                for (to, from) in frames {
+                    to[offset] = from[source_offset];
+                }
+            }
+        }
+
+        channel += stride;
+    }
+}
@@ -0,0 +1,174 @@
+use crate::sample::Sample;
+
+/// One channel of an interleaved buffer, read in place. Use `copy_to` to
+/// get the channel as a contiguous slice, like into a buffer from
+/// `RenderContext::scratch`.
+#[derive(Clone, Copy)]
+pub struct Channel<'a, S> {
+    samples: &'a [S],
+    index: usize,
+    channels: usize,
+}
+
+/// One channel of an interleaved buffer, written in place.
+pub struct ChannelMut<'a, S> {
+    samples: &'a mut [S],
+    index: usize,
+    channels: usize,
+}
+
+/// Every channel of an interleaved buffer, in order.
+pub struct Channels<'a, S> {
+    samples: &'a [S],
+    next: usize,
+    channels: usize,
+}
+
+fn check_index(index: usize, channels: usize) {
+    assert!(
+        index < channels,
+        "Channel {} out of range for a buffer with {} channels",
+        index,
+        channels
+    );
+}
+
+impl<'a, S: Sample> Channel<'a, S> {
+    pub(crate) fn new(samples: &'a [S], index: usize, channels: usize) -> Self {
+        check_index(index, channels);
+        Channel {
+            samples,
+            index,
+            channels,
+        }
+    }
+
+    /// The number of frames.
+    pub fn len(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, frame: usize) -> Option<S> {
+        if frame >= self.len() {
+            return None;
+        }
+
+        Some(self.samples[frame * self.channels + self.index])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = S> + 'a {
+        self.samples[self.index..]
+            .iter()
+            .step_by(self.channels)
+            .copied()
+    }
+
+    /// Deinterleaves the channel into `out`, as many frames as fit, and
+    /// returns how many that was.
+    pub fn copy_to(&self, out: &mut [S]) -> usize {
+        let frames = self.len().min(out.len());
+        if self.channels == 1 {
+            out[..frames].copy_from_slice(&self.samples[..frames]);
+        } else {
+            for (to, from) in out[..frames].iter_mut().zip(self.iter()) {
+                *to = from;
+            }
+        }
+
+        frames
+    }
+}
+
+impl<'a, S: Sample> ChannelMut<'a, S> {
+    pub(crate) fn new(samples: &'a mut [S], index: usize, channels: usize) -> Self {
+        check_index(index, channels);
+        ChannelMut {
+            samples,
+            index,
+            channels,
+        }
+    }
+
+    /// The number of frames.
+    pub fn len(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, frame: usize) -> Option<S> {
+        if frame >= self.len() {
+            return None;
+        }
+
+        Some(self.samples[frame * self.channels + self.index])
+    }
+
+    /// Panics if `frame` is out of range.
+    pub fn set(&mut self, frame: usize, sample: S) {
+        assert!(frame < self.len(), "Frame {} out of range", frame);
+        self.samples[frame * self.channels + self.index] = sample;
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut S> + '_ {
+        self.samples[self.index..].iter_mut().step_by(self.channels)
+    }
+
+    pub fn fill(&mut self, sample: S) {
+        for to in self.iter_mut() {
+            *to = sample;
+        }
+    }
+
+    /// Interleaves `samples` into the channel, as many frames as fit, and
+    /// returns how many that was.
+    pub fn copy_from(&mut self, samples: &[S]) -> usize {
+        let frames = self.len().min(samples.len());
+        if self.channels == 1 {
+            self.samples[..frames].copy_from_slice(&samples[..frames]);
+        } else {
+            for (to, &from) in self.iter_mut().zip(&samples[..frames]) {
+                *to = from;
+            }
+        }
+
+        frames
+    }
+}
+
+impl<'a, S> Channels<'a, S> {
+    pub(crate) fn new(samples: &'a [S], channels: usize) -> Self {
+        Channels {
+            samples,
+            next: 0,
+            channels,
+        }
+    }
+}
+
+impl<'a, S: Sample> Iterator for Channels<'a, S> {
+    type Item = Channel<'a, S>;
+
+    fn next(&mut self) -> Option<Channel<'a, S>> {
+        if self.next >= self.channels {
+            return None;
+        }
+
+        let channel = Channel::new(self.samples, self.next, self.channels);
+        self.next += 1;
+        Some(channel)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.channels - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, S: Sample> ExactSizeIterator for Channels<'a, S> {}
@@ -0,0 +1,83 @@
+//! Which backend `CurrentPlatformBackend` is: the platform's own where it's
+//! been compiled in, and cpal elsewhere, like on Linux, or PortAudio without
+//! cpal. Without any of them, it doesn't exist.
+
+#[cfg(all(feature = "asio", target_os = "windows"))]
+pub use crate::asio::Backend as CurrentPlatformBackend;
+#[cfg(all(feature = "coreaudio", target_os = "macos"))]
+pub use crate::coreaudio::Backend as CurrentPlatformBackend;
+#[cfg(all(
+    feature = "cpal",
+    not(any(
+        all(feature = "coreaudio", target_os = "macos"),
+        all(feature = "ios", target_os = "ios"),
+        all(feature = "asio", target_os = "windows"),
+        all(
+            feature = "oss",
+            any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd")
+        ),
+        all(feature = "web", target_arch = "wasm32"),
+    ))
+))]
+pub use crate::cpal::Backend as CurrentPlatformBackend;
+#[cfg(all(feature = "ios", target_os = "ios"))]
+pub use crate::ios::Backend as CurrentPlatformBackend;
+#[cfg(all(
+    feature = "oss",
+    any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd")
+))]
+pub use crate::oss::Backend as CurrentPlatformBackend;
+#[cfg(all(
+    feature = "portaudio",
+    not(any(
+        feature = "cpal",
+        all(feature = "coreaudio", target_os = "macos"),
+        all(feature = "ios", target_os = "ios"),
+        all(feature = "asio", target_os = "windows"),
+        all(
+            feature = "oss",
+            any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd")
+        ),
+        all(feature = "web", target_arch = "wasm32"),
+    ))
+))]
+pub use crate::portaudio::Backend as CurrentPlatformBackend;
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+pub use crate::web::Backend as CurrentPlatformBackend;
+
+// Only exist along with `CurrentPlatformBackend`.
+#[cfg(any(
+    feature = "cpal",
+    feature = "portaudio",
+    all(feature = "coreaudio", target_os = "macos"),
+    all(feature = "ios", target_os = "ios"),
+    all(feature = "asio", target_os = "windows"),
+    all(
+        feature = "oss",
+        any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd")
+    ),
+    all(feature = "web", target_arch = "wasm32"),
+))]
+pub use self::types::*;
+
+#[cfg(any(
+    feature = "cpal",
+    feature = "portaudio",
+    all(feature = "coreaudio", target_os = "macos"),
+    all(feature = "ios", target_os = "ios"),
+    all(feature = "asio", target_os = "windows"),
+    all(
+        feature = "oss",
+        any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd")
+    ),
+    all(feature = "web", target_arch = "wasm32"),
+))]
+mod types {
+    use super::CurrentPlatformBackend;
+    use crate::traits::Backend;
+
+    pub type CurrentPlatformSession = <CurrentPlatformBackend as Backend>::Session;
+    pub type CurrentPlatformDevice = <CurrentPlatformBackend as Backend>::Device;
+    pub type CurrentPlatformError = <CurrentPlatformBackend as Backend>::Error;
+    pub type CurrentPlatformAudioBuffers = <CurrentPlatformBackend as Backend>::AudioBuffers;
+}
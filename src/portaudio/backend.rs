@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::processor::{processor_callback, Processor};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::PortAudioDevice;
+use super::error::PortAudioError;
+use super::ffi::{self, Library, PaDeviceIndex, PA_NO_DEVICE};
+use super::session::PortAudioSession;
+
+/// Talks to the system's PortAudio library, as a last resort on platforms
+/// nothing else here supports. Lists the devices of every host API
+/// PortAudio was built with.
+pub struct PortAudioBackend {
+    library: Arc<Library>,
+}
+
+impl PortAudioBackend {
+    fn device(&self, index: PaDeviceIndex) -> Result<PortAudioDevice, PortAudioError> {
+        if index == PA_NO_DEVICE {
+            return Err(PortAudioError::NoDefaultDevice);
+        }
+
+        PortAudioDevice::new(index, self.library.clone()).ok_or(PortAudioError::NoDefaultDevice)
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(
+        &self,
+        config: &SessionConfig<Self>,
+    ) -> Result<PortAudioSession, PortAudioError> {
+        if config.physical_format.is_some() {
+            return Err(PortAudioError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(PortAudioError::Unsupported("preroll"));
+        }
+        if config.routing.is_some() {
+            return Err(PortAudioError::Unsupported("routing matrices"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(PortAudioError::Unsupported(
+                "more than one device per direction",
+            ));
+        }
+
+        let mut session = PortAudioSession::new(
+            config.retry,
+            config.sample_rate,
+            config.input_device.clone(),
+            config.output_device.clone(),
+        );
+        if let Some(frames) = config.buffer_size {
+            session.set_buffer_size(frames)?;
+        }
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+impl Backend for PortAudioBackend {
+    type Session = PortAudioSession;
+    type Error = PortAudioError;
+    type Device = PortAudioDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    fn new() -> Result<Self, PortAudioError> {
+        Ok(PortAudioBackend {
+            library: Library::open()?,
+        })
+    }
+
+    fn is_available() -> bool {
+        Self::new().is_ok_and(|backend| {
+            backend
+                .all_devices()
+                .is_ok_and(|devices| !devices.is_empty())
+        })
+    }
+
+    fn all_devices(&self) -> Result<Vec<PortAudioDevice>, PortAudioError> {
+        let count = ffi::check("Pa_GetDeviceCount", unsafe { ffi::Pa_GetDeviceCount() })?;
+
+        Ok((0..count)
+            .filter_map(|index| PortAudioDevice::new(index, self.library.clone()))
+            .collect())
+    }
+
+    fn default_input_device(&self) -> Result<PortAudioDevice, PortAudioError> {
+        self.device(unsafe { ffi::Pa_GetDefaultInputDevice() })
+    }
+
+    fn default_output_device(&self) -> Result<PortAudioDevice, PortAudioError> {
+        self.device(unsafe { ffi::Pa_GetDefaultOutputDevice() })
+    }
+
+    /// PortAudio opens the devices at the session's rate in 32 bit float, so
+    /// a `physical_format` is refused, and so are preroll, routing and
+    /// additional devices. The clock master and application name are
+    /// ignored.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<PortAudioSession, PortAudioError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<PortAudioSession, PortAudioError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        if let Some(timeout) = config.startup_timeout {
+            session.wait_until_running(timeout)?;
+        }
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<PortAudioSession, PortAudioError> {
+        Err(PortAudioError::Unsupported("process taps"))
+    }
+}
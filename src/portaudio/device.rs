@@ -0,0 +1,164 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use crate::direction::Direction;
+use crate::traits::Device;
+
+use super::backend::PortAudioBackend;
+use super::error::PortAudioError;
+use super::ffi::{self, Library, PaDeviceIndex, PaTime};
+
+/// A device of one of PortAudio's host APIs, like ALSA or WASAPI. The same
+/// sound card often shows up once per host API.
+///
+/// Devices compare and order by host API and name. PortAudio's device
+/// indices are only good until it's terminated, so they aren't used to
+/// identify devices.
+#[derive(Clone)]
+pub struct PortAudioDevice {
+    index: PaDeviceIndex,
+    id: String,
+    name: String,
+    host_api: String,
+    inputs: usize,
+    outputs: usize,
+    default_sample_rate: f64,
+    /// PortAudio's suggested latency for interactive use, in seconds, for
+    /// input and output.
+    low_latency: (PaTime, PaTime),
+    _library: Arc<Library>,
+}
+
+impl PortAudioDevice {
+    /// Looks up the device at `index`, or returns None if there is none.
+    pub(super) fn new(index: PaDeviceIndex, library: Arc<Library>) -> Option<Self> {
+        // The info stays valid until PortAudio is terminated, which `library`
+        // holds off.
+        let info = unsafe { ffi::Pa_GetDeviceInfo(index).as_ref()? };
+        let name = unsafe { ffi::string(info.name) };
+        let host_api = unsafe {
+            ffi::Pa_GetHostApiInfo(info.host_api)
+                .as_ref()
+                .map_or_else(String::new, |host_api| ffi::string(host_api.name))
+        };
+
+        Some(PortAudioDevice {
+            index,
+            id: format!("{}:{}", host_api, name),
+            name,
+            host_api,
+            inputs: info.max_input_channels.max(0) as usize,
+            outputs: info.max_output_channels.max(0) as usize,
+            default_sample_rate: info.default_sample_rate,
+            low_latency: (
+                info.default_low_input_latency,
+                info.default_low_output_latency,
+            ),
+            _library: library,
+        })
+    }
+
+    /// The name of the host API the device belongs to, like `ALSA`.
+    pub fn host_api(&self) -> &str {
+        &self.host_api
+    }
+
+    pub(crate) fn index(&self) -> PaDeviceIndex {
+        self.index
+    }
+
+    pub(crate) fn channels(&self, direction: Direction) -> usize {
+        match direction {
+            Direction::Input => self.inputs,
+            Direction::Output => self.outputs,
+        }
+    }
+
+    pub(crate) fn low_latency(&self, direction: Direction) -> PaTime {
+        match direction {
+            Direction::Input => self.low_latency.0,
+            Direction::Output => self.low_latency.1,
+        }
+    }
+}
+
+impl fmt::Debug for PortAudioDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PortAudioDevice").field(&self.id).finish()
+    }
+}
+
+impl PartialEq for PortAudioDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for PortAudioDevice {}
+
+impl Hash for PortAudioDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl PartialOrd for PortAudioDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PortAudioDevice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl Device<PortAudioBackend> for PortAudioDevice {
+    fn num_inputs(&self) -> Result<usize, PortAudioError> {
+        Ok(self.inputs)
+    }
+
+    fn num_outputs(&self) -> Result<usize, PortAudioError> {
+        Ok(self.outputs)
+    }
+
+    fn name(&self) -> Result<String, PortAudioError> {
+        Ok(self.name.clone())
+    }
+
+    /// The host API's name and the device's, like `ALSA:default`.
+    fn persistent_id(&self) -> Result<String, PortAudioError> {
+        Ok(self.id.clone())
+    }
+
+    fn is_default_input(&self) -> Result<bool, PortAudioError> {
+        Ok(unsafe { ffi::Pa_GetDefaultInputDevice() } == self.index)
+    }
+
+    fn is_default_output(&self) -> Result<bool, PortAudioError> {
+        Ok(unsafe { ffi::Pa_GetDefaultOutputDevice() } == self.index)
+    }
+
+    /// PortAudio opens streams at whatever rate they ask for, so sessions
+    /// pick their own rate instead.
+    fn set_nominal_sample_rate(&mut self, _sample_rate: f64) -> Result<(), PortAudioError> {
+        Err(PortAudioError::Unsupported(
+            "changing a device's sample rate outside of a session",
+        ))
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), PortAudioError> {
+        Err(PortAudioError::Unsupported("selecting clock sources"))
+    }
+
+    fn nominal_sample_rate(&self) -> Result<f64, PortAudioError> {
+        Ok(self.default_sample_rate)
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, PortAudioError> {
+        self.nominal_sample_rate()
+    }
+}
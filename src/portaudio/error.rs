@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use super::ffi::{
+    PA_BAD_IO_DEVICE_COMBINATION, PA_DEVICE_UNAVAILABLE, PA_INVALID_CHANNEL_COUNT,
+    PA_INVALID_DEVICE, PA_INVALID_SAMPLE_RATE, PA_SAMPLE_FORMAT_NOT_SUPPORTED,
+};
+
+#[derive(Debug)]
+pub enum PortAudioError {
+    /// A PortAudio call failed, with its error code and PortAudio's text
+    /// for it.
+    PortAudio {
+        call: &'static str,
+        code: i32,
+        message: String,
+    },
+    /// PortAudio has no default device in the direction one was asked for.
+    NoDefaultDevice,
+    /// The session was started, but PortAudio never called it.
+    StartupTimeout(Duration),
+    /// The operation or session option has no PortAudio equivalent.
+    Unsupported(&'static str),
+}
+
+impl PortAudioError {
+    /// Whether the error is expected to clear up by itself, like the device
+    /// being busy.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            PortAudioError::PortAudio {
+                code: PA_DEVICE_UNAVAILABLE,
+                ..
+            }
+        )
+    }
+}
+
+impl fmt::Display for PortAudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortAudioError::PortAudio {
+                call,
+                code,
+                message,
+            } => write!(f, "{} failed: {} ({})", call, message, code),
+            PortAudioError::NoDefaultDevice => write!(f, "PortAudio has no default device"),
+            PortAudioError::StartupTimeout(timeout) => {
+                write!(
+                    f,
+                    "PortAudio did not start the session within {:?}",
+                    timeout
+                )
+            }
+            PortAudioError::Unsupported(what) => {
+                write!(f, "Not supported by PortAudio: {}", what)
+            }
+        }
+    }
+}
+
+impl Error for PortAudioError {}
+
+impl From<PortAudioError> for crate::Error {
+    fn from(error: PortAudioError) -> Self {
+        let kind = match &error {
+            PortAudioError::PortAudio { code, .. } => match *code {
+                PA_INVALID_DEVICE => Self::DeviceNotFound,
+                PA_DEVICE_UNAVAILABLE => Self::DeviceBusy,
+                PA_INVALID_CHANNEL_COUNT
+                | PA_INVALID_SAMPLE_RATE
+                | PA_SAMPLE_FORMAT_NOT_SUPPORTED
+                | PA_BAD_IO_DEVICE_COMBINATION => Self::FormatNotSupported,
+                _ => Self::Backend,
+            },
+            PortAudioError::NoDefaultDevice => Self::DeviceNotFound,
+            PortAudioError::StartupTimeout(_) => Self::StartupTimeout,
+            PortAudioError::Unsupported(_) => Self::Unsupported,
+        };
+
+        kind(Box::new(error))
+    }
+}
@@ -0,0 +1,173 @@
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_double, c_int, c_ulong, c_void};
+use std::sync::{Arc, Mutex};
+
+use super::error::PortAudioError;
+
+// The parts of <portaudio.h> (v19) the backend uses. There's no maintained
+// crate for them, so they're declared here against the system library.
+
+pub(super) type PaError = c_int;
+pub(super) type PaDeviceIndex = c_int;
+pub(super) type PaHostApiIndex = c_int;
+pub(super) type PaTime = c_double;
+pub(super) type PaSampleFormat = c_ulong;
+pub(super) type PaStreamFlags = c_ulong;
+pub(super) type PaStreamCallbackFlags = c_ulong;
+pub(super) type PaStream = c_void;
+
+pub(super) const PA_NO_DEVICE: PaDeviceIndex = -1;
+pub(super) const PA_FLOAT32: PaSampleFormat = 0x0000_0001;
+pub(super) const PA_FRAMES_PER_BUFFER_UNSPECIFIED: c_ulong = 0;
+pub(super) const PA_NO_FLAG: PaStreamFlags = 0;
+pub(super) const PA_INPUT_OVERFLOW: PaStreamCallbackFlags = 0x0000_0002;
+pub(super) const PA_OUTPUT_UNDERFLOW: PaStreamCallbackFlags = 0x0000_0004;
+pub(super) const PA_CONTINUE: c_int = 0;
+
+pub(super) const PA_INVALID_CHANNEL_COUNT: PaError = -9998;
+pub(super) const PA_INVALID_SAMPLE_RATE: PaError = -9997;
+pub(super) const PA_INVALID_DEVICE: PaError = -9996;
+pub(super) const PA_SAMPLE_FORMAT_NOT_SUPPORTED: PaError = -9994;
+pub(super) const PA_BAD_IO_DEVICE_COMBINATION: PaError = -9993;
+pub(super) const PA_DEVICE_UNAVAILABLE: PaError = -9985;
+
+#[repr(C)]
+pub(super) struct PaDeviceInfo {
+    pub struct_version: c_int,
+    pub name: *const c_char,
+    pub host_api: PaHostApiIndex,
+    pub max_input_channels: c_int,
+    pub max_output_channels: c_int,
+    pub default_low_input_latency: PaTime,
+    pub default_low_output_latency: PaTime,
+    pub default_high_input_latency: PaTime,
+    pub default_high_output_latency: PaTime,
+    pub default_sample_rate: c_double,
+}
+
+#[repr(C)]
+pub(super) struct PaHostApiInfo {
+    pub struct_version: c_int,
+    pub type_id: c_int,
+    pub name: *const c_char,
+    pub device_count: c_int,
+    pub default_input_device: PaDeviceIndex,
+    pub default_output_device: PaDeviceIndex,
+}
+
+#[repr(C)]
+pub(super) struct PaStreamParameters {
+    pub device: PaDeviceIndex,
+    pub channel_count: c_int,
+    pub sample_format: PaSampleFormat,
+    pub suggested_latency: PaTime,
+    pub host_api_specific_stream_info: *mut c_void,
+}
+
+#[repr(C)]
+pub(super) struct PaStreamCallbackTimeInfo {
+    pub input_buffer_adc_time: PaTime,
+    pub current_time: PaTime,
+    pub output_buffer_dac_time: PaTime,
+}
+
+#[repr(C)]
+pub(super) struct PaStreamInfo {
+    pub struct_version: c_int,
+    pub input_latency: PaTime,
+    pub output_latency: PaTime,
+    pub sample_rate: c_double,
+}
+
+pub(super) type PaStreamCallback = extern "C" fn(
+    input: *const c_void,
+    output: *mut c_void,
+    frame_count: c_ulong,
+    time_info: *const PaStreamCallbackTimeInfo,
+    status_flags: PaStreamCallbackFlags,
+    user_data: *mut c_void,
+) -> c_int;
+
+pub(super) type PaStreamFinishedCallback = extern "C" fn(user_data: *mut c_void);
+
+#[link(name = "portaudio")]
+extern "C" {
+    fn Pa_Initialize() -> PaError;
+    fn Pa_Terminate() -> PaError;
+    fn Pa_GetErrorText(error: PaError) -> *const c_char;
+
+    pub(super) fn Pa_GetDeviceCount() -> PaDeviceIndex;
+    pub(super) fn Pa_GetDefaultInputDevice() -> PaDeviceIndex;
+    pub(super) fn Pa_GetDefaultOutputDevice() -> PaDeviceIndex;
+    pub(super) fn Pa_GetDeviceInfo(device: PaDeviceIndex) -> *const PaDeviceInfo;
+    pub(super) fn Pa_GetHostApiInfo(host_api: PaHostApiIndex) -> *const PaHostApiInfo;
+
+    pub(super) fn Pa_OpenStream(
+        stream: *mut *mut PaStream,
+        input_parameters: *const PaStreamParameters,
+        output_parameters: *const PaStreamParameters,
+        sample_rate: c_double,
+        frames_per_buffer: c_ulong,
+        stream_flags: PaStreamFlags,
+        stream_callback: Option<PaStreamCallback>,
+        user_data: *mut c_void,
+    ) -> PaError;
+    pub(super) fn Pa_SetStreamFinishedCallback(
+        stream: *mut PaStream,
+        stream_finished_callback: Option<PaStreamFinishedCallback>,
+    ) -> PaError;
+    pub(super) fn Pa_StartStream(stream: *mut PaStream) -> PaError;
+    pub(super) fn Pa_StopStream(stream: *mut PaStream) -> PaError;
+    pub(super) fn Pa_CloseStream(stream: *mut PaStream) -> PaError;
+    pub(super) fn Pa_GetStreamInfo(stream: *mut PaStream) -> *const PaStreamInfo;
+}
+
+/// Turns a negative return value into an error naming `call`.
+pub(super) fn check(call: &'static str, result: c_int) -> Result<c_int, PortAudioError> {
+    if result >= 0 {
+        return Ok(result);
+    }
+
+    // Error texts are static strings, valid even when not initialized.
+    let message = unsafe { string(Pa_GetErrorText(result)) };
+    Err(PortAudioError::PortAudio {
+        call,
+        code: result,
+        message,
+    })
+}
+
+/// Copies a C string owned by PortAudio, which may be null.
+pub(super) unsafe fn string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+/// Keeps PortAudio initialized while alive. PortAudio counts how often it's
+/// been initialized, so every backend holds its own, and devices hold their
+/// backend's, since their indices mean nothing once it's terminated.
+pub(super) struct Library(());
+
+/// PortAudio isn't thread safe, least of all while initializing.
+static LIFECYCLE: Mutex<()> = Mutex::new(());
+
+impl Library {
+    pub fn open() -> Result<Arc<Self>, PortAudioError> {
+        let _lock = LIFECYCLE.lock().unwrap_or_else(|e| e.into_inner());
+        check("Pa_Initialize", unsafe { Pa_Initialize() })?;
+
+        Ok(Arc::new(Library(())))
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        let _lock = LIFECYCLE.lock().unwrap_or_else(|e| e.into_inner());
+        unsafe {
+            Pa_Terminate();
+        }
+    }
+}
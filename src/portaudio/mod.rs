@@ -0,0 +1,10 @@
+mod backend;
+mod device;
+mod error;
+mod ffi;
+mod session;
+
+pub use backend::PortAudioBackend as Backend;
+pub use device::PortAudioDevice;
+pub use error::PortAudioError;
+pub use session::PortAudioSession;
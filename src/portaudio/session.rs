@@ -0,0 +1,565 @@
+use std::os::raw::{c_int, c_ulong, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::CycleTimes;
+use crate::deadline::DeadlineHistogram;
+use crate::direction::Direction;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::{Events, SessionEvent, StopCause};
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::retry::{retry, RetryPolicy};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Device, RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+
+use super::backend::PortAudioBackend;
+use super::device::PortAudioDevice;
+use super::error::PortAudioError;
+use super::ffi::{
+    self, PaStream, PaStreamCallbackFlags, PaStreamCallbackTimeInfo, PaStreamParameters,
+    PA_CONTINUE, PA_FLOAT32, PA_FRAMES_PER_BUFFER_UNSPECIFIED, PA_INPUT_OVERFLOW, PA_NO_FLAG,
+    PA_OUTPUT_UNDERFLOW,
+};
+
+/// How long each buffer size is tried out for when tuning for low latency.
+const LATENCY_TRIAL_PERIOD: Duration = Duration::from_secs(1);
+
+/// The buffer sizes tried when tuning for low latency. PortAudio doesn't
+/// say which sizes the host API supports, and adapts the others itself.
+const MIN_TUNED_BUFFER_SIZE: usize = 16;
+const MAX_TUNED_BUFFER_SIZE: usize = 4096;
+
+/// The most frames a callback gets when PortAudio picks the buffer size.
+/// Larger host buffers are rendered in several callbacks.
+const DEFAULT_MAX_FRAMES: usize = 4096;
+
+/// One PortAudio stream in 32 bit float, duplex if the input device has any
+/// input channels and output only otherwise. PortAudio can only open duplex
+/// streams on two devices of the same host API. Each callback gets one
+/// buffer per direction, with all of the device's channels.
+///
+/// The sample time counts the frames rendered since the stream was opened.
+pub struct PortAudioSession {
+    engine: Arc<RenderEngine<PortAudioBackend>>,
+    input: PortAudioDevice,
+    output: PortAudioDevice,
+    sample_rate: f64,
+    /// Frames per callback, or None to let PortAudio pick.
+    buffer_size: Option<usize>,
+    /// How opening the stream is retried when starting.
+    retry: RetryPolicy,
+    /// Underflows and overflows PortAudio reported.
+    xruns: Arc<AtomicU64>,
+    stream: Option<Stream>,
+}
+
+struct Stream {
+    raw: *mut PaStream,
+    /// Borrowed by the stream callback until the stream is closed.
+    process: Box<Process>,
+}
+
+// PortAudio streams can be stopped and closed from any thread.
+unsafe impl Send for Stream {}
+
+impl PortAudioSession {
+    pub(crate) fn new(
+        retry: RetryPolicy,
+        sample_rate: f64,
+        input: PortAudioDevice,
+        output: PortAudioDevice,
+    ) -> Self {
+        let engine = Arc::new(RenderEngine::new(sample_rate));
+
+        PortAudioSession {
+            engine,
+            input,
+            output,
+            sample_rate,
+            buffer_size: None,
+            retry,
+            xruns: Arc::new(AtomicU64::new(0)),
+            stream: None,
+        }
+    }
+
+    /// Opens the stream, retrying while the devices are busy, and starts it
+    /// with `callback`.
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<PortAudioBackend>>,
+    ) -> Result<(), PortAudioError> {
+        self.engine.set_callback(callback);
+        retry(self.retry, PortAudioError::is_transient, || self.run())
+    }
+
+    fn layout(&self) -> (usize, usize) {
+        (
+            self.input.channels(Direction::Input),
+            self.output.channels(Direction::Output),
+        )
+    }
+
+    /// Opens and starts the stream, unless it already is.
+    fn run(&mut self) -> Result<(), PortAudioError> {
+        if self.stream.is_some() {
+            return Ok(());
+        }
+
+        let (input_channels, output_channels) = self.layout();
+        if output_channels == 0 {
+            return Err(PortAudioError::Unsupported(
+                "output devices without outputs",
+            ));
+        }
+        let max_frames = self.max_frames_per_callback();
+        self.engine
+            .resize_scratch(max_frames, input_channels.max(output_channels));
+
+        let parameters =
+            |device: &PortAudioDevice, direction, channels: usize| PaStreamParameters {
+                device: device.index(),
+                channel_count: channels as c_int,
+                sample_format: PA_FLOAT32,
+                suggested_latency: device.low_latency(direction),
+                host_api_specific_stream_info: ptr::null_mut(),
+            };
+        let input = parameters(&self.input, Direction::Input, input_channels);
+        let output = parameters(&self.output, Direction::Output, output_channels);
+
+        let mut process = Box::new(Process {
+            engine: self.engine.clone(),
+            xruns: self.xruns.clone(),
+            input_buffers: vec![buffer(max_frames, input_channels)],
+            output_buffers: vec![buffer(max_frames, output_channels)],
+            max_frames,
+            sample_time: 0,
+            stopping: AtomicBool::new(false),
+        });
+        let frames_per_buffer = self
+            .buffer_size
+            .map_or(PA_FRAMES_PER_BUFFER_UNSPECIFIED, |frames| frames as c_ulong);
+
+        let mut raw = ptr::null_mut();
+        // The process box outlives the stream, which is closed before it's
+        // dropped.
+        ffi::check("Pa_OpenStream", unsafe {
+            ffi::Pa_OpenStream(
+                &mut raw,
+                if input_channels > 0 {
+                    &input
+                } else {
+                    ptr::null()
+                },
+                &output,
+                self.sample_rate,
+                frames_per_buffer,
+                PA_NO_FLAG,
+                Some(stream_callback),
+                &mut *process as *mut Process as *mut c_void,
+            )
+        })?;
+
+        // PortAudio ends the stream by itself when the host API fails, like
+        // when the device goes away.
+        unsafe {
+            ffi::Pa_SetStreamFinishedCallback(raw, Some(stream_finished_callback));
+        }
+
+        self.engine.reset_timeline();
+        self.engine.valid.store(true, Ordering::Release);
+        if let Err(error) = ffi::check("Pa_StartStream", unsafe { ffi::Pa_StartStream(raw) }) {
+            self.engine.valid.store(false, Ordering::Release);
+            unsafe {
+                ffi::Pa_CloseStream(raw);
+            }
+            return Err(error);
+        }
+
+        self.stream = Some(Stream { raw, process });
+
+        Ok(())
+    }
+
+    /// Returns whether the stream was running.
+    fn halt(&mut self) -> Result<bool, PortAudioError> {
+        match self.stream.take() {
+            Some(stream) => {
+                stream.process.stopping.store(true, Ordering::Release);
+                self.engine.valid.store(false, Ordering::Release);
+                // Stopping waits for the last callback to return, so the
+                // process box can go along with the stream.
+                let stopped =
+                    ffi::check("Pa_StopStream", unsafe { ffi::Pa_StopStream(stream.raw) });
+                unsafe {
+                    ffi::Pa_CloseStream(stream.raw);
+                }
+                stopped?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Opens a new stream for the current devices and buffer size.
+    fn rebuild(&mut self) -> Result<(), PortAudioError> {
+        if self.halt()? {
+            self.run()?;
+        }
+
+        Ok(())
+    }
+
+    /// Asks PortAudio for `frames` frames per callback, reopening the stream
+    /// if it's running.
+    pub fn set_buffer_size(&mut self, frames: usize) -> Result<(), PortAudioError> {
+        self.buffer_size = Some(frames);
+        self.rebuild()
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        self.buffer_size.unwrap_or(DEFAULT_MAX_FRAMES)
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        let (input, output) = self.layout();
+        (vec![input], vec![output])
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.engine.set_output_policy(policy);
+    }
+
+    /// Blocks until PortAudio has called the session at least once.
+    pub fn wait_until_running(&self, timeout: Duration) -> Result<(), PortAudioError> {
+        let deadline = Instant::now() + timeout;
+
+        while self.engine.cycles.load(Ordering::Acquire) == 0 {
+            if Instant::now() >= deadline {
+                return Err(PortAudioError::StartupTimeout(timeout));
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+
+    /// The input and output latency PortAudio settled on for the running
+    /// stream, in frames.
+    fn stream_latency(&self) -> Option<(usize, usize)> {
+        let stream = self.stream.as_ref()?;
+        let info = unsafe { ffi::Pa_GetStreamInfo(stream.raw).as_ref()? };
+
+        Some((
+            (info.input_latency * self.sample_rate).round() as usize,
+            (info.output_latency * self.sample_rate).round() as usize,
+        ))
+    }
+}
+
+impl Drop for PortAudioSession {
+    fn drop(&mut self) {
+        let _ = self.halt();
+
+        // The stream is closed, so this drops the render callback here
+        // rather than on the real-time thread.
+        drop(self.engine.take_callback());
+    }
+}
+
+/// The stream callback's side of the session.
+struct Process {
+    engine: Arc<RenderEngine<PortAudioBackend>>,
+    xruns: Arc<AtomicU64>,
+    input_buffers: Vec<OwnedBuffer<f32>>,
+    output_buffers: Vec<OwnedBuffer<f32>>,
+    max_frames: usize,
+    sample_time: u64,
+    /// Set before the session stops the stream, so that the stream finishing
+    /// isn't reported as unexpected.
+    stopping: AtomicBool,
+}
+
+impl Process {
+    fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        let input_channels = self.input_buffers[0].num_channels();
+        let output_channels = self.output_buffers[0].num_channels();
+        let mut input = input.chunks(self.max_frames * input_channels.max(1));
+
+        for chunk in output.chunks_mut(self.max_frames * output_channels) {
+            self.engine.cycles.fetch_add(1, Ordering::Release);
+            let frames = chunk.len() / output_channels;
+            self.input_buffers[0].reshape(frames, input_channels);
+            self.output_buffers[0].reshape(frames, output_channels);
+
+            let buffer = self.input_buffers[0].interleaved_frames_mut();
+            match input.next() {
+                Some(samples) if samples.len() == buffer.len() => buffer.copy_from_slice(samples),
+                _ => buffer.fill(0.0),
+            }
+
+            unsafe {
+                self.engine.render(
+                    &self.input_buffers,
+                    &mut self.output_buffers,
+                    frames,
+                    CycleTimes::at(self.sample_time as f64),
+                );
+            }
+            self.sample_time += frames as u64;
+
+            chunk.copy_from_slice(self.output_buffers[0].interleaved_frames());
+        }
+    }
+}
+
+extern "C" fn stream_callback(
+    input: *const c_void,
+    output: *mut c_void,
+    frame_count: c_ulong,
+    _time_info: *const PaStreamCallbackTimeInfo,
+    status_flags: PaStreamCallbackFlags,
+    user_data: *mut c_void,
+) -> c_int {
+    // The session keeps the process alive until the stream is closed, and
+    // PortAudio never runs two callbacks of a stream at once.
+    let process = unsafe { &mut *(user_data as *mut Process) };
+
+    if status_flags & (PA_INPUT_OVERFLOW | PA_OUTPUT_UNDERFLOW) != 0 {
+        process.xruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let frames = frame_count as usize;
+    let input_channels = process.input_buffers[0].num_channels();
+    let output_channels = process.output_buffers[0].num_channels();
+    let input = if input.is_null() {
+        &[][..]
+    } else {
+        unsafe { slice::from_raw_parts(input as *const f32, frames * input_channels) }
+    };
+    let output = unsafe { slice::from_raw_parts_mut(output as *mut f32, frames * output_channels) };
+
+    process.process(input, output);
+
+    PA_CONTINUE
+}
+
+extern "C" fn stream_finished_callback(user_data: *mut c_void) {
+    // Called before the stream is closed, which the process outlives.
+    let process = unsafe { &*(user_data as *const Process) };
+
+    if !process.stopping.load(Ordering::Acquire) {
+        process.engine.valid.store(false, Ordering::Release);
+        process
+            .engine
+            .events
+            .push(SessionEvent::StoppedUnexpectedly {
+                cause: StopCause::Unknown,
+            });
+    }
+}
+
+fn buffer(frames: usize, channels: usize) -> OwnedBuffer<f32> {
+    let mut buffer = OwnedBuffer::with_capacity(frames, channels);
+    buffer.reshape(frames, channels);
+    buffer
+}
+
+impl Session<PortAudioBackend> for PortAudioSession {
+    fn input_device(&self) -> Result<PortAudioDevice, PortAudioError> {
+        Ok(self.input.clone())
+    }
+
+    fn output_device(&self) -> Result<PortAudioDevice, PortAudioError> {
+        Ok(self.output.clone())
+    }
+
+    fn set_input_device(&mut self, device: PortAudioDevice) -> Result<(), PortAudioError> {
+        self.input = device;
+        self.rebuild()
+    }
+
+    fn set_output_device(&mut self, device: PortAudioDevice) -> Result<(), PortAudioError> {
+        self.output = device;
+        self.rebuild()
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, PortAudioError> {
+        Ok(PortAudioSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, PortAudioError> {
+        let (input_channels, output_channels) = self.layout();
+        let side = |device: &PortAudioDevice, channels: usize| {
+            Ok(vec![StreamMapping {
+                device_id: device.persistent_id()?,
+                first_device_channel: 0,
+                channels,
+            }])
+        };
+
+        Ok(ChannelMap {
+            input: side(&self.input, input_channels)?,
+            output: side(&self.output, output_channels)?,
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.engine.clock.clone()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<PortAudioBackend>>) {
+        self.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.engine.is_bypassed()
+    }
+
+    /// The latencies PortAudio reports for the stream, or one buffer while
+    /// the session is stopped.
+    fn input_latency_frames(&self) -> Result<usize, PortAudioError> {
+        Ok(self
+            .stream_latency()
+            .map_or(self.max_frames_per_callback(), |(input, _)| input))
+    }
+
+    fn output_latency_frames(&self) -> Result<usize, PortAudioError> {
+        Ok(self
+            .stream_latency()
+            .map_or(self.max_frames_per_callback(), |(_, output)| output))
+    }
+
+    /// Tries fixed buffer sizes, watching for the underflows and overflows
+    /// PortAudio reports. The latencies are the ones PortAudio reports for
+    /// the stream, which include the host API's own buffering.
+    fn tune_for_low_latency(
+        &mut self,
+        target_ms: f64,
+    ) -> Result<Option<LatencyTuning>, PortAudioError> {
+        let target_frames = (target_ms * self.sample_rate / 1000.0) as usize;
+
+        let mut candidates = Vec::new();
+        let mut size = MIN_TUNED_BUFFER_SIZE;
+        while size <= MAX_TUNED_BUFFER_SIZE {
+            candidates.push(size);
+            size *= 2;
+        }
+
+        // Start from the largest size within the target: anything smaller
+        // only adds risk of dropouts.
+        let first = candidates
+            .iter()
+            .rposition(|&size| 2 * size <= target_frames)
+            .unwrap_or(0);
+
+        let original = self.buffer_size;
+        let mut stable = false;
+        let mut buffer_size = PortAudioSession::max_frames_per_callback(self);
+        for &size in &candidates[first..] {
+            self.set_buffer_size(size)?;
+            buffer_size = size;
+
+            let before = self.xruns.load(Ordering::Relaxed);
+            thread::sleep(LATENCY_TRIAL_PERIOD);
+
+            if self.xruns.load(Ordering::Relaxed) == before {
+                stable = true;
+                break;
+            }
+        }
+
+        if !stable {
+            // Nothing ran cleanly, so leave the session as it was found.
+            self.buffer_size = original;
+            self.rebuild()?;
+            return Ok(None);
+        }
+
+        let (input_latency_frames, output_latency_frames) =
+            self.stream_latency().unwrap_or((buffer_size, buffer_size));
+
+        Ok(Some(LatencyTuning {
+            buffer_size,
+            input_latency_frames,
+            output_latency_frames,
+            sample_rate: self.sample_rate,
+        }))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, PortAudioError> {
+        Err(PortAudioError::Unsupported("starting at a host time"))
+    }
+
+    fn stop(&mut self) -> Result<(), PortAudioError> {
+        self.halt()?;
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), PortAudioError> {
+        retry(self.retry, PortAudioError::is_transient, || self.run())
+    }
+
+    fn is_running(&self) -> bool {
+        self.stream.is_some()
+    }
+}
@@ -0,0 +1,59 @@
+use crate::ring_buffer::{ring_buffer, Consumer, Producer};
+use crate::traits::{AudioBuffers, Backend, RenderCallback};
+
+/// Delays every output buffer by a fixed amount of audio, so that output
+/// rendered before the session started is played by the first device cycles.
+pub(crate) struct OutputDelay {
+    lines: Vec<(Producer, Consumer)>,
+}
+
+impl OutputDelay {
+    /// Room for `delay_frames` of primed output plus one more buffer of up
+    /// to `largest_buffer` frames, the most the device could ever ask for.
+    pub fn new(output_channels: &[usize], delay_frames: usize, largest_buffer: usize) -> Self {
+        OutputDelay {
+            lines: output_channels
+                .iter()
+                .map(|&channels| ring_buffer(((delay_frames + largest_buffer) * channels).max(1)))
+                .collect(),
+        }
+    }
+
+    /// Queues output rendered before the session started.
+    pub fn prime<A: AudioBuffers<Sample = f32>>(&mut self, output: &[A]) {
+        for ((producer, _), buffer) in self.lines.iter_mut().zip(output) {
+            producer.try_push(buffer.interleaved_frames());
+        }
+    }
+
+    /// Swaps the freshly rendered output for the oldest queued output. Buffers
+    /// that no longer match the layout the delay was set up for are passed
+    /// through untouched.
+    pub fn apply<A: AudioBuffers<Sample = f32>>(&mut self, output: &mut [A]) {
+        if self.lines.len() != output.len() {
+            return;
+        }
+
+        for ((producer, consumer), buffer) in self.lines.iter_mut().zip(output) {
+            if producer.try_push(buffer.interleaved_frames()) {
+                consumer.pop(buffer.interleaved_frames_mut());
+            }
+        }
+    }
+}
+
+pub(crate) fn delay_output<B: Backend + 'static>(
+    mut callback: Box<RenderCallback<B>>,
+    mut delay: OutputDelay,
+) -> Box<RenderCallback<B>>
+where
+    B::AudioBuffers: AudioBuffers<Sample = f32>,
+{
+    Box::new(move |ctx, input, output| {
+        callback(ctx, input, output);
+        // The delayed output usually isn't silent, so it has to be cleared
+        // here rather than after the delay.
+        ctx.take_output_silence(output);
+        delay.apply(output);
+    })
+}
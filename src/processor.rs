@@ -0,0 +1,41 @@
+use crate::context::RenderContext;
+use crate::traits::{Backend, RenderCallback};
+
+/// A higher level alternative to a bare render callback, for DSP objects that
+/// need to know the stream format up front.
+pub trait Processor<B: Backend>: Send {
+    /// Called on the control thread with the negotiated sample rate and the
+    /// largest number of frames a single `process` call will see, before the
+    /// session starts.
+    fn prepare(&mut self, sample_rate: f64, max_frames: usize);
+
+    /// Called on the control thread after `prepare` with the channel count of
+    /// each input and output buffer the callback will see, for processors
+    /// that need to allocate per buffer.
+    fn prepare_channels(&mut self, _input: &[usize], _output: &[usize]) {}
+
+    fn process(
+        &mut self,
+        input: &[B::AudioBuffers],
+        output: &mut [B::AudioBuffers],
+        ctx: &RenderContext<'_>,
+    );
+
+    /// Called on the real-time thread before processing a cycle that doesn't
+    /// follow on from the previous one. Must not allocate or block.
+    fn reset(&mut self);
+}
+
+pub(crate) fn processor_callback<B, P>(mut processor: P) -> Box<RenderCallback<B>>
+where
+    B: Backend,
+    P: Processor<B> + 'static,
+{
+    Box::new(move |ctx, input, output| {
+        if ctx.is_discontinuity() {
+            processor.reset();
+        }
+
+        processor.process(input, output, ctx);
+    })
+}
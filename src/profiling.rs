@@ -0,0 +1,90 @@
+#[cfg(feature = "profiling")]
+use std::time::Duration;
+
+#[cfg(feature = "profiling")]
+use crate::queue::Queue;
+#[cfg(feature = "profiling")]
+use crate::time;
+
+/// How many scopes a session keeps. Once full, the oldest are dropped to
+/// make room.
+#[cfg(feature = "profiling")]
+pub const PROFILE_CAPACITY: usize = 4096;
+
+/// A named scope timed inside the render callback.
+#[cfg(feature = "profiling")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileRecord {
+    pub name: &'static str,
+    /// The callback cycle the scope ran in, counting from the session's
+    /// first cycle.
+    pub cycle: u64,
+    /// Host times, see the `time` module.
+    pub start: u64,
+    pub end: u64,
+}
+
+#[cfg(feature = "profiling")]
+impl ProfileRecord {
+    pub fn duration(&self) -> Duration {
+        time::host_ticks_to_duration(self.end.saturating_sub(self.start))
+    }
+}
+
+/// The scopes recorded by a session, waiting to be taken.
+#[cfg(feature = "profiling")]
+pub(crate) struct Profiler {
+    records: Queue<ProfileRecord>,
+}
+
+#[cfg(feature = "profiling")]
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            records: Queue::new(PROFILE_CAPACITY),
+        }
+    }
+
+    /// Never blocks or allocates.
+    pub fn record(&self, record: ProfileRecord) {
+        if let Err(record) = self.records.push(record) {
+            self.records.pop();
+            let _ = self.records.push(record);
+        }
+    }
+
+    /// Removes and returns everything recorded so far, oldest first.
+    pub fn take(&self) -> Vec<ProfileRecord> {
+        std::iter::from_fn(|| self.records.pop()).collect()
+    }
+}
+
+/// Times the enclosing block until dropped, as returned by
+/// `RenderContext::scope`. Does nothing unless the `profiling` feature is
+/// enabled.
+pub struct ProfileScope<'a> {
+    #[cfg(feature = "profiling")]
+    pub(crate) profiler: Option<&'a Profiler>,
+    #[cfg(feature = "profiling")]
+    pub(crate) name: &'static str,
+    #[cfg(feature = "profiling")]
+    pub(crate) cycle: u64,
+    #[cfg(feature = "profiling")]
+    pub(crate) start: u64,
+    #[cfg(not(feature = "profiling"))]
+    pub(crate) _marker: std::marker::PhantomData<&'a ()>,
+}
+
+impl Drop for ProfileScope<'_> {
+    fn drop(&mut self) {
+        #[cfg(feature = "profiling")]
+        if let Some(profiler) = self.profiler {
+            profiler.record(ProfileRecord {
+                name: self.name,
+                cycle: self.cycle,
+                start: self.start,
+                end: time::host_time_now(),
+            });
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::config::SessionConfig;
+use crate::context::RenderContext;
+use crate::dropout::DropoutStats;
+use crate::processor::Processor;
+use crate::ring_buffer::{ring_buffer, Consumer, Producer};
+use crate::sample::Sample;
+use crate::traits::{AudioBuffers, Backend, Device, Session};
+use crate::wav::WavWriter;
+
+/// The ring buffer between the real-time and disk threads holds this many
+/// seconds of audio from the input device.
+const RING_BUFFER_SECONDS: usize = 4;
+
+/// Only the first this many input channels are recorded.
+pub const MAX_RECORDED_CHANNELS: usize = 256;
+
+/// Room for `RING_BUFFER_SECONDS` of every channel `input_device` records.
+/// If the session turns out to have more input channels than the device
+/// reports, the buffer holds proportionally less time.
+pub(crate) fn ring_buffer_len<B: Backend>(sample_rate: f64, input_device: &B::Device) -> usize {
+    let channels = input_device
+        .num_inputs()
+        .unwrap_or(0)
+        .clamp(1, MAX_RECORDED_CHANNELS);
+
+    sample_rate.ceil() as usize * RING_BUFFER_SECONDS * channels
+}
+
+pub(crate) const DISK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug)]
+pub enum RecorderError<E> {
+    Backend(E),
+    Io(io::Error),
+}
+
+impl<E: fmt::Display> fmt::Display for RecorderError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecorderError::Backend(e) => write!(f, "Audio backend error: {}", e),
+            RecorderError::Io(e) => write!(f, "Could not write recording: {}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RecorderError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RecorderError::Backend(e) => Some(e),
+            RecorderError::Io(e) => Some(e),
+        }
+    }
+}
+
+/// What ended up on disk once a recording is stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recording {
+    pub channels: usize,
+    pub frames_written: u64,
+    /// Frames the disk thread couldn't keep up with, or that arrived with a
+    /// different channel count than the recording started with.
+    pub overflowed_frames: u64,
+    /// Gaps in the device's own timeline, which never reached us at all.
+    pub dropouts: DropoutStats,
+}
+
+pub(crate) struct RecorderState {
+    pub sample_rate: AtomicU64,
+    pub channels: AtomicUsize,
+    pub overflowed_frames: AtomicU64,
+    pub stop: AtomicBool,
+}
+
+impl RecorderState {
+    pub fn new(sample_rate: f64) -> Arc<Self> {
+        Arc::new(RecorderState {
+            sample_rate: AtomicU64::new(sample_rate.to_bits()),
+            channels: AtomicUsize::new(0),
+            overflowed_frames: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+        })
+    }
+}
+
+/// Records every input channel of a device to a WAV file.
+///
+/// Audio is copied into a ring buffer on the real-time thread, and written to
+/// disk by a separate thread. Dropping the recorder without calling `stop`
+/// still finishes the file, but any error is lost.
+pub struct Recorder<B: Backend> {
+    session: Option<B::Session>,
+    state: Arc<RecorderState>,
+    disk_thread: Option<JoinHandle<io::Result<u64>>>,
+}
+
+impl<B: Backend> Recorder<B> {
+    /// Starts recording from `input_device` to a new file at `path`. The
+    /// default output device makes up the other half of the session, but is
+    /// only ever sent silence.
+    pub fn start(
+        backend: &B,
+        input_device: B::Device,
+        sample_rate: f64,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, RecorderError<B::Error>> {
+        let file = BufWriter::new(File::create(path).map_err(RecorderError::Io)?);
+        let output_device = backend
+            .default_output_device()
+            .map_err(RecorderError::Backend)?;
+
+        let state = RecorderState::new(sample_rate);
+        let (producer, consumer) = ring_buffer(ring_buffer_len::<B>(sample_rate, &input_device));
+        let started_at = SystemTime::now();
+
+        let session = backend
+            .start_session_with_processor(
+                SessionConfig::new(sample_rate, input_device, output_device),
+                RecorderProcessor::new(state.clone(), producer),
+            )
+            .map_err(RecorderError::Backend)?;
+
+        let disk_state = state.clone();
+        let disk_thread = thread::Builder::new()
+            .name("render_callback recorder".to_owned())
+            .spawn(move || write_to_disk(&disk_state, consumer, file, started_at))
+            .map_err(RecorderError::Io)?;
+
+        Ok(Recorder {
+            session: Some(session),
+            state,
+            disk_thread: Some(disk_thread),
+        })
+    }
+
+    pub fn session(&self) -> &B::Session {
+        self.session.as_ref().unwrap()
+    }
+
+    /// Frames lost so far because the disk thread fell behind.
+    pub fn overflowed_frames(&self) -> u64 {
+        self.state.overflowed_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn dropouts(&self) -> DropoutStats {
+        self.session().dropouts()
+    }
+
+    /// Stops the session, waits for all buffered audio to reach the disk and
+    /// finishes the file.
+    pub fn stop(mut self) -> Result<Recording, RecorderError<B::Error>> {
+        let dropouts = self.dropouts();
+        let frames_written = self.finish().map_err(RecorderError::Io)?;
+
+        Ok(Recording {
+            channels: self.state.channels.load(Ordering::Acquire),
+            frames_written,
+            overflowed_frames: self.overflowed_frames(),
+            dropouts,
+        })
+    }
+
+    fn finish(&mut self) -> io::Result<u64> {
+        drop(self.session.take());
+        self.state.stop.store(true, Ordering::Release);
+
+        match self.disk_thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("Disk thread panicked"))),
+            None => Ok(0),
+        }
+    }
+}
+
+impl<B: Backend> Drop for Recorder<B> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Interleaves every input stream into one ring buffer, and sends silence to
+/// the outputs.
+pub(crate) struct RecorderProcessor {
+    state: Arc<RecorderState>,
+    producer: Producer,
+    scratch: Vec<f32>,
+}
+
+impl RecorderProcessor {
+    pub fn new(state: Arc<RecorderState>, producer: Producer) -> Self {
+        RecorderProcessor {
+            state,
+            producer,
+            scratch: Vec::new(),
+        }
+    }
+
+    fn overflow(&self, frames: usize) {
+        self.state
+            .overflowed_frames
+            .fetch_add(frames as u64, Ordering::Relaxed);
+    }
+}
+
+impl<B: Backend> Processor<B> for RecorderProcessor {
+    fn prepare(&mut self, sample_rate: f64, max_frames: usize) {
+        self.state
+            .sample_rate
+            .store(sample_rate.to_bits(), Ordering::Relaxed);
+        self.scratch = vec![0.0; max_frames * MAX_RECORDED_CHANNELS];
+    }
+
+    fn process(
+        &mut self,
+        input: &[B::AudioBuffers],
+        output: &mut [B::AudioBuffers],
+        ctx: &RenderContext<'_>,
+    ) {
+        for buffer in output {
+            buffer.interleaved_frames_mut().fill(Default::default());
+        }
+
+        if !ctx.is_valid() {
+            return;
+        }
+
+        let frames = input.first().map_or(0, |buffer| buffer.num_frames());
+        let channels = input
+            .iter()
+            .map(|buffer| buffer.num_channels())
+            .sum::<usize>()
+            .min(MAX_RECORDED_CHANNELS);
+        if frames == 0 || channels == 0 {
+            return;
+        }
+
+        let recorded_channels = match self.state.channels.compare_exchange(
+            0,
+            channels,
+            Ordering::Release,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => channels,
+            Err(recorded_channels) => recorded_channels,
+        };
+
+        let len = frames * channels;
+        if channels != recorded_channels || len > self.scratch.len() {
+            self.overflow(frames);
+            return;
+        }
+
+        // Interleave all input streams into one frame per sample time, up to
+        // the channels being recorded.
+        let mut offset = 0;
+        for buffer in input {
+            if offset == channels {
+                break;
+            }
+            let stride = buffer.num_channels();
+            let recorded = stride.min(channels - offset);
+            if recorded == 0 {
+                continue;
+            }
+
+            for (frame, samples) in buffer
+                .interleaved_frames()
+                .chunks_exact(stride)
+                .take(frames)
+                .enumerate()
+            {
+                let start = frame * channels + offset;
+                for (to, &from) in self.scratch[start..start + recorded]
+                    .iter_mut()
+                    .zip(samples)
+                {
+                    *to = from.to_f32();
+                }
+            }
+            offset += recorded;
+        }
+
+        if !self.producer.try_push(&self.scratch[..len]) {
+            self.overflow(frames);
+        }
+    }
+
+    fn reset(&mut self) {}
+}
+
+fn write_to_disk(
+    state: &RecorderState,
+    mut consumer: Consumer,
+    file: BufWriter<File>,
+    started_at: SystemTime,
+) -> io::Result<u64> {
+    let mut file = Some(file);
+    let mut writer = None;
+    let mut buffer = vec![0.0; 1 << 16];
+
+    loop {
+        // Check before draining, so everything pushed before the session
+        // stopped is written out.
+        let stopping = state.stop.load(Ordering::Acquire);
+
+        let channels = state.channels.load(Ordering::Acquire);
+        if channels > 0 && writer.is_none() {
+            writer = Some(WavWriter::new(
+                file.take().unwrap(),
+                f64::from_bits(state.sample_rate.load(Ordering::Relaxed)),
+                channels as u16,
+                started_at,
+            )?);
+        }
+
+        if let Some(writer) = writer.as_mut() {
+            let whole_frames = buffer.len() / channels * channels;
+            while consumer.len() >= channels {
+                let available = consumer.len() / channels * channels;
+                let count = consumer.pop(&mut buffer[..available.min(whole_frames)]);
+                writer.write(&buffer[..count])?;
+            }
+        }
+
+        if stopping {
+            break;
+        }
+
+        thread::sleep(DISK_POLL_INTERVAL);
+    }
+
+    match writer {
+        Some(writer) => {
+            let frames = writer.frames();
+            writer.finish()?;
+            Ok(frames)
+        }
+        None => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::{RecorderProcessor, RecorderState, MAX_RECORDED_CHANNELS};
+    use crate::context::RenderContext;
+    use crate::processor::Processor;
+    use crate::ring_buffer::ring_buffer;
+    use crate::sample::testing::wide_layout;
+    use crate::scratch::Scratch;
+    use crate::NullBackend;
+
+    const FRAMES: usize = 16;
+
+    fn tag(channel: usize, frame: usize) -> f32 {
+        (channel * 1000 + frame) as f32
+    }
+
+    #[test]
+    fn interleaves_every_channel_of_a_wide_layout() {
+        // 128 channels in 16 streams of 8, twice what used to fit.
+        let channels = 128;
+        let input = wide_layout(16, 8, FRAMES, tag);
+        let mut output = wide_layout(1, 2, FRAMES, |_, _| 1.0);
+
+        let state = RecorderState::new(48_000.0);
+        let (producer, mut consumer) = ring_buffer(FRAMES * channels);
+        let mut processor = RecorderProcessor::new(state.clone(), producer);
+        Processor::<NullBackend>::prepare(&mut processor, 48_000.0, FRAMES);
+
+        let valid = AtomicBool::new(true);
+        let scratch = Scratch::new(FRAMES, 2);
+        let ctx = RenderContext::for_test(&valid, &scratch, FRAMES);
+        Processor::<NullBackend>::process(&mut processor, &input, &mut output, &ctx);
+
+        assert_eq!(state.channels.load(Ordering::Acquire), channels);
+        assert_eq!(state.overflowed_frames.load(Ordering::Relaxed), 0);
+
+        let mut recorded = vec![0.0; FRAMES * channels];
+        assert_eq!(consumer.pop(&mut recorded), recorded.len());
+        for (frame, samples) in recorded.chunks(channels).enumerate() {
+            for (channel, &sample) in samples.iter().enumerate() {
+                assert_eq!(sample, tag(channel, frame));
+            }
+        }
+
+        assert!(output[0]
+            .interleaved_frames()
+            .iter()
+            .all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn records_the_first_channels_of_a_wider_device() {
+        // 320 channels in 5 streams of 64, more than can be recorded.
+        let input = wide_layout(5, 64, FRAMES, tag);
+        let mut output = wide_layout(1, 2, FRAMES, |_, _| 1.0);
+        let channels = MAX_RECORDED_CHANNELS;
+
+        let state = RecorderState::new(48_000.0);
+        let (producer, mut consumer) = ring_buffer(2 * FRAMES * channels);
+        let mut processor = RecorderProcessor::new(state.clone(), producer);
+        Processor::<NullBackend>::prepare(&mut processor, 48_000.0, FRAMES);
+
+        let valid = AtomicBool::new(true);
+        let scratch = Scratch::new(FRAMES, 2);
+        let ctx = RenderContext::for_test(&valid, &scratch, FRAMES);
+        Processor::<NullBackend>::process(&mut processor, &input, &mut output, &ctx);
+        Processor::<NullBackend>::process(&mut processor, &input, &mut output, &ctx);
+
+        assert_eq!(state.channels.load(Ordering::Acquire), channels);
+        assert_eq!(state.overflowed_frames.load(Ordering::Relaxed), 0);
+
+        let mut recorded = vec![0.0; 2 * FRAMES * channels];
+        assert_eq!(consumer.pop(&mut recorded), recorded.len());
+        for (frame, samples) in recorded.chunks(channels).enumerate() {
+            for (channel, &sample) in samples.iter().enumerate() {
+                assert_eq!(sample, tag(channel, frame % FRAMES));
+            }
+        }
+    }
+}
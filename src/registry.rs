@@ -0,0 +1,193 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::dyn_backend::{DynBackend, DynError};
+use crate::traits::Backend;
+
+/// Picks a backend at runtime out of a list of candidates, trying them in
+/// order of priority until one works.
+///
+/// `BackendRegistry::default` holds every hardware backend compiled into the
+/// crate. The null, file, loopback and network backends always work but
+/// don't play anything, so they're left out unless registered explicitly,
+/// like the null backend as a last resort on headless machines.
+pub struct BackendRegistry {
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    name: &'static str,
+    priority: i32,
+    is_available: fn() -> bool,
+    open: fn(&'static str) -> Result<DynBackend, DynError>,
+}
+
+/// Why a backend wasn't picked.
+#[derive(Debug)]
+pub enum ProbeError {
+    /// `Backend::is_available` said no, so the backend wasn't created.
+    Unavailable,
+    /// Creating the backend failed.
+    Failed(DynError),
+}
+
+/// A backend that was tried and didn't work.
+#[derive(Debug)]
+pub struct ProbeFailure {
+    pub backend: &'static str,
+    pub error: ProbeError,
+}
+
+/// The first backend that worked, and why the ones before it didn't.
+pub struct Detected {
+    pub backend: DynBackend,
+    pub failures: Vec<ProbeFailure>,
+}
+
+/// None of the registered backends worked.
+#[derive(Debug)]
+pub struct NoBackendAvailable {
+    /// Every registered backend, in the order they were tried.
+    pub failures: Vec<ProbeFailure>,
+}
+
+impl BackendRegistry {
+    /// A registry without any backends.
+    pub fn empty() -> Self {
+        BackendRegistry { entries: vec![] }
+    }
+
+    /// Adds `B` under `name`. Backends with a higher priority are tried
+    /// first, and backends with the same priority in the order they were
+    /// registered. Registering a name again replaces the earlier entry.
+    pub fn register<B: Backend + 'static>(&mut self, name: &'static str, priority: i32) {
+        self.entries.retain(|entry| entry.name != name);
+
+        let entry = Entry {
+            name,
+            priority,
+            is_available: B::is_available,
+            open: DynBackend::new::<B>,
+        };
+        let index = self
+            .entries
+            .iter()
+            .position(|other| other.priority < priority)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(index, entry);
+    }
+
+    /// The names of the registered backends, in the order they're tried.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.entries.iter().map(|entry| entry.name).collect()
+    }
+
+    /// Creates the backend registered as `name`, without checking whether
+    /// it's available first. Returns None if there's no such backend.
+    pub fn open(&self, name: &str) -> Option<Result<DynBackend, DynError>> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| (entry.open)(entry.name))
+    }
+
+    /// Tries every backend in order of priority and returns the first one
+    /// that's available and could be created.
+    pub fn detect(&self) -> Result<Detected, NoBackendAvailable> {
+        let mut failures = vec![];
+
+        for entry in &self.entries {
+            let error = if (entry.is_available)() {
+                match (entry.open)(entry.name) {
+                    Ok(backend) => return Ok(Detected { backend, failures }),
+                    Err(error) => ProbeError::Failed(error),
+                }
+            } else {
+                ProbeError::Unavailable
+            };
+
+            failures.push(ProbeFailure {
+                backend: entry.name,
+                error,
+            });
+        }
+
+        Err(NoBackendAvailable { failures })
+    }
+}
+
+/// The hardware backends compiled into the crate. A running JACK server is
+/// preferred over the platform's own audio system, since someone went out
+/// of their way to start it, and cpal and PortAudio come last as the
+/// generic fallbacks.
+impl Default for BackendRegistry {
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut registry = BackendRegistry::empty();
+
+        #[cfg(feature = "jack")]
+        registry.register::<crate::JackBackend>("jack", 30);
+        #[cfg(all(feature = "asio", target_os = "windows"))]
+        registry.register::<crate::AsioBackend>("asio", 20);
+        #[cfg(all(feature = "coreaudio", target_os = "macos"))]
+        registry.register::<crate::coreaudio::Backend>("coreaudio", 10);
+        #[cfg(all(feature = "ios", target_os = "ios"))]
+        registry.register::<crate::IosBackend>("ios", 10);
+        #[cfg(all(
+            feature = "oss",
+            any(target_os = "freebsd", target_os = "dragonfly", target_os = "netbsd")
+        ))]
+        registry.register::<crate::OssBackend>("oss", 10);
+        #[cfg(all(feature = "web", target_arch = "wasm32"))]
+        registry.register::<crate::WebBackend>("web", 10);
+        #[cfg(feature = "cpal")]
+        registry.register::<crate::CpalBackend>("cpal", 0);
+        #[cfg(feature = "portaudio")]
+        registry.register::<crate::PortAudioBackend>("portaudio", -10);
+
+        registry
+    }
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Unavailable => write!(f, "Not available on this machine"),
+            ProbeError::Failed(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for ProbeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProbeError::Unavailable => None,
+            ProbeError::Failed(error) => Some(error),
+        }
+    }
+}
+
+impl fmt::Display for ProbeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            ProbeError::Unavailable => write!(f, "{}: {}", self.backend, self.error),
+            // Already names the backend.
+            ProbeError::Failed(error) => error.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for NoBackendAvailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No audio backend available")?;
+
+        for (index, failure) in self.failures.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { "; " };
+            write!(f, "{}{}", separator, failure)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for NoBackendAvailable {}
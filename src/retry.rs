@@ -0,0 +1,59 @@
+use std::thread;
+use std::time::Duration;
+
+/// How often to try again when setting up a session fails in a way that's
+/// expected to clear up by itself, like the audio system still starting up
+/// right after login.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first. One never retries.
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    /// The delay doubles after every attempt, up to this.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+}
+
+/// Retries for a little over ten seconds, which covers the audio system
+/// coming up at login on slow machines.
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 8,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(4),
+        }
+    }
+}
+
+/// Calls `f` until it succeeds, fails with an error `is_transient` rejects,
+/// or runs out of attempts, sleeping between attempts.
+pub(crate) fn retry<T, E>(
+    policy: RetryPolicy,
+    is_transient: impl Fn(&E) -> bool,
+    mut f: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut delay = policy.initial_delay;
+    let mut attempt = 1;
+
+    loop {
+        match f() {
+            Err(error) if attempt < policy.max_attempts && is_transient(&error) => {
+                thread::sleep(delay);
+                delay = (delay * 2).min(policy.max_delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
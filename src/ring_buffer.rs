@@ -0,0 +1,121 @@
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A fixed size single producer, single consumer queue of samples. Neither
+/// side ever blocks or allocates, so either end can live on the real-time
+/// thread.
+struct RingBuffer {
+    data: *mut f32,
+    capacity: usize,
+    /// Total number of samples ever written, only stored to by the producer.
+    head: AtomicUsize,
+    /// Total number of samples ever read, only stored to by the consumer.
+    tail: AtomicUsize,
+}
+
+unsafe impl Send for RingBuffer {}
+unsafe impl Sync for RingBuffer {}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(self.data, self.capacity)) });
+    }
+}
+
+pub struct Producer {
+    ring: Arc<RingBuffer>,
+}
+
+pub struct Consumer {
+    ring: Arc<RingBuffer>,
+}
+
+pub fn ring_buffer(capacity: usize) -> (Producer, Consumer) {
+    assert!(capacity > 0, "Ring buffer capacity must be non-zero");
+
+    let data = Box::into_raw(vec![0.0; capacity].into_boxed_slice()) as *mut f32;
+    let ring = Arc::new(RingBuffer {
+        data,
+        capacity,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+
+    (Producer { ring: ring.clone() }, Consumer { ring })
+}
+
+impl Producer {
+    pub fn free_len(&self) -> usize {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+
+        self.ring.capacity - head.wrapping_sub(tail)
+    }
+
+    /// Pushes all of `samples`, or nothing if there isn't room for them.
+    pub fn try_push(&mut self, samples: &[f32]) -> bool {
+        if samples.len() > self.free_len() {
+            return false;
+        }
+
+        let head = self.ring.head.load(Ordering::Relaxed);
+        unsafe { self.ring.copy_in(head, samples) };
+        self.ring
+            .head
+            .store(head.wrapping_add(samples.len()), Ordering::Release);
+
+        true
+    }
+}
+
+impl Consumer {
+    pub fn len(&self) -> usize {
+        let head = self.ring.head.load(Ordering::Acquire);
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+
+        head.wrapping_sub(tail)
+    }
+
+    /// Pops up to `out.len()` samples and returns how many were read.
+    pub fn pop(&mut self, out: &mut [f32]) -> usize {
+        let count = out.len().min(self.len());
+
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        unsafe { self.ring.copy_out(tail, &mut out[..count]) };
+        self.ring
+            .tail
+            .store(tail.wrapping_add(count), Ordering::Release);
+
+        count
+    }
+}
+
+impl RingBuffer {
+    /// The two regions of the buffer, in order, that `len` samples starting
+    /// at the absolute position `position` occupy.
+    fn regions(&self, position: usize, len: usize) -> (usize, usize) {
+        let start = position % self.capacity;
+        let first = len.min(self.capacity - start);
+
+        (start, first)
+    }
+
+    unsafe fn copy_in(&self, position: usize, samples: &[f32]) {
+        let (start, first) = self.regions(position, samples.len());
+
+        ptr::copy_nonoverlapping(samples.as_ptr(), self.data.add(start), first);
+        ptr::copy_nonoverlapping(
+            samples.as_ptr().add(first),
+            self.data,
+            samples.len() - first,
+        );
+    }
+
+    unsafe fn copy_out(&self, position: usize, out: &mut [f32]) {
+        let (start, first) = self.regions(position, out.len());
+
+        ptr::copy_nonoverlapping(self.data.add(start), out.as_mut_ptr(), first);
+        ptr::copy_nonoverlapping(self.data, out.as_mut_ptr().add(first), out.len() - first);
+    }
+}
@@ -0,0 +1,326 @@
+use crate::channel_map::StreamMapping;
+use crate::direction::Direction;
+use crate::traits::{AudioBuffers, Backend, Device, Session};
+
+/// A channel of the session's devices, either by its index counting across
+/// all of the session's streams in the order the callback would see them, or
+/// by the name the driver gives it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelRef {
+    Index(usize),
+    /// Matches the first channel with exactly this name, like "Mic 2", on
+    /// any of the session's devices. Survives devices reordering their
+    /// channels, unlike an index.
+    Name(String),
+}
+
+impl From<usize> for ChannelRef {
+    fn from(index: usize) -> Self {
+        ChannelRef::Index(index)
+    }
+}
+
+impl From<&str> for ChannelRef {
+    fn from(name: &str) -> Self {
+        ChannelRef::Name(name.to_owned())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Route {
+    pub device_channel: ChannelRef,
+    pub callback_channel: usize,
+}
+
+/// Which device channels the callback sees, and in what order. With routing,
+/// the callback gets a single input and a single output buffer, each with as
+/// many channels as the highest routed callback channel calls for.
+///
+/// `SessionConfig::validate` rejects routes to channels the session's
+/// devices don't have. Routes are resolved to channel indices when the
+/// session starts, and channels that can't be found then are left silent in
+/// the callback's input, and dropped from its output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoutingMatrix {
+    pub input: Vec<Route>,
+    pub output: Vec<Route>,
+}
+
+impl RoutingMatrix {
+    pub fn new() -> Self {
+        RoutingMatrix::default()
+    }
+
+    /// Feeds `device_channel` to input channel `callback_channel`.
+    pub fn route_input(
+        mut self,
+        device_channel: impl Into<ChannelRef>,
+        callback_channel: usize,
+    ) -> Self {
+        self.input.push(Route {
+            device_channel: device_channel.into(),
+            callback_channel,
+        });
+        self
+    }
+
+    /// Plays output channel `callback_channel` on `device_channel`.
+    pub fn route_output(
+        mut self,
+        callback_channel: usize,
+        device_channel: impl Into<ChannelRef>,
+    ) -> Self {
+        self.output.push(Route {
+            device_channel: device_channel.into(),
+            callback_channel,
+        });
+        self
+    }
+
+    /// Feeds `device_channels` to the callback's input channels in order,
+    /// after any already routed, so that `[2, 3]` has the callback see a
+    /// device's third and fourth input as its first two.
+    pub fn select_inputs<C: Into<ChannelRef>>(
+        mut self,
+        device_channels: impl IntoIterator<Item = C>,
+    ) -> Self {
+        let first = next_callback_channel(&self.input);
+        for (offset, device_channel) in device_channels.into_iter().enumerate() {
+            self = self.route_input(device_channel, first + offset);
+        }
+        self
+    }
+
+    /// Plays the callback's output channels, after any already routed, on
+    /// `device_channels` in order.
+    pub fn select_outputs<C: Into<ChannelRef>>(
+        mut self,
+        device_channels: impl IntoIterator<Item = C>,
+    ) -> Self {
+        let first = next_callback_channel(&self.output);
+        for (offset, device_channel) in device_channels.into_iter().enumerate() {
+            self = self.route_output(first + offset, device_channel);
+        }
+        self
+    }
+
+    /// Looks up the session's channel names and turns every route into a
+    /// pair of indices.
+    pub(crate) fn resolve<B: Backend>(
+        &self,
+        backend: &B,
+        session: &B::Session,
+    ) -> Result<ResolvedRouting, B::Error> {
+        let map = session.channel_map()?;
+
+        let resolve_side = |routes: &[Route], direction, streams: &[StreamMapping]| {
+            let mut names = Vec::new();
+            for stream in streams {
+                let device = backend.device_by_id(&stream.device_id)?;
+                for channel in
+                    stream.first_device_channel..stream.first_device_channel + stream.channels
+                {
+                    names.push(match &device {
+                        Some(device) => device.channel_name(direction, channel)?,
+                        None => None,
+                    });
+                }
+            }
+
+            let pairs: Vec<_> = routes
+                .iter()
+                .filter_map(|route| {
+                    let index = match &route.device_channel {
+                        ChannelRef::Index(index) => {
+                            Some(*index).filter(|&index| index < names.len())
+                        }
+                        ChannelRef::Name(name) => names
+                            .iter()
+                            .position(|candidate| candidate.as_deref() == Some(name.as_str())),
+                    };
+                    index.map(|index| (index, route.callback_channel))
+                })
+                .collect();
+            let channels = next_callback_channel(routes);
+
+            Ok((pairs, channels))
+        };
+
+        let (input, input_channels) = resolve_side(&self.input, Direction::Input, &map.input)?;
+        let (output, output_channels) = resolve_side(&self.output, Direction::Output, &map.output)?;
+
+        Ok(ResolvedRouting {
+            input,
+            output,
+            input_channels,
+            output_channels,
+        })
+    }
+}
+
+fn next_callback_channel(routes: &[Route]) -> usize {
+    routes
+        .iter()
+        .map(|route| route.callback_channel + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// A `RoutingMatrix` with every channel turned into an index.
+pub(crate) struct ResolvedRouting {
+    /// Pairs of device channel and callback channel.
+    pub input: Vec<(usize, usize)>,
+    pub output: Vec<(usize, usize)>,
+    pub input_channels: usize,
+    pub output_channels: usize,
+}
+
+impl ResolvedRouting {
+    /// The channel count of each buffer the callback sees: a single one per
+    /// direction, unless nothing is routed that way.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        let side = |channels: usize| {
+            Some(channels)
+                .filter(|&channels| channels > 0)
+                .into_iter()
+                .collect()
+        };
+        (side(self.input_channels), side(self.output_channels))
+    }
+
+    /// Copies the routed device channels into `routed`, an interleaved buffer
+    /// with `input_channels` channels.
+    pub fn gather<A: AudioBuffers>(&self, input: &[A], routed: &mut A) {
+        let stride = self.input_channels;
+        let samples = routed.interleaved_frames_mut();
+        samples.fill(Default::default());
+
+        for &(device_channel, callback_channel) in &self.input {
+            if let Some((buffer, offset)) = find_channel(input, device_channel) {
+                let frames = samples
+                    .chunks_mut(stride)
+                    .zip(buffer.interleaved_frames().chunks(buffer.num_channels()));
+                for (to, from) in frames {
+                    to[callback_channel] = from[offset];
+                }
+            }
+        }
+    }
+
+    /// Copies the callback's output in `routed` to the routed device
+    /// channels, silencing all others.
+    pub fn scatter<A: AudioBuffers>(&self, routed: &A, output: &mut [A]) {
+        for buffer in output.iter_mut() {
+            buffer.interleaved_frames_mut().fill(Default::default());
+        }
+
+        let stride = self.output_channels;
+        for &(device_channel, callback_channel) in &self.output {
+            if let Some((buffer, offset)) = find_channel_mut(output, device_channel) {
+                let channels = buffer.num_channels();
+                let frames = buffer
+                    .interleaved_frames_mut()
+                    .chunks_mut(channels)
+                    .zip(routed.interleaved_frames().chunks(stride));
+                for (to, from) in frames {
+                    to[offset] = from[callback_channel];
+                }
+            }
+        }
+    }
+}
+
+/// The buffer holding `channel`, counting across all buffers, and the
+/// channel's offset within it.
+pub(crate) fn find_channel<A: AudioBuffers>(
+    buffers: &[A],
+    mut channel: usize,
+) -> Option<(&A, usize)> {
+    for buffer in buffers {
+        let channels = buffer.num_channels();
+        if channel < channels {
+            return Some((buffer, channel));
+        }
+        channel -= channels;
+    }
+
+    None
+}
+
+fn find_channel_mut<A: AudioBuffers>(
+    buffers: &mut [A],
+    mut channel: usize,
+) -> Option<(&mut A, usize)> {
+    for buffer in buffers {
+        let channels = buffer.num_channels();
+        if channel < channels {
+            return Some((buffer, channel));
+        }
+        channel -= channels;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_channel, ResolvedRouting};
+    use crate::sample::testing::wide_layout;
+    use crate::traits::AudioBuffers;
+
+    const FRAMES: usize = 32;
+
+    /// A value that says which channel and frame it came from.
+    fn tag(channel: usize, frame: usize) -> f32 {
+        (channel * 1000 + frame) as f32
+    }
+
+    #[test]
+    fn finds_channels_across_many_streams() {
+        // 96 channels in 12 streams of 8.
+        let buffers = wide_layout(12, 8, FRAMES, tag);
+
+        for channel in [0, 7, 8, 63, 64, 95] {
+            let (buffer, offset) = find_channel(&buffers, channel).unwrap();
+            assert_eq!(buffer.frames().next().unwrap()[offset], tag(channel, 0));
+        }
+        assert!(find_channel(&buffers, 96).is_none());
+    }
+
+    #[test]
+    fn routes_channels_past_64() {
+        // 128 channels in 16 streams of 8.
+        let input = wide_layout(16, 8, FRAMES, tag);
+        let mut output = wide_layout(16, 8, FRAMES, |_, _| 0.0);
+
+        let routing = ResolvedRouting {
+            input: vec![(127, 0), (64, 1), (3, 2)],
+            output: vec![(127, 0), (64, 1), (3, 2)],
+            input_channels: 3,
+            output_channels: 3,
+        };
+
+        let mut routed = wide_layout(1, 3, FRAMES, |_, _| 0.0).remove(0);
+        routing.gather(&input, &mut routed);
+        for (frame, samples) in routed.frames().enumerate() {
+            assert_eq!(samples, [tag(127, frame), tag(64, frame), tag(3, frame)]);
+        }
+
+        routing.scatter(&routed, &mut output);
+        for channel in 0..128 {
+            let (buffer, offset) = find_channel(&output, channel).unwrap();
+            for (frame, samples) in buffer.frames().enumerate() {
+                let expected = match channel {
+                    127 => tag(127, frame),
+                    64 => tag(64, frame),
+                    3 => tag(3, frame),
+                    _ => 0.0,
+                };
+                assert_eq!(samples[offset], expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::ptr;
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(loom)]
+use loom::thread;
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(not(loom))]
+use std::thread;
+
+/// A boxed value shared between a single real-time reader and any number of
+/// control threads that may replace it.
+///
+/// The reader never blocks: it counts the start of a read and loads the
+/// current pointer. Writers swap in the new pointer and then wait for the
+/// read that might still be looking at the old value to finish, so the old
+/// value can be dropped on the writer's thread instead of the real-time one.
+pub struct RtCell<T> {
+    value: AtomicPtr<T>,
+    /// Bumped at the start and end of every read, so it's odd while one is
+    /// in progress. Writers wait for it to change rather than to become
+    /// even, which a reader calling back to back might never let them see.
+    ///
+    /// Both sides update it with read-modify-writes, which always see the
+    /// latest count, so whichever of the reader and a writer gets to it
+    /// second is guaranteed to see what the other did.
+    reads: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for RtCell<T> {}
+unsafe impl<T: Send> Sync for RtCell<T> {}
+
+impl<T> RtCell<T> {
+    pub fn new(value: Option<Box<T>>) -> Self {
+        RtCell {
+            value: AtomicPtr::new(value.map_or(ptr::null_mut(), Box::into_raw)),
+            reads: AtomicUsize::new(0),
+        }
+    }
+
+    /// Runs `f` with exclusive access to the current value, if any.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread may ever read from the cell, and `with` must not be
+    /// called reentrantly from `f`.
+    pub unsafe fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.reads.fetch_add(1, Ordering::AcqRel);
+        let result = self.value.load(Ordering::Acquire).as_mut().map(f);
+        self.reads.fetch_add(1, Ordering::Release);
+
+        result
+    }
+
+    /// Publishes a new value and returns the old one once the reader is
+    /// guaranteed to no longer be using it.
+    pub fn replace(&self, value: Option<Box<T>>) -> Option<Box<T>> {
+        let new = value.map_or(ptr::null_mut(), Box::into_raw);
+        let old = self.value.swap(new, Ordering::AcqRel);
+
+        // Any read starting after this sees the new pointer, so only one in
+        // progress right now can be using the old one.
+        let reads = self.reads.fetch_add(0, Ordering::AcqRel);
+        if reads % 2 == 1 {
+            while self.reads.load(Ordering::Acquire) == reads {
+                thread::yield_now();
+            }
+        }
+
+        if old.is_null() {
+            None
+        } else {
+            Some(unsafe { Box::from_raw(old) })
+        }
+    }
+
+    pub fn take(&self) -> Option<Box<T>> {
+        self.replace(None)
+    }
+}
+
+impl<T> Drop for RtCell<T> {
+    fn drop(&mut self) {
+        let value = self.value.load(Ordering::Relaxed);
+        if !value.is_null() {
+            drop(unsafe { Box::from_raw(value) });
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, ThreadId};
+
+    use super::RtCell;
+
+    /// A value that remembers whether, and on which thread, it was dropped.
+    struct Tracked {
+        id: usize,
+        dropped: Arc<AtomicBool>,
+        dropped_on: Arc<Mutex<Option<ThreadId>>>,
+    }
+
+    impl Tracked {
+        fn new(id: usize) -> (Box<Tracked>, Arc<AtomicBool>, Arc<Mutex<Option<ThreadId>>>) {
+            let dropped = Arc::new(AtomicBool::new(false));
+            let dropped_on = Arc::new(Mutex::new(None));
+            let value = Box::new(Tracked {
+                id,
+                dropped: dropped.clone(),
+                dropped_on: dropped_on.clone(),
+            });
+
+            (value, dropped, dropped_on)
+        }
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            *self.dropped_on.lock().unwrap() = Some(thread::current().id());
+            self.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn with_sees_the_current_value() {
+        let cell = RtCell::new(None);
+        assert_eq!(unsafe { cell.with(|value: &mut usize| *value) }, None);
+
+        cell.replace(Some(Box::new(1)));
+        assert_eq!(unsafe { cell.with(|value| *value) }, Some(1));
+
+        unsafe { cell.with(|value| *value += 1) };
+        assert_eq!(cell.take().map(|value| *value), Some(2));
+        assert_eq!(unsafe { cell.with(|value| *value) }, None);
+    }
+
+    #[test]
+    fn replace_hands_back_the_old_value_undropped() {
+        let (first, dropped, _) = Tracked::new(1);
+        let cell = RtCell::new(Some(first));
+
+        let (second, _, _) = Tracked::new(2);
+        let old = cell.replace(Some(second)).unwrap();
+        assert_eq!(old.id, 1);
+        assert!(!dropped.load(Ordering::SeqCst));
+
+        drop(old);
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn dropping_the_cell_drops_its_value() {
+        let (value, dropped, _) = Tracked::new(1);
+        drop(RtCell::new(Some(value)));
+
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    /// Races a reader that keeps checking the value against a writer that
+    /// keeps replacing it. The reader must never see a value that's been
+    /// dropped, and every old value must be dropped on the writer's thread.
+    #[test]
+    fn replace_racing_with() {
+        const REPLACEMENTS: usize = 2_000;
+
+        let (first, _, _) = Tracked::new(0);
+        let cell = Arc::new(RtCell::new(Some(first)));
+        let done = Arc::new(AtomicBool::new(false));
+        let reads = Arc::new(AtomicUsize::new(0));
+
+        let reader = {
+            let cell = cell.clone();
+            let done = done.clone();
+            let reads = reads.clone();
+            thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    unsafe {
+                        cell.with(|value| {
+                            // Kept outside the value, so it can still be
+                            // checked if the value were freed under us.
+                            let dropped = value.dropped.clone();
+                            for _ in 0..10 {
+                                assert!(!dropped.load(Ordering::SeqCst));
+                                thread::yield_now();
+                            }
+                        })
+                    };
+                    reads.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        };
+
+        // Make sure the two actually overlap.
+        while reads.load(Ordering::Relaxed) == 0 {
+            thread::yield_now();
+        }
+
+        let writer = thread::current().id();
+        for id in 1..=REPLACEMENTS {
+            let (value, _, _) = Tracked::new(id);
+            let old = cell.replace(Some(value)).unwrap();
+            assert_eq!(old.id, id - 1);
+
+            let dropped_on = old.dropped_on.clone();
+            drop(old);
+            assert_eq!(*dropped_on.lock().unwrap(), Some(writer));
+        }
+
+        done.store(true, Ordering::SeqCst);
+        reader.join().unwrap();
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::sync::atomic::{AtomicBool, Ordering};
+    use loom::sync::{Arc, Mutex};
+    use loom::thread::{self, ThreadId};
+
+    use super::RtCell;
+
+    #[derive(Clone, Default)]
+    struct DropState {
+        dropped: Arc<AtomicBool>,
+        dropped_on: Arc<Mutex<Option<ThreadId>>>,
+    }
+
+    struct Tracked(DropState);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            *self.0.dropped_on.lock().unwrap() = Some(thread::current().id());
+            self.0.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Every interleaving of one read with one replacement: the reader must
+    /// never see the old value dropped while it holds it, and the old value
+    /// must be dropped on the writer's thread.
+    #[test]
+    fn replace_racing_with() {
+        loom::model(|| {
+            let state = DropState::default();
+            let cell = Arc::new(RtCell::new(Some(Box::new(Tracked(state.clone())))));
+
+            let reader = {
+                let cell = cell.clone();
+                thread::spawn(move || unsafe {
+                    cell.with(|value| {
+                        let dropped = value.0.dropped.clone();
+                        assert!(!dropped.load(Ordering::SeqCst));
+                        thread::yield_now();
+                        assert!(!dropped.load(Ordering::SeqCst));
+                    });
+                })
+            };
+
+            let old = cell.replace(Some(Box::new(Tracked(DropState::default()))));
+            assert!(!state.dropped.load(Ordering::SeqCst));
+            drop(old);
+            assert_eq!(
+                *state.dropped_on.lock().unwrap(),
+                Some(thread::current().id())
+            );
+
+            reader.join().unwrap();
+        });
+    }
+}
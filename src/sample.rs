@@ -0,0 +1,420 @@
+use std::iter;
+use std::marker::PhantomData;
+use std::slice;
+
+use crate::context::RenderContext;
+use crate::processor::Processor;
+use crate::traits::{AudioBuffers, Backend};
+
+/// A sample type audio buffers can hold. Backends expose buffers in whatever
+/// type the device natively uses, and callbacks can work in another one by
+/// converting at that boundary. Full scale is [-1, 1] for floating point
+/// types and the whole range for integer ones.
+pub trait Sample: Copy + Default + Send + 'static {
+    const FORMAT: SampleFormat;
+
+    fn from_f32(sample: f32) -> Self;
+    fn to_f32(self) -> f32;
+
+    /// Like `from_f32`, without losing the precision of 32 bit integers and
+    /// doubles on the way.
+    fn from_f64(sample: f64) -> Self;
+    fn to_f64(self) -> f64;
+
+    /// Goes through f64, which holds every other sample type exactly, so
+    /// converting to a type at least as wide loses nothing.
+    fn convert<T: Sample>(self) -> T {
+        T::from_f64(self.to_f64())
+    }
+}
+
+/// How a sample is stored in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub enum SampleFormat {
+    I16,
+    /// 24 bit integers packed into three bytes.
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    pub fn bits_per_sample(self) -> u32 {
+        match self {
+            SampleFormat::I16 => 16,
+            SampleFormat::I24 => 24,
+            SampleFormat::I32 | SampleFormat::F32 => 32,
+            SampleFormat::F64 => 64,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, SampleFormat::F32 | SampleFormat::F64)
+    }
+
+    /// The format with the given size and representation, if there is one.
+    pub fn from_bits(bits_per_sample: u32, is_float: bool) -> Option<Self> {
+        match (bits_per_sample, is_float) {
+            (16, false) => Some(SampleFormat::I16),
+            (24, false) => Some(SampleFormat::I24),
+            (32, false) => Some(SampleFormat::I32),
+            (32, true) => Some(SampleFormat::F32),
+            (64, true) => Some(SampleFormat::F64),
+            _ => None,
+        }
+    }
+}
+
+impl Sample for f32 {
+    const FORMAT: SampleFormat = SampleFormat::F32;
+
+    fn from_f32(sample: f32) -> Self {
+        sample
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f64(sample: f64) -> Self {
+        sample as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl Sample for f64 {
+    const FORMAT: SampleFormat = SampleFormat::F64;
+
+    fn from_f32(sample: f32) -> Self {
+        f64::from(sample)
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f64(sample: f64) -> Self {
+        sample
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Sample for i32 {
+    const FORMAT: SampleFormat = SampleFormat::I32;
+
+    fn from_f32(sample: f32) -> Self {
+        // The cast saturates, so full scale positive lands on i32::MAX.
+        (sample.clamp(-1.0, 1.0) * I32_SCALE) as i32
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32 / I32_SCALE
+    }
+
+    fn from_f64(sample: f64) -> Self {
+        (sample.clamp(-1.0, 1.0) * f64::from(I32_SCALE)) as i32
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self) / f64::from(I32_SCALE)
+    }
+}
+
+const I32_SCALE: f32 = 2_147_483_648.0;
+
+impl Sample for i16 {
+    const FORMAT: SampleFormat = SampleFormat::I16;
+
+    fn from_f32(sample: f32) -> Self {
+        (sample.clamp(-1.0, 1.0) * I16_SCALE) as i16
+    }
+
+    fn to_f32(self) -> f32 {
+        f32::from(self) / I16_SCALE
+    }
+
+    fn from_f64(sample: f64) -> Self {
+        (sample.clamp(-1.0, 1.0) * f64::from(I16_SCALE)) as i16
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self) / f64::from(I16_SCALE)
+    }
+}
+
+const I16_SCALE: f32 = 32_768.0;
+
+/// A 24 bit integer sample packed into three bytes in native byte order, the
+/// way devices store them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct I24([u8; 3]);
+
+impl I24 {
+    pub fn new(value: i32) -> Self {
+        let bytes = value.clamp(-(1 << 23), (1 << 23) - 1).to_ne_bytes();
+        if cfg!(target_endian = "little") {
+            I24([bytes[0], bytes[1], bytes[2]])
+        } else {
+            I24([bytes[1], bytes[2], bytes[3]])
+        }
+    }
+
+    pub fn get(self) -> i32 {
+        let [a, b, c] = self.0;
+        // Put into the top of an i32 and shifted back down to sign extend.
+        let shifted = if cfg!(target_endian = "little") {
+            i32::from_ne_bytes([0, a, b, c])
+        } else {
+            i32::from_ne_bytes([a, b, c, 0])
+        };
+        shifted >> 8
+    }
+}
+
+impl Sample for I24 {
+    const FORMAT: SampleFormat = SampleFormat::I24;
+
+    fn from_f32(sample: f32) -> Self {
+        I24::new((sample.clamp(-1.0, 1.0) * I24_SCALE) as i32)
+    }
+
+    fn to_f32(self) -> f32 {
+        self.get() as f32 / I24_SCALE
+    }
+
+    fn from_f64(sample: f64) -> Self {
+        I24::new((sample.clamp(-1.0, 1.0) * f64::from(I24_SCALE)) as i32)
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self.get()) / f64::from(I24_SCALE)
+    }
+}
+
+const I24_SCALE: f32 = 8_388_608.0;
+
+/// An interleaved buffer of samples owned by the crate rather than the
+/// device, used when the callback works in a different sample type.
+pub struct OwnedBuffer<S: Sample> {
+    samples: Vec<S>,
+    channels: usize,
+}
+
+impl<S: Sample> OwnedBuffer<S> {
+    pub(crate) fn with_capacity(frames: usize, channels: usize) -> Self {
+        OwnedBuffer {
+            samples: Vec::with_capacity(frames * channels),
+            channels,
+        }
+    }
+
+    /// Reshapes the buffer without allocating. Returns false if it doesn't
+    /// have room for the new shape.
+    pub(crate) fn reshape(&mut self, frames: usize, channels: usize) -> bool {
+        if frames * channels > self.samples.capacity() {
+            return false;
+        }
+
+        self.samples.resize(frames * channels, S::default());
+        self.channels = channels;
+        true
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.samples.len().checked_div(self.channels).unwrap_or(0)
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn interleaved_frames(&self) -> &[S] {
+        &self.samples
+    }
+
+    pub fn interleaved_frames_mut(&mut self) -> &mut [S] {
+        &mut self.samples
+    }
+}
+
+impl<S: Sample> AudioBuffers for OwnedBuffer<S> {
+    type Sample = S;
+
+    fn num_frames(&self) -> usize {
+        OwnedBuffer::num_frames(self)
+    }
+
+    fn num_channels(&self) -> usize {
+        self.channels
+    }
+
+    fn interleaved_frames(&self) -> &[S] {
+        &self.samples
+    }
+
+    fn interleaved_frames_mut(&mut self) -> &mut [S] {
+        &mut self.samples
+    }
+}
+
+/// The samples of a buffer converted to `T`, as returned by
+/// `AudioBuffers::frames_as`.
+pub type FramesAs<'a, S, T> = iter::Map<iter::Copied<slice::Iter<'a, S>>, fn(S) -> T>;
+
+pub type SampleRenderCallback<S> =
+    dyn FnMut(&RenderContext<'_>, &[OwnedBuffer<S>], &mut [OwnedBuffer<S>]) + Send;
+
+/// Runs a callback in sample type `S` on top of a backend's native buffers.
+pub(crate) struct ConvertingProcessor<S: Sample, B: Backend> {
+    callback: Box<SampleRenderCallback<S>>,
+    max_frames: usize,
+    inputs: Vec<OwnedBuffer<S>>,
+    outputs: Vec<OwnedBuffer<S>>,
+    backend: PhantomData<fn(B)>,
+}
+
+impl<S: Sample, B: Backend> ConvertingProcessor<S, B> {
+    pub fn new(callback: Box<SampleRenderCallback<S>>) -> Self {
+        ConvertingProcessor {
+            callback,
+            max_frames: 0,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            backend: PhantomData,
+        }
+    }
+
+    /// Matches the owned buffers to the device buffers, or returns false if
+    /// the device layout has outgrown what was allocated up front.
+    fn reshape<A: AudioBuffers>(owned: &mut [OwnedBuffer<S>], buffers: &[A]) -> bool {
+        owned.len() == buffers.len()
+            && owned
+                .iter_mut()
+                .zip(buffers)
+                .all(|(owned, buffer)| owned.reshape(buffer.num_frames(), buffer.num_channels()))
+    }
+}
+
+impl<S: Sample, B: Backend> Processor<B> for ConvertingProcessor<S, B> {
+    fn prepare(&mut self, _sample_rate: f64, max_frames: usize) {
+        self.max_frames = max_frames;
+    }
+
+    fn prepare_channels(&mut self, input: &[usize], output: &[usize]) {
+        let max_frames = self.max_frames;
+        self.inputs = input
+            .iter()
+            .map(|&channels| OwnedBuffer::with_capacity(max_frames, channels))
+            .collect();
+        self.outputs = output
+            .iter()
+            .map(|&channels| OwnedBuffer::with_capacity(max_frames, channels))
+            .collect();
+    }
+
+    fn process(
+        &mut self,
+        input: &[B::AudioBuffers],
+        output: &mut [B::AudioBuffers],
+        ctx: &RenderContext<'_>,
+    ) {
+        if !Self::reshape(&mut self.inputs, input) || !Self::reshape(&mut self.outputs, output) {
+            for buffer in output.iter_mut() {
+                buffer.interleaved_frames_mut().fill(Default::default());
+            }
+            return;
+        }
+
+        for (owned, buffer) in self.inputs.iter_mut().zip(input) {
+            for (to, &from) in owned.samples.iter_mut().zip(buffer.interleaved_frames()) {
+                *to = from.convert();
+            }
+        }
+
+        (self.callback)(ctx, &self.inputs, &mut self.outputs);
+
+        for (owned, buffer) in self.outputs.iter().zip(output.iter_mut()) {
+            for (to, &from) in buffer
+                .interleaved_frames_mut()
+                .iter_mut()
+                .zip(&owned.samples)
+            {
+                *to = from.convert();
+            }
+        }
+    }
+
+    fn reset(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sample, I24};
+
+    #[test]
+    fn converting_to_f64_and_back_is_lossless() {
+        for &sample in &[i32::MIN, -1, 0, 1, 123_456_789, i32::MAX] {
+            assert_eq!(sample.convert::<f64>().convert::<i32>(), sample);
+        }
+
+        for &sample in &[-(1 << 23), -1, 0, 1, 1_234_567, (1 << 23) - 1] {
+            let sample = I24::new(sample);
+            assert_eq!(sample.convert::<f64>().convert::<I24>(), sample);
+        }
+    }
+
+    #[test]
+    fn converting_to_the_same_type_is_a_copy() {
+        let sample = 0.1f64 + 1e-12;
+        assert_eq!(sample.convert::<f64>(), sample);
+        assert_eq!(i32::MAX.convert::<i32>(), i32::MAX);
+    }
+
+    #[test]
+    fn converting_clamps_to_full_scale() {
+        assert_eq!(2.0f64.convert::<i32>(), i32::MAX);
+        assert_eq!((-2.0f64).convert::<i16>(), i16::MIN);
+        assert_eq!(1.0f32.convert::<i16>(), i16::MAX);
+    }
+}
+
+/// Buffer layouts for tests that don't need a device.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::OwnedBuffer;
+
+    /// `streams` buffers of `channels_per_stream` channels each, like the
+    /// many identical streams of a MADI interface. `sample` gives each
+    /// sample's value from its channel, counting across all streams, and its
+    /// frame.
+    pub fn wide_layout(
+        streams: usize,
+        channels_per_stream: usize,
+        frames: usize,
+        sample: impl Fn(usize, usize) -> f32,
+    ) -> Vec<OwnedBuffer<f32>> {
+        (0..streams)
+            .map(|stream| {
+                let mut buffer = OwnedBuffer::with_capacity(frames, channels_per_stream);
+                buffer.reshape(frames, channels_per_stream);
+
+                for (frame, samples) in buffer.samples.chunks_mut(channels_per_stream).enumerate() {
+                    for (offset, to) in samples.iter_mut().enumerate() {
+                        *to = sample(stream * channels_per_stream + offset, frame);
+                    }
+                }
+
+                buffer
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,61 @@
+use std::cell::{Cell, UnsafeCell};
+
+/// How many full-size buffers, each large enough for every channel of a whole
+/// callback, a session's scratch arena holds.
+pub const SCRATCH_BUFFERS: usize = 4;
+
+/// Temporary sample buffers for the render callback, allocated once when the
+/// session starts and handed out through `RenderContext::scratch`.
+///
+/// Buffers are carved out of the arena in order and all given back at once
+/// when the callback returns, so nothing is ever allocated or freed on the
+/// real-time thread.
+pub struct Scratch {
+    samples: Box<[UnsafeCell<f32>]>,
+    used: Cell<usize>,
+}
+
+impl Scratch {
+    pub(crate) fn new(max_frames: usize, max_channels: usize) -> Self {
+        let len = max_frames * max_channels * SCRATCH_BUFFERS;
+        Scratch {
+            samples: (0..len).map(|_| UnsafeCell::new(0.0)).collect(),
+            used: Cell::new(0),
+        }
+    }
+
+    /// A zeroed, interleaved buffer of `frames` frames with `channels`
+    /// channels, or None if the arena doesn't have room left for it this
+    /// cycle.
+    // Every buffer handed out is a separate range of the arena, and the
+    // arena can only be reset through `&mut self`, once all of them are gone.
+    #[allow(clippy::mut_from_ref)]
+    pub fn buffer(&self, frames: usize, channels: usize) -> Option<&mut [f32]> {
+        let start = self.used.get();
+        let end = start.checked_add(frames.checked_mul(channels)?)?;
+        if end > self.samples.len() {
+            return None;
+        }
+        self.used.set(end);
+
+        let range = &self.samples[start..end];
+        let samples = unsafe {
+            std::slice::from_raw_parts_mut(UnsafeCell::raw_get(range.as_ptr()), range.len())
+        };
+        samples.fill(0.0);
+        Some(samples)
+    }
+
+    /// Samples still available this cycle.
+    pub fn remaining(&self) -> usize {
+        self.samples.len() - self.used.get()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.used.set(0);
+    }
+}
@@ -0,0 +1,69 @@
+//! Conversions between the platform's host clock, the sample times devices
+//! report, and the standard library's time types.
+//!
+//! Host time is counted in the platform's own ticks, like the ones
+//! `Session::start_at` takes. On macOS that's `mach_absolute_time`, which
+//! doesn't advance while the system sleeps. Elsewhere ticks are nanoseconds.
+
+use std::time::{Duration, Instant};
+
+use crate::host_time;
+
+/// The current host time, in ticks.
+pub fn host_time_now() -> u64 {
+    host_time::now()
+}
+
+pub fn host_ticks_to_duration(ticks: u64) -> Duration {
+    let (numer, denom) = host_time::timebase();
+    let nanos = u128::from(ticks) * u128::from(numer) / u128::from(denom);
+
+    Duration::from_nanos(nanos.min(u128::from(u64::MAX)) as u64)
+}
+
+/// Rounds down to a whole number of ticks.
+pub fn duration_to_host_ticks(duration: Duration) -> u64 {
+    let (numer, denom) = host_time::timebase();
+    let ticks = duration.as_nanos() * u128::from(denom) / u128::from(numer);
+
+    ticks.min(u128::from(u64::MAX)) as u64
+}
+
+/// The `Instant` corresponding to a host time, in the past or the future.
+/// Both clocks are read once to line them up, so the result is only as
+/// accurate as the gap between those two reads, typically well under a
+/// microsecond.
+pub fn host_time_to_instant(host_time: u64) -> Instant {
+    let (now, now_host) = (Instant::now(), host_time_now());
+
+    if host_time >= now_host {
+        now + host_ticks_to_duration(host_time - now_host)
+    } else {
+        now.checked_sub(host_ticks_to_duration(now_host - host_time))
+            .unwrap_or(now)
+    }
+}
+
+/// The host time corresponding to an `Instant`, with the same accuracy as
+/// `host_time_to_instant`. Instants from before the host clock started map
+/// to zero.
+pub fn instant_to_host_time(instant: Instant) -> u64 {
+    let (now, now_host) = (Instant::now(), host_time_now());
+
+    if instant >= now {
+        now_host.saturating_add(duration_to_host_ticks(instant - now))
+    } else {
+        now_host.saturating_sub(duration_to_host_ticks(now - instant))
+    }
+}
+
+/// How long `frames` frames last at `sample_rate`, like the sample times in
+/// device time stamps or `SampleClock::frames`.
+pub fn frames_to_duration(frames: f64, sample_rate: f64) -> Duration {
+    Duration::try_from_secs_f64(frames / sample_rate).unwrap_or_default()
+}
+
+/// How many frames at `sample_rate` last `duration`, with fractions kept.
+pub fn duration_to_frames(duration: Duration, sample_rate: f64) -> f64 {
+    duration.as_secs_f64() * sample_rate
+}
@@ -1,28 +1,246 @@
 use std::error::Error;
 use std::fmt::Debug;
+use std::hash::Hash;
+use std::ops::RangeInclusive;
+use std::slice;
+use std::time::Duration;
 
-pub type RenderCallback<B> =
-    dyn FnMut(&[<B as Backend>::AudioBuffers], &mut [<B as Backend>::AudioBuffers]) + Send;
+use crate::capture::CaptureTarget;
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::{PhysicalFormat, SessionConfig};
+use crate::context::RenderContext;
+use crate::deadline::DeadlineHistogram;
+use crate::device_info::{ClockSource, DataSource, DeviceInfo, DeviceUser};
+use crate::device_watcher::{DeviceEvent, DeviceWatcher};
+use crate::direction::Direction;
+use crate::dropout::DropoutStats;
+use crate::event_log::EventLog;
+#[cfg(feature = "async")]
+use crate::event_stream::EventStream;
+use crate::events::Events;
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::OverloadSubscription;
+use crate::planar::{Channel, ChannelMut, Channels};
+use crate::processor::{processor_callback, Processor};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::sample::{ConvertingProcessor, FramesAs, Sample, SampleFormat, SampleRenderCallback};
+use crate::time;
+use crate::warnings::{Warning, WarningSubscription, WarningThresholds};
+
+pub type RenderCallback<B> = dyn FnMut(&RenderContext<'_>, &[<B as Backend>::AudioBuffers], &mut [<B as Backend>::AudioBuffers])
+    + Send;
+
+/// Name prefixes of virtual devices that loop their output back to their
+/// input, in order of preference.
+const LOOPBACK_DEVICE_NAMES: &[&str] = &[
+    "BlackHole",
+    "Loopback Audio",
+    "Soundflower",
+    "CABLE Output",
+    "Monitor of ",
+];
 
 pub trait Backend: Sized {
     type Session: Session<Self>;
-    type Device: Device<Self> + Debug + Clone;
-    type Error: Error;
+    type Device: Device<Self> + Debug + Clone + Eq + Hash + Ord;
+    type Error: Error + Into<crate::Error>;
     type AudioBuffers: AudioBuffers;
 
     fn new() -> Result<Self, Self::Error>;
 
+    /// Cheaply checks whether the backend can be used on this machine right
+    /// now, without having to construct it and start a session.
+    fn is_available() -> bool;
+
     fn all_devices(&self) -> Result<Vec<Self::Device>, Self::Error>;
     fn default_input_device(&self) -> Result<Self::Device, Self::Error>;
     fn default_output_device(&self) -> Result<Self::Device, Self::Error>;
 
+    /// Like `all_devices`, but sorted by persistent ID, so the order stays the
+    /// same between calls and runs no matter how the platform lists devices.
+    fn all_devices_sorted(&self) -> Result<Vec<Self::Device>, Self::Error> {
+        let mut devices = self
+            .all_devices()?
+            .into_iter()
+            .map(|device| Ok((device.persistent_id()?, device)))
+            .collect::<Result<Vec<_>, Self::Error>>()?;
+        devices.sort();
+
+        Ok(devices.into_iter().map(|(_, device)| device).collect())
+    }
+
+    /// Fetches both default devices, reading them again if either changes in
+    /// the meantime, so that the pair was the default at the same moment.
+    /// Gives up after a few tries and returns the last pair read, rather
+    /// than spinning while the defaults keep changing. Prefer this over two
+    /// separate calls when setting up a session.
+    fn default_devices(&self) -> Result<(Self::Device, Self::Device), Self::Error> {
+        const RETRIES: usize = 3;
+
+        let mut input = self.default_input_device()?;
+        let mut output = self.default_output_device()?;
+
+        for _ in 0..RETRIES {
+            let input_after = self.default_input_device()?;
+            let output_after = self.default_output_device()?;
+            if input_after == input && output_after == output {
+                break;
+            }
+
+            input = input_after;
+            output = output_after;
+        }
+
+        Ok((input, output))
+    }
+
+    /// Lists every device along with its properties, including whether it's
+    /// the default input or output.
+    fn device_infos(&self) -> Result<Vec<DeviceInfo>, Self::Error> {
+        DeviceInfo::collect(self)
+    }
+
+    /// Looks up a device by the ID returned from `Device::persistent_id`.
+    fn device_by_id(&self, id: &str) -> Result<Option<Self::Device>, Self::Error> {
+        for device in self.all_devices()? {
+            if device.persistent_id()? == id {
+                return Ok(Some(device));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The process-wide log of hardware notifications, like devices coming
+    /// and going, default device and sample rate changes, and overloads in
+    /// any session. Backends that don't get notifications only report
+    /// overloads.
+    fn event_log(&self) -> Result<EventLog, Self::Error> {
+        Ok(EventLog::open())
+    }
+
+    /// Calls `f` from a background thread whenever devices are added,
+    /// removed or change, like when the default device moves, until the
+    /// returned watcher is dropped. Backends that get notified of changes
+    /// report them right away, others within a second.
+    fn watch_devices<F>(&self, f: F) -> Result<DeviceWatcher, Self::Error>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+        Self: 'static,
+    {
+        DeviceWatcher::spawn(self, f)
+    }
+
+    /// The same changes as `watch_devices`, as a stream.
+    #[cfg(feature = "async")]
+    fn device_events(&self) -> Result<EventStream<DeviceEvent>, Self::Error>
+    where
+        Self: 'static,
+    {
+        let mut stream = EventStream::new();
+        stream.set_source(self.watch_devices(stream.sender())?);
+        Ok(stream)
+    }
+
+    /// Finds an input device that records what the system plays, such as a
+    /// BlackHole or Soundflower device routed from the system output.
+    ///
+    /// Backends that can capture system audio without a driver, like process
+    /// taps on macOS, do so through `capture_process` with
+    /// `CaptureTarget::System` instead, since those mechanisms don't show up
+    /// as devices.
+    fn system_loopback_input(&self) -> Result<Option<Self::Device>, Self::Error> {
+        let mut best = None;
+        for device in self.all_devices()? {
+            if device.num_inputs()? == 0 {
+                continue;
+            }
+
+            let name = device.name()?;
+            let rank = LOOPBACK_DEVICE_NAMES
+                .iter()
+                .position(|loopback| name.starts_with(loopback));
+            if let Some(rank) = rank {
+                if best.as_ref().is_none_or(|&(best_rank, _)| rank < best_rank) {
+                    best = Some((rank, device));
+                }
+            }
+        }
+
+        Ok(best.map(|(_, device)| device))
+    }
+
+    /// Starts a session with every other setting at its default.
+    #[deprecated(note = "use `start_session_with_config` with a `SessionConfig`")]
     fn start_session(
         &self,
         sample_rate: f64,
         input_device: Self::Device,
         output_device: Self::Device,
         callback: Box<RenderCallback<Self>>,
+    ) -> Result<Self::Session, Self::Error> {
+        self.start_session_with_config(
+            SessionConfig::new(sample_rate, input_device, output_device),
+            callback,
+        )
+    }
+
+    /// Starts a session that only plays to `output_device`. By default this
+    /// is a session with the output device on both ends, so any inputs it has
+    /// still show up as input buffers; backends that can open an output on
+    /// its own do that instead.
+    fn start_playback_session(
+        &self,
+        sample_rate: f64,
+        output_device: Self::Device,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<Self::Session, Self::Error> {
+        self.start_session_with_config(
+            SessionConfig::new(sample_rate, output_device.clone(), output_device),
+            callback,
+        )
+    }
+
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<Self::Session, Self::Error>;
+
+    /// Starts a session that runs `processor`, prepared for the session's
+    /// sample rate and buffers before it starts.
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        processor: P,
     ) -> Result<Self::Session, Self::Error>;
+
+    /// Starts a session whose input is a stereo mixdown of everything the
+    /// target application plays, and whose output is the default output
+    /// device. The application's audio follows any input channels of the
+    /// output device itself.
+    fn capture_process(
+        &self,
+        target: &CaptureTarget,
+        sample_rate: f64,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<Self::Session, Self::Error>;
+
+    /// Starts a session whose callback works in sample type `S`, such as
+    /// `f64`, converting to and from the device's format around it.
+    fn start_session_with_sample_type<S: Sample>(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<SampleRenderCallback<S>>,
+    ) -> Result<Self::Session, Self::Error>
+    where
+        Self: 'static,
+    {
+        self.start_session_with_processor(config, ConvertingProcessor::<S, Self>::new(callback))
+    }
 }
 
 pub trait Session<B: Backend>: Sized {
@@ -31,22 +249,386 @@ pub trait Session<B: Backend>: Sized {
 
     fn set_input_device(&mut self, device: B::Device) -> Result<(), B::Error>;
     fn set_output_device(&mut self, device: B::Device) -> Result<(), B::Error>;
+
+    /// The most frames any single callback will be asked to render, as
+    /// granted by the device. Known as soon as the session exists, so scratch
+    /// buffers can be sized up front rather than grown on the real-time
+    /// thread. `Processor::prepare` is given the same value before the first
+    /// callback.
+    fn max_frames_per_callback(&self) -> Result<usize, B::Error>;
+
+    /// Does any work needed to switch to `devices` ahead of time, so that
+    /// later calls to `set_input_device` and `set_output_device` with them
+    /// are as quick as possible.
+    fn prepare_device_switch(&mut self, _devices: &[B::Device]) -> Result<(), B::Error> {
+        Ok(())
+    }
+
+    /// Which device and channels each buffer in the callback belongs to.
+    fn channel_map(&self) -> Result<ChannelMap, B::Error>;
+
+    fn dropouts(&self) -> DropoutStats;
+
+    fn sample_clock(&self) -> SampleClock;
+
+    /// The rate the session actually runs at, which can differ from the one
+    /// it was started with when the device settled on another.
+    fn sample_rate(&self) -> Result<f64, B::Error> {
+        Ok(self.sample_clock().sample_rate())
+    }
+
+    /// How close callbacks have come to missing their deadline so far.
+    fn deadline_margins(&self) -> DeadlineHistogram;
+
+    fn meters(&self) -> Meters;
+
+    /// Overloads, dropouts and other problems reported by the real-time
+    /// thread, which never logs or blocks to report them itself.
+    fn events(&self) -> Events;
+
+    /// Calls `f` on a background thread when the session keeps running into
+    /// recoverable trouble, like repeated overloads or a drifting clock, at
+    /// most once per `thresholds.min_interval` for each kind of warning.
+    /// Stops when the returned subscription is dropped.
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static;
+
+    /// Callbacks that overran their deadline so far, plus the overloads the
+    /// device reported itself on backends where it does. Both may count the
+    /// same glitch.
+    fn overloads(&self) -> u64;
+
+    /// Calls `f` on a background thread shortly after overloads, with how
+    /// many there were since the last call, until the returned subscription
+    /// is dropped.
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static;
+
+    /// Removes and returns the scopes the callback has timed with
+    /// `RenderContext::scope` since the last call, oldest first. Only the
+    /// most recent `PROFILE_CAPACITY` are kept.
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord>;
+
+    /// Replaces the callback without stopping the session, starting with the
+    /// next cycle. The old callback is dropped on the calling thread, once
+    /// the real-time thread is done with it.
+    fn set_callback(&mut self, callback: Box<RenderCallback<B>>);
+
+    /// Replaces the callback with `processor`, after preparing it for the
+    /// session's current sample rate, buffer size and buffer layout.
+    fn set_processor<P: Processor<B> + 'static>(
+        &mut self,
+        mut processor: P,
+    ) -> Result<(), B::Error> {
+        let channel_map = self.channel_map()?;
+        let channels = |streams: &[StreamMapping]| {
+            streams
+                .iter()
+                .map(|stream| stream.channels)
+                .collect::<Vec<_>>()
+        };
+
+        processor.prepare(self.sample_rate()?, self.max_frames_per_callback()?);
+        processor.prepare_channels(
+            &channels(&channel_map.input),
+            &channels(&channel_map.output),
+        );
+        self.set_callback(processor_callback(processor));
+
+        Ok(())
+    }
+
+    /// While bypassed, the callback isn't called, and input is copied
+    /// straight to the output channel with the same index instead. Outputs
+    /// without a matching input are silent. Takes effect from the next cycle.
+    fn set_bypassed(&self, bypassed: bool);
+    fn is_bypassed(&self) -> bool;
+
+    /// Frames between audio arriving at the input and the callback getting
+    /// it, at the current buffer size. Backends that don't know the latency
+    /// of the device itself count only the buffer.
+    fn input_latency_frames(&self) -> Result<usize, B::Error> {
+        self.max_frames_per_callback()
+    }
+
+    /// Frames between the callback rendering audio and it leaving the
+    /// output, counted the same way.
+    fn output_latency_frames(&self) -> Result<usize, B::Error> {
+        self.max_frames_per_callback()
+    }
+
+    /// The time it takes audio to go from the input, through the callback,
+    /// and out again, to compensate for when recording along with playback.
+    /// Zero while the session has no usable sample rate.
+    fn round_trip_latency(&self) -> Result<Duration, B::Error> {
+        let frames = self.input_latency_frames()? + self.output_latency_frames()?;
+        Ok(time::frames_to_duration(
+            frames as f64,
+            self.sample_clock().sample_rate(),
+        ))
+    }
+
+    /// Shrinks the buffer size until the round trip latency is at or below
+    /// `target_ms`, but no further, then checks that the session runs without
+    /// dropouts at that size, backing off to larger sizes until it does.
+    /// Blocks while each size is tried out. If none of them does, the session
+    /// is put back at the buffer size it had before and this returns `None`.
+    fn tune_for_low_latency(&mut self, target_ms: f64) -> Result<Option<LatencyTuning>, B::Error>;
+
+    /// Stops the session and starts it again at `host_time`, in the units of
+    /// the platform's host clock (`mach_absolute_time` on macOS), so several
+    /// sessions can start in sync. Returns the host time the first cycle will
+    /// actually start at, which may be rounded to the device's I/O cycle.
+    /// The `time` module converts host times to and from `Instant`.
+    fn start_at(&mut self, host_time: u64) -> Result<u64, B::Error>;
+
+    /// Stops calling the callback, but keeps the devices and everything else
+    /// set up, so that `start` picks up again quickly. Settings changed while
+    /// stopped, like the devices or buffer size, apply once started again.
+    fn stop(&mut self) -> Result<(), B::Error>;
+
+    /// Starts a stopped session again. The first cycle after is marked as a
+    /// discontinuity. Does nothing if the session is already running.
+    fn start(&mut self) -> Result<(), B::Error>;
+
+    /// False after `stop`, until `start`.
+    fn is_running(&self) -> bool;
 }
 
 pub trait Device<B: Backend> {
+    /// The number of input channels, across all of the device's streams.
     fn num_inputs(&self) -> Result<usize, B::Error>;
     fn num_outputs(&self) -> Result<usize, B::Error>;
+    /// The name to show users, localized where the driver supports it. Use
+    /// `persistent_id` to identify the device.
     fn name(&self) -> Result<String, B::Error>;
 
+    /// The name of a single channel, counting from zero, if the driver gives
+    /// it one.
+    fn channel_name(
+        &self,
+        _direction: Direction,
+        _channel: usize,
+    ) -> Result<Option<String>, B::Error> {
+        Ok(None)
+    }
+
+    fn data_sources(&self, _direction: Direction) -> Result<Vec<DataSource>, B::Error> {
+        Ok(Vec::new())
+    }
+
+    fn current_data_source(&self, _direction: Direction) -> Result<Option<DataSource>, B::Error> {
+        Ok(None)
+    }
+
+    /// The clocks the device can sync to, on interfaces that have a choice.
+    fn clock_sources(&self) -> Result<Vec<ClockSource>, B::Error> {
+        Ok(Vec::new())
+    }
+
+    fn current_clock_source(&self) -> Result<Option<ClockSource>, B::Error> {
+        Ok(None)
+    }
+
+    /// Makes the device sync to the clock source with `id`, one of those
+    /// from `clock_sources`.
+    fn set_clock_source(&mut self, id: u32) -> Result<(), B::Error>;
+
+    /// The processes currently doing I/O on the device, including this one,
+    /// to explain why a device is busy. May be incomplete where the platform
+    /// doesn't say who is using a device.
+    fn users(&self) -> Result<Vec<DeviceUser>, B::Error> {
+        Ok(Vec::new())
+    }
+
+    /// An identifier that stays the same for the same device across
+    /// reconnections and reboots, unlike the device handle itself.
+    fn persistent_id(&self) -> Result<String, B::Error>;
+
+    /// Whether the device is the system default input right now. False when
+    /// the system has no default input.
+    fn is_default_input(&self) -> Result<bool, B::Error>;
+
+    fn is_default_output(&self) -> Result<bool, B::Error>;
+
+    /// The format callbacks get this device's samples in.
+    fn default_sample_format(&self) -> Result<SampleFormat, B::Error> {
+        Ok(<B::AudioBuffers as AudioBuffers>::Sample::FORMAT)
+    }
+
+    /// The format the hardware itself currently runs at in `direction`, or
+    /// None if it has no streams that way or the platform doesn't say.
+    fn native_format(&self, _direction: Direction) -> Result<Option<PhysicalFormat>, B::Error> {
+        Ok(None)
+    }
+
+    /// Bits per sample of the hardware format in `direction`.
+    fn bit_depth(&self, direction: Direction) -> Result<Option<u32>, B::Error> {
+        Ok(self
+            .native_format(direction)?
+            .map(|format| format.bits_per_sample))
+    }
+
     fn set_nominal_sample_rate(&mut self, sample_rate: f64) -> Result<(), B::Error>;
     fn nominal_sample_rate(&self) -> Result<f64, B::Error>;
     fn actual_sample_rate(&self) -> Result<f64, B::Error>;
+
+    /// The nominal sample rates the device can be set to, as ranges.
+    /// Devices with a fixed list of rates give each one as a single-value
+    /// range. Defaults to just the current rate.
+    fn available_sample_rates(&self) -> Result<Vec<RangeInclusive<f64>>, B::Error> {
+        let rate = self.nominal_sample_rate()?;
+        Ok(vec![rate..=rate])
+    }
+
+    /// The smallest and largest number of frames per cycle the device
+    /// accepts, or None if the platform doesn't say.
+    fn buffer_frame_size_range(&self) -> Result<Option<RangeInclusive<usize>>, B::Error> {
+        Ok(None)
+    }
+}
+
+/// Hardware volume and mute controls, for backends whose devices have them.
+/// `channel` counts from zero, and None is the device's master control,
+/// which many devices don't have. Controls a device lacks are errors.
+pub trait DeviceControls<B: Backend>: Device<B> {
+    /// From 0 to 1, on the curve the system's own volume slider uses.
+    fn volume(&self, direction: Direction, channel: Option<usize>) -> Result<f32, B::Error>;
+    fn set_volume(
+        &mut self,
+        direction: Direction,
+        channel: Option<usize>,
+        volume: f32,
+    ) -> Result<(), B::Error>;
+
+    fn is_muted(&self, direction: Direction, channel: Option<usize>) -> Result<bool, B::Error>;
+    fn set_muted(
+        &mut self,
+        direction: Direction,
+        channel: Option<usize>,
+        muted: bool,
+    ) -> Result<(), B::Error>;
 }
 
 pub trait AudioBuffers {
+    /// The type the device's samples are stored in. Use `Sample::to_f32` and
+    /// `Sample::from_f32` to process them without caring which it is.
+    type Sample: Sample;
+
     fn num_frames(&self) -> usize;
     fn num_channels(&self) -> usize;
 
-    fn interleaved_frames(&self) -> &[f32];
-    fn interleaved_frames_mut(&mut self) -> &mut [f32];
+    fn interleaved_frames(&self) -> &[Self::Sample];
+    fn interleaved_frames_mut(&mut self) -> &mut [Self::Sample];
+
+    /// The interleaved samples converted to `T`, for callbacks that process
+    /// in a type other than the device's.
+    fn frames_as<T: Sample>(&self) -> FramesAs<'_, Self::Sample, T> {
+        self.interleaved_frames()
+            .iter()
+            .copied()
+            .map(Sample::convert::<T>)
+    }
+
+    /// Converts `samples`, interleaved, into the buffer. Stops at the end of
+    /// whichever of the two is shorter.
+    fn copy_frames_from<T: Sample>(&mut self, samples: &[T]) {
+        for (sample, &from) in self.interleaved_frames_mut().iter_mut().zip(samples) {
+            *sample = from.convert();
+        }
+    }
+
+    /// The samples one frame at a time, each a slice with one sample per
+    /// channel.
+    fn frames(&self) -> slice::ChunksExact<'_, Self::Sample> {
+        // A buffer without channels has no samples, and no frames to yield.
+        let channels = self.num_channels().max(1);
+        self.interleaved_frames().chunks_exact(channels)
+    }
+
+    fn frames_mut(&mut self) -> slice::ChunksExactMut<'_, Self::Sample> {
+        let channels = self.num_channels().max(1);
+        self.interleaved_frames_mut().chunks_exact_mut(channels)
+    }
+
+    /// The samples as one array per frame, like `&[[f32; 2]]` for stereo, or
+    /// None if the buffer doesn't have exactly `N` channels.
+    fn frames_chunked<const N: usize>(&self) -> Option<&[[Self::Sample; N]]> {
+        if N == 0 || self.num_channels() != N {
+            return None;
+        }
+
+        let samples = self.interleaved_frames();
+        let frames = samples.len() / N;
+        Some(unsafe { slice::from_raw_parts(samples.as_ptr() as *const [Self::Sample; N], frames) })
+    }
+
+    fn frames_chunked_mut<const N: usize>(&mut self) -> Option<&mut [[Self::Sample; N]]> {
+        if N == 0 || self.num_channels() != N {
+            return None;
+        }
+
+        let samples = self.interleaved_frames_mut();
+        let frames = samples.len() / N;
+        Some(unsafe {
+            slice::from_raw_parts_mut(samples.as_mut_ptr() as *mut [Self::Sample; N], frames)
+        })
+    }
+
+    /// The samples as `[left, right]` pairs, or None if the buffer isn't
+    /// stereo.
+    fn as_stereo(&self) -> Option<&[[Self::Sample; 2]]> {
+        self.frames_chunked::<2>()
+    }
+
+    fn as_stereo_mut(&mut self) -> Option<&mut [[Self::Sample; 2]]> {
+        self.frames_chunked_mut::<2>()
+    }
+
+    /// The samples of a mono buffer, or None if it has more channels.
+    fn as_mono(&self) -> Option<&[Self::Sample]> {
+        if self.num_channels() != 1 {
+            return None;
+        }
+
+        Some(self.interleaved_frames())
+    }
+
+    fn as_mono_mut(&mut self) -> Option<&mut [Self::Sample]> {
+        if self.num_channels() != 1 {
+            return None;
+        }
+
+        Some(self.interleaved_frames_mut())
+    }
+
+    /// Splits the interleaved samples into the frames before `frame` and the
+    /// frames from it on. Panics if `frame > num_frames()`.
+    fn split_at_frame(&self, frame: usize) -> (&[Self::Sample], &[Self::Sample]) {
+        let channels = self.num_channels();
+        self.interleaved_frames().split_at(frame * channels)
+    }
+
+    fn split_at_frame_mut(&mut self, frame: usize) -> (&mut [Self::Sample], &mut [Self::Sample]) {
+        let channels = self.num_channels();
+        self.interleaved_frames_mut().split_at_mut(frame * channels)
+    }
+
+    /// One channel's samples, read in place, for code that works a channel
+    /// at a time. Panics if `index >= num_channels()`.
+    fn channel(&self, index: usize) -> Channel<'_, Self::Sample> {
+        Channel::new(self.interleaved_frames(), index, self.num_channels())
+    }
+
+    fn channel_mut(&mut self, index: usize) -> ChannelMut<'_, Self::Sample> {
+        let channels = self.num_channels();
+        ChannelMut::new(self.interleaved_frames_mut(), index, channels)
+    }
+
+    fn channels(&self) -> Channels<'_, Self::Sample> {
+        Channels::new(self.interleaved_frames(), self.num_channels())
+    }
 }
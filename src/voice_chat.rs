@@ -0,0 +1,316 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::config::SessionConfig;
+use crate::context::RenderContext;
+use crate::processor::Processor;
+use crate::ring_buffer::{ring_buffer, Consumer, Producer};
+use crate::sample::Sample;
+use crate::traits::{AudioBuffers, Backend, Session};
+
+pub const VOICE_CHAT_SAMPLE_RATE: f64 = 48000.0;
+
+/// How much capture and playback audio can be buffered between the
+/// application and the real-time thread.
+const BUFFER_FRAMES: usize = 48000;
+
+#[derive(Debug)]
+pub enum VoiceChatError<E> {
+    Backend(E),
+    /// The device runs at this rate instead of `VOICE_CHAT_SAMPLE_RATE`.
+    SampleRate(f64),
+}
+
+impl<E: fmt::Display> fmt::Display for VoiceChatError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoiceChatError::Backend(e) => write!(f, "Audio backend error: {}", e),
+            VoiceChatError::SampleRate(rate) => write!(
+                f,
+                "The device runs at {} Hz instead of {} Hz",
+                rate, VOICE_CHAT_SAMPLE_RATE
+            ),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for VoiceChatError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VoiceChatError::Backend(e) => Some(e),
+            VoiceChatError::SampleRate(_) => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct VoiceChatStats {
+    capture_overflows: AtomicU64,
+    playback_underruns: AtomicU64,
+}
+
+/// A 48 kHz mono capture and playback session for voice communication.
+///
+/// All input channels are mixed down to a single capture channel, and the
+/// mono playback signal is sent to every channel of the first output stream
+/// that has any. Capture and playback go through lock-free buffers, so the
+/// network and codec threads never touch the real-time thread directly.
+///
+/// Starting fails with `VoiceChatError::SampleRate` if the device doesn't
+/// run at 48 kHz.
+///
+/// The session asks for voice processing, which only the iOS backend has so
+/// far: it cancels the playback's echo from the capture and evens out its
+/// level. Elsewhere, use headphones, or run the capture through a separate
+/// echo canceller fed with the playback signal.
+pub struct VoiceChatSession<B: Backend> {
+    session: B::Session,
+    capture: Consumer,
+    playback: Producer,
+    stats: Arc<VoiceChatStats>,
+}
+
+impl<B: Backend> VoiceChatSession<B> {
+    pub fn start(backend: &B) -> Result<Self, VoiceChatError<B::Error>> {
+        let (input_device, output_device) =
+            backend.default_devices().map_err(VoiceChatError::Backend)?;
+
+        Self::start_with_devices(backend, input_device, output_device)
+    }
+
+    pub fn start_with_devices(
+        backend: &B,
+        input_device: B::Device,
+        output_device: B::Device,
+    ) -> Result<Self, VoiceChatError<B::Error>> {
+        let (capture_producer, capture) = ring_buffer(BUFFER_FRAMES);
+        let (playback, playback_consumer) = ring_buffer(BUFFER_FRAMES);
+        let stats = Arc::new(VoiceChatStats::default());
+
+        let config = SessionConfig::new(VOICE_CHAT_SAMPLE_RATE, input_device, output_device)
+            .voice_processing(true);
+        let session = backend
+            .start_session_with_processor(
+                config,
+                VoiceChatProcessor {
+                    capture: capture_producer,
+                    playback: playback_consumer,
+                    stats: stats.clone(),
+                    scratch: Vec::new(),
+                },
+            )
+            .map_err(VoiceChatError::Backend)?;
+
+        let sample_rate = session.sample_rate().map_err(VoiceChatError::Backend)?;
+        if sample_rate != VOICE_CHAT_SAMPLE_RATE {
+            return Err(VoiceChatError::SampleRate(sample_rate));
+        }
+
+        Ok(VoiceChatSession {
+            session,
+            capture,
+            playback,
+            stats,
+        })
+    }
+
+    pub fn session(&self) -> &B::Session {
+        &self.session
+    }
+
+    pub fn session_mut(&mut self) -> &mut B::Session {
+        &mut self.session
+    }
+
+    /// Number of captured samples waiting to be read.
+    pub fn capture_available(&self) -> usize {
+        self.capture.len()
+    }
+
+    /// Reads captured samples into `out` and returns how many were read.
+    pub fn read_capture(&mut self, out: &mut [f32]) -> usize {
+        self.capture.pop(out)
+    }
+
+    /// Number of samples that can currently be queued for playback.
+    pub fn playback_free(&self) -> usize {
+        self.playback.free_len()
+    }
+
+    /// Queues samples for playback. Returns false, without queueing anything,
+    /// if there isn't room for all of them.
+    pub fn write_playback(&mut self, samples: &[f32]) -> bool {
+        self.playback.try_push(samples)
+    }
+
+    /// Captured frames lost because they weren't read quickly enough.
+    pub fn capture_overflows(&self) -> u64 {
+        self.stats.capture_overflows.load(Ordering::Relaxed)
+    }
+
+    /// Frames of silence played because no playback audio was queued.
+    pub fn playback_underruns(&self) -> u64 {
+        self.stats.playback_underruns.load(Ordering::Relaxed)
+    }
+}
+
+struct VoiceChatProcessor {
+    capture: Producer,
+    playback: Consumer,
+    stats: Arc<VoiceChatStats>,
+    scratch: Vec<f32>,
+}
+
+impl VoiceChatProcessor {
+    fn capture<A: AudioBuffers>(&mut self, input: &[A], frames: usize) {
+        let channels: usize = input.iter().map(|buffer| buffer.num_channels()).sum();
+        if channels == 0 {
+            return;
+        }
+
+        let mono = &mut self.scratch[..frames];
+        for sample in mono.iter_mut() {
+            *sample = 0.0;
+        }
+
+        for buffer in input {
+            let stride = buffer.num_channels();
+            if stride == 0 {
+                continue;
+            }
+
+            for (sample, frame) in mono
+                .iter_mut()
+                .zip(buffer.interleaved_frames().chunks(stride))
+            {
+                *sample += frame.iter().map(|sample| sample.to_f32()).sum::<f32>();
+            }
+        }
+
+        let scale = 1.0 / channels as f32;
+        for sample in mono.iter_mut() {
+            *sample *= scale;
+        }
+
+        if !self.capture.try_push(mono) {
+            self.stats
+                .capture_overflows
+                .fetch_add(frames as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn play<A: AudioBuffers>(&mut self, output: &mut [A], frames: usize) {
+        let mono = &mut self.scratch[..frames];
+        let played = self.playback.pop(mono);
+        for sample in &mut mono[played..] {
+            *sample = 0.0;
+        }
+
+        if played < frames {
+            self.stats
+                .playback_underruns
+                .fetch_add((frames - played) as u64, Ordering::Relaxed);
+        }
+
+        let mut first = true;
+        for buffer in output.iter_mut() {
+            let stride = buffer.num_channels();
+            if stride == 0 {
+                continue;
+            }
+
+            for (frame, &sample) in buffer
+                .interleaved_frames_mut()
+                .chunks_mut(stride)
+                .zip(mono.iter())
+            {
+                for target in frame {
+                    *target = A::Sample::from_f32(if first { sample } else { 0.0 });
+                }
+            }
+            first = false;
+        }
+    }
+}
+
+impl<B: Backend> Processor<B> for VoiceChatProcessor {
+    fn prepare(&mut self, _sample_rate: f64, max_frames: usize) {
+        self.scratch = vec![0.0; max_frames];
+    }
+
+    fn process(
+        &mut self,
+        input: &[B::AudioBuffers],
+        output: &mut [B::AudioBuffers],
+        _ctx: &RenderContext<'_>,
+    ) {
+        let frames = output
+            .iter()
+            .chain(input)
+            .find(|buffer| buffer.num_channels() > 0)
+            .map_or(0, |buffer| buffer.num_frames())
+            .min(self.scratch.len());
+
+        self.capture(input, frames);
+        self.play(output, frames);
+    }
+
+    fn reset(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::{VoiceChatProcessor, VoiceChatStats};
+    use crate::context::RenderContext;
+    use crate::processor::Processor;
+    use crate::ring_buffer::ring_buffer;
+    use crate::sample::testing::wide_layout;
+    use crate::sample::OwnedBuffer;
+    use crate::scratch::Scratch;
+    use crate::NullBackend;
+
+    const FRAMES: usize = 16;
+
+    #[test]
+    fn skips_streams_without_channels() {
+        let mut input = vec![OwnedBuffer::with_capacity(0, 0)];
+        input.extend(wide_layout(1, 2, FRAMES, |channel, _| channel as f32));
+        let mut output = vec![OwnedBuffer::with_capacity(0, 0)];
+        output.extend(wide_layout(2, 2, FRAMES, |_, _| 1.0));
+
+        let (capture_producer, mut capture) = ring_buffer(FRAMES);
+        let (mut playback, playback_consumer) = ring_buffer(FRAMES);
+        let stats = Arc::new(VoiceChatStats::default());
+        let mut processor = VoiceChatProcessor {
+            capture: capture_producer,
+            playback: playback_consumer,
+            stats: stats.clone(),
+            scratch: Vec::new(),
+        };
+        Processor::<NullBackend>::prepare(&mut processor, 48_000.0, FRAMES);
+        assert!(playback.try_push(&[0.25; FRAMES]));
+
+        let valid = AtomicBool::new(true);
+        let scratch = Scratch::new(FRAMES, 2);
+        let ctx = RenderContext::for_test(&valid, &scratch, FRAMES);
+        Processor::<NullBackend>::process(&mut processor, &input, &mut output, &ctx);
+
+        let mut captured = [0.0; FRAMES];
+        assert_eq!(capture.pop(&mut captured), FRAMES);
+        assert!(captured.iter().all(|&sample| sample == 0.5));
+
+        assert!(output[1]
+            .interleaved_frames()
+            .iter()
+            .all(|&sample| sample == 0.25));
+        assert!(output[2]
+            .interleaved_frames()
+            .iter()
+            .all(|&sample| sample == 0.0));
+        assert_eq!(stats.playback_underruns.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::dropout::DropoutStats;
+
+/// How often the session's counters are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the sample clock has to run undisturbed before its drift is
+/// judged. Shorter periods are dominated by the jitter of callback timing.
+const DRIFT_MEASUREMENT_PERIOD: Duration = Duration::from_secs(30);
+
+/// A recoverable problem with a session, worth showing to users as a sign
+/// that the audio system is struggling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Warning {
+    /// `count` callbacks overran their deadline within `window`.
+    RepeatedOverloads { count: u64, window: Duration },
+    /// `count` dropouts, losing `frames` frames in total, since the last time
+    /// this was reported.
+    Dropouts { count: u64, frames: u64 },
+    /// `count` cycles had buffers that didn't match the expected layout since
+    /// the last time this was reported.
+    InvalidBuffers { count: u64 },
+    /// The device's clock runs this many parts per million faster, or slower
+    /// if negative, than its nominal sample rate says, measured against the
+    /// system clock.
+    ClockDrift { ppm: f64 },
+}
+
+/// When to warn, and how often.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WarningThresholds {
+    /// Overruns within a second that count as repeated overloads.
+    pub overloads_per_second: u64,
+    pub drift_ppm: f64,
+    /// Each kind of warning is reported at most once per this interval. What
+    /// happens in between is folded into the next report where the warning
+    /// carries a count, and dropped otherwise.
+    pub min_interval: Duration,
+}
+
+impl Default for WarningThresholds {
+    fn default() -> Self {
+        WarningThresholds {
+            overloads_per_second: 3,
+            drift_ppm: 1000.0,
+            min_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The session counters warnings are derived from.
+pub(crate) struct WarningSnapshot {
+    pub overruns: u64,
+    pub dropouts: DropoutStats,
+    pub invalid_buffers: u64,
+    pub frames: u64,
+    pub sample_rate: f64,
+}
+
+#[derive(Default)]
+struct RateLimit {
+    last: Option<Instant>,
+}
+
+impl RateLimit {
+    fn allow(&mut self, now: Instant, interval: Duration) -> bool {
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < interval)
+        {
+            return false;
+        }
+
+        self.last = Some(now);
+        true
+    }
+}
+
+/// Turns successive snapshots into warnings.
+struct WarningMonitor {
+    thresholds: WarningThresholds,
+    previous: WarningSnapshot,
+    limits: [RateLimit; 4],
+    pending_dropouts: (u64, u64),
+    pending_invalid_buffers: u64,
+    drift_baseline: (Instant, u64),
+}
+
+impl WarningMonitor {
+    fn new(thresholds: WarningThresholds, first: WarningSnapshot) -> Self {
+        WarningMonitor {
+            thresholds,
+            drift_baseline: (Instant::now(), first.frames),
+            previous: first,
+            limits: Default::default(),
+            pending_dropouts: (0, 0),
+            pending_invalid_buffers: 0,
+        }
+    }
+
+    fn check(&mut self, current: WarningSnapshot, mut f: impl FnMut(Warning)) {
+        let now = Instant::now();
+        let interval = self.thresholds.min_interval;
+        let previous = &self.previous;
+
+        let overruns = current.overruns.saturating_sub(previous.overruns);
+        if overruns >= self.thresholds.overloads_per_second && self.limits[0].allow(now, interval) {
+            f(Warning::RepeatedOverloads {
+                count: overruns,
+                window: CHECK_INTERVAL,
+            });
+        }
+
+        let dropouts = current
+            .dropouts
+            .count
+            .saturating_sub(previous.dropouts.count);
+        self.pending_dropouts.0 += dropouts;
+        self.pending_dropouts.1 += current
+            .dropouts
+            .total_dropped_frames
+            .saturating_sub(previous.dropouts.total_dropped_frames);
+        if self.pending_dropouts.0 > 0 && self.limits[1].allow(now, interval) {
+            let (count, frames) = std::mem::take(&mut self.pending_dropouts);
+            f(Warning::Dropouts { count, frames });
+        }
+
+        self.pending_invalid_buffers += current
+            .invalid_buffers
+            .saturating_sub(previous.invalid_buffers);
+        if self.pending_invalid_buffers > 0 && self.limits[2].allow(now, interval) {
+            f(Warning::InvalidBuffers {
+                count: std::mem::take(&mut self.pending_invalid_buffers),
+            });
+        }
+
+        // Anything that stops or skips the clock makes it look slow, so the
+        // measurement starts over.
+        let disturbed = current.frames == previous.frames
+            || dropouts > 0
+            || overruns > 0
+            || current.sample_rate != previous.sample_rate;
+        if disturbed {
+            self.drift_baseline = (now, current.frames);
+        } else {
+            let (since, frames) = self.drift_baseline;
+            let elapsed = now.duration_since(since);
+            if elapsed >= DRIFT_MEASUREMENT_PERIOD {
+                let expected = elapsed.as_secs_f64() * current.sample_rate;
+                let ppm = ((current.frames - frames) as f64 / expected - 1.0) * 1e6;
+                if ppm.abs() > self.thresholds.drift_ppm && self.limits[3].allow(now, interval) {
+                    f(Warning::ClockDrift { ppm });
+                }
+            }
+        }
+
+        self.previous = current;
+    }
+}
+
+/// Calls `f` on a background thread with warnings derived from `snapshot`,
+/// until the returned subscription is dropped. On targets without threads,
+/// like wasm32-unknown-unknown, `f` is never called.
+pub(crate) fn watch<S, F>(
+    thresholds: WarningThresholds,
+    mut snapshot: S,
+    mut f: F,
+) -> WarningSubscription
+where
+    S: FnMut() -> WarningSnapshot + Send + 'static,
+    F: FnMut(Warning) + Send + 'static,
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+
+    let thread = thread::Builder::new()
+        .name("render_callback warnings".to_owned())
+        .spawn(move || {
+            let mut monitor = WarningMonitor::new(thresholds, snapshot());
+
+            loop {
+                thread::park_timeout(CHECK_INTERVAL);
+                if thread_stop.load(Ordering::Acquire) {
+                    break;
+                }
+
+                monitor.check(snapshot(), &mut f);
+            }
+        })
+        .ok();
+
+    WarningSubscription { stop, thread }
+}
+
+pub struct WarningSubscription {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for WarningSubscription {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
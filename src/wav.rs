@@ -0,0 +1,378 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sample::{Sample, SampleFormat};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// KSDATAFORMAT_SUBTYPE_IEEE_FLOAT, in on-disk byte order.
+const SUBTYPE_IEEE_FLOAT: [u8; 16] = [
+    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+const BEXT_SIZE: u32 = 602;
+
+/// The largest `fmt ` chunk read: a `WAVE_FORMAT_EXTENSIBLE` one is 40
+/// bytes, and anything much bigger is a broken or hostile file.
+const MAX_FMT_SIZE: u32 = 64;
+
+/// Writes 32 bit float WAV files with a broadcast extension (`bext`) chunk
+/// recording when the recording started.
+///
+/// The chunk sizes in the header are placeholders until `finish` is called.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    channels: u16,
+    frames: u64,
+    fact_offset: u64,
+    data_offset: u64,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(
+        mut writer: W,
+        sample_rate: f64,
+        channels: u16,
+        started_at: SystemTime,
+    ) -> io::Result<Self> {
+        let sample_rate = sample_rate.round() as u32;
+        let block_align = channels
+            .checked_mul(4)
+            .ok_or_else(|| invalid_input("too many channels for a WAV file"))?;
+        let byte_rate = sample_rate
+            .checked_mul(u32::from(block_align))
+            .ok_or_else(|| invalid_input("sample rate too high for a WAV file"))?;
+        let extensible = channels > 2;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&(if extensible { 40u32 } else { 18 }).to_le_bytes())?;
+        writer.write_all(
+            &(if extensible {
+                WAVE_FORMAT_EXTENSIBLE
+            } else {
+                WAVE_FORMAT_IEEE_FLOAT
+            })
+            .to_le_bytes(),
+        )?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&32u16.to_le_bytes())?;
+        if extensible {
+            writer.write_all(&22u16.to_le_bytes())?;
+            writer.write_all(&32u16.to_le_bytes())?;
+            // No speaker positions: the channels are whatever the device has.
+            writer.write_all(&0u32.to_le_bytes())?;
+            writer.write_all(&SUBTYPE_IEEE_FLOAT)?;
+        } else {
+            writer.write_all(&0u16.to_le_bytes())?;
+        }
+
+        writer.write_all(b"fact")?;
+        writer.write_all(&4u32.to_le_bytes())?;
+        let fact_offset = writer.stream_position()?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        writer.write_all(b"bext")?;
+        writer.write_all(&BEXT_SIZE.to_le_bytes())?;
+        writer.write_all(&bext(sample_rate, started_at))?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        let data_offset = writer.stream_position()?;
+
+        Ok(WavWriter {
+            writer,
+            channels,
+            frames: 0,
+            fact_offset,
+            data_offset,
+        })
+    }
+
+    /// Appends interleaved samples. `samples` must hold whole frames.
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        debug_assert_eq!(samples.len() % usize::from(self.channels), 0);
+
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.frames += (samples.len() / usize::from(self.channels)) as u64;
+
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Fills in the chunk sizes. Files too large for the 32 bit RIFF sizes
+    /// get saturated ones, which most readers treat as "until end of file".
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.frames * u64::from(self.channels) * 4;
+        let riff_size = self.data_offset - 8 + data_size;
+
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&saturate(riff_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(self.fact_offset))?;
+        self.writer
+            .write_all(&saturate(self.frames).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(self.data_offset - 4))?;
+        self.writer.write_all(&saturate(data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+/// Reads integer PCM and floating point WAV files, converting the samples to
+/// `f32`. 8 bit files aren't supported.
+pub struct WavReader<R: Read> {
+    reader: R,
+    format: SampleFormat,
+    channels: u16,
+    sample_rate: u32,
+    /// Frames left in the data chunk. Saturated sizes, as written by
+    /// `WavWriter` for huge files, are read until the end of the file.
+    frames_left: u64,
+    bytes: Vec<u8>,
+}
+
+impl<R: Read> WavReader<R> {
+    /// Reads the header, leaving the reader at the start of the samples.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            return Err(invalid_data("not a WAV file"));
+        }
+
+        let mut fmt = None;
+        loop {
+            let mut chunk = [0; 8];
+            reader.read_exact(&mut chunk)?;
+            let size = u32_at(&chunk, 4);
+
+            match &chunk[0..4] {
+                b"fmt " => {
+                    if size > MAX_FMT_SIZE {
+                        return Err(invalid_data("fmt chunk too long"));
+                    }
+                    // Only the extensible fields matter; the rest is skipped.
+                    let mut body = vec![0; size.min(40) as usize];
+                    reader.read_exact(&mut body)?;
+                    fmt = Some(parse_fmt(&body)?);
+                    skip(&mut reader, u64::from(size - size.min(40) + size % 2))?;
+                }
+                b"data" => {
+                    let (format, channels, sample_rate) =
+                        fmt.ok_or_else(|| invalid_data("data chunk before fmt chunk"))?;
+                    let frame_bytes = u64::from(channels) * u64::from(format.bits_per_sample() / 8);
+                    let frames_left = if size == u32::MAX {
+                        u64::MAX
+                    } else {
+                        u64::from(size) / frame_bytes
+                    };
+
+                    return Ok(WavReader {
+                        reader,
+                        format,
+                        channels,
+                        sample_rate,
+                        frames_left,
+                        bytes: Vec::new(),
+                    });
+                }
+                _ => skip(&mut reader, u64::from(size) + u64::from(size % 2))?,
+            }
+        }
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Fills `samples`, which must hold whole frames, with as many frames
+    /// as are left, and returns how many that was. Zero means the end of the
+    /// file.
+    pub fn read(&mut self, samples: &mut [f32]) -> io::Result<usize> {
+        let channels = usize::from(self.channels);
+        debug_assert_eq!(samples.len() % channels, 0);
+
+        let sample_bytes = (self.format.bits_per_sample() / 8) as usize;
+        let frames =
+            (samples.len() / channels).min(self.frames_left.min(usize::MAX as u64) as usize);
+        self.bytes.resize(frames * channels * sample_bytes, 0);
+
+        // Short reads only happen at the end of a file whose data chunk
+        // claims more than it has.
+        let mut filled = 0;
+        while filled < self.bytes.len() {
+            match self.reader.read(&mut self.bytes[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        let frames = filled / (channels * sample_bytes);
+        self.frames_left -= frames as u64;
+
+        let bytes = self.bytes[..frames * channels * sample_bytes].chunks_exact(sample_bytes);
+        for (sample, bytes) in samples.iter_mut().zip(bytes) {
+            *sample = match self.format {
+                SampleFormat::I16 => f32::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32_768.0,
+                SampleFormat::I24 => {
+                    // Shifted into the top of an i32 to sign extend it.
+                    i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]).to_f32()
+                }
+                SampleFormat::I32 => {
+                    i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_f32()
+                }
+                SampleFormat::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                SampleFormat::F64 => {
+                    let mut le = [0; 8];
+                    le.copy_from_slice(bytes);
+                    f64::from_le_bytes(le) as f32
+                }
+            };
+        }
+
+        Ok(frames)
+    }
+}
+
+/// The sample format, channel count and sample rate in a `fmt ` chunk.
+fn parse_fmt(body: &[u8]) -> io::Result<(SampleFormat, u16, u32)> {
+    if body.len() < 16 {
+        return Err(invalid_data("fmt chunk too short"));
+    }
+
+    let mut tag = u16_at(body, 0);
+    let channels = u16_at(body, 2);
+    let sample_rate = u32_at(body, 4);
+    let bits_per_sample = u16_at(body, 14);
+    if tag == WAVE_FORMAT_EXTENSIBLE {
+        if body.len() < 40 {
+            return Err(invalid_data("fmt chunk too short"));
+        }
+        // The subformat GUID starts with the format tag it stands for.
+        tag = u16_at(body, 24);
+    }
+
+    let is_float = match tag {
+        WAVE_FORMAT_PCM => false,
+        WAVE_FORMAT_IEEE_FLOAT => true,
+        _ => return Err(invalid_data("unsupported WAV format")),
+    };
+    let format = SampleFormat::from_bits(u32::from(bits_per_sample), is_float)
+        .ok_or_else(|| invalid_data("unsupported WAV sample size"))?;
+    if channels == 0 {
+        return Err(invalid_data("WAV file without channels"));
+    }
+
+    Ok((format, channels, sample_rate))
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn skip<R: Read>(reader: &mut R, bytes: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.by_ref().take(bytes), &mut io::sink())?;
+    if skipped < bytes {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn saturate(size: u64) -> u32 {
+    size.min(u64::from(u32::MAX)) as u32
+}
+
+/// The EBU Tech 3285 broadcast extension chunk body. Dates and times are in
+/// UTC, and the time reference counts samples since midnight.
+fn bext(sample_rate: u32, started_at: SystemTime) -> Vec<u8> {
+    let since_epoch = started_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days((since_epoch / 86400) as i64);
+    let seconds_today = since_epoch % 86400;
+    let time_reference = seconds_today * u64::from(sample_rate);
+
+    let mut chunk = Vec::with_capacity(BEXT_SIZE as usize);
+    // Description, originator and originator reference.
+    chunk.resize(256, 0);
+    chunk.extend_from_slice(&fixed_ascii(env!("CARGO_PKG_NAME"), 32));
+    chunk.resize(256 + 32 + 32, 0);
+    chunk.extend_from_slice(format!("{:04}-{:02}-{:02}", year, month, day).as_bytes());
+    chunk.extend_from_slice(
+        format!(
+            "{:02}:{:02}:{:02}",
+            seconds_today / 3600,
+            seconds_today / 60 % 60,
+            seconds_today % 60
+        )
+        .as_bytes(),
+    );
+    chunk.extend_from_slice(&time_reference.to_le_bytes());
+    // Version 1, which adds the UMID. The loudness fields of version 2 would
+    // require us to measure the recording.
+    chunk.extend_from_slice(&1u16.to_le_bytes());
+    chunk.resize(BEXT_SIZE as usize, 0);
+
+    chunk
+}
+
+fn fixed_ascii(s: &str, len: usize) -> Vec<u8> {
+    let mut bytes = s.as_bytes()[..s.len().min(len)].to_vec();
+    bytes.resize(len, 0);
+    bytes
+}
+
+/// Converts days since the Unix epoch to a proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
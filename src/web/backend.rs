@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use js_sys::{global, Reflect};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{MediaDeviceInfo, MediaDeviceKind};
+
+use crate::capture::CaptureTarget;
+use crate::config::SessionConfig;
+use crate::device_watcher::{DeviceEvent, DeviceWatcher};
+use crate::direction::Direction;
+use crate::event_log::EventLog;
+use crate::processor::{processor_callback, Processor};
+use crate::sample::OwnedBuffer;
+use crate::traits::{Backend, RenderCallback};
+
+use super::device::{WebDevice, DEFAULT_DEVICE_ID};
+use super::error::WebError;
+use super::session::WebSession;
+
+/// Web Audio in a browser page. Everything happens on the thread that
+/// created the backend, which is usually the page's main thread.
+///
+/// Listing devices is asynchronous in browsers, so `all_devices` returns
+/// what the last `refresh_devices` found, which is only the defaults until
+/// it has been awaited once.
+pub struct WebBackend {
+    devices: Rc<RefCell<Vec<WebDevice>>>,
+}
+
+impl WebBackend {
+    /// Fetches the page's media devices. Browsers only list the devices of a
+    /// kind once the page has been allowed to use one of them.
+    pub async fn refresh_devices(&self) -> Result<(), WebError> {
+        let media_devices = web_sys::window()
+            .ok_or(WebError::NoWindow)?
+            .navigator()
+            .media_devices()?;
+        let infos = JsFuture::from(media_devices.enumerate_devices()?).await?;
+
+        let mut devices = default_devices();
+        for info in js_sys::Array::from(&infos).iter() {
+            let info: MediaDeviceInfo = info.unchecked_into();
+            let direction = match info.kind() {
+                MediaDeviceKind::Audioinput => Direction::Input,
+                MediaDeviceKind::Audiooutput => Direction::Output,
+                _ => continue,
+            };
+            // The defaults are listed under their own IDs too.
+            if info.device_id() == DEFAULT_DEVICE_ID || info.device_id().is_empty() {
+                continue;
+            }
+
+            devices.push(WebDevice::new(info.device_id(), info.label(), direction));
+        }
+
+        *self.devices.borrow_mut() = devices;
+        Ok(())
+    }
+
+    /// A session set up the way `config` asks, but not started yet.
+    fn configured_session(&self, config: &SessionConfig<Self>) -> Result<WebSession, WebError> {
+        if config.physical_format.is_some() {
+            return Err(WebError::Unsupported("physical formats"));
+        }
+        if config.preroll_buffers > 0 {
+            return Err(WebError::Unsupported("preroll"));
+        }
+        if config.routing.is_some() {
+            return Err(WebError::Unsupported("routing matrices"));
+        }
+        if !config.additional_input_devices.is_empty()
+            || !config.additional_output_devices.is_empty()
+        {
+            return Err(WebError::Unsupported("more than one device per direction"));
+        }
+
+        let session = WebSession::new(
+            config.sample_rate,
+            config.input_device.clone(),
+            config.output_device.clone(),
+            config.buffer_size,
+        )?;
+        session.set_output_policy(config.output_policy);
+
+        Ok(session)
+    }
+}
+
+fn default_devices() -> Vec<WebDevice> {
+    vec![
+        WebDevice::default_for(Direction::Input),
+        WebDevice::default_for(Direction::Output),
+    ]
+}
+
+impl Backend for WebBackend {
+    type Session = WebSession;
+    type Error = WebError;
+    type Device = WebDevice;
+    type AudioBuffers = OwnedBuffer<f32>;
+
+    fn new() -> Result<Self, WebError> {
+        if !Self::is_available() {
+            return Err(WebError::NotCrossOriginIsolated);
+        }
+
+        Ok(WebBackend {
+            devices: Rc::new(RefCell::new(default_devices())),
+        })
+    }
+
+    fn is_available() -> bool {
+        let global = global();
+        ["AudioWorkletNode", "SharedArrayBuffer"]
+            .iter()
+            .all(|name| Reflect::has(&global, &(*name).into()).unwrap_or(false))
+    }
+
+    /// The log's timestamps come from a clock that doesn't exist on
+    /// wasm32-unknown-unknown.
+    fn event_log(&self) -> Result<EventLog, WebError> {
+        Err(WebError::Unsupported("the event log"))
+    }
+
+    /// Watching needs a thread of its own.
+    fn watch_devices<F>(&self, _f: F) -> Result<DeviceWatcher, WebError>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        Err(WebError::Unsupported("watching devices"))
+    }
+
+    fn all_devices(&self) -> Result<Vec<WebDevice>, WebError> {
+        Ok(self.devices.borrow().clone())
+    }
+
+    fn default_input_device(&self) -> Result<WebDevice, WebError> {
+        Ok(WebDevice::default_for(Direction::Input))
+    }
+
+    fn default_output_device(&self) -> Result<WebDevice, WebError> {
+        Ok(WebDevice::default_for(Direction::Output))
+    }
+
+    /// A page has one output, and browsers pick the sample format, so
+    /// additional devices and a `physical_format` are refused. So are
+    /// preroll and routing. The buffer size sets how many frames the
+    /// callback renders at a time, and retries and the startup timeout are
+    /// ignored since the session starts in the background.
+    fn start_session_with_config(
+        &self,
+        config: SessionConfig<Self>,
+        callback: Box<RenderCallback<Self>>,
+    ) -> Result<WebSession, WebError> {
+        let mut session = self.configured_session(&config)?;
+        session.start_with(callback)?;
+
+        Ok(session)
+    }
+
+    fn start_session_with_processor<P: Processor<Self> + 'static>(
+        &self,
+        config: SessionConfig<Self>,
+        mut processor: P,
+    ) -> Result<WebSession, WebError> {
+        let mut session = self.configured_session(&config)?;
+
+        processor.prepare(config.sample_rate, session.max_frames_per_callback());
+        let (input_channels, output_channels) = session.buffer_channels();
+        processor.prepare_channels(&input_channels, &output_channels);
+
+        session.start_with(processor_callback(processor))?;
+
+        Ok(session)
+    }
+
+    fn capture_process(
+        &self,
+        _target: &CaptureTarget,
+        _sample_rate: f64,
+        _callback: Box<RenderCallback<Self>>,
+    ) -> Result<WebSession, WebError> {
+        Err(WebError::Unsupported("process taps"))
+    }
+}
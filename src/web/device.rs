@@ -0,0 +1,147 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+use crate::direction::Direction;
+use crate::traits::Device;
+
+use super::backend::WebBackend;
+use super::error::WebError;
+
+/// The ID browsers give the device the user picked as their default.
+pub(crate) const DEFAULT_DEVICE_ID: &str = "default";
+
+/// Browsers don't say how many channels a device has until it's opened, so
+/// sessions mix every device to and from stereo.
+pub(crate) const CHANNELS: usize = 2;
+
+/// A media device as listed by `navigator.mediaDevices`, either an audio
+/// input or an audio output.
+///
+/// Browsers only give devices labels once the page has been allowed to use
+/// the microphone. Devices compare and order by ID and direction.
+#[derive(Debug, Clone)]
+pub struct WebDevice {
+    id: String,
+    label: String,
+    direction: Direction,
+}
+
+impl WebDevice {
+    pub(crate) fn new(id: String, label: String, direction: Direction) -> Self {
+        WebDevice {
+            id,
+            label,
+            direction,
+        }
+    }
+
+    /// Whatever the browser and the user pick, which is the only device
+    /// there is to choose before the device list has been fetched.
+    pub(crate) fn default_for(direction: Direction) -> Self {
+        let label = match direction {
+            Direction::Input => "Default input",
+            Direction::Output => "Default output",
+        };
+
+        WebDevice::new(DEFAULT_DEVICE_ID.to_owned(), label.to_owned(), direction)
+    }
+
+    /// The `deviceId`, which is specific to the page's origin.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        self.id == DEFAULT_DEVICE_ID
+    }
+
+    fn key(&self) -> (&str, bool) {
+        (&self.id, self.direction == Direction::Output)
+    }
+}
+
+impl PartialEq for WebDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for WebDevice {}
+
+impl Hash for WebDevice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for WebDevice {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for WebDevice {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Device<WebBackend> for WebDevice {
+    fn num_inputs(&self) -> Result<usize, WebError> {
+        Ok(match self.direction {
+            Direction::Input => CHANNELS,
+            Direction::Output => 0,
+        })
+    }
+
+    fn num_outputs(&self) -> Result<usize, WebError> {
+        Ok(match self.direction {
+            Direction::Input => 0,
+            Direction::Output => CHANNELS,
+        })
+    }
+
+    fn name(&self) -> Result<String, WebError> {
+        Ok(self.label.clone())
+    }
+
+    /// Device IDs stay the same for the page's origin until the user clears
+    /// its data.
+    fn persistent_id(&self) -> Result<String, WebError> {
+        Ok(self.id.clone())
+    }
+
+    /// Only the `"default"` device follows the browser's default, since
+    /// pages can't find out which of the others it is.
+    fn is_default_input(&self) -> Result<bool, WebError> {
+        Ok(self.is_default() && self.direction == Direction::Input)
+    }
+
+    fn is_default_output(&self) -> Result<bool, WebError> {
+        Ok(self.is_default() && self.direction == Direction::Output)
+    }
+
+    fn set_nominal_sample_rate(&mut self, _sample_rate: f64) -> Result<(), WebError> {
+        Err(WebError::Unsupported(
+            "device sample rates, which browsers resample from",
+        ))
+    }
+
+    fn set_clock_source(&mut self, _id: u32) -> Result<(), WebError> {
+        Err(WebError::Unsupported("selecting clock sources"))
+    }
+
+    fn nominal_sample_rate(&self) -> Result<f64, WebError> {
+        Err(WebError::Unsupported(
+            "device sample rates, which browsers resample from",
+        ))
+    }
+
+    fn actual_sample_rate(&self) -> Result<f64, WebError> {
+        self.nominal_sample_rate()
+    }
+}
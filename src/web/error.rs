@@ -0,0 +1,53 @@
+use std::error::Error;
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+#[derive(Debug)]
+pub enum WebError {
+    /// A browser API threw, or rejected its promise.
+    Js(JsValue),
+    /// `SharedArrayBuffer` only exists on cross-origin isolated pages, which
+    /// are served with the `Cross-Origin-Opener-Policy: same-origin` and
+    /// `Cross-Origin-Embedder-Policy: require-corp` headers.
+    NotCrossOriginIsolated,
+    /// The backend is running outside a window, like in a worker, where
+    /// there are no media devices.
+    NoWindow,
+    /// The operation or session option has no Web Audio equivalent.
+    Unsupported(&'static str),
+}
+
+impl From<JsValue> for WebError {
+    fn from(value: JsValue) -> Self {
+        WebError::Js(value)
+    }
+}
+
+impl fmt::Display for WebError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebError::Js(value) => write!(f, "JavaScript error: {:?}", value),
+            WebError::NotCrossOriginIsolated => {
+                write!(f, "SharedArrayBuffer needs a cross-origin isolated page")
+            }
+            WebError::NoWindow => write!(f, "No window to get media devices from"),
+            WebError::Unsupported(what) => write!(f, "Not supported by Web Audio: {}", what),
+        }
+    }
+}
+
+impl Error for WebError {}
+
+impl From<WebError> for crate::Error {
+    fn from(error: WebError) -> Self {
+        let kind = match &error {
+            WebError::Js(_) => Self::Backend,
+            WebError::NotCrossOriginIsolated | WebError::NoWindow | WebError::Unsupported(_) => {
+                Self::Unsupported
+            }
+        };
+
+        kind(Box::new(error))
+    }
+}
@@ -0,0 +1,10 @@
+mod backend;
+mod device;
+mod error;
+mod ring;
+mod session;
+
+pub use backend::WebBackend as Backend;
+pub use device::WebDevice;
+pub use error::WebError;
+pub use session::WebSession;
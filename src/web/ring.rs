@@ -0,0 +1,141 @@
+use js_sys::{Atomics, Float32Array, Int32Array, Object, Reflect, SharedArrayBuffer};
+use wasm_bindgen::JsValue;
+
+use super::error::WebError;
+
+/// Slots in a ring's index array. Positions count frames and wrap at 32
+/// bits, which is why capacities are powers of two.
+const READ: u32 = 0;
+const WRITE: u32 = 1;
+/// Frames the worklet had to drop or make up because the session fell
+/// behind.
+const LOST: u32 = 2;
+
+/// A single producer, single consumer ring of interleaved frames in shared
+/// memory, with the session on one end and the AudioWorkletProcessor in
+/// `worklet.js` on the other.
+pub(crate) struct Ring {
+    indices: Int32Array,
+    samples: Float32Array,
+    channels: usize,
+    capacity: usize,
+}
+
+impl Ring {
+    /// A ring with room for at least `frames` frames.
+    pub fn new(frames: usize, channels: usize) -> Result<Self, WebError> {
+        let has_shared_memory = Reflect::has(&js_sys::global(), &"SharedArrayBuffer".into())?;
+        if !has_shared_memory {
+            return Err(WebError::NotCrossOriginIsolated);
+        }
+
+        let capacity = frames.next_power_of_two();
+        let indices = SharedArrayBuffer::new(3 * 4);
+        let samples = SharedArrayBuffer::new((capacity * channels.max(1) * 4) as u32);
+
+        Ok(Ring {
+            indices: Int32Array::new(&indices),
+            samples: Float32Array::new(&samples),
+            channels,
+            capacity,
+        })
+    }
+
+    /// The shared buffers, for the processor to open its end with.
+    pub fn options(&self) -> Result<Object, WebError> {
+        let options = Object::new();
+        Reflect::set(&options, &"indices".into(), &self.indices.buffer())?;
+        Reflect::set(&options, &"samples".into(), &self.samples.buffer())?;
+        Reflect::set(
+            &options,
+            &"channels".into(),
+            &JsValue::from(self.channels as u32),
+        )?;
+
+        Ok(options)
+    }
+
+    fn load(&self, index: u32) -> i32 {
+        Atomics::load(&self.indices, index).unwrap_or(0)
+    }
+
+    /// Frames written but not yet read.
+    pub fn available(&self) -> usize {
+        self.load(WRITE).wrapping_sub(self.load(READ)) as u32 as usize
+    }
+
+    pub fn space(&self) -> usize {
+        self.capacity - self.available()
+    }
+
+    /// The worklet's count of lost frames, which wraps.
+    pub fn lost(&self) -> u32 {
+        self.load(LOST) as u32
+    }
+
+    /// Reads the frames that fit in `frames`, which must be available.
+    pub fn read(&self, frames: &mut [f32]) {
+        let start = self.load(READ);
+        let count = self.copy(start, frames.len(), |ring, at| {
+            ring.subarray(at.start, at.end)
+                .copy_to(&mut frames[at.offset..at.offset + at.len()])
+        });
+        let _ = Atomics::store(&self.indices, READ, start.wrapping_add(count as i32));
+    }
+
+    /// Writes `frames`, which must fit in the space left.
+    pub fn write(&self, frames: &[f32]) {
+        let start = self.load(WRITE);
+        let count = self.copy(start, frames.len(), |ring, at| {
+            ring.subarray(at.start, at.end)
+                .copy_from(&frames[at.offset..at.offset + at.len()])
+        });
+        let _ = Atomics::store(&self.indices, WRITE, start.wrapping_add(count as i32));
+    }
+
+    /// Calls `f` with the one or two stretches of the ring that `samples`
+    /// samples starting at frame `position` cover. Returns the frame count.
+    fn copy(&self, position: i32, samples: usize, mut f: impl FnMut(&Float32Array, Span)) -> usize {
+        if self.channels == 0 {
+            return 0;
+        }
+
+        let frames = samples / self.channels;
+        let first = position as u32 as usize % self.capacity;
+        let before_wrap = frames.min(self.capacity - first);
+
+        f(
+            &self.samples,
+            Span::new(first, before_wrap, 0, self.channels),
+        );
+        if before_wrap < frames {
+            f(
+                &self.samples,
+                Span::new(0, frames - before_wrap, before_wrap, self.channels),
+            );
+        }
+
+        frames
+    }
+}
+
+/// A stretch of the ring's samples, and where it goes in the caller's.
+struct Span {
+    start: u32,
+    end: u32,
+    offset: usize,
+}
+
+impl Span {
+    fn new(first_frame: usize, frames: usize, frame_offset: usize, channels: usize) -> Self {
+        Span {
+            start: (first_frame * channels) as u32,
+            end: ((first_frame + frames) * channels) as u32,
+            offset: frame_offset * channels,
+        }
+    }
+
+    fn len(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+}
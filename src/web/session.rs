@@ -0,0 +1,498 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    AudioContext, AudioContextOptions, AudioWorkletNode, AudioWorkletNodeOptions, Blob,
+    BlobPropertyBag, ChannelCountMode, MediaStream, MediaStreamAudioSourceNode,
+    MediaStreamConstraints, MediaStreamTrack, MessageEvent, Url,
+};
+
+use crate::channel_map::{ChannelMap, StreamMapping};
+use crate::clock::SampleClock;
+use crate::config::OutputPolicy;
+use crate::context::CycleTimes;
+use crate::deadline::DeadlineHistogram;
+use crate::dropout::DropoutStats;
+use crate::engine::RenderEngine;
+use crate::events::{Events, SessionEvent, StopCause};
+use crate::latency::LatencyTuning;
+use crate::meters::Meters;
+use crate::overload::{self, OverloadSubscription};
+#[cfg(feature = "profiling")]
+use crate::profiling::ProfileRecord;
+use crate::sample::OwnedBuffer;
+use crate::traits::{RenderCallback, Session};
+use crate::warnings::{self, Warning, WarningSubscription, WarningThresholds};
+
+use super::backend::WebBackend;
+use super::device::{WebDevice, CHANNELS};
+use super::error::WebError;
+use super::ring::Ring;
+
+const WORKLET_SOURCE: &str = include_str!("worklet.js");
+const PROCESSOR_NAME: &str = "render-callback";
+
+/// The frames per callback unless the session config asks for another
+/// size. Callbacks run on the thread that started the session, usually the
+/// page's main thread, so they need some slack to be scheduled in time.
+pub(crate) const DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// The output ring starts out with this many blocks of silence, which is
+/// the session's latency on top of the browser's own.
+const PREFILL_BLOCKS: usize = 2;
+/// The rings have room for this many blocks.
+const RING_BLOCKS: usize = 4;
+
+/// An AudioContext whose AudioWorkletProcessor trades audio with the
+/// session through shared memory rings. The worklet wakes the session each
+/// render quantum, and the session renders whole blocks on its own thread
+/// whenever there's enough input and room for the output. The callback gets
+/// one stereo buffer per direction.
+///
+/// Setting up the worklet and asking for the microphone happen in the
+/// background, so the callback starts being called some time after the
+/// session is created. Browsers also keep the context suspended until the
+/// user has interacted with the page.
+pub struct WebSession {
+    engine: Arc<RenderEngine<WebBackend>>,
+    context: AudioContext,
+    input: WebDevice,
+    output: WebDevice,
+    block_size: usize,
+    pump: Rc<RefCell<Pump>>,
+    graph: Rc<RefCell<GraphState>>,
+    /// Set by `Session::stop`, so that a worklet that finishes loading
+    /// afterwards doesn't resume the context.
+    stopped: Rc<Cell<bool>>,
+}
+
+enum GraphState {
+    Connecting,
+    Connected(Graph),
+    Closed,
+}
+
+/// The nodes and handlers set up once the worklet has loaded.
+struct Graph {
+    node: AudioWorkletNode,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    source: Option<(MediaStreamAudioSourceNode, MediaStream)>,
+}
+
+impl Graph {
+    fn disconnect(self) {
+        if let Ok(port) = self.node.port() {
+            port.set_onmessage(None);
+        }
+        let _ = self.node.disconnect();
+
+        if let Some((source, stream)) = self.source {
+            let _ = source.disconnect();
+            for track in stream.get_audio_tracks().iter() {
+                track.unchecked_into::<MediaStreamTrack>().stop();
+            }
+        }
+    }
+}
+
+impl WebSession {
+    pub(crate) fn new(
+        sample_rate: f64,
+        input: WebDevice,
+        output: WebDevice,
+        block_size: Option<usize>,
+    ) -> Result<Self, WebError> {
+        if !output.is_default() {
+            return Err(WebError::Unsupported("outputs other than the default"));
+        }
+
+        let options = AudioContextOptions::new();
+        options.set_sample_rate(sample_rate as f32);
+        let context = AudioContext::new_with_context_options(&options)?;
+
+        let block_size = block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+        let engine = Arc::new(RenderEngine::new(sample_rate));
+        engine.resize_scratch(block_size, CHANNELS);
+
+        let pump = Pump::new(engine.clone(), block_size)?;
+
+        Ok(WebSession {
+            engine,
+            context,
+            input,
+            output,
+            block_size,
+            pump: Rc::new(RefCell::new(pump)),
+            graph: Rc::new(RefCell::new(GraphState::Connecting)),
+            stopped: Rc::new(Cell::new(false)),
+        })
+    }
+
+    /// Sets up the worklet in the background, and calls `callback` once it
+    /// runs.
+    pub fn start_with(
+        &mut self,
+        callback: Box<RenderCallback<WebBackend>>,
+    ) -> Result<(), WebError> {
+        self.engine.set_callback(callback);
+        self.engine.valid.store(true, Ordering::Release);
+
+        let processor_options = Object::new();
+        {
+            let pump = self.pump.borrow();
+            Reflect::set(
+                &processor_options,
+                &"input".into(),
+                &pump.input.options()?.into(),
+            )?;
+            Reflect::set(
+                &processor_options,
+                &"output".into(),
+                &pump.output.options()?.into(),
+            )?;
+        }
+
+        let context = self.context.clone();
+        let input = self.input.clone();
+        let pump = self.pump.clone();
+        let graph = self.graph.clone();
+        let engine = self.engine.clone();
+        let stopped = self.stopped.clone();
+        spawn_local(async move {
+            let result = connect(&context, &input, processor_options, pump).await;
+
+            let mut state = graph.borrow_mut();
+            match (result, &*state) {
+                (Ok(connected), GraphState::Connecting) => {
+                    *state = GraphState::Connected(connected);
+                    // Resolves only once the user has interacted with the
+                    // page, so there's no point waiting for it.
+                    if !stopped.get() {
+                        let _ = context.resume();
+                    }
+                }
+                // The session was dropped while connecting.
+                (Ok(connected), _) => connected.disconnect(),
+                (Err(_), _) => {
+                    engine.valid.store(false, Ordering::Release);
+                    engine.events.push(SessionEvent::StoppedUnexpectedly {
+                        cause: StopCause::Unknown,
+                    });
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn max_frames_per_callback(&self) -> usize {
+        self.block_size
+    }
+
+    /// The channel count of each input and output buffer the callback gets.
+    pub fn buffer_channels(&self) -> (Vec<usize>, Vec<usize>) {
+        (vec![CHANNELS], vec![CHANNELS])
+    }
+
+    pub fn set_output_policy(&self, policy: OutputPolicy) {
+        self.engine.set_output_policy(policy);
+    }
+}
+
+impl Drop for WebSession {
+    fn drop(&mut self) {
+        self.engine.valid.store(false, Ordering::Release);
+
+        if let GraphState::Connected(graph) = self.graph.replace(GraphState::Closed) {
+            graph.disconnect();
+        }
+        let _ = self.context.close();
+
+        // The callback is only ever called from this thread, by the message
+        // handler that was just removed.
+        drop(self.engine.take_callback());
+    }
+}
+
+/// Loads the worklet, creates its node, and connects the microphone to it.
+async fn connect(
+    context: &AudioContext,
+    input: &WebDevice,
+    processor_options: Object,
+    pump: Rc<RefCell<Pump>>,
+) -> Result<Graph, WebError> {
+    let url = worklet_url()?;
+    let loaded = context.audio_worklet()?.add_module(&url);
+    Url::revoke_object_url(&url)?;
+    JsFuture::from(loaded?).await?;
+
+    let options = AudioWorkletNodeOptions::new();
+    options.set_number_of_inputs(1);
+    options.set_number_of_outputs(1);
+    options.set_channel_count(CHANNELS as u32);
+    options.set_channel_count_mode(ChannelCountMode::Explicit);
+    options.set_output_channel_count(&Array::of1(&JsValue::from(CHANNELS as u32)));
+    options.set_processor_options(Some(&processor_options));
+    let node = AudioWorkletNode::new_with_options(context, PROCESSOR_NAME, &options)?;
+
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |_| pump.borrow_mut().pump());
+    node.port()?
+        .set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    node.connect_with_audio_node(&context.destination())?;
+
+    let stream = microphone(input).await?;
+    let source = context.create_media_stream_source(&stream)?;
+    source.connect_with_audio_node(&node)?;
+
+    Ok(Graph {
+        node,
+        _on_message: on_message,
+        source: Some((source, stream)),
+    })
+}
+
+/// The worklet's source as a URL `addModule` can load.
+fn worklet_url() -> Result<String, WebError> {
+    let parts = Array::of1(&JsValue::from_str(WORKLET_SOURCE));
+    let properties = BlobPropertyBag::new();
+    properties.set_type("text/javascript");
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &properties)?;
+
+    Ok(Url::create_object_url_with_blob(&blob)?)
+}
+
+/// Asks for the microphone, with the echo cancellation, noise suppression
+/// and gain control browsers apply for calls turned off.
+async fn microphone(device: &WebDevice) -> Result<MediaStream, WebError> {
+    let media_devices = web_sys::window()
+        .ok_or(WebError::NoWindow)?
+        .navigator()
+        .media_devices()?;
+
+    let audio = Object::new();
+    for processing in &["echoCancellation", "noiseSuppression", "autoGainControl"] {
+        Reflect::set(&audio, &(*processing).into(), &JsValue::FALSE)?;
+    }
+    if !device.is_default() {
+        let id = Object::new();
+        Reflect::set(&id, &"exact".into(), &device.id().into())?;
+        Reflect::set(&audio, &"deviceId".into(), &id)?;
+    }
+
+    let constraints = MediaStreamConstraints::new();
+    constraints.set_audio(&audio);
+    let stream =
+        JsFuture::from(media_devices.get_user_media_with_constraints(&constraints)?).await?;
+
+    Ok(stream.unchecked_into())
+}
+
+/// The session's end of the rings.
+struct Pump {
+    engine: Arc<RenderEngine<WebBackend>>,
+    block_size: usize,
+    input: Ring,
+    output: Ring,
+    input_buffers: Vec<OwnedBuffer<f32>>,
+    output_buffers: Vec<OwnedBuffer<f32>>,
+    rendered: u64,
+    /// The worklet's last count of lost output frames, and the total.
+    lost: (u32, u64),
+}
+
+impl Pump {
+    fn new(engine: Arc<RenderEngine<WebBackend>>, block_size: usize) -> Result<Self, WebError> {
+        let input = Ring::new(block_size * RING_BLOCKS, CHANNELS)?;
+        let output = Ring::new(block_size * RING_BLOCKS, CHANNELS)?;
+        output.write(&vec![0.0; block_size * PREFILL_BLOCKS * CHANNELS]);
+
+        Ok(Pump {
+            engine,
+            block_size,
+            input,
+            output,
+            input_buffers: vec![buffer(block_size, CHANNELS)],
+            output_buffers: vec![buffer(block_size, CHANNELS)],
+            rendered: 0,
+            lost: (0, 0),
+        })
+    }
+
+    /// Renders blocks for as long as there's input for them and room for
+    /// their output.
+    fn pump(&mut self) {
+        while self.output.space() >= self.block_size && self.input.available() >= self.block_size {
+            self.render_block();
+        }
+    }
+
+    fn render_block(&mut self) {
+        self.engine.cycles.fetch_add(1, Ordering::Release);
+
+        for buffer in &mut self.input_buffers {
+            self.input.read(buffer.interleaved_frames_mut());
+        }
+
+        // Silence the worklet played in place of output counts as skipped
+        // frames, so the engine reports it as a dropout.
+        let (last, total) = self.lost;
+        let lost = self.output.lost();
+        self.lost = (lost, total + u64::from(lost.wrapping_sub(last)));
+        let sample_time = self.rendered + self.lost.1;
+
+        // The message handler is the only caller, on the session's thread.
+        unsafe {
+            self.engine.render(
+                &self.input_buffers,
+                &mut self.output_buffers,
+                self.block_size,
+                CycleTimes::at(sample_time as f64),
+            );
+        }
+        self.rendered += self.block_size as u64;
+
+        for buffer in &self.output_buffers {
+            self.output.write(buffer.interleaved_frames());
+        }
+    }
+}
+
+fn buffer(frames: usize, channels: usize) -> OwnedBuffer<f32> {
+    let mut buffer = OwnedBuffer::with_capacity(frames, channels);
+    buffer.reshape(frames, channels);
+    buffer
+}
+
+impl Session<WebBackend> for WebSession {
+    fn input_device(&self) -> Result<WebDevice, WebError> {
+        Ok(self.input.clone())
+    }
+
+    fn output_device(&self) -> Result<WebDevice, WebError> {
+        Ok(self.output.clone())
+    }
+
+    fn set_input_device(&mut self, _device: WebDevice) -> Result<(), WebError> {
+        Err(WebError::Unsupported("switching devices while running"))
+    }
+
+    fn set_output_device(&mut self, _device: WebDevice) -> Result<(), WebError> {
+        Err(WebError::Unsupported("switching devices while running"))
+    }
+
+    fn max_frames_per_callback(&self) -> Result<usize, WebError> {
+        Ok(WebSession::max_frames_per_callback(self))
+    }
+
+    fn channel_map(&self) -> Result<ChannelMap, WebError> {
+        let side = |device: &WebDevice| {
+            vec![StreamMapping {
+                device_id: device.id().to_owned(),
+                first_device_channel: 0,
+                channels: CHANNELS,
+            }]
+        };
+
+        Ok(ChannelMap {
+            input: side(&self.input),
+            output: side(&self.output),
+        })
+    }
+
+    fn dropouts(&self) -> DropoutStats {
+        self.engine.dropouts.stats()
+    }
+
+    fn sample_clock(&self) -> SampleClock {
+        self.engine.clock.clone()
+    }
+
+    fn deadline_margins(&self) -> DeadlineHistogram {
+        self.engine.deadlines.histogram()
+    }
+
+    fn meters(&self) -> Meters {
+        Meters::new(self.engine.meters.clone())
+    }
+
+    fn events(&self) -> Events {
+        Events::new(self.engine.events.clone())
+    }
+
+    fn warnings<F>(&self, thresholds: WarningThresholds, f: F) -> WarningSubscription
+    where
+        F: FnMut(Warning) + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        warnings::watch(thresholds, move || engine.warning_snapshot(0), f)
+    }
+
+    fn overloads(&self) -> u64 {
+        self.engine.overloads.count()
+    }
+
+    fn on_overload<F>(&self, f: F) -> OverloadSubscription
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        overload::watch(self.engine.overloads.clone(), f)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn take_profile(&self) -> Vec<ProfileRecord> {
+        self.engine.profiler.take()
+    }
+
+    fn set_callback(&mut self, callback: Box<RenderCallback<WebBackend>>) {
+        self.engine.set_callback(callback);
+    }
+
+    fn set_bypassed(&self, bypassed: bool) {
+        self.engine.set_bypassed(bypassed);
+    }
+
+    fn is_bypassed(&self) -> bool {
+        self.engine.is_bypassed()
+    }
+
+    /// Trying out buffer sizes means waiting, which a page's main thread
+    /// can't do. Pick the block size in the session config instead.
+    fn tune_for_low_latency(&mut self, _target_ms: f64) -> Result<Option<LatencyTuning>, WebError> {
+        Err(WebError::Unsupported("tuning for low latency"))
+    }
+
+    fn start_at(&mut self, _host_time: u64) -> Result<u64, WebError> {
+        Err(WebError::Unsupported("starting at a host time"))
+    }
+
+    /// Suspends the context, which stops the worklet and so the callback.
+    fn stop(&mut self) -> Result<(), WebError> {
+        self.stopped.set(true);
+        let _ = self.context.suspend()?;
+        Ok(())
+    }
+
+    /// Resumes the context, which browsers only allow once the user has
+    /// interacted with the page.
+    fn start(&mut self) -> Result<(), WebError> {
+        if !self.stopped.replace(false) {
+            return Ok(());
+        }
+
+        self.engine.reset_timeline();
+        if let GraphState::Connected(_) = &*self.graph.borrow() {
+            let _ = self.context.resume()?;
+        }
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        !self.stopped.get()
+    }
+}
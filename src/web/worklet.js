@@ -0,0 +1,91 @@
+// Runs on the browser's audio rendering thread, moving audio between Web
+// Audio and the rings a session renders from and into, one render quantum
+// at a time. The layout of the rings matches ring.rs.
+
+const READ = 0;
+const WRITE = 1;
+const LOST = 2;
+
+class Ring {
+  constructor({ indices, samples, channels }) {
+    this.indices = new Int32Array(indices);
+    this.samples = new Float32Array(samples);
+    this.channels = channels;
+    this.capacity = channels > 0 ? this.samples.length / channels : 0;
+  }
+
+  available() {
+    return (Atomics.load(this.indices, WRITE) - Atomics.load(this.indices, READ)) >>> 0;
+  }
+
+  offset(position, frame) {
+    return (((position + frame) >>> 0) % this.capacity) * this.channels;
+  }
+}
+
+class RenderCallbackProcessor extends AudioWorkletProcessor {
+  constructor(options) {
+    super();
+    this.input = new Ring(options.processorOptions.input);
+    this.output = new Ring(options.processorOptions.output);
+  }
+
+  process(inputs, outputs) {
+    const output = outputs[0];
+    const frames = output[0].length;
+
+    this.write(inputs[0], frames);
+    this.read(output, frames);
+
+    // Wakes the session up to render more.
+    this.port.postMessage(null);
+    return true;
+  }
+
+  // Input the session hasn't made room for is dropped. Channels without a
+  // source connected are silent.
+  write(input, frames) {
+    const ring = this.input;
+    if (ring.channels === 0) {
+      return;
+    }
+    if (ring.capacity - ring.available() < frames) {
+      Atomics.add(ring.indices, LOST, frames);
+      return;
+    }
+
+    const start = Atomics.load(ring.indices, WRITE);
+    for (let frame = 0; frame < frames; frame++) {
+      const offset = ring.offset(start, frame);
+      for (let channel = 0; channel < ring.channels; channel++) {
+        const samples = input[channel];
+        ring.samples[offset + channel] = samples ? samples[frame] : 0;
+      }
+    }
+    Atomics.store(ring.indices, WRITE, (start + frames) | 0);
+  }
+
+  // Plays silence while the session is behind.
+  read(output, frames) {
+    const ring = this.output;
+    if (ring.available() < frames) {
+      for (const channel of output) {
+        channel.fill(0);
+      }
+      Atomics.add(ring.indices, LOST, frames);
+      return;
+    }
+
+    const start = Atomics.load(ring.indices, READ);
+    const channels = Math.min(ring.channels, output.length);
+    for (let frame = 0; frame < frames; frame++) {
+      const offset = ring.offset(start, frame);
+      for (let channel = 0; channel < channels; channel++) {
+        output[channel][frame] = ring.samples[offset + channel];
+      }
+    }
+    Atomics.store(ring.indices, READ, (start + frames) | 0);
+  }
+}
+
+registerProcessor("render-callback", RenderCallbackProcessor);